@@ -0,0 +1,2 @@
+-- Add image counter row for the two-player compare image
+INSERT OR IGNORE INTO image_counter (image_type, count, last_updated) VALUES ('compare', 0, datetime('now'));
@@ -0,0 +1,7 @@
+-- Daily snapshots of player RKS, used to compute RKS gained over recent days
+CREATE TABLE IF NOT EXISTS rks_snapshots (
+    player_id TEXT NOT NULL,
+    snapshot_date TEXT NOT NULL, -- UTC date, YYYY-MM-DD
+    rks REAL NOT NULL,
+    PRIMARY KEY (player_id, snapshot_date)
+);
@@ -0,0 +1,12 @@
+-- Make image_type unique so counters can be upserted on first use.
+-- New image types no longer need a seed row here; increment_counter creates it.
+
+-- Merge any duplicate rows into the oldest row of each type before adding the constraint
+UPDATE image_counter
+SET count = (SELECT SUM(c.count) FROM image_counter c WHERE c.image_type = image_counter.image_type)
+WHERE id IN (SELECT MIN(id) FROM image_counter GROUP BY image_type);
+
+DELETE FROM image_counter
+WHERE id NOT IN (SELECT MIN(id) FROM image_counter GROUP BY image_type);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_image_counter_type_unique ON image_counter (image_type);
@@ -0,0 +1,2 @@
+-- Index for comparing a player's score against everyone's current score on a chart
+CREATE INDEX IF NOT EXISTS idx_chart_scores_chart_current_acc ON chart_scores (song_id, difficulty, is_current, acc);
@@ -0,0 +1,12 @@
+-- Raw (still encrypted) cloud save blobs, stored only when STORE_SAVE_SNAPSHOTS is enabled
+CREATE TABLE IF NOT EXISTS save_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    player_id TEXT NOT NULL,
+    checksum TEXT NOT NULL, -- MD5 of data, hex
+    size INTEGER NOT NULL, -- bytes
+    data BLOB NOT NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE (player_id, checksum)
+);
+
+CREATE INDEX IF NOT EXISTS idx_save_snapshots_player_id ON save_snapshots (player_id, id);
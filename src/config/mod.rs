@@ -8,19 +8,43 @@ pub struct AppConfig {
     pub host: String,
     pub port: u16,
     pub cors_allowed_origins: Vec<String>,
+    pub cors_allow_credentials: bool,
     pub info_data_path: String,
     pub difficulty_file: String,
     pub info_file: String,
     pub nicklist_file: String,
+    pub cover_cdn_base_url: Option<String>,
+    pub rks_min_acc: f64,
+    pub rks_webhook_url: Option<String>,
+    pub rks_webhook_delta: f64,
+    pub admin_token: Option<String>,
+    pub image_signing_secret: Option<String>,
+    pub default_theme: String,
+    pub default_background_mode: String,
+    pub render_profile: String,
+    pub grade_thresholds: String,
+    pub max_bn: u32,
+    pub leaderboard_default_limit: usize,
+    pub leaderboard_max_limit: usize,
+    pub song_name_full_width_px: f64,
+    pub song_name_half_width_px: f64,
+    pub cover_warm_count: usize,
     pub taptap_client_id: String,
     pub leancloud_app_key: String,
     pub leancloud_client_id: String,
     pub leancloud_base_url: String,
+    pub leancloud_user_agent: String,
+    pub external_fallback_enabled: bool,
+    pub external_breaker_threshold: usize,
+    pub external_breaker_cooldown_secs: u64,
     pub maintenance_mode: bool,
     pub maintenance_message: String,
     pub maintenance_start_time: Option<String>,
     pub maintenance_end_time: Option<String>,
     pub maintenance_cron: Option<String>,
+    pub store_save_snapshots: bool,
+    pub save_snapshot_retention: usize,
+    pub save_snapshot_max_bytes: usize,
 }
 
 impl Default for AppConfig {
@@ -31,13 +55,63 @@ impl Default for AppConfig {
                 .unwrap_or_else(|_| "8080".to_string())
                 .parse()
                 .unwrap_or(8080),
-            cors_allowed_origins: vec!["*".to_string()],
+            cors_allowed_origins: env::var("CORS_ALLOWED_ORIGINS")
+                .ok()
+                .map(|v| {
+                    v.split(',')
+                        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                        .filter(|origin| !origin.is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|origins| !origins.is_empty())
+                .unwrap_or_else(|| vec!["*".to_string()]),
+            cors_allow_credentials: env::var("CORS_ALLOW_CREDENTIALS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
             info_data_path: env::var("INFO_DATA_PATH").unwrap_or_else(|_| "../info".to_string()),
             difficulty_file: env::var("DIFFICULTY_FILE")
                 .unwrap_or_else(|_| "difficulty.csv".to_string()),
             info_file: env::var("INFO_FILE").unwrap_or_else(|_| "info.csv".to_string()),
             nicklist_file: env::var("NICKLIST_FILE")
                 .unwrap_or_else(|_| "nicklist.yaml".to_string()),
+            cover_cdn_base_url: env::var("COVER_CDN_BASE_URL")
+                .ok()
+                .filter(|url| !url.trim().is_empty()),
+            rks_min_acc: parse_rks_min_acc(env::var("RKS_MIN_ACC").ok().as_deref()),
+            rks_webhook_url: env::var("RKS_WEBHOOK_URL")
+                .ok()
+                .filter(|url| !url.trim().is_empty()),
+            rks_webhook_delta: env::var("RKS_WEBHOOK_DELTA")
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v >= 0.0)
+                .unwrap_or(0.01),
+            admin_token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.trim().is_empty()),
+            image_signing_secret: env::var("IMAGE_SIGNING_SECRET")
+                .ok()
+                .filter(|secret| !secret.trim().is_empty()),
+            default_theme: env::var("DEFAULT_THEME").unwrap_or_else(|_| "black".to_string()),
+            default_background_mode: env::var("DEFAULT_BACKGROUND_MODE")
+                .unwrap_or_else(|_| "random".to_string()),
+            render_profile: env::var("RENDER_PROFILE").unwrap_or_else(|_| "pretty".to_string()),
+            grade_thresholds: env::var("GRADE_THRESHOLDS")
+                .unwrap_or_else(|_| "960000,920000,880000,820000,700000".to_string()),
+            max_bn: env::var("MAX_BN")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(100),
+            leaderboard_default_limit: positive_usize_env("LEADERBOARD_DEFAULT_LIMIT", 20),
+            leaderboard_max_limit: positive_usize_env("LEADERBOARD_MAX_LIMIT", 100),
+            song_name_full_width_px: positive_f64_env("SONG_NAME_FULL_WIDTH_PX", 19.0),
+            song_name_half_width_px: positive_f64_env("SONG_NAME_HALF_WIDTH_PX", 10.5),
+            cover_warm_count: env::var("COVER_WARM_COUNT")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0),
             taptap_client_id: env::var("TAPTAP_CLIENT_ID")
                 .unwrap_or_else(|_| "rAK3FfdieFob2Nn8Am".to_string()),
             leancloud_app_key: env::var("LEANCLOUD_APP_KEY")
@@ -46,6 +120,15 @@ impl Default for AppConfig {
                 .unwrap_or_else(|_| "rAK3FfdieFob2Nn8Am".to_string()),
             leancloud_base_url: env::var("LEANCLOUD_BASE_URL")
                 .unwrap_or_else(|_| "https://rak3ffdi.cloud.tds1.tapapis.cn/1.1".to_string()),
+            leancloud_user_agent: env::var("LEANCLOUD_USER_AGENT")
+                .unwrap_or_else(|_| "LeanCloud-CSharp-SDK/1.0.3".to_string()),
+            external_fallback_enabled: env::var("EXTERNAL_FALLBACK_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            external_breaker_threshold: positive_usize_env("EXTERNAL_BREAKER_THRESHOLD", 5),
+            external_breaker_cooldown_secs: positive_usize_env("EXTERNAL_BREAKER_COOLDOWN_SECS", 30)
+                as u64,
             maintenance_mode: env::var("MAINTENANCE_MODE")
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
@@ -55,10 +138,115 @@ impl Default for AppConfig {
             maintenance_start_time: env::var("MAINTENANCE_START_TIME").ok(),
             maintenance_end_time: env::var("MAINTENANCE_END_TIME").ok(),
             maintenance_cron: env::var("MAINTENANCE_CRON").ok(),
+            store_save_snapshots: env::var("STORE_SAVE_SNAPSHOTS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            save_snapshot_retention: positive_usize_env("SAVE_SNAPSHOT_RETENTION", 5),
+            save_snapshot_max_bytes: positive_usize_env("SAVE_SNAPSHOT_MAX_BYTES", 1024 * 1024),
         }
     }
 }
 
+/// 读取正数类型的环境变量，缺失或无效时使用默认值
+fn positive_f64_env(name: &str, default: f64) -> f64 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .unwrap_or(default)
+}
+
+/// 计入 RKS 的默认最低 ACC，与游戏规则一致
+pub const DEFAULT_RKS_MIN_ACC: f64 = 70.0;
+/// RKS_MIN_ACC 允许的下限：ACC 低于 55 时 ((ACC-55)/45)^2 会重新增大，低 ACC 反而得到更高的 RKS
+pub const RKS_MIN_ACC_FLOOR: f64 = 55.0;
+
+/// 解析 RKS_MIN_ACC：缺失或不在 0~100 内时使用默认值，低于 55 时按 55 处理
+pub fn parse_rks_min_acc(value: Option<&str>) -> f64 {
+    value
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|v| (0.0..=100.0).contains(v))
+        .map_or(DEFAULT_RKS_MIN_ACC, |v| v.max(RKS_MIN_ACC_FLOOR))
+}
+
+/// 读取正整数类型的环境变量，缺失或无效时使用默认值
+fn positive_usize_env(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+/// 校验 CORS 来源列表，`*` 表示允许任意来源
+fn validate_cors_origins(origins: &[String]) -> Result<(), String> {
+    for origin in origins.iter().filter(|origin| *origin != "*") {
+        let valid = origin.parse::<actix_web::http::Uri>().is_ok_and(|uri| {
+            matches!(uri.scheme_str(), Some("http" | "https"))
+                && uri.host().is_some_and(|host| !host.is_empty())
+                && uri.path_and_query().is_none_or(|p| p.as_str() == "/")
+        });
+        if !valid {
+            return Err(format!(
+                "CORS_ALLOWED_ORIGINS 中的来源 '{origin}' 无效，应为 scheme://host[:port] 形式，如 https://example.com"
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl AppConfig {
+    /// 是否允许任意来源跨域访问 (未配置或配置为 `*` 时)
+    pub fn cors_allows_any_origin(&self) -> bool {
+        self.cors_allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// 校验 CORS 允许的来源：每个来源都必须是 `scheme://host[:port]` 形式，否则跨域中间件无法创建
+    pub fn validate_cors(&self) -> Result<(), String> {
+        validate_cors_origins(&self.cors_allowed_origins)
+    }
+
+    /// 校验访问 LeanCloud 所需的配置：必须非空，且请求头的值必须是合法的 HTTP 头部值
+    pub fn validate_leancloud(&self) -> Result<(), String> {
+        let headers = [
+            ("LEANCLOUD_CLIENT_ID", &self.leancloud_client_id),
+            ("LEANCLOUD_APP_KEY", &self.leancloud_app_key),
+            ("LEANCLOUD_USER_AGENT", &self.leancloud_user_agent),
+        ];
+        for (name, value) in headers {
+            if value.trim().is_empty() {
+                return Err(format!("{name} 不能为空"));
+            }
+            if reqwest::header::HeaderValue::from_str(value).is_err() {
+                return Err(format!("{name} 包含无法用作HTTP请求头的字符"));
+            }
+        }
+        if self.leancloud_base_url.trim().is_empty() {
+            return Err("LEANCLOUD_BASE_URL 不能为空".to_string());
+        }
+        Ok(())
+    }
+
+    /// 校验排行榜条数配置：默认条数不能超过最大条数
+    pub fn validate_leaderboard(&self) -> Result<(), String> {
+        if self.leaderboard_default_limit > self.leaderboard_max_limit {
+            return Err(format!(
+                "LEADERBOARD_DEFAULT_LIMIT ({}) 不能大于 LEADERBOARD_MAX_LIMIT ({})",
+                self.leaderboard_default_limit, self.leaderboard_max_limit
+            ));
+        }
+        Ok(())
+    }
+
+    /// 排行榜实际返回的条数：未指定时使用默认条数，超过最大条数时截断
+    pub fn leaderboard_limit(&self, requested: Option<usize>) -> usize {
+        requested
+            .unwrap_or(self.leaderboard_default_limit)
+            .min(self.leaderboard_max_limit)
+    }
+}
+
 lazy_static! {
     pub static ref CONFIG: Arc<AppConfig> = Arc::new(AppConfig::default());
 }
@@ -70,3 +258,41 @@ pub const AES_IV_BASE64: &str = "Kk/wisgNYwcAV8WVGMgyUw==";
 // 存档文件列表
 #[allow(dead_code)]
 pub const SAVE_FILE_LIST: [&str; 5] = ["gameKey", "gameProgress", "gameRecord", "settings", "user"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rks_min_acc_is_parsed_and_clamped() {
+        assert_eq!(parse_rks_min_acc(None), DEFAULT_RKS_MIN_ACC);
+        assert_eq!(parse_rks_min_acc(Some("abc")), DEFAULT_RKS_MIN_ACC);
+        assert_eq!(parse_rks_min_acc(Some("120")), DEFAULT_RKS_MIN_ACC);
+        assert_eq!(parse_rks_min_acc(Some(" 65.5 ")), 65.5);
+        assert_eq!(parse_rks_min_acc(Some("10")), RKS_MIN_ACC_FLOOR);
+    }
+
+    #[test]
+    fn cors_origins_must_be_scheme_and_host() {
+        let origins = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(validate_cors_origins(&origins(&["*"])).is_ok());
+        assert!(validate_cors_origins(&origins(&[
+            "https://example.com",
+            "http://localhost:3000",
+            "http://[::1]:8080",
+        ]))
+        .is_ok());
+        for invalid in [
+            "example.com",
+            "https://",
+            "https://example.com/app",
+            "ftp://example.com",
+            "https://exa mple.com",
+        ] {
+            assert!(
+                validate_cors_origins(&origins(&["https://ok.example", invalid])).is_err(),
+                "{invalid} 应被拒绝"
+            );
+        }
+    }
+}
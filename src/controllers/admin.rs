@@ -0,0 +1,409 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::cache::{CacheEvictResult, CacheInfo, CacheName};
+use crate::models::player_archive::{
+    ArchiveMergeResult, ArchiveResetResult, DuplicateArchiveCandidate, RksRecomputeResult,
+    SaveSnapshotInfo, SaveSnapshotParseResult,
+};
+use crate::models::song::UnknownSongReport;
+use crate::models::user::ApiResponse;
+use crate::services::image_service::ImageService;
+use crate::services::player_archive_service::PlayerArchiveService;
+use crate::services::song::SongService;
+use crate::utils::admin_auth::require_admin;
+use crate::utils::data_loader;
+use crate::utils::error::{AppError, AppResult};
+use crate::utils::save_parser::parse_save_with_difficulty;
+
+/// 默认的 RKS 容差
+const DEFAULT_RKS_TOLERANCE: f64 = 0.1;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DuplicateArchivesQuery {
+    /// 判定为重复时允许的最大 RKS 差值，默认 0.1
+    pub rks_tolerance: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MergeArchivesRequest {
+    /// 保留的存档ID
+    pub primary_id: String,
+    /// 合并后删除的存档ID
+    pub alias_id: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResetArchiveRequest {
+    /// 要重置的存档ID
+    pub player_id: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EvictCacheRequest {
+    /// 要清除的缓存
+    pub cache: CacheName,
+    /// 只清除与该键相关的条目：图片缓存为存档校验和 (可通过 /save/info 获取)，存档缓存为玩家ID，歌曲查询缓存为查询字符串；省略时清空整个缓存
+    pub key: Option<String>,
+}
+
+/// 列出疑似重复的玩家存档
+///
+/// 返回玩家名称相同且 RKS 接近的存档对，并给出建议保留的存档ID。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    get,
+    path = "/admin/archives/duplicates",
+    params(DuplicateArchivesQuery),
+    responses(
+        (status = 200, description = "检测完成", body = ApiResponse<Vec<DuplicateArchiveCandidate>>),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用")
+    )
+)]
+#[get("/admin/archives/duplicates")]
+pub async fn find_duplicate_archives(
+    req: HttpRequest,
+    query: web::Query<DuplicateArchivesQuery>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let rks_tolerance = query.rks_tolerance.unwrap_or(DEFAULT_RKS_TOLERANCE);
+    if !rks_tolerance.is_finite() || rks_tolerance < 0.0 {
+        return Err(AppError::BadRequest(
+            "rks_tolerance 必须是非负数".to_string(),
+        ));
+    }
+
+    let candidates = player_archive_service
+        .find_duplicate_archives(rks_tolerance)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some(format!("发现 {} 组疑似重复的存档", candidates.len())),
+        data: Some(candidates),
+    }))
+}
+
+/// 合并玩家存档
+///
+/// 将 `alias_id` 的成绩记录与推分ACC迁移到 `primary_id`，删除 `alias_id` 存档并重新计算 RKS。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    post,
+    path = "/admin/archives/merge",
+    request_body = MergeArchivesRequest,
+    responses(
+        (status = 200, description = "合并成功", body = ApiResponse<ArchiveMergeResult>),
+        (status = 400, description = "请求参数错误"),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用"),
+        (status = 404, description = "存档不存在")
+    )
+)]
+#[post("/admin/archives/merge")]
+pub async fn merge_player_archives(
+    req: HttpRequest,
+    body: web::Json<MergeArchivesRequest>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let result = player_archive_service
+        .merge_player_archives(&body.primary_id, &body.alias_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some("存档合并成功".to_string()),
+        data: Some(result),
+    }))
+}
+
+/// 重置玩家存档
+///
+/// 在事务中删除该玩家的成绩记录、推分ACC、RKS 快照与存档本身，并清除缓存，用于修复损坏的存档。
+/// 玩家下次查询时会按最新云存档重新建立存档。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    post,
+    path = "/admin/archives/reset",
+    request_body = ResetArchiveRequest,
+    responses(
+        (status = 200, description = "重置成功", body = ApiResponse<ArchiveResetResult>),
+        (status = 400, description = "请求参数错误"),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用"),
+        (status = 404, description = "存档不存在")
+    )
+)]
+#[post("/admin/archives/reset")]
+pub async fn reset_player_archive(
+    req: HttpRequest,
+    body: web::Json<ResetArchiveRequest>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let player_id = body.player_id.trim();
+    if player_id.is_empty() {
+        return Err(AppError::BadRequest("player_id 不能为空".to_string()));
+    }
+
+    let result = player_archive_service
+        .reset_player_archive(player_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some("存档已重置".to_string()),
+        data: Some(result),
+    }))
+}
+
+/// 列出玩家的原始存档快照
+///
+/// 返回快照的ID、校验和、大小与保存时间，按保存时间倒序，不包含存档内容。
+/// 仅在启用 `STORE_SAVE_SNAPSHOTS` 后获取的存档会被保存。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    get,
+    path = "/admin/archives/{player_id}/snapshots",
+    params(
+        ("player_id" = String, Path, description = "玩家ID")
+    ),
+    responses(
+        (status = 200, description = "查询成功", body = ApiResponse<Vec<SaveSnapshotInfo>>),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用")
+    )
+)]
+#[get("/admin/archives/{player_id}/snapshots")]
+pub async fn list_save_snapshots(
+    req: HttpRequest,
+    path: web::Path<String>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let snapshots = player_archive_service
+        .list_save_snapshots(path.trim())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some(format!("共 {} 份存档快照", snapshots.len())),
+        data: Some(snapshots),
+    }))
+}
+
+/// 重新解析原始存档快照
+///
+/// 解密并解析保存的存档，按当前定数表填充难度定数与单曲 RKS，用于离线重新处理历史存档。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    post,
+    path = "/admin/snapshots/{id}/parse",
+    params(
+        ("id" = i64, Path, description = "快照ID")
+    ),
+    responses(
+        (status = 200, description = "解析成功", body = ApiResponse<SaveSnapshotParseResult>),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用"),
+        (status = 404, description = "快照不存在")
+    )
+)]
+#[post("/admin/snapshots/{id}/parse")]
+pub async fn parse_save_snapshot(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let (snapshot, save_data) = player_archive_service
+        .get_save_snapshot(path.into_inner())
+        .await?;
+    let save = parse_save_with_difficulty(&save_data)?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: None,
+        data: Some(SaveSnapshotParseResult { snapshot, save }),
+    }))
+}
+
+/// 按当前定数重算全部玩家的 RKS
+///
+/// 更新 difficulty.csv 并重启服务后调用，使已存档成绩的 RKS 与排行榜使用新的定数。
+/// 逐个玩家更新定数或 RKS 变化的当前成绩，并重新计算其总 RKS 与推分ACC；历史成绩记录不变。
+/// 玩家较多时耗时较长，进度会输出到服务日志。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    post,
+    path = "/admin/recompute-all",
+    responses(
+        (status = 200, description = "重算完成", body = ApiResponse<RksRecomputeResult>),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用")
+    )
+)]
+#[post("/admin/recompute-all")]
+pub async fn recompute_all_rks(
+    req: HttpRequest,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let result = player_archive_service.recompute_all_rks().await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some("RKS 重算完成".to_string()),
+        data: Some(result),
+    }))
+}
+
+/// 列出数据文件未收录的歌曲
+///
+/// 汇总自启动以来解析的存档及存档数据库中出现、但 info.csv 或 difficulty.csv 缺失的歌曲ID，
+/// 便于维护者及时更新数据文件。结果按出现次数降序排列。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    get,
+    path = "/admin/songs/unknown",
+    responses(
+        (status = 200, description = "统计完成", body = ApiResponse<Vec<UnknownSongReport>>),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用")
+    )
+)]
+#[get("/admin/songs/unknown")]
+pub async fn list_unknown_songs(
+    req: HttpRequest,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let seen_in_saves = data_loader::unknown_save_songs();
+    let archived = player_archive_service
+        .count_unknown_archived_songs()
+        .await?;
+
+    let mut song_ids: Vec<&String> = seen_in_saves.keys().chain(archived.keys()).collect();
+    song_ids.sort();
+    song_ids.dedup();
+
+    let mut reports: Vec<UnknownSongReport> = song_ids
+        .into_iter()
+        .map(|song_id| UnknownSongReport {
+            song_id: song_id.clone(),
+            missing_info: !data_loader::SONG_ID_TO_NAME.contains_key(song_id),
+            missing_difficulty: !data_loader::DIFFICULTY_MAP.contains_key(song_id),
+            seen_in_saves: seen_in_saves.get(song_id).copied().unwrap_or(0),
+            archived_players: archived.get(song_id).copied().unwrap_or(0),
+        })
+        .collect();
+    reports.sort_by(|a, b| {
+        (b.seen_in_saves, b.archived_players).cmp(&(a.seen_in_saves, a.archived_players))
+    });
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some(format!("发现 {} 首未收录的歌曲", reports.len())),
+        data: Some(reports),
+    }))
+}
+
+/// 查看各缓存的占用
+///
+/// 返回各图片缓存、玩家存档缓存与歌曲查询缓存的条目数及加权大小，条目数为近似值。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    get,
+    path = "/admin/cache",
+    responses(
+        (status = 200, description = "查询成功", body = ApiResponse<Vec<CacheInfo>>),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用")
+    )
+)]
+#[get("/admin/cache")]
+pub async fn list_caches(
+    req: HttpRequest,
+    image_service: web::Data<ImageService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+    song_service: web::Data<SongService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let mut caches = image_service.image_cache_infos().await;
+    caches.push(player_archive_service.cache_info().await);
+    caches.push(song_service.search_cache_info());
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: None,
+        data: Some(caches),
+    }))
+}
+
+/// 清除缓存条目
+///
+/// 指定 `key` 时只清除相关条目 (例如某个存档渲染出的全部 BN 图片)，否则清空整个缓存。
+/// 用于错误的渲染结果被缓存后立即失效，无需等待过期或重启服务。
+/// 需要在请求头 `X-Admin-Token` 中提供管理员令牌。
+#[utoipa::path(
+    post,
+    path = "/admin/cache/evict",
+    request_body = EvictCacheRequest,
+    responses(
+        (status = 200, description = "清除完成", body = ApiResponse<CacheEvictResult>),
+        (status = 400, description = "key 为空或该缓存不支持按键清除"),
+        (status = 401, description = "管理员令牌无效"),
+        (status = 403, description = "管理接口未启用")
+    )
+)]
+#[post("/admin/cache/evict")]
+pub async fn evict_cache(
+    req: HttpRequest,
+    body: web::Json<EvictCacheRequest>,
+    image_service: web::Data<ImageService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+    song_service: web::Data<SongService>,
+) -> AppResult<HttpResponse> {
+    require_admin(&req)?;
+
+    let key = body.key.as_deref().map(str::trim);
+    if key == Some("") {
+        return Err(AppError::BadRequest("key 不能为空".to_string()));
+    }
+
+    let evicted = match body.cache {
+        CacheName::PlayerArchive => player_archive_service.evict_cache(key).await,
+        CacheName::SongSearch => song_service.evict_search_cache(key),
+        cache => image_service.evict_image_cache(cache, key).await?,
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some(format!("已清除 {evicted} 条缓存")),
+        data: Some(CacheEvictResult {
+            cache: body.cache,
+            evicted,
+        }),
+    }))
+}
@@ -1,5 +1,4 @@
 use actix_web::{post, web, HttpResponse};
-use std::collections::HashMap;
 use utoipa;
 
 use crate::models::b30::B30Result;
@@ -98,43 +97,14 @@ pub async fn get_b30(
         .unwrap_or(&player_id)
         .to_string();
 
-    // 从存档构建 FC Map
-    let mut fc_map = HashMap::new();
-    if let Some(game_record_map) = &save.game_record {
-        for (song_id, difficulties) in game_record_map {
-            for (diff_name, record) in difficulties {
-                if let Some(true) = record.fc {
-                    let key = format!("{song_id}-{diff_name}");
-                    fc_map.insert(key, true);
-                }
-            }
-        }
-    }
-
     // 更新数据库中的玩家存档和 RKS
-    let archive_service_clone = player_archive_service.clone();
-    let player_id_clone = player_id.clone();
-    let player_name_clone = player_name.clone();
-    let records_clone = rks_result.records.clone();
-    let fc_map_clone = fc_map.clone();
-
-    tokio::spawn(async move {
-        log::info!("[后台任务] (get_b30) 开始为玩家 {player_name_clone} ({player_id_clone}) 更新数据库存档...");
-        let is_external = req.data_source.as_deref() == Some("external");
-        match archive_service_clone
-            .update_player_scores_from_rks_records(
-                &player_id_clone,
-                &player_name_clone,
-                &records_clone,
-                &fc_map_clone,
-                is_external,
-            )
-            .await
-        {
-            Ok(_) => log::info!("[后台任务] (get_b30) 玩家 {player_name_clone} ({player_id_clone}) 数据库存档更新完成。"),
-            Err(e) => log::error!("[后台任务] (get_b30) 更新玩家 {player_name_clone} ({player_id_clone}) 数据库存档失败: {e}"),
-        }
-    });
+    player_archive_service.spawn_update_from_save(
+        player_id,
+        player_name,
+        &save,
+        rks_result.records,
+        req.data_source.as_deref() == Some("external"),
+    );
 
     // 计算 B30
     let b30_result = calculate_b30(&save)?;
@@ -1,16 +1,18 @@
-use actix_web::{post, web, HttpResponse};
+use actix_web::{get, post, web, HttpResponse};
 use chrono::Utc;
+use serde::Deserialize;
 use serde_json::json;
-use utoipa;
+use utoipa::{self, IntoParams};
 
 use crate::models::user::{
-    ApiResponse, BindRequest, IdentifierRequest, PlatformBinding, TokenListResponse,
-    UnbindInitiateResponse,
+    ApiResponse, BindRequest, BindValidateResponse, IdentifierRequest, PlatformBinding,
+    TokenListResponse, TokenRequest, TokenValidateResponse, UnbindInitiateResponse,
 };
 use crate::services::phigros::PhigrosService;
 use crate::services::user::UserService;
 use crate::utils::error::{AppError, AppResult};
 use crate::utils::save_parser::check_session_token;
+use crate::utils::token_helper::REAUTH_HINT;
 
 /// 绑定平台账号
 ///
@@ -105,6 +107,76 @@ pub async fn bind_user(
     }
 }
 
+/// 绑定预检
+///
+/// 在实际绑定前检查 Token 格式、Token 是否仍然有效，以及该平台账号是否已有绑定，
+/// 返回检查报告而不修改任何数据。平台账号已绑定到其他 Token 时，实际绑定会更新为新 Token。
+#[utoipa::path(
+    post,
+    path = "/bind/validate",
+    request_body = BindRequest,
+    responses(
+        (status = 200, description = "预检完成", body = ApiResponse<BindValidateResponse>)
+    )
+)]
+#[post("/bind/validate")]
+pub async fn validate_bind(
+    bind_req: web::Json<BindRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    let platform = bind_req.platform.to_lowercase();
+    let platform_id = bind_req.platform_id.clone();
+
+    let token_format_valid = check_session_token(&bind_req.token).is_ok();
+    let (token_valid, nickname) = if token_format_valid {
+        match phigros_service.get_profile(&bind_req.token).await {
+            Ok(profile) => (Some(true), Some(profile.nickname)),
+            Err(AppError::AuthError(_)) => (Some(false), None),
+            Err(e) => return Err(e),
+        }
+    } else {
+        (None, None)
+    };
+
+    let platform_id_bound = user_service
+        .is_platform_id_bound(&platform, &platform_id)
+        .await?;
+    let bound_to_same_token = platform_id_bound
+        && user_service
+            .get_binding_by_platform_id(&platform, &platform_id)
+            .await?
+            .session_token
+            == bind_req.token;
+
+    let can_bind = token_valid == Some(true);
+    let message = if !token_format_valid {
+        "Token 格式错误".to_string()
+    } else if !can_bind {
+        format!("Token 无效或已过期，{REAUTH_HINT}")
+    } else if bound_to_same_token {
+        format!("平台 {platform} 的 ID {platform_id} 已绑定到同一Token")
+    } else if platform_id_bound {
+        format!("平台 {platform} 的 ID {platform_id} 已绑定，绑定后将更新为新的Token")
+    } else {
+        "可以绑定".to_string()
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some(message),
+        data: Some(BindValidateResponse {
+            can_bind,
+            token_format_valid,
+            token_valid,
+            nickname,
+            platform_id_bound,
+            bound_to_same_token,
+        }),
+    }))
+}
+
 /// 列出所有绑定的Token
 ///
 /// 根据提供的任一标识（Token 或 平台+平台ID），找出其所属的内部用户，并列出该内部用户绑定的所有平台账号信息。
@@ -121,9 +193,61 @@ pub async fn list_tokens(
     req: web::Json<IdentifierRequest>,
     user_service: web::Data<UserService>,
 ) -> AppResult<HttpResponse> {
-    let platform = req.platform.as_ref().map(|p| p.to_lowercase());
+    token_list_response(
+        req.token.as_deref(),
+        req.platform.as_deref(),
+        req.platform_id.as_deref(),
+        &user_service,
+    )
+    .await
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct TokenListQuery {
+    /// 已绑定的 SessionToken
+    pub token: Option<String>,
+    /// 平台名称，需与 platform_id 同时提供
+    pub platform: Option<String>,
+    /// 平台用户ID
+    pub platform_id: Option<String>,
+}
+
+/// 列出所有绑定的Token (查询参数版本)
+///
+/// 与 `POST /token/list` 相同，标识改由查询参数提供，便于只读查询时无需构造请求体。
+/// 访问日志中查询参数里的 Token 会被截断。
+#[utoipa::path(
+    get,
+    path = "/token/list",
+    params(TokenListQuery),
+    responses(
+        (status = 200, description = "成功获取Token列表", body = ApiResponse<TokenListResponse>)
+    )
+)]
+#[get("/token/list")]
+pub async fn list_tokens_by_query(
+    query: web::Query<TokenListQuery>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    token_list_response(
+        query.token.as_deref(),
+        query.platform.as_deref(),
+        query.platform_id.as_deref(),
+        &user_service,
+    )
+    .await
+}
+
+// 按 Token 或 平台+平台ID 找到内部用户，返回其全部绑定
+async fn token_list_response(
+    token: Option<&str>,
+    platform: Option<&str>,
+    platform_id: Option<&str>,
+    user_service: &UserService,
+) -> AppResult<HttpResponse> {
+    let platform = platform.map(str::to_lowercase);
 
-    let internal_id = match (&req.token, &platform, &req.platform_id) {
+    let internal_id = match (token, &platform, platform_id) {
         (Some(token), _, _) => {
             let binding = user_service.get_binding_by_token(token).await?;
             binding.internal_id
@@ -149,6 +273,52 @@ pub async fn list_tokens(
     }))
 }
 
+/// 校验 Session Token 是否有效
+///
+/// 仅请求一次用户 Profile 来判断 Token 是否仍然可用，比获取完整存档更轻量。
+/// Token 已失效时返回 `valid: false`，而不是错误。
+#[utoipa::path(
+    post,
+    path = "/token/validate",
+    request_body = TokenRequest,
+    responses(
+        (status = 200, description = "校验完成", body = ApiResponse<TokenValidateResponse>),
+        (status = 400, description = "Token 格式错误")
+    )
+)]
+#[post("/token/validate")]
+pub async fn validate_token(
+    req: web::Json<TokenRequest>,
+    phigros_service: web::Data<PhigrosService>,
+) -> AppResult<HttpResponse> {
+    check_session_token(&req.token)?;
+
+    let (result, message) = match phigros_service.get_profile(&req.token).await {
+        Ok(profile) => (
+            TokenValidateResponse {
+                valid: true,
+                nickname: Some(profile.nickname),
+            },
+            "Token 有效".to_string(),
+        ),
+        Err(AppError::AuthError(_)) => (
+            TokenValidateResponse {
+                valid: false,
+                nickname: None,
+            },
+            format!("Token 无效或已过期，{REAUTH_HINT}"),
+        ),
+        Err(e) => return Err(e),
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "success".to_string(),
+        message: Some(message),
+        data: Some(result),
+    }))
+}
+
 /// 解绑平台账号
 ///
 /// 提供两种解绑模式:
@@ -1,4 +1,9 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::utils::circuit_breaker::{self, BreakerState};
 
 /// 健康检查端点
 ///
@@ -14,4 +19,79 @@ use actix_web::{get, HttpResponse, Responder};
 #[get("/health")]
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("OK")
-}
\ No newline at end of file
+}
+
+/// 健康详情
+///
+/// 返回外部数据源的熔断器状态；外部数据源熔断不影响本接口的状态码。
+/// `/health` 只返回纯文本 OK，供 systemd Watchdog 等存活检查使用。
+#[utoipa::path(
+    get,
+    path = "/health/details",
+    responses(
+        (status = 200, description = "服务健康，附带外部数据源状态", body = HealthResponse)
+    )
+)]
+#[get("/health/details")]
+pub async fn health_details() -> impl Responder {
+    let external_sources = circuit_breaker::all_states();
+    let status = if external_sources.iter().any(|source| source.state == "open") {
+        "DEGRADED"
+    } else {
+        "OK"
+    };
+    HttpResponse::Ok().json(HealthResponse {
+        status,
+        external_sources,
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    /// OK，或有外部数据源处于熔断状态时为 DEGRADED (服务本身仍可用)
+    pub status: &'static str,
+    /// 已使用过的外部数据源的熔断器状态
+    pub external_sources: Vec<BreakerState>,
+}
+
+/// 数据库连接池状态
+#[derive(Serialize, ToSchema)]
+pub struct DbPoolMetrics {
+    /// 连接池允许的最大连接数 (DB_MAX_CONNECTIONS)
+    pub max_connections: u32,
+    /// 当前已建立的连接数
+    pub size: u32,
+    /// 空闲连接数
+    pub idle: usize,
+    /// 正在使用的连接数
+    pub in_use: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MetricsResponse {
+    pub db_pool: DbPoolMetrics,
+}
+
+/// 运行指标
+///
+/// 返回数据库连接池的当前使用情况，便于在存档写入与读请求并发较高时调整连接池参数。
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "当前运行指标", body = MetricsResponse)
+    )
+)]
+#[get("/metrics")]
+pub async fn get_metrics(pool: web::Data<SqlitePool>) -> impl Responder {
+    let size = pool.size();
+    let idle = pool.num_idle();
+    HttpResponse::Ok().json(MetricsResponse {
+        db_pool: DbPoolMetrics {
+            max_connections: pool.options().get_max_connections(),
+            size,
+            idle,
+            in_use: (size as usize).saturating_sub(idle),
+        },
+    })
+}
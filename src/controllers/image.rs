@@ -1,52 +1,525 @@
-use actix_web::{get, post, web, HttpResponse, Result};
-use serde::Deserialize;
+use actix_web::{get, post, web, HttpResponse, HttpResponseBuilder, Result};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::str::FromStr;
 use utoipa::{IntoParams, ToSchema};
 
-use crate::models::user::IdentifierRequest;
-use crate::services::image_service::ImageService;
+use crate::config::CONFIG;
+
+use crate::models::rks::RksRecord;
+use crate::models::user::{ApiResponse, IdentifierRequest};
+use crate::services::image_service::{ImageService, RenderedImage};
 use crate::services::phigros::PhigrosService;
 use crate::services::player_archive_service::PlayerArchiveService;
 use crate::services::song::SongService;
 use crate::services::user::UserService;
 use crate::utils::error::AppError;
+use crate::utils::image_renderer;
+use crate::utils::image_signing;
+use crate::utils::save_parser::normalize_difficulty;
+use crate::utils::token_helper;
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
-#[serde(rename_all = "lowercase")]
-#[derive(Default, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, ToSchema)]
+#[schema(rename_all = "lowercase")]
 pub enum Theme {
-    #[default]
     Black,
     White,
+    /// 透明背景（不绘制背景图与渐变），文字与卡片沿用黑色主题配色
+    Transparent,
+}
+
+impl Theme {
+    /// 所有可用的主题名称
+    pub const VALUES: [&'static str; 3] = ["black", "white", "transparent"];
+
+    /// 解析请求中的主题参数，未指定时回退到配置的默认主题
+    pub fn resolve(theme: Option<&str>) -> Result<Theme, AppError> {
+        theme.map_or_else(|| Ok(Theme::default()), Theme::from_str)
+    }
+
+    /// 主题的规范名称，与 [`Theme::VALUES`] 一致
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::Black => "black",
+            Theme::White => "white",
+            Theme::Transparent => "transparent",
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+impl Default for Theme {
+    /// 默认主题由 `DEFAULT_THEME` 配置决定，未配置或无效时为 black
+    fn default() -> Self {
+        CONFIG.default_theme.parse().unwrap_or(Theme::Black)
+    }
+}
+
+impl FromStr for Theme {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "black" => Ok(Theme::Black),
+            "white" => Ok(Theme::White),
+            "transparent" => Ok(Theme::Transparent),
+            _ => Err(AppError::BadRequest(format!(
+                "无效的主题 '{s}'，可选值: {}",
+                Theme::VALUES.join(", ")
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 #[derive(Default, ToSchema)]
 pub enum ImageFormat {
     #[default]
     Png,
+    /// 直接返回 SVG 矢量图，跳过 PNG 栅格化；曲绘等图片以 base64 内嵌，文件可独立显示
     Svg,
 }
 
+impl ImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Svg => "image/svg+xml; charset=utf-8",
+        }
+    }
+}
+
+/// BN 图片模式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+#[derive(Default, ToSchema)]
+pub enum BnMode {
+    /// 按 RKS 排序的全部成绩
+    #[default]
+    All,
+    /// 仅展示 AP (ACC 100%) 成绩
+    Ap,
+}
+
+/// Best N 图片的卡片布局
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[derive(Default, ToSchema)]
+pub enum BnLayout {
+    /// 按 RKS 排序的单一网格
+    #[default]
+    Flat,
+    /// 按难度 (EZ/HD/IN/AT) 分组，各组带标题且组内按 RKS 排序
+    ByDifficulty,
+}
+
+/// 图片文字标签的语言
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+#[derive(Default, ToSchema)]
+pub enum ImageLang {
+    /// 英文（默认，与原有标签一致）
+    #[default]
+    En,
+    /// 简体中文
+    Zh,
+    /// 日文
+    Ja,
+}
+
+impl ImageLang {
+    /// 排行榜与对比图原有的标签为中文，省略 lang 时保持不变
+    fn zh() -> Self {
+        ImageLang::Zh
+    }
+}
+
+/// 图片背景的来源
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundMode {
+    /// 从模糊曲绘中选取 (BN 图)，或使用当前曲目曲绘、缺失时另选 (单曲图)；是否每次随机由 random_bg 决定
+    Random,
+    /// 始终使用渐变背景，不读取任何背景图，渲染更快且结果稳定
+    Gradient,
+    /// 使用曲目自身的曲绘：BN 图取 RKS 最高的成绩，单曲图取当前曲目；缺失时回退到渐变背景
+    SongCover,
+    /// 使用玩家在游戏中选择的背景 (仅 BN 图)，无法对应到已知曲绘时按 random 处理；单曲图视同 random
+    PlayerBackground,
+}
+
+impl BackgroundMode {
+    /// 所有可用的背景模式名称
+    pub const VALUES: [&'static str; 4] = ["random", "gradient", "song_cover", "player_background"];
+}
+
+impl Default for BackgroundMode {
+    /// 默认背景模式由 `DEFAULT_BACKGROUND_MODE` 配置决定，未配置或无效时为 random
+    fn default() -> Self {
+        CONFIG
+            .default_background_mode
+            .parse()
+            .unwrap_or(BackgroundMode::Random)
+    }
+}
+
+impl FromStr for BackgroundMode {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "random" => Ok(BackgroundMode::Random),
+            "gradient" => Ok(BackgroundMode::Gradient),
+            "song_cover" => Ok(BackgroundMode::SongCover),
+            "player_background" => Ok(BackgroundMode::PlayerBackground),
+            _ => Err(AppError::BadRequest(format!(
+                "无效的背景模式 '{s}'，可选值: {}",
+                BackgroundMode::VALUES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// 图片输出的缩放倍数，以百分比整数保存以便作为缓存键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageScale(u16);
+
+impl ImageScale {
+    pub const MIN: f32 = 1.0;
+    pub const MAX: f32 = 3.0;
+
+    /// 解析请求中的缩放倍数：省略时为 1.0，超出范围时截断到 [1.0, 3.0]
+    pub fn new(scale: Option<f32>) -> Result<Self, AppError> {
+        let scale = scale.unwrap_or(Self::MIN);
+        if !scale.is_finite() {
+            return Err(AppError::BadRequest("scale 必须是有效的数字".to_string()));
+        }
+        let percent = (scale.clamp(Self::MIN, Self::MAX) * 100.0).round();
+        Ok(Self(percent as u16))
+    }
+
+    /// 按输出格式解析缩放倍数：SVG 不使用缩放，统一为默认值以免产生重复的缓存条目
+    pub fn for_format(format: ImageFormat, scale: Option<f32>) -> Result<Self, AppError> {
+        match format {
+            ImageFormat::Png => Self::new(scale),
+            ImageFormat::Svg => Ok(Self::default()),
+        }
+    }
+
+    /// 缩放倍数
+    pub fn factor(self) -> f32 {
+        f32::from(self.0) / 100.0
+    }
+}
+
+impl Default for ImageScale {
+    fn default() -> Self {
+        Self(100)
+    }
+}
+
+/// Best N 的成绩筛选条件：在按 RKS 排名前先按模式与定数范围筛选成绩
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BnFilter {
+    pub mode: BnMode,
+    /// 定数下限（含）
+    pub min_constant: Option<f64>,
+    /// 定数上限（含）
+    pub max_constant: Option<f64>,
+}
+
+impl BnFilter {
+    /// 校验并构造筛选条件：定数必须为非负有限值，且下限不大于上限
+    pub fn new(
+        mode: BnMode,
+        min_constant: Option<f64>,
+        max_constant: Option<f64>,
+    ) -> Result<Self, AppError> {
+        for (name, value) in [
+            ("min_constant", min_constant),
+            ("max_constant", max_constant),
+        ] {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                return Err(AppError::BadRequest(format!("{name} 必须是非负数")));
+            }
+        }
+        if let (Some(min), Some(max)) = (min_constant, max_constant) {
+            if min > max {
+                return Err(AppError::BadRequest(format!(
+                    "min_constant ({min}) 不能大于 max_constant ({max})"
+                )));
+            }
+        }
+        Ok(Self {
+            mode,
+            min_constant,
+            max_constant,
+        })
+    }
+
+    /// 成绩是否满足筛选条件
+    pub fn matches(&self, record: &RksRecord) -> bool {
+        (self.mode == BnMode::All || record.acc >= 100.0)
+            && self
+                .min_constant
+                .is_none_or(|min| record.difficulty_value >= min)
+            && self
+                .max_constant
+                .is_none_or(|max| record.difficulty_value <= max)
+    }
+
+    /// 是否限定了定数范围
+    pub fn has_constant_range(&self) -> bool {
+        self.min_constant.is_some() || self.max_constant.is_some()
+    }
+
+    /// 定数范围的展示文本，未限定范围时为 `None`
+    pub fn constant_range_label(&self) -> Option<String> {
+        match (self.min_constant, self.max_constant) {
+            (None, None) => None,
+            (Some(min), Some(max)) => Some(format!("{min:.1} - {max:.1}")),
+            (Some(min), None) => Some(format!("≥ {min:.1}")),
+            (None, Some(max)) => Some(format!("≤ {max:.1}")),
+        }
+    }
+}
+
+// 构造时已保证定数为有限值，可按位比较与哈希，用作图片缓存键
+impl Eq for BnFilter {}
+
+impl std::hash::Hash for BnFilter {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.mode.hash(state);
+        self.min_constant.map(f64::to_bits).hash(state);
+        self.max_constant.map(f64::to_bits).hash(state);
+    }
+}
+
 #[derive(Deserialize, Debug, ToSchema, IntoParams)]
 pub struct BnImageQuery {
-    #[serde(default)]
-    pub theme: Theme,
+    /// 图片主题: black, white, transparent (透明背景)；省略时使用 DEFAULT_THEME 配置 (默认 black)
+    #[schema(value_type = Option<Theme>)]
+    #[param(value_type = Option<Theme>)]
+    pub theme: Option<String>,
+    /// 输出格式: png (默认), svg
     #[serde(default)]
     pub format: ImageFormat,
+    /// 图片模式: all (默认), ap (仅AP成绩，即 AP Best N)
+    #[serde(default)]
+    pub mode: BnMode,
+    /// 卡片布局: flat (默认，单一网格), by_difficulty (按难度分组)
+    #[serde(default)]
+    pub layout: BnLayout,
+    /// 只统计定数不低于该值的谱面
+    pub min_constant: Option<f64>,
+    /// 只统计定数不高于该值的谱面
+    pub max_constant: Option<f64>,
+    /// 玩家无任何成绩时返回占位图而不是报错，默认 false
+    #[serde(default)]
+    pub allow_empty: bool,
+    /// ACC 显示 4 位小数（默认 2 位），便于观察是否接近推分阈值
+    #[serde(default)]
+    pub precise_acc: bool,
+    /// 随机选取背景图；默认 false，即按存档固定选取，存档不变时图片保持一致
+    #[serde(default)]
+    pub random_bg: bool,
+    /// 背景来源: random, gradient (纯渐变), song_cover (RKS 最高成绩的曲绘), player_background (玩家在游戏中选择的背景)；省略时使用 DEFAULT_BACKGROUND_MODE 配置 (默认 random)
+    #[serde(default)]
+    pub background_mode: BackgroundMode,
+    /// 在成绩卡片上标注达成日期（来自玩家存档，无存档记录的成绩不标注），默认 false
+    #[serde(default)]
+    pub show_play_date: bool,
+    /// 图片文字标签的语言: en (默认), zh, ja
+    #[serde(default)]
+    pub lang: ImageLang,
+    /// 输出尺寸的缩放倍数 (1.0-3.0，默认 1.0)，超出范围时截断；仅对栅格图片生效，适合高分屏或打印
+    pub scale: Option<f32>,
+}
+
+impl BnImageQuery {
+    /// 解析请求的主题，未指定时回退到配置的默认主题
+    pub fn resolve_theme(&self) -> Result<Theme, AppError> {
+        Theme::resolve(self.theme.as_deref())
+    }
+
+    /// 由模式与定数范围构造成绩筛选条件
+    pub fn filter(&self) -> Result<BnFilter, AppError> {
+        BnFilter::new(self.mode, self.min_constant, self.max_constant)
+    }
+
+    /// 校验查询参数并转换为渲染参数
+    pub fn render_options(&self) -> Result<BnRenderOptions, AppError> {
+        Ok(BnRenderOptions {
+            theme: self.resolve_theme()?,
+            filter: self.filter()?,
+            allow_empty: self.allow_empty,
+            acc_decimals: image_renderer::acc_decimals(self.precise_acc),
+            random_background: self.random_bg,
+            background_mode: self.background_mode,
+            show_play_date: self.show_play_date,
+            lang: self.lang,
+            layout: self.layout,
+            scale: ImageScale::for_format(self.format, self.scale)?,
+        })
+    }
 }
 
+/// Best N 图片的渲染参数，同时作为 BN 图片缓存键的一部分
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BnRenderOptions {
+    pub theme: Theme,
+    /// 成绩筛选条件 (模式与定数范围)
+    pub filter: BnFilter,
+    /// 玩家无任何成绩时渲染占位图而不是报错
+    pub allow_empty: bool,
+    /// ACC 显示的小数位数
+    pub acc_decimals: usize,
+    /// 每次随机选取背景，否则按存档校验和固定选取
+    pub random_background: bool,
+    pub background_mode: BackgroundMode,
+    /// 在成绩卡片上标注达成日期
+    pub show_play_date: bool,
+    pub lang: ImageLang,
+    pub layout: BnLayout,
+    /// 缩放倍数，SVG 固定为默认值
+    pub scale: ImageScale,
+}
+
+impl BnRenderOptions {
+    /// 使用指定主题，其余参数均为默认值
+    pub fn with_theme(theme: Theme) -> Self {
+        Self {
+            theme,
+            filter: BnFilter::default(),
+            allow_empty: false,
+            acc_decimals: image_renderer::acc_decimals(false),
+            random_background: false,
+            background_mode: BackgroundMode::default(),
+            show_play_date: false,
+            lang: ImageLang::default(),
+            layout: BnLayout::default(),
+            scale: ImageScale::default(),
+        }
+    }
+}
+
+/// 单曲图片的输出格式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+#[derive(Default, ToSchema)]
+pub enum SongImageFormat {
+    #[default]
+    Png,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Svg,
+}
+
+/// 单曲图片的编码参数（格式 + JPEG 质量），同时作为缓存键的一部分
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SongImageEncoding {
+    pub format: SongImageFormat,
+    /// JPEG 质量，PNG 与 SVG 固定为 0
+    pub quality: u8,
+    /// 缩放倍数，SVG 固定为默认值
+    pub scale: ImageScale,
+}
+
+/// JPEG 默认质量
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
 #[derive(Deserialize, Debug, ToSchema, IntoParams)]
 pub struct SongImageQuery {
     /// 歌曲的名称、ID或别名
     q: String,
+    /// 输出格式: png (默认), jpeg, svg
+    #[serde(default)]
+    format: SongImageFormat,
+    /// JPEG 质量 (1-100，默认 85)，仅在 format=jpeg 时生效
+    quality: Option<u8>,
+    /// 是否只渲染玩家有成绩的难度，默认 false（显示全部四个难度）
+    #[serde(default)]
+    played_only: bool,
+    /// ACC 显示 4 位小数（默认 2 位），便于观察是否接近推分阈值
+    #[serde(default)]
+    precise_acc: bool,
+    /// 曲绘缺失时随机选取背景图；默认 false，即按存档固定选取
+    #[serde(default)]
+    random_bg: bool,
+    /// 背景来源: random, gradient (纯渐变), song_cover (仅使用当前曲目曲绘)，player_background 视同 random；省略时使用 DEFAULT_BACKGROUND_MODE 配置 (默认 random)
+    #[serde(default)]
+    background_mode: BackgroundMode,
+    /// 输出尺寸的缩放倍数 (1.0-3.0，默认 1.0)，超出范围时截断；仅对栅格图片生效，适合高分屏或打印
+    scale: Option<f32>,
+    /// 图片文字标签的语言: en (默认), zh, ja
+    #[serde(default)]
+    lang: ImageLang,
 }
 
 #[derive(Debug, Deserialize, ToSchema, IntoParams)]
 pub struct LeaderboardQuery {
-    /// 返回的排行榜条目数量，默认为10
+    /// 返回的排行榜条目数量，省略时使用 LEADERBOARD_DEFAULT_LIMIT 配置 (默认 20)，超过 LEADERBOARD_MAX_LIMIT (默认 100) 时截断
     pub limit: Option<usize>,
+    /// 图片主题: black, white, transparent (透明背景)；省略时使用 DEFAULT_THEME 配置 (默认 black)
+    #[schema(value_type = Option<Theme>)]
+    #[param(value_type = Option<Theme>)]
+    pub theme: Option<String>,
+    /// 输出格式: png (默认), svg
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// 输出尺寸的缩放倍数 (1.0-3.0，默认 1.0)，超出范围时截断；仅对栅格图片生效，适合高分屏或打印
+    pub scale: Option<f32>,
+    /// 图片文字标签的语言: zh (默认), en, ja
+    #[serde(default = "ImageLang::zh")]
+    #[schema(default = "zh")]
+    #[param(default = "zh")]
+    pub lang: ImageLang,
+}
+
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct CompareImageQuery {
+    /// 图片主题: black, white, transparent (透明背景)；省略时使用 DEFAULT_THEME 配置 (默认 black)
+    #[schema(value_type = Option<Theme>)]
+    #[param(value_type = Option<Theme>)]
+    pub theme: Option<String>,
+    /// 输出格式: png (默认), svg
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// 输出尺寸的缩放倍数 (1.0-3.0，默认 1.0)，超出范围时截断；仅对栅格图片生效，适合高分屏或打印
+    pub scale: Option<f32>,
+    /// 图片文字标签的语言: zh (默认), en, ja
+    #[serde(default = "ImageLang::zh")]
+    #[schema(default = "zh")]
+    #[param(default = "zh")]
+    pub lang: ImageLang,
+}
+
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct Ap3ImageQuery {
+    /// 图片主题: black, white, transparent (透明背景)；省略时使用 DEFAULT_THEME 配置 (默认 black)
+    #[schema(value_type = Option<Theme>)]
+    #[param(value_type = Option<Theme>)]
+    pub theme: Option<String>,
+    /// 输出格式: png (默认), svg
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// 输出尺寸的缩放倍数 (1.0-3.0，默认 1.0)，超出范围时截断；仅对栅格图片生效，适合高分屏或打印
+    pub scale: Option<f32>,
+    /// AP 成绩不足 3 个时是否渲染已有的 AP 成绩 (默认 false，不足时返回 400)
+    #[serde(default)]
+    pub allow_partial: bool,
+    /// 图片文字标签的语言: en (默认), zh, ja
+    #[serde(default)]
+    pub lang: ImageLang,
+}
+
+/// 双人对比图请求体，左右两侧分别使用各自的身份标识
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CompareImageRequest {
+    /// 左侧玩家
+    pub left: IdentifierRequest,
+    /// 右侧玩家
+    pub right: IdentifierRequest,
 }
 
 #[derive(Deserialize, Debug, ToSchema)]
@@ -60,13 +533,23 @@ pub struct UserScoreRecord {
     /// 难度级别 (EZ, HD, IN, AT)
     #[serde(default = "default_difficulty")]
     pub difficulty: String,
+    /// 是否 Full Combo (可选)；准确率为 100 时视为 AP，自动按 FC 处理
+    #[serde(default)]
+    pub is_fc: Option<bool>,
 }
 
+/// 用户自定义成绩单次最多提交的成绩条数，每条成绩都需要搜索一次曲目
+pub const MAX_USER_SCORES: usize = 100;
+/// 用户自定义成绩中玩家名称的最大字符数
+pub const MAX_USER_PLAYER_NAME_CHARS: usize = 64;
+/// 用户自定义成绩中曲目名称的最大字符数
+pub const MAX_USER_SONG_NAME_CHARS: usize = 128;
+
 #[derive(Deserialize, Debug, ToSchema)]
 pub struct UserGeneratedBnData {
-    /// 玩家名称
+    /// 玩家名称 (最多 64 个字符)
     pub player_name: String,
-    /// 成绩列表
+    /// 成绩列表 (1-100 条)
     pub scores: Vec<UserScoreRecord>,
 }
 
@@ -77,7 +560,7 @@ fn default_difficulty() -> String {
 /// 生成Best N成绩图片
 ///
 /// 根据用户的RKS计算结果，生成一张包含其最好N项成绩的图片。
-/// 图片上显示的更新时间为用户云端存档的真实更新时间。
+/// 图片上显示的更新时间为用户云端存档的真实更新时间，同时通过 `X-Save-Updated-At` 响应头返回。
 #[utoipa::path(
     post,
     path = "/bn/{n}",
@@ -87,7 +570,9 @@ fn default_difficulty() -> String {
     ),
     request_body = IdentifierRequest,
     responses(
-        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>)
+        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>),
+        (status = 200, description = "成功生成图片 (format=svg)", content_type = "image/svg+xml", body = String),
+        (status = 400, description = "参数错误（如 N 为 0、超过 MAX_BN 或主题无效）")
     )
 )]
 #[post("/bn/{n}")]
@@ -101,53 +586,234 @@ pub async fn generate_bn_image(
     image_service: web::Data<ImageService>,
 ) -> Result<HttpResponse, AppError> {
     let n = path.into_inner();
-
-    if n == 0 {
-        return Err(AppError::BadRequest("N must be greater than 0".to_string()));
-    }
+    validate_bn_n(n)?;
+    let options = query.render_options()?;
 
     if query.format == ImageFormat::Svg {
-        let svg = image_service
+        let rendered = image_service
             .generate_bn_svg(
                 n,
                 req,
-                &query.theme,
+                &options,
                 phigros_service,
                 user_service,
                 player_archive_service,
             )
             .await?;
 
-        Ok(HttpResponse::Ok()
-            .content_type("image/svg+xml; charset=utf-8")
-            .body(svg))
+        Ok(rendered_image_response(&rendered)
+            .content_type(ImageFormat::Svg.content_type())
+            .body(rendered.data))
     } else {
-        let image_bytes = image_service
+        let rendered = image_service
             .generate_bn_image(
                 n,
                 req,
-                &query.theme,
+                &options,
                 phigros_service,
                 user_service,
                 player_archive_service,
             )
             .await?;
 
-        Ok(HttpResponse::Ok().content_type("image/png").body(image_bytes))
+        Ok(rendered_image_response(&rendered)
+            .content_type("image/png")
+            .body(rendered.data))
+    }
+}
+
+/// 校验 Best N 图片的 N：必须大于 0 且不超过 MAX_BN
+fn validate_bn_n(n: u32) -> Result<(), AppError> {
+    if n == 0 {
+        return Err(AppError::BadRequest("N must be greater than 0".to_string()));
+    }
+    if n > CONFIG.max_bn {
+        return Err(AppError::BadRequest(format!(
+            "N 不能超过 {} (由 MAX_BN 配置)",
+            CONFIG.max_bn
+        )));
+    }
+    Ok(())
+}
+
+/// 响应头：图片所用存档的更新时间 (LeanCloud `updatedAt`)
+///
+/// 客户端可与上次获取的值比较，存档未变化时无需重新下载图片。外部数据源不返回此响应头。
+pub const SAVE_UPDATED_AT_HEADER: &str = "X-Save-Updated-At";
+
+/// 创建 200 响应，已知存档更新时间时附带 [`SAVE_UPDATED_AT_HEADER`]
+pub fn ok_with_save_updated_at(save_updated_at: Option<&str>) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    if let Some(updated_at) = save_updated_at {
+        response.insert_header((SAVE_UPDATED_AT_HEADER, updated_at));
+    }
+    response
+}
+
+/// 创建图片的 200 响应，附带存档更新时间与实际使用的数据源
+fn rendered_image_response<T>(rendered: &RenderedImage<T>) -> HttpResponseBuilder {
+    let mut response = ok_with_save_updated_at(rendered.save_updated_at.as_deref());
+    response.insert_header((token_helper::DATA_SOURCE_HEADER, rendered.data_source));
+    response
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct BnSignQuery {
+    /// 图片主题: black, white, transparent；省略时使用 DEFAULT_THEME 配置，签发后固定不变
+    #[param(value_type = Option<Theme>)]
+    pub theme: Option<String>,
+    /// 链接有效期（秒），默认 3600，最长 7 天
+    pub expires_in: Option<u64>,
+}
+
+/// 签名后的 Best N 图片链接
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SignedImageUrl {
+    /// 相对于服务根路径的图片地址，可直接用于 GET 请求
+    pub path: String,
+    /// 过期时间 (Unix 时间戳，秒)
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SignedBnImageQuery {
+    /// 加密后的身份信息
+    pub id: String,
+    /// 签发时确定的图片主题
+    pub theme: String,
+    /// 过期时间 (Unix 时间戳，秒)
+    pub expires: i64,
+    /// 签名
+    pub sig: String,
+}
+
+/// 签发 Best N 图片的限时链接
+///
+/// 返回一个无需请求体即可访问的 GET 地址，适合嵌入网页或聊天消息。
+/// 链接中的身份信息经过加密，N、主题与过期时间受签名保护，篡改或过期后将被拒绝。
+#[utoipa::path(
+    post,
+    path = "/bn/{n}/sign",
+    params(
+        ("n" = u32, Path, description = "要生成的Best N图片"),
+        BnSignQuery
+    ),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功签发链接", body = ApiResponse<SignedImageUrl>),
+        (status = 400, description = "参数错误（如 N 无效、主题无效或有效期超出上限）"),
+        (status = 403, description = "未配置 IMAGE_SIGNING_SECRET")
+    )
+)]
+#[post("/bn/{n}/sign")]
+pub async fn sign_bn_image(
+    path: web::Path<u32>,
+    query: web::Query<BnSignQuery>,
+    req: web::Json<IdentifierRequest>,
+) -> Result<HttpResponse, AppError> {
+    let secret = image_signing::signing_secret()?;
+    let n = path.into_inner();
+    validate_bn_n(n)?;
+    let theme = Theme::resolve(query.theme.as_deref())?;
+
+    let expires_in = query
+        .expires_in
+        .unwrap_or(image_signing::DEFAULT_EXPIRES_IN);
+    if expires_in == 0 || expires_in > image_signing::MAX_EXPIRES_IN {
+        return Err(AppError::BadRequest(format!(
+            "expires_in 必须在 1 到 {} 秒之间",
+            image_signing::MAX_EXPIRES_IN
+        )));
     }
+    let expires_at = chrono::Utc::now().timestamp() + expires_in as i64;
+
+    let sealed = image_signing::seal_identifier(secret, &req)?;
+    let sig = image_signing::sign_bn(secret, &sealed, n, theme.as_str(), expires_at);
+    let path = format!(
+        "/image/bn/{n}/signed?id={sealed}&theme={}&expires={expires_at}&sig={sig}",
+        theme.as_str()
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "ok".to_string(),
+        message: None,
+        data: Some(SignedImageUrl { path, expires_at }),
+    }))
+}
+
+/// 通过签名链接获取Best N成绩图片
+///
+/// 校验 `POST /image/bn/{n}/sign` 签发的链接，通过后按签发时的参数生成 PNG 图片。
+#[utoipa::path(
+    get,
+    path = "/bn/{n}/signed",
+    params(
+        ("n" = u32, Path, description = "要生成的Best N图片"),
+        SignedBnImageQuery
+    ),
+    responses(
+        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>),
+        (status = 401, description = "签名无效或链接已过期"),
+        (status = 403, description = "未配置 IMAGE_SIGNING_SECRET")
+    )
+)]
+#[get("/bn/{n}/signed")]
+pub async fn get_signed_bn_image(
+    path: web::Path<u32>,
+    query: web::Query<SignedBnImageQuery>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+    image_service: web::Data<ImageService>,
+) -> Result<HttpResponse, AppError> {
+    let secret = image_signing::signing_secret()?;
+    let n = path.into_inner();
+    image_signing::verify_bn(
+        secret,
+        &query.id,
+        n,
+        &query.theme,
+        query.expires,
+        &query.sig,
+    )?;
+    validate_bn_n(n)?;
+    let theme = Theme::from_str(&query.theme)?;
+    let identifier = image_signing::open_identifier(secret, &query.id)?;
+
+    let rendered = image_service
+        .generate_bn_image(
+            n,
+            web::Json(identifier),
+            &BnRenderOptions::with_theme(theme),
+            phigros_service,
+            user_service,
+            player_archive_service,
+        )
+        .await?;
+
+    // 链接在过期前内容不变（存档更新除外），允许客户端在剩余有效期内缓存
+    let max_age = (query.expires - chrono::Utc::now().timestamp()).max(0);
+    Ok(rendered_image_response(&rendered)
+        .content_type("image/png")
+        .insert_header(("Cache-Control", format!("private, max-age={max_age}")))
+        .body(rendered.data))
 }
 
 /// 生成单曲成绩图片
 ///
 /// 根据用户成绩和歌曲信息，生成一张包含单曲成绩详情的图片。
-/// 图片上显示的更新时间为用户云端存档的真实更新时间。
+/// 图片上显示的更新时间为用户云端存档的真实更新时间，同时通过 `X-Save-Updated-At` 响应头返回。
 #[utoipa::path(
     post,
     path = "/song",
     params(SongImageQuery),
     request_body = IdentifierRequest,
     responses(
-        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>)
+        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>),
+        (status = 200, description = "成功生成图片 (format=jpeg)", content_type = "image/jpeg", body = Vec<u8>),
+        (status = 200, description = "成功生成图片 (format=svg)", content_type = "image/svg+xml", body = String),
+        (status = 400, description = "参数错误")
     )
 )]
 #[post("/song")]
@@ -160,11 +826,50 @@ pub async fn generate_song_image(
     player_archive_service: web::Data<PlayerArchiveService>,
     image_service: web::Data<ImageService>,
 ) -> Result<HttpResponse, AppError> {
-    let song_query = query.into_inner().q;
+    let SongImageQuery {
+        q: song_query,
+        format,
+        quality,
+        played_only,
+        precise_acc,
+        random_bg,
+        background_mode,
+        scale,
+        lang,
+    } = query.into_inner();
 
-    let image_bytes = image_service
+    // PNG 与 SVG 不使用质量参数，统一置 0 以免产生重复的缓存条目
+    let quality = match format {
+        SongImageFormat::Png | SongImageFormat::Svg => 0,
+        SongImageFormat::Jpeg => {
+            let quality = quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+            if !(1..=100).contains(&quality) {
+                return Err(AppError::BadRequest(format!(
+                    "quality 必须在 1 到 100 之间，当前为 {quality}"
+                )));
+            }
+            quality
+        }
+    };
+    // SVG 不使用缩放，统一为默认值
+    let scale = match format {
+        SongImageFormat::Svg => ImageScale::default(),
+        SongImageFormat::Png | SongImageFormat::Jpeg => ImageScale::new(scale)?,
+    };
+
+    let rendered = image_service
         .generate_song_image(
             song_query,
+            SongImageEncoding {
+                format,
+                quality,
+                scale,
+            },
+            played_only,
+            image_renderer::acc_decimals(precise_acc),
+            random_bg,
+            background_mode,
+            lang,
             req,
             phigros_service,
             user_service,
@@ -173,9 +878,14 @@ pub async fn generate_song_image(
         )
         .await?;
 
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(image_bytes))
+    let content_type = match format {
+        SongImageFormat::Png => "image/png",
+        SongImageFormat::Jpeg => "image/jpeg",
+        SongImageFormat::Svg => ImageFormat::Svg.content_type(),
+    };
+    Ok(rendered_image_response(&rendered)
+        .content_type(content_type)
+        .body(rendered.data))
 }
 
 /// RKS排行榜图片
@@ -186,7 +896,9 @@ pub async fn generate_song_image(
     path = "/leaderboard/rks",
     params(LeaderboardQuery),
     responses(
-        (status = 200, description = "成功生成排行榜图片", content_type = "image/png", body = Vec<u8>)
+        (status = 200, description = "成功生成排行榜图片", content_type = "image/png", body = Vec<u8>),
+        (status = 200, description = "成功生成排行榜图片 (format=svg)", content_type = "image/svg+xml", body = String),
+        (status = 400, description = "主题无效")
     )
 )]
 #[get("/leaderboard/rks")]
@@ -195,16 +907,114 @@ pub async fn get_rks_leaderboard(
     player_archive_service: web::Data<PlayerArchiveService>,
     image_service: web::Data<ImageService>,
 ) -> Result<HttpResponse, AppError> {
+    let theme = Theme::resolve(query.theme.as_deref())?;
+    let scale = ImageScale::for_format(query.format, query.scale)?;
     let result = image_service
-        .generate_rks_leaderboard_image(query.limit, player_archive_service)
+        .generate_rks_leaderboard_image(
+            query.limit,
+            theme,
+            query.format,
+            scale,
+            query.lang,
+            player_archive_service,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(result))
+}
+
+/// 生成双人对比图片
+///
+/// 左右并排展示两名玩家的名称、RKS、B27 平均、AP Top 3 平均以及 RKS 最高的 5 个谱面。
+/// 某一侧获取数据失败时，该侧显示错误提示，另一侧照常渲染。
+#[utoipa::path(
+    post,
+    path = "/compare/image",
+    params(CompareImageQuery),
+    request_body = CompareImageRequest,
+    responses(
+        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>),
+        (status = 200, description = "成功生成图片 (format=svg)", content_type = "image/svg+xml", body = String),
+        (status = 400, description = "主题无效")
+    )
+)]
+#[post("/compare/image")]
+pub async fn generate_compare_image(
+    query: web::Query<CompareImageQuery>,
+    req: web::Json<CompareImageRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+    image_service: web::Data<ImageService>,
+) -> Result<HttpResponse, AppError> {
+    let theme = Theme::resolve(query.theme.as_deref())?;
+    let scale = ImageScale::for_format(query.format, query.scale)?;
+    let CompareImageRequest { left, right } = req.into_inner();
+    let image_bytes = image_service
+        .generate_compare_image(
+            &left,
+            &right,
+            theme,
+            query.format,
+            scale,
+            query.lang,
+            phigros_service,
+            user_service,
+        )
         .await?;
 
-    Ok(HttpResponse::Ok().content_type("image/png").body(result))
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(image_bytes))
+}
+
+/// 生成 AP Top 3 图片
+///
+/// 只包含玩家 RKS 最高的 3 个 AP 成绩卡片与简要的页眉，适合作为签名档或头图。
+/// AP 成绩不足 3 个时返回 400，指定 `allow_partial=true` 时渲染已有的 AP 成绩。
+#[utoipa::path(
+    post,
+    path = "/ap3",
+    params(Ap3ImageQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功生成图片", content_type = "image/png", body = Vec<u8>),
+        (status = 200, description = "成功生成图片 (format=svg)", content_type = "image/svg+xml", body = String),
+        (status = 400, description = "主题无效，或 AP 成绩不足 3 个 (未指定 allow_partial) / 没有 AP 成绩")
+    )
+)]
+#[post("/ap3")]
+pub async fn generate_ap3_image(
+    query: web::Query<Ap3ImageQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+    image_service: web::Data<ImageService>,
+) -> Result<HttpResponse, AppError> {
+    let theme = Theme::resolve(query.theme.as_deref())?;
+    let scale = ImageScale::for_format(query.format, query.scale)?;
+    let image_bytes = image_service
+        .generate_ap3_image(
+            &req,
+            theme,
+            query.format,
+            scale,
+            query.allow_partial,
+            query.lang,
+            phigros_service,
+            user_service,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(image_bytes))
 }
 
 /// 获取图片缓存统计信息
 ///
-/// 返回各个图片缓存的命中率和统计信息。
+/// 返回各个图片缓存的命中率和统计信息，以及后台存档写入队列的状态。
 #[utoipa::path(
     get,
     path = "/cache/stats",
@@ -215,8 +1025,10 @@ pub async fn get_rks_leaderboard(
 #[get("/cache/stats")]
 pub async fn get_cache_stats(
     image_service: web::Data<ImageService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
 ) -> Result<HttpResponse, AppError> {
-    let stats = image_service.get_cache_stats();
+    let mut stats = image_service.get_cache_stats();
+    stats["archive_write_queue"] = json!(player_archive_service.write_queue_stats());
     Ok(HttpResponse::Ok().json(stats))
 }
 
@@ -245,7 +1057,7 @@ pub async fn get_image_stats(
     get,
     path = "/stats/{image_type}",
     params(
-        ("image_type" = String, Path, description = "图片类型 (bn, song, leaderboard)")
+        ("image_type" = String, Path, description = "图片类型 (bn, song, leaderboard, compare, ap3, user-generated)")
     ),
     responses(
         (status = 200, description = "成功获取指定类型的图片生成统计信息", body = serde_json::Value)
@@ -257,7 +1069,14 @@ pub async fn get_image_stats_by_type(
     image_service: web::Data<ImageService>,
 ) -> Result<HttpResponse, AppError> {
     let image_type = path.into_inner();
-    let valid_types = ["bn", "song", "leaderboard"];
+    let valid_types = [
+        "bn",
+        "song",
+        "leaderboard",
+        "compare",
+        "ap3",
+        "user-generated",
+    ];
 
     if !valid_types.contains(&image_type.as_str()) {
         return Err(AppError::BadRequest(format!(
@@ -283,6 +1102,53 @@ pub async fn get_image_stats_by_type(
     }
 }
 
+/// 校验用户提供的单条成绩，难度名称统一为大写写法后再校验与匹配定数
+///
+/// 错误信息中注明是第几条成绩 (从 1 开始)。
+fn validate_user_score(index: usize, score: &mut UserScoreRecord) -> Result<(), AppError> {
+    score.difficulty = normalize_difficulty(&score.difficulty);
+
+    if score.song_name.chars().count() > MAX_USER_SONG_NAME_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "第{}条成绩的曲目名称不能超过{MAX_USER_SONG_NAME_CHARS}个字符",
+            index + 1
+        )));
+    }
+
+    if score.score > 1_000_000 {
+        return Err(AppError::BadRequest(format!(
+            "第{}条成绩的分数无效: {} (必须在 0 到 1000000 之间)",
+            index + 1,
+            score.score
+        )));
+    }
+
+    // 区间判断同时排除 NaN，避免无效准确率算出错误的 RKS
+    if !(0.0..=100.0).contains(&score.acc) {
+        return Err(AppError::BadRequest(format!(
+            "第{}条成绩的准确率无效: {} (必须在 0 到 100 之间)",
+            index + 1,
+            score.acc
+        )));
+    }
+
+    if score.acc == 100.0 && score.is_fc == Some(false) {
+        return Err(AppError::BadRequest(format!(
+            "第{}条成绩的准确率为100 (AP)，is_fc 不能为 false",
+            index + 1
+        )));
+    }
+
+    if !["EZ", "HD", "IN", "AT"].contains(&score.difficulty.as_str()) {
+        return Err(AppError::BadRequest(format!(
+            "第{}条成绩的难度无效: {} (必须是 EZ, HD, IN, AT 之一)",
+            index + 1,
+            score.difficulty
+        )));
+    }
+    Ok(())
+}
+
 /// 根据用户提供的成绩数据生成Best N成绩图片
 ///
 /// 用户可以提供自己的成绩数据，系统会将其转换为标准的成绩记录并生成图片。
@@ -303,39 +1169,28 @@ pub async fn generate_bn_image_from_user_data(
     song_service: web::Data<SongService>,
     image_service: web::Data<ImageService>,
 ) -> Result<HttpResponse, AppError> {
-    let user_data = req.into_inner();
+    let mut user_data = req.into_inner();
 
     // 验证成绩数量
     if user_data.scores.is_empty() {
         return Err(AppError::BadRequest("成绩列表不能为空".to_string()));
     }
 
-    if user_data.scores.len() > 100 {
-        return Err(AppError::BadRequest("成绩数量不能超过100".to_string()));
+    if user_data.scores.len() > MAX_USER_SCORES {
+        return Err(AppError::BadRequest(format!(
+            "成绩数量不能超过{MAX_USER_SCORES}，当前为 {}",
+            user_data.scores.len()
+        )));
     }
 
-    // 验证每个成绩记录
-    for (index, score) in user_data.scores.iter().enumerate() {
-        if score.score == 0 || score.score > 1_000_000 {
-            return Err(AppError::BadRequest(format!(
-                "第{}条成绩的分数无效: {}",
-                index + 1, score.score
-            )));
-        }
-
-        if score.acc < 0.0 || score.acc > 100.0 {
-            return Err(AppError::BadRequest(format!(
-                "第{}条成绩的准确率无效: {}",
-                index + 1, score.acc
-            )));
-        }
+    if user_data.player_name.chars().count() > MAX_USER_PLAYER_NAME_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "玩家名称不能超过{MAX_USER_PLAYER_NAME_CHARS}个字符"
+        )));
+    }
 
-        if !["EZ", "HD", "IN", "AT"].contains(&score.difficulty.as_str()) {
-            return Err(AppError::BadRequest(format!(
-                "第{}条成绩的难度无效: {} (必须是 EZ, HD, IN, AT 之一)",
-                index + 1, score.difficulty
-            )));
-        }
+    for (index, score) in user_data.scores.iter_mut().enumerate() {
+        validate_user_score(index, score)?;
     }
 
     let image_bytes = image_service
@@ -346,3 +1201,68 @@ pub async fn generate_bn_image_from_user_data(
         .content_type("image/png")
         .body(image_bytes))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_score(score: u32, acc: f64) -> UserScoreRecord {
+        UserScoreRecord {
+            song_name: "Rrhar'il".to_string(),
+            score,
+            acc,
+            difficulty: "at".to_string(),
+            is_fc: None,
+        }
+    }
+
+    fn rejection(index: usize, mut score: UserScoreRecord) -> String {
+        match validate_user_score(index, &mut score) {
+            Err(AppError::BadRequest(message)) => message,
+            other => panic!("应返回 BadRequest，实际为 {other:?}"),
+        }
+    }
+
+    #[test]
+    fn user_score_in_range_is_accepted() {
+        for (score, acc) in [(0, 0.0), (1_000_000, 100.0), (987_654, 98.76)] {
+            let mut record = user_score(score, acc);
+            assert!(validate_user_score(0, &mut record).is_ok());
+            assert_eq!(record.difficulty, "AT");
+        }
+    }
+
+    #[test]
+    fn mixed_case_user_difficulty_is_accepted() {
+        for difficulty in ["in", "In", "iN", " IN "] {
+            let mut record = UserScoreRecord {
+                difficulty: difficulty.to_string(),
+                ..user_score(990_000, 99.0)
+            };
+            assert!(validate_user_score(0, &mut record).is_ok());
+            assert_eq!(record.difficulty, "IN");
+        }
+        let message = rejection(
+            1,
+            UserScoreRecord {
+                difficulty: "sp".to_string(),
+                ..user_score(990_000, 99.0)
+            },
+        );
+        assert!(message.starts_with("第2条成绩的难度无效"), "{message}");
+    }
+
+    #[test]
+    fn out_of_range_acc_is_rejected_with_its_index() {
+        for acc in [150.0, -5.0, 100.0001, f64::NAN, f64::INFINITY] {
+            let message = rejection(2, user_score(900_000, acc));
+            assert!(message.starts_with("第3条成绩的准确率无效"), "{message}");
+        }
+    }
+
+    #[test]
+    fn out_of_range_score_is_rejected_with_its_index() {
+        let message = rejection(0, user_score(1_000_001, 99.0));
+        assert!(message.starts_with("第1条成绩的分数无效"), "{message}");
+    }
+}
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod auth;
 pub mod b30;
 pub mod binding;
@@ -6,5 +7,6 @@ pub mod image;
 pub mod rks;
 pub mod save;
 pub mod song;
+pub mod stats;
 
 pub mod status;
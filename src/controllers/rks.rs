@@ -1,17 +1,39 @@
-use actix_web::{post, web, HttpResponse};
+use actix_web::{get, post, web, HttpResponse};
 use log::debug;
-use std::collections::HashMap;
-use utoipa;
+use serde::Deserialize;
+use utoipa::{self, IntoParams, ToSchema};
 
-use crate::models::rks::{RksRecord, RksResult};
+use crate::config::CONFIG;
+use crate::controllers::image::{BnFilter, BnMode};
+use crate::models::player_archive::{ChartPercentile, ChartScore, RKSRankingEntry, RksHistory};
+use crate::models::rks::{
+    RksCalcChartResult, RksCalcResult, RksRecord, RksResult, RksTargetPlan, TierPushAccEntry,
+};
+use crate::models::save::GameSave;
 use crate::models::user::{ApiResponse, IdentifierRequest};
 use crate::services::phigros::PhigrosService;
 use crate::services::player_archive_service::PlayerArchiveService;
+use crate::services::song::SongService;
 use crate::services::user::UserService;
-use crate::utils::error::AppResult;
-use crate::utils::save_parser::check_session_token;
-use crate::utils::token_helper::resolve_token;
-use tokio;
+use crate::utils::{bn_text, rks_utils};
+use crate::utils::error::{AppError, AppResult};
+use crate::utils::save_parser::{is_ap, normalize_difficulty};
+use crate::utils::token_helper::resolve_request;
+
+/// 按请求的数据源获取 RKS 结果与存档，返回 `(RKS 结果, 存档, PlayerId, 玩家名)`
+///
+/// 内部数据源使用解析后的 Token (包括通过平台绑定查询到的)，Token 被拒绝时注明失效的绑定。
+async fn fetch_rks(
+    req: &web::Json<IdentifierRequest>,
+    phigros_service: &PhigrosService,
+    user_service: &web::Data<UserService>,
+) -> AppResult<(RksResult, GameSave, String, String)> {
+    let (request, resolved) = resolve_request(req, user_service).await?;
+    phigros_service
+        .get_rks_with_source(&request)
+        .await
+        .map_err(|e| resolved.annotate_error(e))
+}
 
 /// 计算并返回玩家的RKS及b19和r10成绩
 ///
@@ -31,68 +53,685 @@ pub async fn get_rks(
     user_service: web::Data<UserService>,
     player_archive_service: web::Data<PlayerArchiveService>,
 ) -> AppResult<HttpResponse> {
-    let (rks_result, save, player_id, player_name) = if req.data_source.as_deref() == Some("external") {
-        // 外部数据源：直接调用服务方法，不需要token验证
-        phigros_service.get_rks_with_source(&req).await?
-    } else {
-        // 内部数据源：需要token验证
-        let _token = resolve_token(&req, &user_service).await?;
-        check_session_token(&_token)?;
-        phigros_service.get_rks_with_source(&req).await?
-    };
+    let (rks_result, save, player_id, player_name) =
+        fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    player_archive_service.spawn_update_from_save(
+        player_id,
+        player_name,
+        &save,
+        rks_result.records.clone(),
+        req.data_source.as_deref() == Some("external"),
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "ok".to_string(),
+        message: None,
+        data: Some(rks_result),
+    }))
+}
+
+/// 获取玩家的RKS历史与增长速度
+///
+/// 返回存档中的当前RKS、最近7天与30天的RKS变化以及最近30天的每日快照。
+/// 快照在每次存档RKS重新计算时记录，每天保留最后一次；本次请求也会触发一次存档更新。
+#[utoipa::path(
+    post,
+    path = "/rks/history",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取RKS历史", body = ApiResponse<RksHistory>),
+        (status = 404, description = "玩家尚无存档")
+    )
+)]
+#[post("/rks/history")]
+pub async fn get_rks_history(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    let (rks_result, save, player_id, player_name) =
+        fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    // 存档在后台更新，本次返回的历史基于已有的存档与快照
+    player_archive_service.spawn_update_from_save(
+        player_id.clone(),
+        player_name,
+        &save,
+        rks_result.records,
+        req.data_source.as_deref() == Some("external"),
+    );
+    let history = player_archive_service.get_rks_history(&player_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "ok".to_string(),
+        message: None,
+        data: Some(history),
+    }))
+}
+
+/// 最近游玩的谱面默认返回的数量
+const DEFAULT_RECENT_LIMIT: usize = 20;
+/// 最近游玩的谱面最多返回的数量
+const MAX_RECENT_LIMIT: usize = 200;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct RksRecentQuery {
+    /// 最多返回的谱面数，默认 20，最大 200
+    pub limit: Option<usize>,
+}
+
+/// 获取玩家最近游玩的谱面
+///
+/// 从存档的成绩记录中返回最近有成绩变化的谱面，按记录时间倒序，用于查看玩家最近在推哪些谱面。
+/// 记录时间为本服务发现成绩变化的时间，而不是游戏内的游玩时间；本次请求也会触发一次存档更新。
+#[utoipa::path(
+    post,
+    path = "/rks/recent",
+    params(RksRecentQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取最近游玩的谱面", body = ApiResponse<Vec<ChartScore>>),
+        (status = 400, description = "limit 无效")
+    )
+)]
+#[post("/rks/recent")]
+pub async fn get_recent_records(
+    query: web::Query<RksRecentQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_RECENT_LIMIT);
+    if limit == 0 || limit > MAX_RECENT_LIMIT {
+        return Err(AppError::BadRequest(format!(
+            "参数limit必须在 1 到 {MAX_RECENT_LIMIT} 之间"
+        )));
+    }
+
+    let (rks_result, save, player_id, player_name) =
+        fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    // 存档在后台更新，本次返回的是已有的存档记录
+    player_archive_service.spawn_update_from_save(
+        player_id.clone(),
+        player_name,
+        &save,
+        rks_result.records,
+        req.data_source.as_deref() == Some("external"),
+    );
+    let charts = player_archive_service
+        .get_recent_chart_scores(&player_id, limit)
+        .await?;
+    let message = charts
+        .is_empty()
+        .then(|| "该玩家尚无存档记录，本次查询已开始建立存档，请稍后再试".to_string());
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "ok".to_string(),
+        message,
+        data: Some(charts),
+    }))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct ChartPercentileQuery {
+    /// 歌曲ID、名称或别名
+    pub q: String,
+    /// 难度 (EZ, HD, IN, AT)
+    pub difficulty: String,
+}
+
+/// 获取玩家在某个谱面上相对已存档玩家的排位
+///
+/// 以玩家当前的 ACC 与数据库中其他玩家在该谱面的当前成绩比较，返回超过的玩家百分比与名次。
+/// 统计范围仅限本服务存档过的玩家；本次请求也会触发一次存档更新。
+#[utoipa::path(
+    post,
+    path = "/rks/percentile",
+    params(ChartPercentileQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功计算谱面排位", body = ApiResponse<ChartPercentile>),
+        (status = 400, description = "难度无效"),
+        (status = 404, description = "找不到歌曲，或玩家在该难度没有成绩")
+    )
+)]
+#[post("/rks/percentile")]
+pub async fn get_chart_percentile(
+    query: web::Query<ChartPercentileQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    song_service: web::Data<SongService>,
+    user_service: web::Data<UserService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到谱面排位请求: {query:?}");
+
+    let difficulty = normalize_difficulty(&query.difficulty);
+    if !["EZ", "HD", "IN", "AT"].contains(&difficulty.as_str()) {
+        return Err(AppError::BadRequest(format!(
+            "无效的难度: {}，可选值为 EZ, HD, IN, AT",
+            query.difficulty
+        )));
+    }
+    let song_id = song_service.get_song_id(&query.q)?;
+
+    let (rks_result, save, player_id, player_name) =
+        fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    let record = rks_result
+        .records
+        .iter()
+        .find(|r| r.song_id == song_id && r.difficulty == difficulty)
+        .cloned()
+        .ok_or_else(|| {
+            AppError::RecordNotFound(format!("歌曲 {song_id} 的 {difficulty} 难度没有成绩"))
+        })?;
+
+    player_archive_service.spawn_update_from_save(
+        player_id.clone(),
+        player_name,
+        &save,
+        rks_result.records,
+        req.data_source.as_deref() == Some("external"),
+    );
+    let percentile = player_archive_service
+        .get_chart_percentile(&player_id, &record)
+        .await?;
+    let message = percentile
+        .percentile
+        .is_none()
+        .then(|| "暂无其他玩家在该谱面的成绩".to_string());
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "ok".to_string(),
+        message,
+        data: Some(percentile),
+    }))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct RksLeaderboardQuery {
+    /// 返回的排行榜条目数量，省略时使用 LEADERBOARD_DEFAULT_LIMIT 配置 (默认 20)，超过 LEADERBOARD_MAX_LIMIT (默认 100) 时截断
+    pub limit: Option<usize>,
+}
+
+/// 获取 RKS 排行榜
+///
+/// 以 JSON 返回已存档玩家按 RKS 降序排列的排行榜，条数限制与排行榜图片一致。
+#[utoipa::path(
+    get,
+    path = "/leaderboard/rks",
+    params(RksLeaderboardQuery),
+    responses(
+        (status = 200, description = "成功获取排行榜", body = ApiResponse<Vec<RKSRankingEntry>>)
+    )
+)]
+#[get("/leaderboard/rks")]
+pub async fn get_rks_ranking(
+    query: web::Query<RksLeaderboardQuery>,
+    player_archive_service: web::Data<PlayerArchiveService>,
+) -> AppResult<HttpResponse> {
+    let limit = CONFIG.leaderboard_limit(query.limit);
+    let entries = player_archive_service.get_rks_ranking(limit).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(entries),
+    }))
+}
+
+/// 难度推分预览默认返回的谱面数
+const DEFAULT_TIER_PUSH_ACC_LIMIT: usize = 20;
+/// 难度推分预览最多返回的谱面数
+pub const MAX_TIER_PUSH_ACC_LIMIT: usize = 200;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct TierPushAccQuery {
+    /// 难度 (EZ, HD, IN, AT)
+    pub difficulty: String,
+    /// 最多返回的谱面数，默认 20，最大 200
+    pub limit: Option<usize>,
+}
+
+/// 预览某一难度下所有谱面的推分ACC
+///
+/// 返回该难度下已游玩且未 AP 的谱面各自的推分ACC (使总 RKS 增加 0.01 所需的 ACC)
+/// 与达到该 ACC 时单曲 RKS 的提升，按提升从小到大排序，越靠前越容易推分。
+#[utoipa::path(
+    post,
+    path = "/rks/push-acc/tier",
+    params(TierPushAccQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功计算推分ACC", body = ApiResponse<Vec<TierPushAccEntry>>),
+        (status = 400, description = "难度或 limit 无效")
+    )
+)]
+#[post("/rks/push-acc/tier")]
+pub async fn get_tier_push_acc(
+    query: web::Query<TierPushAccQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到难度推分预览请求: {query:?}");
+
+    let difficulty = normalize_difficulty(&query.difficulty);
+    if !["EZ", "HD", "IN", "AT"].contains(&difficulty.as_str()) {
+        return Err(AppError::BadRequest(format!(
+            "无效的难度: {}，可选值为 EZ, HD, IN, AT",
+            query.difficulty
+        )));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_TIER_PUSH_ACC_LIMIT);
+    if limit == 0 || limit > MAX_TIER_PUSH_ACC_LIMIT {
+        return Err(AppError::BadRequest(format!(
+            "参数limit必须在 1 到 {MAX_TIER_PUSH_ACC_LIMIT} 之间"
+        )));
+    }
+
+    let (rks_result, _, _, _) = fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    // 推分模拟需要全部成绩，批量计算后再按难度筛选
+    let records = &rks_result.records;
+    let mut entries: Vec<TierPushAccEntry> = records
+        .iter()
+        .zip(rks_utils::calculate_push_acc_batch(records))
+        .filter(|(record, _)| record.difficulty == difficulty)
+        .filter_map(|(record, push_acc)| {
+            let push_acc = push_acc?;
+            Some(TierPushAccEntry {
+                song_id: record.song_id.clone(),
+                song_name: record.song_name.clone(),
+                difficulty: record.difficulty.clone(),
+                difficulty_value: record.difficulty_value,
+                acc: record.acc,
+                rks: record.rks,
+                push_acc,
+                rks_gain: rks_utils::calculate_chart_rks(push_acc, record.difficulty_value)
+                    - record.rks,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.rks_gain.total_cmp(&b.rks_gain));
+    entries.truncate(limit);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(entries),
+    }))
+}
 
-    let mut fc_map = HashMap::new();
-    if let Some(game_record_map) = &save.game_record {
-        for (song_id, difficulties) in game_record_map {
-            for (diff_name, record) in difficulties {
-                if let Some(true) = record.fc {
-                    let key = format!("{song_id}-{diff_name}");
-                    fc_map.insert(key, true);
-                }
-            }
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct RksTargetQuery {
+    /// 目标 RKS (四舍五入到两位小数后比较)
+    pub target_rks: f64,
+}
+
+/// 规划达到目标 RKS 的推分路径
+///
+/// 基于玩家现有的谱面反复计算推分ACC，返回需要提升的谱面及各自需要达到的 ACC；
+/// 现有谱面全部 AP 仍无法达到目标时 `reachable` 为 false 且不返回规划，
+/// 达到推分次数上限仍未达到目标时 `iteration_limit_reached` 为 true。
+#[utoipa::path(
+    post,
+    path = "/rks/target",
+    params(RksTargetQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功规划推分路径", body = ApiResponse<RksTargetPlan>),
+        (status = 400, description = "目标 RKS 无效")
+    )
+)]
+#[post("/rks/target")]
+pub async fn plan_target_rks(
+    query: web::Query<RksTargetQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到目标 RKS 规划请求: {query:?}");
+
+    let target_rks = query.target_rks;
+    if !target_rks.is_finite() || target_rks <= 0.0 {
+        return Err(AppError::BadRequest("参数target_rks必须为正数".to_string()));
+    }
+
+    let (rks_result, _, _, _) = fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    // 规划需要多轮推分模拟，移至阻塞线程执行
+    let mut records = rks_result.records;
+    let plan = web::block(move || {
+        records.sort_by(rks_utils::compare_records);
+        rks_utils::plan_target_rks(&records, target_rks)
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("目标 RKS 规划失败: {e}")))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(plan),
+    }))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct RksFilterQuery {
+    /// 最低 ACC (含)，0-100
+    pub min_acc: Option<f64>,
+    /// 最高 ACC (含)，0-100
+    pub max_acc: Option<f64>,
+    /// 最低定数 (含)
+    pub min_constant: Option<f64>,
+    /// 最高定数 (含)
+    pub max_constant: Option<f64>,
+    /// 只返回 Full Combo 的谱面 (AP 也算在内)
+    #[serde(default)]
+    pub fc_only: bool,
+    /// 只返回 AP 的谱面
+    #[serde(default)]
+    pub ap_only: bool,
+}
+
+/// 按 ACC、定数与 FC/AP 状态筛选玩家的全部成绩
+///
+/// 所有条件均为可选且同时生效，区间两端都包含在内；结果按单曲 RKS 从高到低排序。
+#[utoipa::path(
+    post,
+    path = "/rks/filter",
+    params(RksFilterQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功筛选成绩", body = ApiResponse<Vec<RksRecord>>),
+        (status = 400, description = "筛选条件无效")
+    )
+)]
+#[post("/rks/filter")]
+pub async fn filter_records(
+    query: web::Query<RksFilterQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到成绩筛选请求: {query:?}");
+
+    for (name, value) in [("min_acc", query.min_acc), ("max_acc", query.max_acc)] {
+        if value.is_some_and(|acc| !(0.0..=100.0).contains(&acc)) {
+            return Err(AppError::BadRequest(format!(
+                "参数{name}必须在 0 到 100 之间"
+            )));
+        }
+    }
+    for (name, value) in [
+        ("min_constant", query.min_constant),
+        ("max_constant", query.max_constant),
+    ] {
+        if value.is_some_and(|constant| !constant.is_finite() || constant < 0.0) {
+            return Err(AppError::BadRequest(format!("参数{name}必须为非负数")));
         }
     }
+    if let (Some(min), Some(max)) = (query.min_acc, query.max_acc) {
+        if min > max {
+            return Err(AppError::BadRequest(
+                "参数min_acc不能大于max_acc".to_string(),
+            ));
+        }
+    }
+    if let (Some(min), Some(max)) = (query.min_constant, query.max_constant) {
+        if min > max {
+            return Err(AppError::BadRequest(
+                "参数min_constant不能大于max_constant".to_string(),
+            ));
+        }
+    }
+
+    let (rks_result, _, _, _) = fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    let mut records: Vec<RksRecord> = rks_result
+        .records
+        .into_iter()
+        .filter(|record| {
+            let ap = is_ap(record.score.unwrap_or(0.0), record.acc);
+            query.min_acc.is_none_or(|min| record.acc >= min)
+                && query.max_acc.is_none_or(|max| record.acc <= max)
+                && query
+                    .min_constant
+                    .is_none_or(|min| record.difficulty_value >= min)
+                && query
+                    .max_constant
+                    .is_none_or(|max| record.difficulty_value <= max)
+                && (!query.fc_only || record.is_fc || ap)
+                && (!query.ap_only || ap)
+        })
+        .collect();
+    records.sort_by(rks_utils::compare_records);
 
-    let archive_service_clone = player_archive_service.clone();
-    let player_id_clone = player_id.clone();
-    let player_name_clone = player_name.clone();
-    let records_clone = rks_result.records.clone();
-    let fc_map_clone = fc_map.clone();
-
-    tokio::spawn(async move {
-        log::info!("[后台任务] (get_rks) 开始为玩家 {player_name_clone} ({player_id_clone}) 更新数据库存档...");
-        let is_external = req.data_source.as_deref() == Some("external");
-        match archive_service_clone
-            .update_player_scores_from_rks_records(
-                &player_id_clone,
-                &player_name_clone,
-                &records_clone,
-                &fc_map_clone,
-                is_external,
-            )
-            .await
-        {
-            Ok(_) => log::info!("[后台任务] (get_rks) 玩家 {player_name_clone} ({player_id_clone}) 数据库存档更新完成。"),
-            Err(e) => log::error!("[后台任务] (get_rks) 更新玩家 {player_name_clone} ({player_id_clone}) 数据库存档失败: {e}"),
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(records),
+    }))
+}
+
+/// RKS 计算器单次最多计算的谱面数
+pub const MAX_CALC_CHARTS: usize = 1000;
+
+/// RKS 计算器的输入谱面
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct RksCalcChart {
+    /// 准确率 (0-100)
+    pub acc: f64,
+    /// 谱面定数 (大于 0)
+    pub constant: f64,
+}
+
+/// 根据 ACC 与定数计算 RKS
+///
+/// 无需存档的纯计算接口：返回每个谱面的单曲 RKS，并按 Best 27 + AP Top 3 计算玩家 RKS。
+/// ACC 为 100 的谱面视为 AP。
+#[utoipa::path(
+    post,
+    path = "/rks/calc",
+    request_body = Vec<RksCalcChart>,
+    responses(
+        (status = 200, description = "成功计算RKS", body = ApiResponse<RksCalcResult>),
+        (status = 400, description = "ACC 或定数无效")
+    )
+)]
+#[post("/rks/calc")]
+pub async fn calculate_rks(req: web::Json<Vec<RksCalcChart>>) -> AppResult<HttpResponse> {
+    let charts = req.into_inner();
+    if charts.is_empty() {
+        return Err(AppError::BadRequest("谱面列表不能为空".to_string()));
+    }
+    if charts.len() > MAX_CALC_CHARTS {
+        return Err(AppError::BadRequest(format!(
+            "谱面数量不能超过{MAX_CALC_CHARTS}，当前为 {}",
+            charts.len()
+        )));
+    }
+
+    for (index, chart) in charts.iter().enumerate() {
+        if !(0.0..=100.0).contains(&chart.acc) {
+            return Err(AppError::BadRequest(format!(
+                "第{}个谱面的准确率无效: {} (必须在 0 到 100 之间)",
+                index + 1,
+                chart.acc
+            )));
+        }
+        if !chart.constant.is_finite() || chart.constant <= 0.0 {
+            return Err(AppError::BadRequest(format!(
+                "第{}个谱面的定数无效: {} (必须大于 0)",
+                index + 1,
+                chart.constant
+            )));
         }
+    }
+
+    let mut results: Vec<RksCalcChartResult> = charts
+        .iter()
+        .map(|chart| RksCalcChartResult {
+            acc: chart.acc,
+            constant: chart.constant,
+            rks: rks_utils::calculate_chart_rks(chart.acc, chart.constant),
+            in_best_27: false,
+            in_ap_top_3: false,
+        })
+        .collect();
+
+    // 按单曲 RKS 降序排列输入下标，玩家 RKS 的计算要求记录已排序
+    let mut order: Vec<usize> = (0..results.len()).collect();
+    order.sort_by(|&a, &b| {
+        results[b]
+            .rks
+            .total_cmp(&results[a].rks)
+            .then_with(|| results[b].acc.total_cmp(&results[a].acc))
     });
+    let records: Vec<RksRecord> = order
+        .iter()
+        .map(|&index| RksRecord {
+            song_id: String::new(),
+            song_name: String::new(),
+            difficulty: String::new(),
+            difficulty_value: results[index].constant,
+            acc: results[index].acc,
+            score: None,
+            rks: results[index].rks,
+            is_fc: results[index].acc >= 100.0,
+        })
+        .collect();
+    let (rks, rks_rounded) = rks_utils::calculate_player_rks_details(&records);
+
+    for &index in order.iter().take(27) {
+        results[index].in_best_27 = results[index].rks > 0.0;
+    }
+    let ap_indices: Vec<usize> = order
+        .iter()
+        .copied()
+        .filter(|&index| results[index].acc >= 100.0 && results[index].rks > 0.0)
+        .take(3)
+        .collect();
+    for index in ap_indices {
+        results[index].in_ap_top_3 = true;
+    }
 
     Ok(HttpResponse::Ok().json(ApiResponse {
         code: 200,
         status: "ok".to_string(),
         message: None,
-        data: Some(rks_result),
+        data: Some(RksCalcResult {
+            charts: results,
+            rks,
+            rks_rounded,
+        }),
     }))
 }
 
+/// 导出玩家全部成绩为 CSV
+///
+/// 包含所有参与 RKS 计算的谱面，按 RKS 降序排列，便于导入表格软件分析。
+#[utoipa::path(
+    post,
+    path = "/rks/export.csv",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功导出成绩", content_type = "text/csv", body = String)
+    )
+)]
+#[post("/rks/export.csv")]
+pub async fn export_records_csv(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到成绩 CSV 导出请求");
+
+    let (request, resolved) = resolve_request(&req, &user_service).await?;
+    let full_data = phigros_service
+        .get_full_save_data_with_source(&request)
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
+    full_data.save.played_records()?;
+
+    let mut records = full_data.rks_result.records;
+    records.sort_by(rks_utils::compare_records);
+    let csv = records_to_csv(&records)?;
+    let filename = format!(
+        "phigros-records-{}.csv",
+        chrono::Utc::now().format("%Y%m%d")
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{filename}\""),
+        ))
+        .body(csv))
+}
+
+fn records_to_csv(records: &[RksRecord]) -> AppResult<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "song_id",
+        "song_name",
+        "difficulty",
+        "constant",
+        "score",
+        "acc",
+        "rks",
+        "is_fc",
+        "is_ap",
+    ])?;
+    for record in records {
+        writer.write_record([
+            record.song_id.clone(),
+            record.song_name.clone(),
+            record.difficulty.clone(),
+            record.difficulty_value.to_string(),
+            record.score.map(|s| s.to_string()).unwrap_or_default(),
+            format!("{:.4}", record.acc),
+            format!("{:.4}", record.rks),
+            record.is_fc.to_string(),
+            (record.acc >= 100.0).to_string(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| AppError::InternalError(format!("写入 CSV 失败: {e}")))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct BnQuery {
+    /// 只统计定数不低于该值的谱面
+    pub min_constant: Option<f64>,
+    /// 只统计定数不高于该值的谱面
+    pub max_constant: Option<f64>,
+}
+
 /// 获取玩家最好的N项成绩
 ///
-/// 根据计算出的RKS，返回玩家分数最高的N条记录。
+/// 根据计算出的RKS，返回玩家分数最高的N条记录；可通过定数范围只统计部分谱面。
 #[utoipa::path(
     post,
     path = "/bn/{n}",
     params(
-        ("n" = u32, Path, description = "要获取的最高成绩数量")
+        ("n" = u32, Path, description = "要获取的最高成绩数量"),
+        BnQuery
     ),
     request_body = IdentifierRequest,
     responses(
@@ -103,9 +742,11 @@ pub async fn get_rks(
 #[post("/bn/{n}")]
 pub async fn get_bn(
     n: web::Path<u32>,
+    query: web::Query<BnQuery>,
     req: web::Json<IdentifierRequest>,
     phigros_service: web::Data<PhigrosService>,
     user_service: web::Data<UserService>,
+    player_archive_service: web::Data<PlayerArchiveService>,
 ) -> AppResult<HttpResponse> {
     let n = n.into_inner();
     debug!("接收到B{n}查询请求");
@@ -118,19 +759,30 @@ pub async fn get_bn(
             data: None::<Vec<()>>,
         }));
     }
+    if n > CONFIG.max_bn {
+        return Err(AppError::BadRequest(format!(
+            "参数n不能超过 {} (由 MAX_BN 配置)",
+            CONFIG.max_bn
+        )));
+    }
+    let filter = BnFilter::new(BnMode::All, query.min_constant, query.max_constant)?;
 
-    let (rks_result, _, _, _) = if req.data_source.as_deref() == Some("external") {
-        // 外部数据源：直接调用服务方法，不需要token验证
-        phigros_service.get_rks_with_source(&req).await?
-    } else {
-        // 内部数据源：需要token验证
-        let _token = resolve_token(&req, &user_service).await?;
-        phigros_service.get_rks_with_source(&req).await?
-    };
+    let (rks_result, save, player_id, player_name) =
+        fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    // 更新数据库中的玩家存档和 RKS (后台排队执行，受写入并发限制)
+    player_archive_service.spawn_update_from_save(
+        player_id,
+        player_name,
+        &save,
+        rks_result.records.clone(),
+        req.data_source.as_deref() == Some("external"),
+    );
 
     let bn = rks_result
         .records
         .into_iter()
+        .filter(|record| filter.matches(record))
         .take(n as usize)
         .collect::<Vec<_>>();
 
@@ -141,3 +793,74 @@ pub async fn get_bn(
         data: Some(bn),
     }))
 }
+
+/// Best N 文本的输出格式
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BnTextFormat {
+    /// 纯文本，每行一条成绩
+    #[default]
+    Text,
+    /// Markdown 表格
+    Markdown,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct BnTextQuery {
+    /// 最多输出的成绩行数，默认等于 N，可小于 N 以缩短消息
+    pub limit: Option<u32>,
+    /// 输出格式: text (默认) 或 markdown
+    #[serde(default)]
+    #[param(value_type = Option<BnTextFormat>)]
+    pub format: BnTextFormat,
+}
+
+/// 获取玩家 Best N 成绩的文本摘要
+///
+/// 面向聊天机器人等不需要图片的场景，按行输出排名、曲名、难度、ACC、RKS 与推分提示。
+/// 输出长度不超过 2000 字符，超出时截断并注明省略条数。
+#[utoipa::path(
+    post,
+    path = "/bn/{n}/text",
+    params(
+        ("n" = u32, Path, description = "要输出的最高成绩数量"),
+        BnTextQuery
+    ),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功生成文本摘要", content_type = "text/plain", body = String),
+        (status = 400, description = "无效的n或limit值")
+    )
+)]
+#[post("/bn/{n}/text")]
+pub async fn get_bn_text(
+    n: web::Path<u32>,
+    query: web::Query<BnTextQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    let n = n.into_inner();
+    debug!("接收到B{n}文本摘要请求: {query:?}");
+
+    if n == 0 || n > CONFIG.max_bn {
+        return Err(AppError::BadRequest(format!(
+            "参数n必须在 1 到 {} 之间 (由 MAX_BN 配置)",
+            CONFIG.max_bn
+        )));
+    }
+    if query.limit == Some(0) {
+        return Err(AppError::BadRequest("参数limit必须大于0".to_string()));
+    }
+    let lines = query.limit.map_or(n, |limit| limit.min(n)) as usize;
+
+    let (rks_result, _, _, player_name) = fetch_rks(&req, &phigros_service, &user_service).await?;
+
+    let text = bn_text::render_bn_text(&player_name, &rks_result.records, n, lines, query.format);
+    let content_type = match query.format {
+        BnTextFormat::Text => "text/plain; charset=utf-8",
+        BnTextFormat::Markdown => "text/markdown; charset=utf-8",
+    };
+
+    Ok(HttpResponse::Ok().content_type(content_type).body(text))
+}
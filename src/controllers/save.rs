@@ -2,26 +2,30 @@ use actix_web::{post, web, HttpResponse};
 use log::debug;
 use utoipa;
 
-use crate::models::save::GameSave;
+use crate::controllers::image::ok_with_save_updated_at;
+use crate::models::save::{GameProgress, GameSave, PlayerSettings, SaveSummary};
 use crate::models::user::{ApiResponse, IdentifierRequest};
 use crate::services::phigros::PhigrosService;
 use crate::services::user::UserService;
-use crate::utils::error::AppResult;
+use crate::utils::error::{AppError, AppResult};
 use crate::utils::save_parser::check_session_token;
-use crate::utils::token_helper::resolve_token;
+use crate::utils::token_helper::{
+    data_source_of, external_fallback, resolve_token_with_binding, DATA_SOURCE_HEADER,
+};
 use serde_json::json;
 use tokio;
 
 /// 获取云存档（不含难度）
 ///
 /// 获取玩家的原始云存档，并附加玩家昵称。
+/// 外部数据源在返回头像、地区时额外附带 `avatar`、`region` 字段。
 /// 返回的 `game_record` 被简化，只包含 `score`, `acc`, `fc`。
 #[utoipa::path(
     post,
     path = "/get/cloud/saves",
     request_body = IdentifierRequest,
     responses(
-        (status = 200, description = "成功获取云存档", body = ApiResponse<serde_json::Value>)
+        (status = 200, description = "成功获取云存档（额外附带 `nickname` 字段）", body = ApiResponse<GameSave>)
     )
 )]
 #[post("/get/cloud/saves")]
@@ -30,30 +34,37 @@ pub async fn get_cloud_saves(
     phigros_service: web::Data<PhigrosService>,
     user_service: web::Data<UserService>,
 ) -> AppResult<HttpResponse> {
-    let (save_result, profile_result) = if req.data_source.as_deref() == Some("external") {
-        // 外部数据源：获取完整存档数据以获得nickname
-        let full_data = phigros_service.get_full_save_data_with_source(&req).await?;
-        let save_result = Ok(full_data.save);
-
-        // 从外部数据源获取nickname
-        let nickname = full_data.cloud_summary["results"][0]["nickname"]
-            .as_str()
-            .unwrap_or("External User")
-            .to_string();
-
-        (save_result, Ok(crate::models::user::UserProfile {
-            object_id: "external".to_string(),
-            nickname
-        }))
+    let mut data_source = data_source_of(&req);
+    // 外部数据源额外提供的资料字段（头像、地区），缺失时不出现在响应中
+    let (save_result, profile_result, external_profile) = if data_source == "external" {
+        let (save, profile, external_profile) =
+            fetch_external_cloud_save(&req, &phigros_service).await?;
+        (Ok(save), Ok(profile), external_profile)
     } else {
         // 内部数据源：并行获取数据
-        let token = resolve_token(&req, &user_service).await?;
-        check_session_token(&token)?;
+        let resolved = resolve_token_with_binding(&req, &user_service).await?;
+        check_session_token(&resolved.token).map_err(|e| resolved.annotate_error(e))?;
 
-        tokio::join!(
-            phigros_service.get_save_with_source(&req),
-            phigros_service.get_profile(&token)
-        )
+        let (save_result, profile_result) = tokio::join!(
+            phigros_service.get_save(&resolved.token),
+            phigros_service.get_profile(&resolved.token)
+        );
+        match save_result {
+            Ok(save) => (Ok(save), profile_result, serde_json::Map::new()),
+            Err(e) => match external_fallback(&req, &e) {
+                Some(fallback) => {
+                    data_source = "external";
+                    let (save, profile, external_profile) =
+                        fetch_external_cloud_save(&fallback, &phigros_service).await?;
+                    (Ok(save), Ok(profile), external_profile)
+                }
+                None => (
+                    Err(resolved.annotate_error(e)),
+                    profile_result,
+                    serde_json::Map::new(),
+                ),
+            },
+        }
     };
 
     let save_data = save_result?;
@@ -95,18 +106,53 @@ pub async fn get_cloud_saves(
         "user": save_data.user
     });
 
-    if let Some(nickname) = player_nickname {
-        if let Some(obj) = response_data.as_object_mut() {
+    if let Some(obj) = response_data.as_object_mut() {
+        if let Some(nickname) = player_nickname {
             obj.insert("nickname".to_string(), json!(nickname));
         }
+        obj.extend(external_profile);
     }
 
-    Ok(HttpResponse::Ok().json(ApiResponse {
-        code: 200,
-        status: "ok".to_string(),
-        message: None,
-        data: Some(response_data),
-    }))
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "ok".to_string(),
+            message: None,
+            data: Some(response_data),
+        }))
+}
+
+/// 从外部数据源获取存档、昵称与额外的资料字段（头像、地区）
+async fn fetch_external_cloud_save(
+    req: &IdentifierRequest,
+    phigros_service: &PhigrosService,
+) -> AppResult<(
+    GameSave,
+    crate::models::user::UserProfile,
+    serde_json::Map<String, serde_json::Value>,
+)> {
+    // 外部数据源：获取完整存档数据以获得nickname
+    let full_data = phigros_service.get_full_save_data_with_source(req).await?;
+    let mut external_profile = serde_json::Map::new();
+    for field in ["avatar", "region"] {
+        let value = &full_data.cloud_summary["results"][0][field];
+        if value.is_string() {
+            external_profile.insert(field.to_string(), value.clone());
+        }
+    }
+
+    // 从外部数据源获取nickname
+    let nickname = full_data.cloud_summary["results"][0]["nickname"]
+        .as_str()
+        .unwrap_or("External User")
+        .to_string();
+
+    let profile = crate::models::user::UserProfile {
+        object_id: "external".to_string(),
+        nickname,
+    };
+    Ok((full_data.save, profile, external_profile))
 }
 
 /// 获取带难度定数的云存档
@@ -128,22 +174,34 @@ pub async fn get_cloud_saves_with_difficulty(
 ) -> AppResult<HttpResponse> {
     debug!("接收到获取带难度定数的云存档请求");
 
-    let _token = if req.data_source.as_deref() == Some("external") {
-        // 外部数据源：使用占位符token
-        "external_placeholder_token".to_string()
+    let mut data_source = data_source_of(&req);
+    let save = if data_source == "external" {
+        phigros_service.get_save_with_difficulty_and_source(&req).await?
     } else {
-        // 内部数据源：解析真实token
-        resolve_token(&req, &user_service).await?
+        // 内部数据源：使用解析出的 token，绑定的 token 失效时注明对应账号
+        let resolved = resolve_token_with_binding(&req, &user_service).await?;
+        match phigros_service.get_save_with_difficulty(&resolved.token).await {
+            Ok(save) => save,
+            Err(e) => match external_fallback(&req, &e) {
+                Some(fallback) => {
+                    data_source = "external";
+                    phigros_service
+                        .get_save_with_difficulty_and_source(&fallback)
+                        .await?
+                }
+                None => return Err(resolved.annotate_error(e)),
+            },
+        }
     };
 
-    let save = phigros_service.get_save_with_difficulty_and_source(&req).await?;
-
-    Ok(HttpResponse::Ok().json(ApiResponse {
-        code: 200,
-        status: "OK".to_string(),
-        message: None,
-        data: Some(save),
-    }))
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message: None,
+            data: Some(save),
+        }))
 }
 /// 获取原始的云存档元数据 (saveInfo)
 ///
@@ -165,10 +223,13 @@ pub async fn get_cloud_save_info(
 ) -> AppResult<HttpResponse> {
     debug!("接收到获取原始云存档元数据 (saveInfo) 的请求");
 
-    let token = resolve_token(&req, &user_service).await?;
-    check_session_token(&token)?;
+    let resolved = resolve_token_with_binding(&req, &user_service).await?;
+    check_session_token(&resolved.token).map_err(|e| resolved.annotate_error(e))?;
 
-    let save_info = phigros_service.get_cloud_save_info(&token).await?;
+    let save_info = phigros_service
+        .get_cloud_save_info(&resolved.token)
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
 
     Ok(HttpResponse::Ok().json(ApiResponse {
         code: 200,
@@ -177,3 +238,144 @@ pub async fn get_cloud_save_info(
         data: Some(save_info),
     }))
 }
+
+/// 获取解码后的云存档摘要
+///
+/// 只请求一次存档元数据，不下载也不解析存档文件，适合用于判断存档自某个时间点后是否有更新。
+/// 返回校验和、更新时间、文件大小，以及从摘要中解码的游戏版本、RKS、课题等级等信息。
+/// 更新时间同时通过 `X-Save-Updated-At` 响应头返回，与图片接口一致。
+/// 仅支持内部数据源（会话令牌）。
+#[utoipa::path(
+    post,
+    path = "/save/info",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取云存档摘要", body = ApiResponse<SaveSummary>),
+        (status = 400, description = "不支持外部数据源")
+    )
+)]
+#[post("/save/info")]
+pub async fn get_save_summary(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到获取云存档摘要的请求");
+
+    if req.data_source.as_deref() == Some("external") {
+        return Err(AppError::BadRequest(
+            "外部数据源不提供存档元数据，请使用会话令牌".to_string(),
+        ));
+    }
+    let resolved = resolve_token_with_binding(&req, &user_service).await?;
+    check_session_token(&resolved.token).map_err(|e| resolved.annotate_error(e))?;
+
+    let summary = phigros_service
+        .get_save_summary(&resolved.token)
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
+
+    let mut response = ok_with_save_updated_at(Some(&summary.update_at));
+    Ok(response.json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(summary),
+    }))
+}
+
+/// 获取玩家的游戏设置
+///
+/// 返回存档中记录的设备名称、谱面延迟、按键缩放、音量等设置，仅供展示。
+/// 旧版本存档或设置解析失败时，无法读取的字段为 null。
+#[utoipa::path(
+    post,
+    path = "/save/settings",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取游戏设置", body = ApiResponse<PlayerSettings>)
+    )
+)]
+#[post("/save/settings")]
+pub async fn get_save_settings(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到获取游戏设置的请求");
+
+    let (save, data_source) = fetch_save(&req, &phigros_service, &user_service).await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message: None,
+            data: Some(PlayerSettings::from_save(save.settings.as_ref())),
+        }))
+}
+
+/// 获取玩家的游戏进度
+///
+/// 将存档 gameProgress 中的位标志整理为具名字段返回，包括课题等级、Data 数量、
+/// 各类解锁标志与第八章进度，客户端无需了解存档的位布局。
+/// 旧版本存档缺少的字段为 null；原始映射仍可通过 `/get/cloud/saves` 获取。
+#[utoipa::path(
+    post,
+    path = "/save/progress",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取游戏进度", body = ApiResponse<GameProgress>)
+    )
+)]
+#[post("/save/progress")]
+pub async fn get_save_progress(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到获取游戏进度的请求");
+
+    let (save, data_source) = fetch_save(&req, &phigros_service, &user_service).await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message: None,
+            data: Some(GameProgress::from_save(save.game_progress.as_ref())),
+        }))
+}
+
+/// 按数据源获取存档，返回存档与实际使用的数据源；内部 Token 被拒绝时按配置回退到外部数据源
+async fn fetch_save(
+    req: &web::Json<IdentifierRequest>,
+    phigros_service: &PhigrosService,
+    user_service: &web::Data<UserService>,
+) -> AppResult<(GameSave, &'static str)> {
+    if data_source_of(req) == "external" {
+        let save = phigros_service
+            .get_full_save_data_with_source(req)
+            .await?
+            .save;
+        return Ok((save, "external"));
+    }
+
+    let resolved = resolve_token_with_binding(req, user_service).await?;
+    check_session_token(&resolved.token).map_err(|e| resolved.annotate_error(e))?;
+    match phigros_service.get_save(&resolved.token).await {
+        Ok(save) => Ok((save, "internal")),
+        Err(e) => match external_fallback(req, &e) {
+            Some(fallback) => {
+                let save = phigros_service
+                    .get_full_save_data_with_source(&fallback)
+                    .await?
+                    .save;
+                Ok((save, "external"))
+            }
+            None => Err(resolved.annotate_error(e)),
+        },
+    }
+}
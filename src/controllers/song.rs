@@ -1,4 +1,4 @@
-use actix_web::{get, post, web, HttpResponse};
+use actix_web::{get, http::header, post, web, HttpRequest, HttpResponse};
 use log::debug;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -7,15 +7,22 @@ use utoipa::{IntoParams, ToSchema};
 use crate::models::{
     predictions::PredictionResponse,
     save::SongRecord,
-    song::SongInfo,
+    song::{
+        ChartRecordWithPush, SongCandidate, SongCreditField, SongDetail, SongInfo, SongListPage,
+    },
     user::{ApiResponse, IdentifierRequest},
 };
 use crate::services::phigros::PhigrosService;
 use crate::services::song::SongService;
 use crate::services::user::UserService;
-use crate::utils::data_loader::get_predicted_constant;
+use crate::utils::cover_loader::{self, CoverSize};
+use crate::utils::data_loader::{
+    self, get_difficulty_by_id, get_predicted_constant, get_song_name_by_id,
+};
 use crate::utils::error::{AppError, AppResult};
-use crate::utils::token_helper::resolve_token;
+use crate::utils::rks_utils;
+use crate::utils::save_parser::normalize_difficulty;
+use crate::utils::token_helper::resolve_request;
 
 #[derive(Deserialize, Debug, IntoParams)]
 #[allow(dead_code)]
@@ -26,13 +33,15 @@ struct SongSearchQuery {
     difficulty: Option<String>,
 }
 
-use crate::models::song::AmbiguousSongItem;
+use crate::models::song::{AmbiguousSongItem, SongInfoWithConstants};
 use serde::Serialize;
 
 /// 歌曲搜索的响应体，能够根据结果数量呈现不同结构
 #[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(untagged)]
 pub enum SongSearchResponse {
+    /// 当只有一个精确匹配结果且请求了 include_constants 时返回
+    SingleWithConstants(SongInfoWithConstants),
     /// 当只有一个精确匹配结果时返回
     Single(SongInfo),
     /// 当有多个可能的匹配结果时返回
@@ -45,7 +54,10 @@ pub enum SongSearchResponse {
 #[utoipa::path(
     get,
     path = "/song/search",
-    params(SongSearchQuery),
+    params(
+        ("q" = String, Query, description = "歌曲的名称、ID或别名"),
+        ("include_constants" = Option<bool>, Query, description = "是否在结果中附带各难度定数（官方定数缺失时附带预测定数），默认 false")
+    ),
     responses(
         // 更新响应体文档
         (status = 200, description = "成功找到歌曲信息", body = ApiResponse<SongSearchResponse>)
@@ -59,6 +71,14 @@ pub async fn search_song(
     let q = query
         .get("q")
         .ok_or_else(|| crate::utils::error::AppError::BadRequest("缺少查询参数q".to_string()))?;
+    let include_constants = match query.get("include_constants") {
+        Some(v) => v.parse::<bool>().map_err(|_| {
+            AppError::BadRequest(format!(
+                "include_constants 必须是 true 或 false，当前为 '{v}'"
+            ))
+        })?,
+        None => false,
+    };
 
     // 1. 调用你新创建的 search_songs 服务函数
     let results = song_service.search_songs(q)?;
@@ -66,12 +86,19 @@ pub async fn search_song(
     // 2. 根据结果数量决定响应结构
     let response_data = if results.len() == 1 {
         // 只有一个结果，返回完整的 SongInfo
-        SongSearchResponse::Single(results.into_iter().next().unwrap())
+        let info = results.into_iter().next().unwrap();
+        if include_constants {
+            let constants = song_service.get_song_constants(&info.id);
+            SongSearchResponse::SingleWithConstants(SongInfoWithConstants { info, constants })
+        } else {
+            SongSearchResponse::Single(info)
+        }
     } else {
         // 多个结果，转换为 AmbiguousSongItem 列表
         let items = results
             .into_iter()
             .map(|info| AmbiguousSongItem {
+                constants: include_constants.then(|| song_service.get_song_constants(&info.id)),
                 song_id: info.id,
                 song_name: info.song,
                 composer: info.composer,
@@ -112,14 +139,15 @@ pub async fn search_song_record(
     let q = query
         .get("q")
         .ok_or_else(|| crate::utils::error::AppError::BadRequest("缺少查询参数q".to_string()))?;
-    let difficulty = query.get("difficulty").map(|s| s.as_str());
+    let difficulty = query.get("difficulty").map(|s| normalize_difficulty(s));
     debug!("接收到歌曲记录搜索请求: q={q}, difficulty={difficulty:?}");
 
     let song_id = song_service.get_song_id(q)?;
-    let _token = resolve_token(&req, &user_service).await?;
+    let (request, resolved) = resolve_request(&req, &user_service).await?;
     let song_records = phigros_service
-        .get_song_record_with_source(&req, &song_id, difficulty)
-        .await?;
+        .get_song_record_with_source(&request, &song_id, difficulty.as_deref())
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
 
     Ok(HttpResponse::Ok().json(ApiResponse {
         code: 200,
@@ -129,6 +157,100 @@ pub async fn search_song_record(
     }))
 }
 
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SongRecordFullQuery {
+    /// 歌曲的名称、ID或别名
+    q: String,
+    /// 难度 (EZ, HD, IN, AT)
+    difficulty: String,
+}
+
+/// 获取单个谱面的成绩及推分ACC
+///
+/// 返回玩家在该谱面的成绩，以及基于玩家全部成绩计算的推分ACC和达到后该谱面的 RKS，
+/// 与 BN 图片卡片上的推分标注一致。
+#[utoipa::path(
+    post,
+    path = "/song/record/full",
+    params(SongRecordFullQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取谱面成绩及推分ACC", body = ApiResponse<ChartRecordWithPush>),
+        (status = 400, description = "难度无效"),
+        (status = 404, description = "找不到歌曲，或玩家在该难度没有成绩")
+    )
+)]
+#[post("/song/record/full")]
+pub async fn get_song_record_full(
+    query: web::Query<SongRecordFullQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    song_service: web::Data<SongService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到谱面成绩及推分请求: {query:?}");
+
+    let difficulty = normalize_difficulty(&query.difficulty);
+    if !["EZ", "HD", "IN", "AT"].contains(&difficulty.as_str()) {
+        return Err(AppError::BadRequest(format!(
+            "无效的难度: {}，可选值为 EZ, HD, IN, AT",
+            query.difficulty
+        )));
+    }
+    let song_id = song_service.get_song_id(&query.q)?;
+
+    let (request, resolved) = resolve_request(&req, &user_service).await?;
+    let full_data = phigros_service
+        .get_full_save_data_with_source(&request)
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
+
+    let mut record = full_data
+        .save
+        .played_records()?
+        .get(&song_id)
+        .and_then(|records| records.get(&difficulty))
+        .cloned()
+        .ok_or_else(|| {
+            AppError::RecordNotFound(format!("歌曲 {song_id} 的 {difficulty} 难度没有成绩"))
+        })?;
+    if record.difficulty.is_none() {
+        record.difficulty = get_difficulty_by_id(&song_id, &difficulty);
+    }
+    record.song_name = get_song_name_by_id(&song_id);
+    record.predicted_constant = get_predicted_constant(&song_id, &difficulty);
+
+    // 推分ACC需要玩家全部成绩按 RKS 排序后的列表
+    let constant = record.difficulty.unwrap_or(0.0);
+    let acc = record.acc.unwrap_or(0.0);
+    let push_acc = if acc < 100.0 && constant > 0.0 {
+        let mut sorted_records = full_data.rks_result.records;
+        sorted_records.sort_by(rks_utils::compare_records);
+        rks_utils::calculate_target_chart_push_acc(
+            &format!("{song_id}-{difficulty}"),
+            constant,
+            &sorted_records,
+        )
+    } else {
+        None
+    };
+    let push_chart_rks =
+        push_acc.map(|push_acc| rks_utils::calculate_chart_rks(push_acc, constant));
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(ChartRecordWithPush {
+            song_id,
+            difficulty,
+            record,
+            push_acc,
+            push_chart_rks,
+        }),
+    }))
+}
+
 // --- 旧版兼容接口 ---
 
 #[derive(Deserialize, Debug, ToSchema, IntoParams)]
@@ -214,11 +336,12 @@ pub async fn get_song_record(
         ));
     };
 
-    let difficulty = query.difficulty.as_deref();
-    let _token = resolve_token(&req, &user_service).await?;
+    let difficulty = query.difficulty.as_deref().map(normalize_difficulty);
+    let (request, resolved) = resolve_request(&req, &user_service).await?;
     let song_records = phigros_service
-        .get_song_record_with_source(&req, &song_id, difficulty)
-        .await?;
+        .get_song_record_with_source(&request, &song_id, difficulty.as_deref())
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
 
     Ok(HttpResponse::Ok().json(ApiResponse {
         code: 200,
@@ -228,6 +351,182 @@ pub async fn get_song_record(
     }))
 }
 
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SongSearchByQuery {
+    /// 检索字段 (charter, composer, illustrator)
+    #[param(value_type = SongCreditField)]
+    field: String,
+    /// 检索关键词，按子串匹配且不区分大小写
+    q: String,
+}
+
+/// 按谱师/作曲者/插画师搜索歌曲
+///
+/// 返回署名中包含关键词的所有歌曲，谱师会匹配任一难度。
+#[utoipa::path(
+    get,
+    path = "/song/search/by",
+    params(SongSearchByQuery),
+    responses(
+        (status = 200, description = "匹配的歌曲列表（可能为空）", body = ApiResponse<Vec<SongInfo>>),
+        (status = 400, description = "检索字段无效或关键词为空")
+    )
+)]
+#[get("/song/search/by")]
+pub async fn search_song_by_credit(
+    query: web::Query<SongSearchByQuery>,
+    song_service: web::Data<SongService>,
+) -> AppResult<HttpResponse> {
+    let field: SongCreditField = query.field.parse()?;
+    debug!("接收到按署名搜索歌曲请求: field={field:?}, q={}", query.q);
+
+    let songs = song_service.search_songs_by_credit(field, &query.q)?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(songs),
+    }))
+}
+
+/// 搜索候选默认返回的歌曲数
+const DEFAULT_SEARCH_CANDIDATES_LIMIT: usize = 20;
+/// 搜索候选最多返回的歌曲数
+pub const MAX_SEARCH_CANDIDATES_LIMIT: usize = 100;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SongCandidatesQuery {
+    /// 歌曲的名称、ID或别名
+    q: String,
+    /// 最多返回的候选数，默认 20，最大 100
+    limit: Option<usize>,
+}
+
+/// 列出歌曲搜索的全部候选
+///
+/// 与 `/song/search` 使用相同的匹配规则，但无论匹配到几首都返回候选列表，
+/// 按匹配程度排序并附带歌曲ID，便于客户端让用户选择后再以ID精确查询 (如生成单曲图片)。
+#[utoipa::path(
+    get,
+    path = "/song/search/candidates",
+    params(SongCandidatesQuery),
+    responses(
+        (status = 200, description = "候选歌曲列表（可能为空）", body = ApiResponse<Vec<SongCandidate>>),
+        (status = 400, description = "关键词为空或 limit 无效")
+    )
+)]
+#[get("/song/search/candidates")]
+pub async fn search_song_candidates(
+    query: web::Query<SongCandidatesQuery>,
+    song_service: web::Data<SongService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到歌曲候选搜索请求: {query:?}");
+
+    if query.q.trim().is_empty() {
+        return Err(AppError::BadRequest("查询参数 q 不能为空".to_string()));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_CANDIDATES_LIMIT);
+    if limit == 0 || limit > MAX_SEARCH_CANDIDATES_LIMIT {
+        return Err(AppError::BadRequest(format!(
+            "参数limit必须在 1 到 {MAX_SEARCH_CANDIDATES_LIMIT} 之间"
+        )));
+    }
+
+    let mut candidates = song_service.search_song_candidates(&query.q)?;
+    candidates.truncate(limit);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(candidates),
+    }))
+}
+
+/// 歌曲列表默认每页返回的歌曲数
+const DEFAULT_SONG_LIST_LIMIT: usize = 100;
+/// 歌曲列表每页最多返回的歌曲数
+const MAX_SONG_LIST_LIMIT: usize = 500;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SongListQuery {
+    /// 过滤关键词，只返回ID、曲名或别名包含该关键词的歌曲 (不区分大小写)；省略时返回全部歌曲
+    q: Option<String>,
+    /// 每页返回的歌曲数，默认 100，最大 500
+    limit: Option<usize>,
+    /// 跳过的歌曲数，默认 0
+    offset: Option<usize>,
+}
+
+/// 分页列出全部歌曲及各难度定数
+///
+/// 供客户端构建歌曲选择器。每首歌曲附带各难度定数 (官方定数缺失时为预测定数) 与其中的最高定数。
+/// 响应带有基于数据文件加载时间的 `ETag`，数据未更新时携带 `If-None-Match` 请求将返回 304。
+#[utoipa::path(
+    get,
+    path = "/songs",
+    params(SongListQuery),
+    responses(
+        (status = 200, description = "歌曲列表", body = ApiResponse<SongListPage>),
+        (status = 304, description = "歌曲数据未变化 (If-None-Match 与 ETag 一致)"),
+        (status = 400, description = "limit 无效")
+    )
+)]
+#[get("/songs")]
+pub async fn list_songs(
+    req: HttpRequest,
+    query: web::Query<SongListQuery>,
+    song_service: web::Data<SongService>,
+) -> AppResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_SONG_LIST_LIMIT);
+    if limit == 0 || limit > MAX_SONG_LIST_LIMIT {
+        return Err(AppError::BadRequest(format!(
+            "参数limit必须在 1 到 {MAX_SONG_LIST_LIMIT} 之间"
+        )));
+    }
+    let offset = query.offset.unwrap_or(0);
+
+    // 歌曲数据只在启动时加载一次，加载时间即可标识响应内容的版本
+    let etag = format!(
+        "\"songs-{}\"",
+        data_loader::data_loaded_at().timestamp_millis()
+    );
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == etag)
+        });
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish());
+    }
+
+    let songs = song_service.list_songs(query.q.as_deref());
+    let total = songs.len();
+    let songs = songs.into_iter().skip(offset).take(limit).collect();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message: None,
+            data: Some(SongListPage {
+                total,
+                offset,
+                limit,
+                songs,
+            }),
+        }))
+}
+
 /// 搜索歌曲预测常数
 #[utoipa::path(
     get,
@@ -245,17 +544,17 @@ pub async fn search_song_predictions(
     let q = query
         .get("q")
         .ok_or_else(|| crate::utils::error::AppError::BadRequest("缺少查询参数q".to_string()))?;
-    let difficulty = query.get("difficulty").map(|s| s.as_str());
+    let difficulty = query.get("difficulty").map(|s| normalize_difficulty(s));
     debug!("接收到歌曲预测常数搜索请求: q={q}, difficulty={difficulty:?}");
 
     let song_id = song_service.get_song_id(q)?;
 
     let result = match difficulty {
         Some(diff) => {
-            let predicted_constant = get_predicted_constant(&song_id, diff);
+            let predicted_constant = get_predicted_constant(&song_id, &diff);
             vec![PredictionResponse {
                 song_id: song_id.clone(),
-                difficulty: diff.to_string(),
+                difficulty: diff,
                 predicted_constant,
             }]
         }
@@ -281,3 +580,153 @@ pub async fn search_song_predictions(
         data: Some(result),
     }))
 }
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverSizeParam {
+    /// 缩略图 (illLow)
+    #[default]
+    Low,
+    /// 原图 (ill)
+    Full,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SongCoverQuery {
+    /// 曲绘尺寸，low 为缩略图，full 为原图，默认为 low
+    #[serde(default)]
+    size: CoverSizeParam,
+}
+
+/// 获取歌曲曲绘
+///
+/// 根据歌曲ID返回曲绘图片 (PNG/JPG)，本地缺失时会尝试从配置的曲绘源下载。
+#[utoipa::path(
+    get,
+    path = "/song/{id}/cover",
+    params(
+        ("id" = String, Path, description = "歌曲ID"),
+        SongCoverQuery
+    ),
+    responses(
+        (status = 200, description = "成功获取曲绘", content_type = "image/png", body = Vec<u8>),
+        (status = 404, description = "曲绘不存在")
+    )
+)]
+#[get("/song/{id}/cover")]
+pub async fn get_song_cover(
+    path: web::Path<String>,
+    query: web::Query<SongCoverQuery>,
+) -> AppResult<HttpResponse> {
+    let song_id = path.into_inner();
+    debug!("接收到曲绘请求: id={song_id}, size={:?}", query.size);
+
+    if !cover_loader::is_safe_song_id(&song_id) {
+        return Err(AppError::BadRequest(format!("无效的歌曲ID: {song_id}")));
+    }
+
+    let size = match query.size {
+        CoverSizeParam::Low => CoverSize::Low,
+        CoverSizeParam::Full => CoverSize::Full,
+    };
+
+    cover_loader::fetch_missing_cover(&song_id).await;
+    let cover_path = cover_loader::find_cover_path(&song_id, size)
+        .ok_or_else(|| AppError::CoverNotFound(song_id.clone()))?;
+
+    let content_type = if cover_path.extension().is_some_and(|ext| ext == "jpg") {
+        "image/jpeg"
+    } else {
+        "image/png"
+    };
+    let bytes = tokio::fs::read(&cover_path).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Cache-Control", "public, max-age=86400"))
+        .body(bytes))
+}
+
+/// 获取单曲详情
+///
+/// 根据歌曲ID返回歌曲信息，以及每个难度的谱师、官方定数与预测定数。
+#[utoipa::path(
+    get,
+    path = "/song/{id}/detail",
+    params(
+        ("id" = String, Path, description = "歌曲ID")
+    ),
+    responses(
+        (status = 200, description = "成功获取单曲详情", body = ApiResponse<SongDetail>),
+        (status = 404, description = "歌曲不存在")
+    )
+)]
+#[get("/song/{id}/detail")]
+pub async fn get_song_detail(
+    path: web::Path<String>,
+    song_service: web::Data<SongService>,
+) -> AppResult<HttpResponse> {
+    let song_id = path.into_inner();
+    debug!("接收到单曲详情请求: id={song_id}");
+
+    let detail = song_service.get_song_detail(&song_id)?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(detail),
+    }))
+}
+
+/// 获取单曲详情及玩家成绩
+///
+/// 与 `GET /song/{id}/detail` 相同，并在每个难度上附带玩家的成绩（未游玩的难度不返回成绩）。
+#[utoipa::path(
+    post,
+    path = "/song/{id}/detail",
+    params(
+        ("id" = String, Path, description = "歌曲ID")
+    ),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取单曲详情及成绩", body = ApiResponse<SongDetail>),
+        (status = 404, description = "歌曲不存在")
+    )
+)]
+#[post("/song/{id}/detail")]
+pub async fn get_song_detail_with_records(
+    path: web::Path<String>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    song_service: web::Data<SongService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    let song_id = path.into_inner();
+    debug!("接收到单曲详情(含成绩)请求: id={song_id}");
+
+    // 先校验歌曲存在，避免无效ID也去拉取存档
+    let mut detail = song_service.get_song_detail(&song_id)?;
+
+    let (request, resolved) = resolve_request(&req, &user_service).await?;
+    let save = phigros_service
+        .get_save_with_difficulty_and_source(&request)
+        .await
+        .map_err(|e| resolved.annotate_error(e))?;
+    if let Some(records) = save
+        .game_record
+        .as_ref()
+        .and_then(|game_record| game_record.get(&song_id))
+    {
+        for chart in &mut detail.charts {
+            chart.record = records.get(&chart.difficulty).cloned();
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        code: 200,
+        status: "OK".to_string(),
+        message: None,
+        data: Some(detail),
+    }))
+}
@@ -0,0 +1,292 @@
+use actix_web::{post, web, HttpResponse};
+use log::debug;
+use serde::Deserialize;
+use std::collections::HashMap;
+use utoipa::IntoParams;
+
+use crate::models::cloud_save::FullSaveData;
+use crate::models::save::SongRecord;
+use crate::models::stats::{
+    ChallengeRank, ChartCoverageResponse, CoverageStats, DataAmount, DifficultyCoverage,
+    LegacyCoverage,
+};
+use crate::models::user::{ApiResponse, IdentifierRequest};
+use crate::services::phigros::PhigrosService;
+use crate::services::user::UserService;
+use crate::utils::data_loader::DIFFICULTY_MAP;
+use crate::utils::error::AppResult;
+use crate::utils::save_parser::{decode_challenge_rank, decode_data_amount, is_ap};
+use crate::utils::token_helper::{
+    data_source_of, external_fallback, resolve_request, DATA_SOURCE_HEADER,
+};
+
+const DIFFICULTIES: [&str; 4] = ["EZ", "HD", "IN", "AT"];
+
+/// 低于该分数为 F 评级，视为未通过
+const CLEAR_SCORE: f64 = 700_000.0;
+
+/// 存档中 Legacy 难度的键名
+const LEGACY_DIFFICULTY: &str = "Legacy";
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct CoverageQuery {
+    /// 为 true 时额外返回 Legacy 难度的统计 (不计入 RKS 与 overall)
+    #[serde(default)]
+    pub include_legacy: bool,
+}
+
+/// 获取玩家的谱面覆盖统计
+///
+/// 统计已游玩、已通过、FC 与 AP 的谱面数量，以及相对全部谱面的覆盖率，并按难度拆分。
+/// 指定 `include_legacy=true` 时单独返回 Legacy 难度的记录数，不与可计入 RKS 的谱面混合统计。
+#[utoipa::path(
+    post,
+    path = "/stats/coverage",
+    params(CoverageQuery),
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取谱面覆盖统计", body = ApiResponse<ChartCoverageResponse>)
+    )
+)]
+#[post("/stats/coverage")]
+pub async fn get_chart_coverage(
+    query: web::Query<CoverageQuery>,
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到谱面覆盖统计请求");
+
+    let (full_data, data_source) = fetch_full_save(&req, &phigros_service, &user_service).await?;
+
+    let game_record = full_data.save.game_record.as_ref();
+    let mut coverage = compute_coverage(game_record);
+    if query.include_legacy {
+        coverage.legacy = Some(compute_legacy_coverage(game_record));
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message: None,
+            data: Some(coverage),
+        }))
+}
+
+/// 获取玩家的课题模式等级
+///
+/// 从存档的 gameProgress 中解析课题等级（颜色与等级），无需生成图片即可展示课题徽章。
+/// 玩家尚未获得课题等级时 `data` 为 null。
+#[utoipa::path(
+    post,
+    path = "/stats/challenge",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取课题等级", body = ApiResponse<ChallengeRank>)
+    )
+)]
+#[post("/stats/challenge")]
+pub async fn get_challenge_rank(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到课题等级请求");
+
+    let (full_data, data_source) = fetch_full_save(&req, &phigros_service, &user_service).await?;
+
+    let rank = full_data
+        .save
+        .game_progress
+        .as_ref()
+        .and_then(decode_challenge_rank)
+        .map(|(color, level)| ChallengeRank {
+            color: color.to_string(),
+            level,
+        });
+    let message = rank.is_none().then(|| "玩家尚未获得课题等级".to_string());
+
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message,
+            data: rank,
+        }))
+}
+
+/// 获取玩家的 Data 数量
+///
+/// 从存档的 gameProgress 中解码 money 字段，按 KB/MB/GB/TB/PB 分别返回，
+/// 并附带与 BN 图片页脚一致的显示文本，便于机器人自行展示。
+/// 存档中没有 Data 记录时 `data` 为 null。
+#[utoipa::path(
+    post,
+    path = "/stats/data",
+    request_body = IdentifierRequest,
+    responses(
+        (status = 200, description = "成功获取 Data 数量", body = ApiResponse<DataAmount>)
+    )
+)]
+#[post("/stats/data")]
+pub async fn get_data_amount(
+    req: web::Json<IdentifierRequest>,
+    phigros_service: web::Data<PhigrosService>,
+    user_service: web::Data<UserService>,
+) -> AppResult<HttpResponse> {
+    debug!("接收到 Data 数量请求");
+
+    let (full_data, data_source) = fetch_full_save(&req, &phigros_service, &user_service).await?;
+
+    let amount = full_data
+        .save
+        .game_progress
+        .as_ref()
+        .and_then(decode_data_amount);
+    let message = amount.is_none().then(|| "存档中没有 Data 记录".to_string());
+
+    Ok(HttpResponse::Ok()
+        .insert_header((DATA_SOURCE_HEADER, data_source))
+        .json(ApiResponse {
+            code: 200,
+            status: "OK".to_string(),
+            message,
+            data: amount,
+        }))
+}
+
+/// 按请求的数据源获取完整存档，并返回实际使用的数据源
+///
+/// 内部 Token 被拒绝且允许回退时改用外部数据源；否则错误中附带需要重新绑定的账号。
+async fn fetch_full_save(
+    req: &web::Json<IdentifierRequest>,
+    phigros_service: &PhigrosService,
+    user_service: &web::Data<UserService>,
+) -> AppResult<(FullSaveData, &'static str)> {
+    let (request, resolved) = resolve_request(req, user_service).await?;
+    let data_source = data_source_of(&request);
+    match phigros_service
+        .get_full_save_data_with_source(&request)
+        .await
+    {
+        Ok(full_data) => Ok((full_data, data_source)),
+        Err(e) => match external_fallback(&request, &e) {
+            Some(fallback) => {
+                let full_data = phigros_service
+                    .get_full_save_data_with_source(&fallback)
+                    .await?;
+                Ok((full_data, "external"))
+            }
+            None => Err(resolved.annotate_error(e)),
+        },
+    }
+}
+
+/// 根据存档成绩与定数表计算覆盖统计，定数表中不存在的谱面不计入
+fn compute_coverage(
+    game_record: Option<&HashMap<String, HashMap<String, SongRecord>>>,
+) -> ChartCoverageResponse {
+    let mut per_difficulty: [CoverageStats; 4] = Default::default();
+
+    for song in DIFFICULTY_MAP.values() {
+        for (stats, constant) in per_difficulty
+            .iter_mut()
+            .zip([song.ez, song.hd, song.inl, song.at])
+        {
+            if constant.is_some() {
+                stats.total_charts += 1;
+            }
+        }
+    }
+
+    for (song_id, difficulties) in game_record.into_iter().flatten() {
+        let Some(song) = DIFFICULTY_MAP.get(song_id) else {
+            continue;
+        };
+        let constants = [song.ez, song.hd, song.inl, song.at];
+
+        for (diff_name, record) in difficulties {
+            let Some(idx) = DIFFICULTIES.iter().position(|d| d == diff_name) else {
+                continue;
+            };
+            if constants[idx].is_none() {
+                continue;
+            }
+
+            let score = record.score.unwrap_or(0.0);
+            let ap = is_ap(score, record.acc.unwrap_or(0.0));
+            let stats = &mut per_difficulty[idx];
+            stats.played += 1;
+            if score >= CLEAR_SCORE {
+                stats.cleared += 1;
+            }
+            // 解析时 fc 标记不含 AP，这里统一把 AP 计入 FC
+            if ap || record.fc == Some(true) {
+                stats.fc += 1;
+            }
+            if ap {
+                stats.ap += 1;
+            }
+        }
+    }
+
+    let mut overall = CoverageStats::default();
+    for stats in per_difficulty.iter_mut() {
+        stats.coverage = coverage_percent(stats.played, stats.total_charts);
+        overall.total_charts += stats.total_charts;
+        overall.played += stats.played;
+        overall.cleared += stats.cleared;
+        overall.fc += stats.fc;
+        overall.ap += stats.ap;
+    }
+    overall.coverage = coverage_percent(overall.played, overall.total_charts);
+
+    ChartCoverageResponse {
+        overall,
+        by_difficulty: DIFFICULTIES
+            .iter()
+            .zip(per_difficulty)
+            .map(|(difficulty, stats)| DifficultyCoverage {
+                difficulty: difficulty.to_string(),
+                stats,
+            })
+            .collect(),
+        legacy: None,
+    }
+}
+
+/// 统计存档中 Legacy 难度的记录，统计口径与其他难度相同
+fn compute_legacy_coverage(
+    game_record: Option<&HashMap<String, HashMap<String, SongRecord>>>,
+) -> LegacyCoverage {
+    let mut legacy = LegacyCoverage::default();
+    for record in game_record
+        .into_iter()
+        .flatten()
+        .filter_map(|(_, difficulties)| difficulties.get(LEGACY_DIFFICULTY))
+    {
+        let score = record.score.unwrap_or(0.0);
+        let ap = is_ap(score, record.acc.unwrap_or(0.0));
+        legacy.played += 1;
+        if score >= CLEAR_SCORE {
+            legacy.cleared += 1;
+        }
+        if ap || record.fc == Some(true) {
+            legacy.fc += 1;
+        }
+        if ap {
+            legacy.ap += 1;
+        }
+    }
+    legacy
+}
+
+fn coverage_percent(played: u32, total: u32) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (played as f64 / total as f64 * 10000.0).round() / 100.0
+}
@@ -1,15 +1,30 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
 use chrono::{DateTime, Utc};
 use cron::Schedule;
 use serde::Serialize;
 use std::str::FromStr;
+use std::time::Instant;
 use utoipa::ToSchema;
 
 use crate::config::CONFIG;
 
+/// 进程启动时间，用于计算运行时长
+pub struct ServerStartTime(pub Instant);
+
+/// 启动时迁移完成后的数据库 schema 版本
+pub struct SchemaVersion(pub i64);
+
 #[derive(Serialize, ToSchema)]
 pub struct StatusResponse {
     pub status: String,
+    /// 服务版本 (Cargo.toml 中的版本号)
+    pub version: String,
+    /// 构建时的 git 提交，无法获取时为 unknown
+    pub git_commit: String,
+    /// 服务已运行的秒数
+    pub uptime_seconds: u64,
+    /// 数据库 schema 版本 (最新已应用迁移的版本号)
+    pub schema_version: i64,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -27,7 +42,10 @@ pub struct MaintenanceResponse {
     )
 )]
 #[get("/status")]
-pub async fn get_status() -> impl Responder {
+pub async fn get_status(
+    start_time: web::Data<ServerStartTime>,
+    schema_version: web::Data<SchemaVersion>,
+) -> impl Responder {
     // 1. 检查手动维护模式
     if CONFIG.maintenance_mode {
         return HttpResponse::ServiceUnavailable().json(MaintenanceResponse {
@@ -73,5 +91,9 @@ pub async fn get_status() -> impl Responder {
     // 如果所有检查都通过，则服务正常
     HttpResponse::Ok().json(StatusResponse {
         status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("GIT_COMMIT_HASH").to_string(),
+        uptime_seconds: start_time.0.elapsed().as_secs(),
+        schema_version: schema_version.0,
     })
 }
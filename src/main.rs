@@ -20,7 +20,13 @@ use services::phigros::PhigrosService;
 use services::player_archive_service::PlayerArchiveService;
 use services::song::SongService;
 use services::user::UserService;
+use utils::crypto;
 use utils::cover_loader;
+use utils::data_loader;
+use utils::image_renderer;
+use utils::render_pool;
+use utils::request_id;
+use utils::token_helper;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -28,37 +34,138 @@ use utils::cover_loader;
         controllers::auth::generate_qr_code,
         controllers::auth::check_qr_status,
         controllers::binding::bind_user,
+        controllers::binding::validate_bind,
         controllers::binding::unbind_user,
         controllers::binding::list_tokens,
+        controllers::binding::list_tokens_by_query,
+        controllers::binding::validate_token,
         controllers::b30::get_b30,
         controllers::rks::get_rks,
+        controllers::rks::get_rks_history,
+        controllers::rks::get_recent_records,
+        controllers::rks::get_chart_percentile,
+        controllers::rks::export_records_csv,
+        controllers::rks::calculate_rks,
+        controllers::rks::get_tier_push_acc,
+        controllers::rks::plan_target_rks,
+        controllers::rks::filter_records,
+        controllers::rks::get_rks_ranking,
         controllers::rks::get_bn,
+        controllers::rks::get_bn_text,
         controllers::save::get_cloud_saves,
         controllers::save::get_cloud_saves_with_difficulty,
+        controllers::save::get_save_summary,
+        controllers::save::get_save_settings,
+        controllers::save::get_save_progress,
         controllers::song::search_song,
         controllers::song::search_song_record,
+        controllers::song::get_song_record_full,
         controllers::song::search_song_predictions,
+        controllers::song::search_song_by_credit,
+        controllers::song::search_song_candidates,
+        controllers::song::list_songs,
         controllers::song::get_song_info,
         controllers::song::get_song_record,
+        controllers::song::get_song_cover,
+        controllers::song::get_song_detail,
+        controllers::song::get_song_detail_with_records,
+        controllers::stats::get_chart_coverage,
+        controllers::stats::get_challenge_rank,
+        controllers::stats::get_data_amount,
         controllers::image::generate_bn_image,
+        controllers::image::sign_bn_image,
+        controllers::image::get_signed_bn_image,
         controllers::image::generate_song_image,
         controllers::image::get_rks_leaderboard,
+        controllers::image::generate_compare_image,
+        controllers::image::generate_ap3_image,
         controllers::image::get_cache_stats,
-        controllers::status::get_status
+        controllers::status::get_status,
+        controllers::health::health_details,
+        controllers::health::get_metrics,
+        controllers::admin::find_duplicate_archives,
+        controllers::admin::merge_player_archives,
+        controllers::admin::reset_player_archive,
+        controllers::admin::list_save_snapshots,
+        controllers::admin::parse_save_snapshot,
+        controllers::admin::recompute_all_rks,
+        controllers::admin::list_unknown_songs,
+        controllers::admin::list_caches,
+        controllers::admin::evict_cache
     ),
     components(
         schemas(
             models::user::IdentifierRequest,
             models::user::TokenListResponse,
+            models::user::TokenRequest,
+            models::user::TokenValidateResponse,
+            models::user::BindValidateResponse,
             models::user::PlatformBindingInfo,
             models::rks::RksResult,
+            models::rks::RksRecord,
+            models::rks::RksCalcResult,
+            models::rks::TierPushAccEntry,
+            models::rks::RksTargetStep,
+            models::rks::RksTargetPlan,
+            models::rks::RksCalcChartResult,
+            controllers::rks::RksCalcChart,
             models::b30::B30Result,
             models::save::GameSave,
+            models::save::SongRecord,
+            models::save::SaveSummary,
+            models::save::PlayerSettings,
+            models::save::GameProgress,
+            models::save::UnlockFlags,
+            models::save::Chapter8Progress,
             models::song::SongInfo,
+            models::song::SongCreditField,
+            models::song::SongMatchType,
+            models::song::SongCandidate,
+            models::song::SongConstants,
+            models::song::ChartConstant,
+            models::song::SongInfoWithConstants,
+            models::song::SongListItem,
+            models::song::SongListPage,
+            models::song::SongChartDetail,
+            models::song::SongDetail,
+            models::song::ChartRecordWithPush,
+            models::song::UnknownSongReport,
             models::predictions::PredictionResponse,
+            models::stats::CoverageStats,
+            models::stats::DifficultyCoverage,
+            models::stats::LegacyCoverage,
+            models::stats::ChartCoverageResponse,
+            models::stats::ChallengeRank,
+            models::stats::DataAmount,
+            models::player_archive::PlayerBasicInfo,
+            models::player_archive::DuplicateArchiveCandidate,
+            models::player_archive::ArchiveMergeResult,
+            models::player_archive::ArchiveResetResult,
+            models::player_archive::SaveSnapshotInfo,
+            models::player_archive::SaveSnapshotParseResult,
+            models::player_archive::RksRecomputeResult,
+            models::player_archive::RksSnapshot,
+            models::player_archive::RksGain,
+            models::player_archive::RksHistory,
+            models::player_archive::ChartScore,
+            models::player_archive::ChartPercentile,
+            models::player_archive::RKSRankingEntry,
+            controllers::admin::MergeArchivesRequest,
+            controllers::admin::ResetArchiveRequest,
+            controllers::admin::EvictCacheRequest,
+            models::cache::CacheName,
+            models::cache::CacheInfo,
+            models::cache::CacheEvictResult,
+            controllers::image::CompareImageRequest,
+            controllers::image::SignedImageUrl,
+            controllers::rks::BnTextFormat,
             ApiResponse<serde_json::Value>,
             controllers::status::StatusResponse,
-            controllers::status::MaintenanceResponse
+            controllers::status::MaintenanceResponse,
+            controllers::health::MetricsResponse,
+            controllers::health::DbPoolMetrics,
+            controllers::health::HealthResponse,
+            utils::circuit_breaker::BreakerState
         )
     ),
     tags(
@@ -100,9 +207,91 @@ fn setup_systemd_notify(health_url: String) {
 #[cfg(not(target_os = "linux"))]
 fn setup_systemd_notify(_health_url: String) {}
 
+/// Watchdog 健康检查的目标：TCP 绑定时走 HTTP URL，Unix 套接字绑定时直接连接套接字文件
+enum HealthCheckTarget {
+    Http(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl std::fmt::Display for HealthCheckTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthCheckTarget::Http(url) => write!(f, "{url}"),
+            #[cfg(unix)]
+            HealthCheckTarget::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// 根据 CORS 配置构建跨域中间件
+///
+/// 来源列表包含 `*` 时允许任意来源；此时浏览器不接受携带凭据的跨域请求，因此不启用凭据。
+/// 配置了明确的来源时，携带其他 `Origin` 的请求直接返回 400，并按 `CORS_ALLOW_CREDENTIALS`
+/// 决定是否允许凭据；不带 `Origin` 的请求 (如机器人、命令行工具) 不受影响。
+fn build_cors(config: &config::AppConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allow_any_method()
+        .allow_any_header()
+        .expose_headers(vec![
+            request_id::REQUEST_ID_HEADER,
+            controllers::image::SAVE_UPDATED_AT_HEADER,
+            utils::token_helper::DATA_SOURCE_HEADER,
+        ])
+        .max_age(3600);
+
+    if config.cors_allows_any_origin() {
+        cors = cors.allow_any_origin();
+    } else {
+        for origin in &config.cors_allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+        cors = cors.block_on_origin_mismatch(true);
+        if config.cors_allow_credentials {
+            cors = cors.supports_credentials();
+        }
+    }
+    cors
+}
+
+/// 根据监听地址推导本机可访问的健康检查主机：通配地址回落到回环地址，IPv6 地址加方括号
+fn health_check_host(host: &str) -> String {
+    match host
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<std::net::IpAddr>()
+    {
+        Ok(std::net::IpAddr::V4(ip)) if ip.is_unspecified() => "127.0.0.1".to_string(),
+        Ok(std::net::IpAddr::V6(ip)) if ip.is_unspecified() => "[::1]".to_string(),
+        Ok(std::net::IpAddr::V4(ip)) => ip.to_string(),
+        Ok(std::net::IpAddr::V6(ip)) => format!("[{ip}]"),
+        Err(_) => host.to_string(),
+    }
+}
+
+/// 通过 Unix 套接字发送一个最小的 HTTP/1.1 请求，检查 /health 是否返回 200
+#[cfg(target_os = "linux")]
+async fn check_health_over_uds(path: &std::path::Path) -> Result<bool, String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|e| format!("连接 Unix 套接字失败: {e}"))?;
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .map_err(|e| format!("发送健康检查请求失败: {e}"))?;
+    let mut buf = [0u8; 64];
+    let n = stream
+        .read(&mut buf)
+        .await
+        .map_err(|e| format!("读取健康检查响应失败: {e}"))?;
+    let status_line = String::from_utf8_lossy(&buf[..n]);
+    Ok(status_line.starts_with("HTTP/1.1 200") || status_line.starts_with("HTTP/1.0 200"))
+}
+
 // 新增：基于 /health 的 systemd Watchdog 喂狗
 #[cfg(target_os = "linux")]
-fn setup_systemd_watchdog_health(health_url: String) {
+fn setup_systemd_watchdog_health(target: HealthCheckTarget) {
     if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
         log::debug!("发送 systemd READY 通知失败: {e}");
     } else {
@@ -130,14 +319,27 @@ fn setup_systemd_watchdog_health(health_url: String) {
             loop {
                 ticker.tick().await;
                 let check_timeout = period * 7 / 10;
-                let ok = match tokio::time::timeout(check_timeout, client.get(&health_url).send()).await {
-                    Ok(Ok(resp)) => resp.status().is_success(),
+                let result = match &target {
+                    HealthCheckTarget::Http(health_url) => {
+                        tokio::time::timeout(check_timeout, client.get(health_url).send())
+                            .await
+                            .map(|r| {
+                                r.map(|resp| resp.status().is_success())
+                                    .map_err(|e| e.to_string())
+                            })
+                    }
+                    HealthCheckTarget::Unix(path) => {
+                        tokio::time::timeout(check_timeout, check_health_over_uds(path)).await
+                    }
+                };
+                let ok = match result {
+                    Ok(Ok(ok)) => ok,
                     Ok(Err(e)) => {
                         log::warn!("Watchdog 健康检查请求失败: {e}");
                         false
                     }
                     Err(_) => {
-                        log::warn!("Watchdog 健康检查超时: {}", &health_url);
+                        log::warn!("Watchdog 健康检查超时: {target}");
                         false
                     }
                 };
@@ -156,7 +358,7 @@ fn setup_systemd_watchdog_health(health_url: String) {
 }
 
 #[cfg(not(target_os = "linux"))]
-fn setup_systemd_watchdog_health(_health_url: String) {}
+fn setup_systemd_watchdog_health(_target: HealthCheckTarget) {}
 
 #[cfg(target_os = "linux")]
 async fn wait_for_shutdown_signal() {
@@ -186,6 +388,11 @@ async fn wait_for_shutdown_signal() {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // 记录进程启动时间，供 /status 计算运行时长
+    let server_start_time = web::Data::new(controllers::status::ServerStartTime(
+        std::time::Instant::now(),
+    ));
+
     // 初始化配置
     if let Err(e) = crate::utils::config::init_config() {
         eprintln!("启动失败：无法加载配置: {e}");
@@ -194,25 +401,148 @@ async fn main() -> std::io::Result<()> {
     let app_config = crate::utils::config::get_config().unwrap(); // 在此之后可以安全地unwrap
 
     // 初始化日志
-    env_logger::init_from_env(Env::default().default_filter_or(&app_config.log_level));
+    env_logger::Builder::from_env(Env::default().default_filter_or(&app_config.log_level))
+        .format(request_id::format_log_record)
+        .init();
 
     // --- 获取配置 ---
     let database_url = app_config.database_url.clone();
     let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
     let port = app_config.server_port;
+    // 设置 BIND_UDS 后改为监听 Unix 域套接字（适用于反向代理同机部署），否则按 HOST:PORT 监听 TCP
+    let bind_uds = env::var("BIND_UDS")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let bind_uds_mode = match env::var("BIND_UDS_MODE") {
+        Ok(s) if !s.trim().is_empty() => u32::from_str_radix(s.trim(), 8).map_err(|e| {
+            std::io::Error::other(format!("BIND_UDS_MODE 不是合法的八进制权限: {e}"))
+        })?,
+        _ => 0o660,
+    };
 
     log::info!("应用配置:");
     log::info!("- 数据库URL: {database_url}");
-    log::info!("- 服务器地址: {host}:{port}");
+    match &bind_uds {
+        Some(path) => log::info!("- 服务器地址: unix:{path} (权限 {bind_uds_mode:o})"),
+        None => log::info!("- 服务器地址: {host}:{port}"),
+    }
     log::info!("- 日志级别: {}", app_config.log_level);
     log::info!("- 页脚文本: {}", app_config.custom_footer_text);
 
+    // LeanCloud 请求头缺失会导致所有存档请求被拒绝，启动时即校验
+    if let Err(e) = config::CONFIG.validate_leancloud() {
+        log::error!("LeanCloud 配置无效: {e}");
+        return Err(std::io::Error::other(format!("Invalid LeanCloud config: {e}")));
+    }
+
+    if let Err(e) = config::CONFIG.validate_leaderboard() {
+        log::error!("排行榜配置无效: {e}");
+        return Err(std::io::Error::other(format!(
+            "Invalid leaderboard config: {e}"
+        )));
+    }
+
+    // 无效的来源会使每个 worker 创建跨域中间件时 panic，启动时即校验
+    if let Err(e) = config::CONFIG.validate_cors() {
+        log::error!("CORS 配置无效: {e}");
+        return Err(std::io::Error::other(format!("Invalid CORS config: {e}")));
+    }
+
+    // AES 配置错误会导致所有存档解密失败，启动时用已知向量自检并尽早失败
+    if let Err(e) = crypto::self_test() {
+        log::error!("AES 加解密自检失败，请检查 AES_KEY_BASE64 / AES_IV_BASE64 配置: {e}");
+        return Err(std::io::Error::other(format!("AES self-test failed: {e}")));
+    }
+    log::info!("AES 加解密自检通过");
+
+    // 缺少 CJK 字体时图片中的中文会显示为方框且不会报错，启动时即检查
+    match image_renderer::check_cjk_font() {
+        Ok(family) => log::info!("CJK 字体可用: {family}"),
+        Err(e) => {
+            log::error!("字体检查失败: {e}");
+            return Err(std::io::Error::other(format!("No CJK font available: {e}")));
+        }
+    }
+
     if let Err(e) = cover_loader::ensure_covers_available() {
         log::error!("初始化曲绘资源失败: {e:?}");
     } else {
         log::info!("曲绘资源检查/准备完成.");
     }
 
+    // 在后台预热曲绘缓存，不阻塞启动
+    let cover_warm_count = config::CONFIG.cover_warm_count;
+    if cover_warm_count > 0 {
+        tokio::task::spawn_blocking(move || {
+            let start = std::time::Instant::now();
+            let warmed = image_renderer::warm_cover_cache(cover_warm_count);
+            log::info!(
+                "曲绘缓存预热完成: 已载入 {warmed} 张 (目标 {cover_warm_count})，耗时 {:?}",
+                start.elapsed()
+            );
+        });
+    }
+
+    let duplicate_difficulty_ids = data_loader::get_difficulty_duplicate_count();
+    if duplicate_difficulty_ids > 0 {
+        log::warn!("difficulty.csv 中发现 {duplicate_difficulty_ids} 个重复的歌曲ID，请检查数据文件");
+    } else {
+        log::info!("difficulty.csv 未发现重复的歌曲ID");
+    }
+
+    if config::CONFIG.cors_allows_any_origin() {
+        if config::CONFIG.cors_allow_credentials {
+            log::warn!(
+                "CORS_ALLOWED_ORIGINS 允许任意来源时不支持携带凭据，已忽略 CORS_ALLOW_CREDENTIALS"
+            );
+        }
+    } else {
+        log::info!(
+            "CORS 仅允许以下来源: {}",
+            config::CONFIG.cors_allowed_origins.join(", ")
+        );
+    }
+
+    if let Err(e) = config::CONFIG.default_theme.parse::<controllers::image::Theme>() {
+        log::warn!("DEFAULT_THEME 配置无效，将使用 black: {e}");
+    }
+    if let Err(e) = config::CONFIG
+        .default_background_mode
+        .parse::<controllers::image::BackgroundMode>()
+    {
+        log::warn!("DEFAULT_BACKGROUND_MODE 配置无效，将使用 random: {e}");
+    }
+    if let Err(e) = config::CONFIG
+        .render_profile
+        .parse::<utils::image_renderer::RenderProfile>()
+    {
+        log::warn!("RENDER_PROFILE 配置无效，将使用 pretty: {e}");
+    }
+    if let Some(min_acc) = env::var("RKS_MIN_ACC")
+        .ok()
+        .and_then(|v| v.trim().parse::<f64>().ok())
+    {
+        if !(0.0..=100.0).contains(&min_acc) {
+            log::warn!(
+                "RKS_MIN_ACC ({min_acc}) 不在 0~100 之间，将使用默认值 {}",
+                config::DEFAULT_RKS_MIN_ACC
+            );
+        } else if min_acc < config::RKS_MIN_ACC_FLOOR {
+            log::warn!(
+                "RKS_MIN_ACC ({min_acc}) 低于 {}，ACC 低于该值时 RKS 公式会随 ACC 降低而增大，已按 {} 处理",
+                config::RKS_MIN_ACC_FLOOR,
+                config::RKS_MIN_ACC_FLOOR
+            );
+        }
+    }
+    if let Err(e) = config::CONFIG
+        .grade_thresholds
+        .parse::<utils::grade::GradeThresholds>()
+    {
+        log::warn!("GRADE_THRESHOLDS 配置无效，将使用默认分数线: {e}");
+    }
+
     log::info!("正在连接数据库: {database_url}");
 
     let connect_options = SqliteConnectOptions::from_str(&database_url)
@@ -224,10 +554,38 @@ async fn main() -> std::io::Result<()> {
         .journal_mode(SqliteJournalMode::Wal)
         .busy_timeout(std::time::Duration::from_secs(5));
 
+    // 连接池参数，读写并发较高时可通过环境变量调整而无需重新编译
+    let db_max_connections = env::var("DB_MAX_CONNECTIONS")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(10);
+    let db_acquire_timeout_secs = env::var("DB_ACQUIRE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(30);
+    // 为 0 时不回收空闲连接
+    let db_idle_timeout_secs = env::var("DB_IDLE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(600);
+    log::info!(
+        "数据库连接池配置: 最大连接数={db_max_connections}, 获取超时={db_acquire_timeout_secs}s, 空闲超时={}",
+        if db_idle_timeout_secs == 0 {
+            "不回收".to_string()
+        } else {
+            format!("{db_idle_timeout_secs}s")
+        }
+    );
+
     let pool = SqlitePoolOptions::new()
-        .max_connections(10)
-        .acquire_timeout(std::time::Duration::from_secs(30))
-        .idle_timeout(Some(std::time::Duration::from_secs(600)))
+        .max_connections(db_max_connections)
+        .acquire_timeout(std::time::Duration::from_secs(db_acquire_timeout_secs))
+        .idle_timeout(
+            (db_idle_timeout_secs > 0)
+                .then(|| std::time::Duration::from_secs(db_idle_timeout_secs)),
+        )
         .connect_with(connect_options)
         .await
         .map_err(|e| {
@@ -236,46 +594,127 @@ async fn main() -> std::io::Result<()> {
         })?;
 
     log::info!("正在运行数据库迁移...");
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .map_err(|e| {
-            log::error!("数据库迁移失败: {e}");
-            std::io::Error::other(format!("Failed to run database migrations: {e}"))
-        })?;
-    log::info!("数据库迁移完成");
-
+    let schema_version = utils::schema::migrate(&pool).await.map_err(|e| {
+        log::error!("数据库迁移失败: {e}");
+        std::io::Error::other(format!("Failed to run database migrations: {e}"))
+    })?;
+    log::info!("数据库迁移完成，schema 版本: {schema_version}");
+    let schema_version = web::Data::new(controllers::status::SchemaVersion(schema_version));
+
+    // 后台存档写入并发上限，需小于数据库连接池大小，避免写入任务占满连接导致读请求饥饿
+    let max_archive_writes = env::var("ARCHIVE_WRITE_CONCURRENCY")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(4);
+    log::info!("后台存档写入并发限制设置为: {max_archive_writes}");
+    if max_archive_writes as u32 >= db_max_connections {
+        log::warn!(
+            "ARCHIVE_WRITE_CONCURRENCY ({max_archive_writes}) 不小于 DB_MAX_CONNECTIONS ({db_max_connections})，后台写入可能占满连接导致读请求等待"
+        );
+    }
+    let history_max_records = env::var("ARCHIVE_HISTORY_MAX_RECORDS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(10);
     let archive_config = crate::models::player_archive::ArchiveConfig {
         store_push_acc: true,
         best_n_count: 27,
-        history_max_records: 10,
+        history_max_records,
+        max_concurrent_writes: max_archive_writes,
     };
     let player_archive_service = PlayerArchiveService::new(pool.clone(), Some(archive_config));
 
+    // 定期清理超出保留数量的历史成绩，0 表示不定期清理（成绩更新时仍会清理该玩家的历史）
+    let history_prune_interval_secs = env::var("ARCHIVE_HISTORY_PRUNE_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(3600);
+    if history_prune_interval_secs > 0 {
+        log::info!(
+            "历史成绩保留数量: {history_max_records}，每 {history_prune_interval_secs} 秒清理一次"
+        );
+        player_archive_service
+            .spawn_history_pruning(std::time::Duration::from_secs(history_prune_interval_secs));
+    } else {
+        log::info!("历史成绩保留数量: {history_max_records}，已关闭定期清理");
+    }
+
+    if config::CONFIG.store_save_snapshots {
+        log::info!(
+            "已启用原始存档快照存储，每个玩家保留 {} 份，单份上限 {} 字节",
+            config::CONFIG.save_snapshot_retention,
+            config::CONFIG.save_snapshot_max_bytes
+        );
+    }
+
+    // 定期清理过期的解绑验证码，0 表示不定期清理（校验时仍会删除过期的验证码）
+    let verification_code_cleanup_interval_secs =
+        env::var("VERIFICATION_CODE_CLEANUP_INTERVAL_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(600);
+    if verification_code_cleanup_interval_secs > 0 {
+        log::info!("每 {verification_code_cleanup_interval_secs} 秒清理一次过期的解绑验证码");
+        UserService::new(pool.clone()).spawn_verification_code_cleanup(
+            std::time::Duration::from_secs(verification_code_cleanup_interval_secs),
+        );
+    }
+
+    // 优雅停机时等待进行中请求（含图片渲染）完成的时间，超时后强制关闭连接
+    let shutdown_timeout_secs = env::var("SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(5);
+    log::info!("优雅停机超时设置为: {shutdown_timeout_secs} 秒");
+
+    // 从环境变量读取并发限制，如果未设置则使用CPU核心数的一半作为默认值
+    let max_renders = env::var("MAX_CONCURRENT_RENDERS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or_else(|| (num_cpus::get() / 2).max(1)); // 至少为1
+    log::info!("图片渲染并发限制设置为: {max_renders}");
+
+    // 渲染在专用线程池中执行，不占用 Actix 的阻塞线程池；默认线程数为 CPU 核心数。
+    // 已获得渲染许可的任务超过线程数时在线程池中排队，许可在渲染完成后才释放
+    let render_threads = env::var("RENDER_THREADS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(num_cpus::get);
+    let render_threads = render_pool::init(render_threads);
+    log::info!("图片渲染线程池大小: {render_threads}");
+
+    // Actix 每个 worker 的阻塞线程池上限 (web::block)，未配置时使用 Actix 默认值 512 / worker 数
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let blocking_threads = env::var("BLOCKING_THREADS_PER_WORKER")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| (512 / workers).max(1));
+    log::info!("HTTP worker 数: {workers}，每个 worker 的阻塞线程池上限: {blocking_threads}");
+
+    // 所有 worker 共用同一个 ImageService，图片缓存、缓存统计与渲染并发限制在进程内共享，
+    // 管理接口查看或清除缓存时也不会只作用于处理该请求的 worker
+    let image_service = web::Data::new(ImageService::new(max_renders).with_db_pool(pool.clone()));
+    // 歌曲查找映射与查询缓存同样只构建一份，避免每个 worker 各自预热一份缓存
+    let song_service = web::Data::new(SongService::new());
+
     log::info!("正在启动服务器 http://{host}:{port}");
     log::info!("API 文档位于 http://{host}:{port}/swagger-ui/");
 
     // 1. 构建服务器实例，但不立即 .await 它
     let server = HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
-
-        let phigros_service = web::Data::new(PhigrosService::new());
-        let song_service = web::Data::new(SongService::new());
+        let cors = build_cors(&config::CONFIG);
+
+        let phigros_service = if config::CONFIG.store_save_snapshots {
+            PhigrosService::new().with_snapshot_store(player_archive_service.clone())
+        } else {
+            PhigrosService::new()
+        };
+        let phigros_service = web::Data::new(phigros_service);
         let user_service = web::Data::new(UserService::new(pool.clone()));
         let player_archive_service = web::Data::new(player_archive_service.clone());
-        // 从环境变量读取并发限制，如果未设置则使用CPU核心数的一半作为默认值
-        let max_renders = env::var("MAX_CONCURRENT_RENDERS")
-            .ok()
-            .and_then(|s| s.parse::<usize>().ok())
-            .unwrap_or_else(|| (num_cpus::get() / 2).max(1)); // 至少为1
-        log::info!("图片渲染并发限制设置为: {max_renders}");
-
-        let image_service =
-            web::Data::new(ImageService::new(max_renders).with_db_pool(pool.clone()));
 
         let openapi = ApiDoc::openapi();
 
@@ -285,16 +724,63 @@ async fn main() -> std::io::Result<()> {
             .app_data(user_service.clone())
             .app_data(player_archive_service.clone())
             .app_data(image_service.clone())
-            .wrap(middleware::Logger::default())
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(server_start_time.clone())
+            .app_data(schema_version.clone())
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(utils::error::JSON_BODY_LIMIT)
+                    .error_handler(utils::error::json_error_handler),
+            )
+            .wrap(middleware::from_fn(request_id::request_id_middleware))
+            .wrap(
+                middleware::Logger::new(
+                    r#"%a "%{request_line}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{x-request-id}o"#,
+                )
+                .custom_request_replace("request_line", token_helper::redacted_request_line),
+            )
             .wrap(cors)
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
             )
             .configure(routes::configure)
     })
-    .shutdown_timeout(5)
-    .bind((host, port))?
-    .run();
+    .workers(workers)
+    .worker_max_blocking_threads(blocking_threads)
+    .shutdown_timeout(shutdown_timeout_secs);
+
+    let (server, health_target) = match &bind_uds {
+        #[cfg(unix)]
+        Some(path) => {
+            use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+            // 清理上次异常退出遗留的套接字文件；若同名路径不是套接字则拒绝覆盖
+            if let Ok(meta) = std::fs::symlink_metadata(path) {
+                if !meta.file_type().is_socket() {
+                    return Err(std::io::Error::other(format!(
+                        "BIND_UDS 路径已存在且不是套接字文件: {path}"
+                    )));
+                }
+                std::fs::remove_file(path)?;
+            }
+            let server = server.bind_uds(path)?;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(bind_uds_mode))?;
+            log::info!("已绑定 Unix 套接字: {path}");
+            (server, HealthCheckTarget::Unix(path.into()))
+        }
+        #[cfg(not(unix))]
+        Some(_) => {
+            return Err(std::io::Error::other("当前平台不支持 BIND_UDS"));
+        }
+        None => {
+            let health_url = format!("http://{}:{port}/health", health_check_host(&host));
+            (
+                server.bind((host.as_str(), port))?,
+                HealthCheckTarget::Http(health_url),
+            )
+        }
+    };
+    let server = server.run();
 
     // 2. 获取服务器的句柄，以便稍后我们可以向它发送停止命令
     let server_handle = server.handle();
@@ -304,8 +790,7 @@ async fn main() -> std::io::Result<()> {
     let mut server_task = tokio::spawn(server);
 
     // 启动后向 systemd 报告 READY，并基于 /health 定期喂狗（若启用）
-    let health_url = format!("http://127.0.0.1:{}/health", port);
-    setup_systemd_watchdog_health(health_url);
+    setup_systemd_watchdog_health(health_target);
 
     // 4. 等待关闭信号 (Ctrl+C 或 SIGTERM on Linux)，或服务器异常退出
     tokio::select! {
@@ -337,8 +822,20 @@ async fn main() -> std::io::Result<()> {
 
     // 5. 使用句柄来优雅地停止服务器
     //    stop(true) 表示 graceful shutdown
+    let active_renders = services::image_service::active_renders();
+    if active_renders > 0 {
+        log::info!(
+            "停机时仍有 {active_renders} 个图片渲染任务进行中，最多等待 {shutdown_timeout_secs} 秒"
+        );
+    }
     server_handle.stop(true).await;
 
+    // 超时后连接已被关闭，仍未结束的渲染结果无法送达，进程退出时随之终止
+    let abandoned_renders = services::image_service::active_renders();
+    if abandoned_renders > 0 {
+        log::warn!("停机超时，{abandoned_renders} 个图片渲染任务未能完成");
+    }
+
     log::info!("服务器已停止。");
 
     // 等待服务器任务真正结束，避免悬挂
@@ -346,6 +843,13 @@ async fn main() -> std::io::Result<()> {
         log::error!("等待服务器任务结束失败: {e}");
     }
 
+    // 移除 Unix 套接字文件，避免下次启动时残留
+    if let Some(path) = &bind_uds {
+        if let Err(e) = std::fs::remove_file(path) {
+            log::debug!("移除 Unix 套接字文件失败: {e}");
+        }
+    }
+
     // 可以在这里添加任何其他的清理代码
     // pool.close().await;
     // log::info!("数据库连接池已关闭。");
@@ -353,3 +857,70 @@ async fn main() -> std::io::Result<()> {
     log::info!("程序退出。");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, HttpResponse};
+
+    fn cors_app_config(origins: &[&str]) -> config::AppConfig {
+        config::AppConfig {
+            cors_allowed_origins: origins.iter().map(|s| s.to_string()).collect(),
+            cors_allow_credentials: false,
+            ..config::AppConfig::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn cors_rejects_origins_outside_the_allow_list() {
+        let config = cors_app_config(&["https://allowed.example"]);
+        let app = test::init_service(
+            App::new()
+                .wrap(build_cors(&config))
+                .route("/ping", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/ping")
+            .insert_header((header::ORIGIN, "https://allowed.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://allowed.example"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/ping")
+            .insert_header((header::ORIGIN, "https://evil.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // 不带 Origin 的请求 (命令行工具等) 不受影响
+        let req = test::TestRequest::get().uri("/ping").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn cors_wildcard_allows_any_origin() {
+        let config = cors_app_config(&["*"]);
+        let app = test::init_service(
+            App::new()
+                .wrap(build_cors(&config))
+                .route("/ping", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/ping")
+            .insert_header((header::ORIGIN, "https://any.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
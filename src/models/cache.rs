@@ -0,0 +1,62 @@
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use std::hash::Hash;
+use utoipa::ToSchema;
+
+/// 可通过管理接口查看与清除的缓存
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheName {
+    /// BN 图片 (PNG/JPEG/WebP)
+    Bn,
+    /// BN 图片 (SVG)
+    BnSvg,
+    /// 单曲图片
+    Song,
+    /// 排行榜图片
+    Leaderboard,
+    /// 双人对比图片
+    Compare,
+    /// AP Top 3 图片
+    Ap3,
+    /// 玩家存档
+    PlayerArchive,
+    /// 歌曲查询结果
+    SongSearch,
+}
+
+/// 单个缓存的占用情况
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CacheInfo {
+    /// 缓存名称
+    pub name: CacheName,
+    /// 条目数 (近似值)
+    pub entry_count: u64,
+    /// 加权大小：图片缓存为字节数，其他缓存与条目数相同
+    pub weighted_size: u64,
+}
+
+impl CacheInfo {
+    /// 读取缓存的当前占用，先执行待处理的维护任务使计数尽量准确
+    pub async fn of<K, V>(name: CacheName, cache: &Cache<K, V>) -> Self
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        cache.run_pending_tasks().await;
+        Self {
+            name,
+            entry_count: cache.entry_count(),
+            weighted_size: cache.weighted_size(),
+        }
+    }
+}
+
+/// 清除缓存的结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CacheEvictResult {
+    /// 缓存名称
+    pub cache: CacheName,
+    /// 被清除的条目数
+    pub evicted: u64,
+}
@@ -1,10 +1,12 @@
 pub mod b30;
+pub mod cache;
 pub mod image_counter;
 pub mod player_archive;
 pub mod predictions;
 pub mod rks;
 pub mod save;
 pub mod song;
+pub mod stats;
 pub mod user;
 
 pub mod cloud_save;
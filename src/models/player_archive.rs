@@ -2,6 +2,9 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::models::save::GameSave;
 
 /// 玩家存档结构体
 /// 包含玩家的所有游戏数据和成绩记录
@@ -27,7 +30,7 @@ pub struct PlayerArchive {
 
 /// 玩家基本信息结构体
 /// 包含玩家的基本信息
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct PlayerBasicInfo {
     /// 玩家ID
     pub player_id: String,
@@ -36,12 +39,13 @@ pub struct PlayerBasicInfo {
     /// 玩家RKS值
     pub rks: f64,
     /// 更新时间
+    #[schema(value_type = String, format = DateTime)]
     pub update_time: DateTime<Utc>,
 }
 
 /// 谱面成绩结构体
 /// 包含单个谱面的具体成绩信息
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow, ToSchema)]
 pub struct ChartScore {
     /// 歌曲ID
     pub song_id: String,
@@ -62,6 +66,7 @@ pub struct ChartScore {
     /// 是否Phi (ACC >= 100%)
     pub is_phi: bool,
     /// 游玩时间
+    #[schema(value_type = String, format = DateTime)]
     pub play_time: DateTime<Utc>,
 }
 
@@ -121,6 +126,8 @@ pub struct ArchiveConfig {
     pub best_n_count: u32,
     /// 历史记录最大数量
     pub history_max_records: usize,
+    /// 同时执行的后台存档写入任务上限（含RKS/推分ACC重新计算），其余任务排队等待
+    pub max_concurrent_writes: usize,
 }
 
 impl Default for ArchiveConfig {
@@ -129,13 +136,14 @@ impl Default for ArchiveConfig {
             store_push_acc: true,
             best_n_count: 27,
             history_max_records: 10,
+            max_concurrent_writes: 4,
         }
     }
 }
 
 /// RKS排行榜条目结构体
 /// 包含排行榜中单个玩家的信息
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct RKSRankingEntry {
     /// 玩家ID
     pub player_id: String,
@@ -150,5 +158,167 @@ pub struct RKSRankingEntry {
     /// AP总数（可选）
     pub ap_count: Option<usize>,
     /// 更新时间
+    #[schema(value_type = String, format = DateTime)]
     pub update_time: DateTime<Utc>,
 }
+
+/// 疑似重复的玩家存档
+/// 同名且 RKS 接近的两条存档，通常是同一玩家分别以内部ID与外部ID (`platform:id`) 写入
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DuplicateArchiveCandidate {
+    /// 玩家名称
+    pub player_name: String,
+    /// 两条存档的 RKS 差值（绝对值）
+    pub rks_diff: f64,
+    /// 建议保留的存档ID（优先内部ID，其次最近更新）
+    pub suggested_primary_id: String,
+    /// 疑似重复的存档
+    pub archives: Vec<PlayerBasicInfo>,
+}
+
+/// 存档合并结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ArchiveMergeResult {
+    /// 保留的存档ID
+    pub primary_id: String,
+    /// 被合并并删除的存档ID
+    pub alias_id: String,
+    /// 迁移到主存档的成绩记录数
+    pub moved_scores: u64,
+    /// 因与主存档记录完全重复而丢弃的成绩记录数
+    pub dropped_scores: u64,
+    /// 因同一谱面存在更好成绩而转为历史的当前成绩数
+    pub retired_scores: u64,
+    /// 合并后重新计算的主存档 RKS
+    pub rks: f64,
+}
+
+/// 存档重置结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ArchiveResetResult {
+    /// 被重置的存档ID
+    pub player_id: String,
+    /// 删除的成绩记录数（含历史记录）
+    pub deleted_scores: u64,
+    /// 删除的推分ACC记录数
+    pub deleted_push_acc: u64,
+    /// 删除的存档数（0 或 1）
+    pub deleted_archives: u64,
+    /// 删除的 RKS 快照数
+    pub deleted_snapshots: u64,
+    /// 删除的原始存档快照数
+    pub deleted_save_snapshots: u64,
+}
+
+/// 按当前定数全量重算 RKS 的结果
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct RksRecomputeResult {
+    /// 处理的玩家存档数
+    pub players_total: u64,
+    /// 有谱面被更新、并重新计算了总 RKS 的玩家数
+    pub players_updated: u64,
+    /// 定数或 RKS 发生变化而被更新的当前成绩数
+    pub charts_updated: u64,
+    /// 定数表中找不到定数、保留原值的当前成绩数
+    pub charts_without_constant: u64,
+}
+
+/// 每日 RKS 快照
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct RksSnapshot {
+    /// 快照日期 (UTC, YYYY-MM-DD)
+    pub snapshot_date: String,
+    /// 当日最后一次更新后的 RKS
+    pub rks: f64,
+}
+
+/// 一段时间内的 RKS 变化
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RksGain {
+    /// 统计的天数
+    pub days: u32,
+    /// 作为基准的快照日期；快照不足该天数时为最早的快照
+    pub baseline_date: String,
+    /// 基准快照的 RKS
+    pub baseline_rks: f64,
+    /// 当前 RKS 相对基准的变化量
+    pub gained: f64,
+}
+
+/// 玩家 RKS 历史与增长速度
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RksHistory {
+    /// 玩家ID
+    pub player_id: String,
+    /// 存档中的当前 RKS
+    pub current_rks: f64,
+    /// 最近 7 天的 RKS 变化，没有任何快照时为空
+    pub gained_7d: Option<RksGain>,
+    /// 最近 30 天的 RKS 变化，没有任何快照时为空
+    pub gained_30d: Option<RksGain>,
+    /// 最近 30 天的每日快照，按日期升序
+    pub snapshots: Vec<RksSnapshot>,
+}
+
+/// 后台存档写入队列状态
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ArchiveWriteQueueStats {
+    /// 允许同时执行的写入任务数
+    pub max_concurrent: usize,
+    /// 正在执行的写入任务数
+    pub running: usize,
+    /// 排队等待的写入任务数
+    pub queued: usize,
+    /// 启动以来因同一玩家已有写入任务而被合并 (未单独写入) 的更新次数
+    pub coalesced: u64,
+}
+
+/// 玩家在单个谱面上相对已存档玩家的排位
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChartPercentile {
+    /// 玩家ID
+    pub player_id: String,
+    /// 歌曲ID
+    pub song_id: String,
+    /// 歌曲名称
+    pub song_name: String,
+    /// 难度级别 (EZ, HD, IN, AT)
+    pub difficulty: String,
+    /// 玩家当前的 ACC
+    pub acc: f64,
+    /// 玩家当前的分数
+    pub score: Option<f64>,
+    /// 该谱面有成绩的其他已存档玩家数
+    pub total_players: i64,
+    /// ACC 低于该玩家的其他玩家数
+    pub players_below: i64,
+    /// 按 ACC 计的名次，与该玩家 ACC 相同的玩家并列
+    pub rank: i64,
+    /// 超过的玩家百分比 (players_below / total_players)，没有其他玩家的成绩时为空
+    pub percentile: Option<f64>,
+}
+
+/// 原始云存档快照的元数据 (不含存档内容)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct SaveSnapshotInfo {
+    /// 快照ID
+    pub id: i64,
+    /// 玩家ID
+    pub player_id: String,
+    /// 存档文件的 MD5 校验和
+    pub checksum: String,
+    /// 存档文件大小 (字节)
+    pub size: i64,
+    /// 获取到该存档的时间
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Utc>,
+}
+
+/// 重新解析原始存档快照的结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SaveSnapshotParseResult {
+    /// 快照的元数据
+    pub snapshot: SaveSnapshotInfo,
+    /// 按当前定数表解析出的存档
+    pub save: GameSave,
+}
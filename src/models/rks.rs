@@ -97,10 +97,91 @@ impl RksResult {
     /// * `records` - RKS记录列表
     pub fn new(records: Vec<RksRecord>) -> Self {
         let mut all_records = records.clone();
-        all_records.sort_by(|a, b| b.rks.partial_cmp(&a.rks).unwrap_or(Ordering::Equal));
+        all_records.sort_by(crate::utils::rks_utils::compare_records);
 
         Self {
             records: all_records,
         }
     }
 }
+
+/// RKS 计算器中单个谱面的计算结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RksCalcChartResult {
+    /// 准确率
+    pub acc: f64,
+    /// 谱面定数
+    pub constant: f64,
+    /// 单曲 RKS；ACC 低于计入下限时为 0
+    pub rks: f64,
+    /// 是否计入 Best 27
+    pub in_best_27: bool,
+    /// 是否计入 AP Top 3
+    pub in_ap_top_3: bool,
+}
+
+/// RKS 计算器的计算结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RksCalcResult {
+    /// 各谱面的计算结果，顺序与请求一致
+    pub charts: Vec<RksCalcChartResult>,
+    /// 按 Best 27 + AP Top 3 计算的玩家 RKS (未取整)
+    pub rks: f64,
+    /// 四舍五入到两位小数的玩家 RKS
+    pub rks_rounded: f64,
+}
+
+/// 某一难度下单个谱面的推分目标
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TierPushAccEntry {
+    pub song_id: String,
+    pub song_name: String,
+    pub difficulty: String,
+    /// 谱面定数
+    pub difficulty_value: f64,
+    /// 当前 ACC
+    pub acc: f64,
+    /// 当前单曲 RKS
+    pub rks: f64,
+    /// 使总 RKS (四舍五入后) 增加 0.01 所需的 ACC；ACC 100% 仍无法达到时为 100
+    pub push_acc: f64,
+    /// 达到推分 ACC 时单曲 RKS 的提升
+    pub rks_gain: f64,
+}
+
+/// 目标 RKS 规划中的单个谱面
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RksTargetStep {
+    pub song_id: String,
+    pub song_name: String,
+    pub difficulty: String,
+    /// 谱面定数
+    pub difficulty_value: f64,
+    /// 当前 ACC
+    pub acc: f64,
+    /// 需要达到的 ACC
+    pub target_acc: f64,
+    /// 当前单曲 RKS
+    pub rks: f64,
+    /// 达到目标 ACC 时的单曲 RKS
+    pub target_chart_rks: f64,
+}
+
+/// 目标 RKS 的推分规划
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RksTargetPlan {
+    /// 当前 RKS (四舍五入到两位小数)
+    pub current_rks: f64,
+    /// 目标 RKS
+    pub target_rks: f64,
+    /// 当前所有谱面全部 AP 时的 RKS，即现有谱面所能达到的上限
+    pub max_rks: f64,
+    /// 按规划完成后的 RKS (四舍五入到两位小数)
+    pub planned_rks: f64,
+    /// 现有谱面能否达到目标，即目标不超过 `max_rks`
+    pub reachable: bool,
+    /// 规划达到推分次数上限而停止；此时 `steps` 只是已规划的部分，`planned_rks` 仍低于目标
+    pub iteration_limit_reached: bool,
+    /// 需要提升的谱面，按规划中首次提升的先后排列
+    pub steps: Vec<RksTargetStep>,
+}
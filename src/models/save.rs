@@ -2,6 +2,10 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use utoipa::ToSchema;
 
+use crate::models::stats::{ChallengeRank, DataAmount};
+use crate::utils::error::{AppError, AppResult};
+use crate::utils::save_parser::{decode_challenge_rank, decode_data_amount};
+
 /// 游戏存档结构体
 /// 包含游戏的各种数据，如密钥、进度、记录、设置和用户信息
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -11,6 +15,7 @@ pub struct GameSave {
     /// 游戏进度数据
     pub game_progress: Option<HashMap<String, serde_json::Value>>,
     /// 游戏记录数据，包含每首歌的成绩记录
+    /// 外层键为歌曲ID，内层键为难度 (EZ, HD, IN, AT)
     pub game_record: Option<HashMap<String, HashMap<String, SongRecord>>>,
     /// 游戏设置数据
     pub settings: Option<HashMap<String, serde_json::Value>>,
@@ -18,6 +23,16 @@ pub struct GameSave {
     pub user: Option<HashMap<String, serde_json::Value>>,
 }
 
+impl GameSave {
+    /// 存档中的成绩记录；新账号的存档没有 gameRecord 或其中没有任何成绩时返回 `EmptySave`
+    pub fn played_records(&self) -> AppResult<&HashMap<String, HashMap<String, SongRecord>>> {
+        self.game_record
+            .as_ref()
+            .filter(|records| !records.is_empty())
+            .ok_or(AppError::EmptySave)
+    }
+}
+
 /// 歌曲记录结构体
 /// 包含单首歌曲在特定难度下的成绩信息
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
@@ -28,12 +43,18 @@ pub struct SongRecord {
     pub acc: Option<f64>,
     /// 是否Full Combo
     pub fc: Option<bool>,
-    /// 难度定数（可选）
+    /// 难度定数（可选，仅在带难度定数的接口中返回）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub difficulty: Option<f64>,
-    /// RKS值（可选）
+    /// RKS值（可选，仅在带难度定数的接口中返回）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rks: Option<f64>,
+    /// 歌曲名称（可选，仅在单曲成绩查询接口中返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub song_name: Option<String>,
+    /// 预测定数（可选，仅在单曲成绩查询接口中返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub predicted_constant: Option<f32>,
 }
 
 /// 存档摘要结构体
@@ -46,6 +67,8 @@ pub struct SaveSummary {
     pub update_at: String,
     /// 存档下载URL
     pub url: String,
+    /// 存档文件大小（字节），元数据中缺失时为空
+    pub file_size: Option<u64>,
     /// 存档版本
     pub save_version: u8,
     /// 挑战模式等级
@@ -56,12 +79,221 @@ pub struct SaveSummary {
     pub game_version: u8,
     /// 头像
     pub avatar: String,
-    /// EZ难度统计数据 [游玩次数, 完成次数, Phi次数]
+    /// EZ难度统计数据 [通过数, FC数, Phi数]
     pub ez: [u16; 3],
-    /// HD难度统计数据 [游玩次数, 完成次数, Phi次数]
+    /// HD难度统计数据 [通过数, FC数, Phi数]
     pub hd: [u16; 3],
-    /// IN难度统计数据 [游玩次数, 完成次数, Phi次数]
+    /// IN难度统计数据 [通过数, FC数, Phi数]
     pub inl: [u16; 3],
-    /// AT难度统计数据 [游玩次数, 完成次数, Phi次数]
+    /// AT难度统计数据 [通过数, FC数, Phi数]
     pub at: [u16; 3],
 }
+
+/// 玩家的游戏设置
+///
+/// 由存档中的 settings 文件解析得到。旧版本存档或解析失败时缺少的字段为 null。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct PlayerSettings {
+    /// 设备名称
+    pub device_name: Option<String>,
+    /// 谱面延迟 (秒)
+    pub sound_offset: Option<f64>,
+    /// 按键缩放
+    pub note_scale: Option<f64>,
+    /// 背景亮度
+    pub bright: Option<f64>,
+    /// 音乐音量
+    pub music_volume: Option<f64>,
+    /// 界面音效音量
+    pub effect_volume: Option<f64>,
+    /// 打击音效音量
+    pub hit_sound_volume: Option<f64>,
+    /// 是否开启多押辅助
+    pub chord_support: Option<bool>,
+    /// 是否开启 FC/AP 指示器
+    pub fc_ap_indicator: Option<bool>,
+    /// 是否开启打击音效
+    pub enable_hit_sound: Option<bool>,
+    /// 是否开启低分辨率模式
+    pub low_resolution_mode: Option<bool>,
+}
+
+impl PlayerSettings {
+    /// 从存档解析出的 settings 映射中读取设置，缺失或类型不符的字段为 None
+    pub fn from_save(settings: Option<&HashMap<String, serde_json::Value>>) -> Self {
+        let Some(map) = settings else {
+            return Self::default();
+        };
+        let number = |key: &str| map.get(key).and_then(|v| v.as_f64());
+        let flag = |key: &str| map.get(key).and_then(|v| v.as_bool());
+
+        Self {
+            device_name: map
+                .get("deviceName")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            sound_offset: number("soundOffset"),
+            note_scale: number("noteScale"),
+            bright: number("bright"),
+            music_volume: number("musicVolume"),
+            effect_volume: number("effectVolume"),
+            hit_sound_volume: number("hitSoundVolume"),
+            chord_support: flag("chordSupport"),
+            fc_ap_indicator: flag("fcAPIndicator"),
+            enable_hit_sound: flag("enableHitSound"),
+            low_resolution_mode: flag("lowResolutionMode"),
+        }
+    }
+}
+
+/// 玩家的游戏进度
+///
+/// 由存档中的 gameProgress 解析得到，将原始映射中的位标志整理为具名字段。
+/// 旧版本存档缺少的字段为 null；原始映射仍可通过 `/get/cloud/saves` 的 `game_progress` 获取。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct GameProgress {
+    /// 是否首次运行
+    pub is_first_run: Option<bool>,
+    /// 是否已完成旧版章节
+    pub legacy_chapter_finished: Option<bool>,
+    /// 是否已显示收藏品提示
+    pub already_show_collection_tip: Option<bool>,
+    /// 是否已显示自动解锁 IN 难度的提示
+    pub already_show_auto_unlock_in_tip: Option<bool>,
+    /// 已完成的章节
+    pub completed: Option<String>,
+    /// 曲目更新信息
+    pub song_update_info: Option<u64>,
+    /// 课题模式等级，尚未获得时为 null
+    pub challenge_rank: Option<ChallengeRank>,
+    /// Data 数量
+    pub data: Option<DataAmount>,
+    /// 各类解锁标志
+    pub unlock_flags: UnlockFlags,
+    /// 第八章进度
+    pub chapter8: Chapter8Progress,
+}
+
+/// 存档中的解锁标志，每个元素对应一个位，顺序与存档一致
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UnlockFlags {
+    /// Spasmodic 的解锁进度 (4 位)
+    pub spasmodic: Option<Vec<bool>>,
+    /// Igallta 的解锁进度 (4 位)
+    pub igallta: Option<Vec<bool>>,
+    /// Rrhar'il 的解锁进度 (4 位)
+    pub rrharil: Option<Vec<bool>>,
+    /// 曲目记录钥匙 (8 位)
+    pub song_record_key: Option<Vec<bool>>,
+    /// 曲目记录钥匙 Takumi (3 位)，仅新版本存档包含
+    pub song_record_key_takumi: Option<Vec<bool>>,
+    /// 随机版本的解锁状态 (6 位)
+    pub random_version: Option<Vec<bool>>,
+}
+
+/// 第八章的解锁与通关进度
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Chapter8Progress {
+    /// 是否已开始解锁
+    pub unlock_begin: Option<bool>,
+    /// 是否进入解锁第二阶段
+    pub unlock_second_phase: Option<bool>,
+    /// 是否已通关
+    pub passed: Option<bool>,
+    /// 各曲目的解锁状态 (6 位)
+    pub song_unlocked: Option<Vec<bool>>,
+}
+
+impl GameProgress {
+    /// 从存档解析出的 gameProgress 映射中读取进度，缺失或类型不符的字段为 None
+    pub fn from_save(game_progress: Option<&HashMap<String, serde_json::Value>>) -> Self {
+        let Some(map) = game_progress else {
+            return Self::default();
+        };
+        let flag = |key: &str| map.get(key).and_then(|v| v.as_bool());
+        let bits = |key: &str| {
+            map.get(key)?
+                .as_array()?
+                .iter()
+                .map(|v| v.as_bool())
+                .collect::<Option<Vec<bool>>>()
+        };
+
+        Self {
+            is_first_run: flag("isFirstRun"),
+            legacy_chapter_finished: flag("legacyChapterFinished"),
+            already_show_collection_tip: flag("alreadyShowCollectionTip"),
+            already_show_auto_unlock_in_tip: flag("alreadyShowAutoUnlockINTip"),
+            completed: map
+                .get("completed")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            song_update_info: map.get("songUpdateInfo").and_then(|v| v.as_u64()),
+            challenge_rank: decode_challenge_rank(map).map(|(color, level)| ChallengeRank {
+                color: color.to_string(),
+                level,
+            }),
+            data: decode_data_amount(map),
+            unlock_flags: UnlockFlags {
+                spasmodic: bits("unlockFlagOfSpasmodic"),
+                igallta: bits("unlockFlagOfIgallta"),
+                rrharil: bits("unlockFlagOfRrharil"),
+                song_record_key: bits("flagOfSongRecordKey"),
+                song_record_key_takumi: bits("flagOfSongRecordKeyTakumi"),
+                random_version: bits("randomVersionUnlocked"),
+            },
+            chapter8: Chapter8Progress {
+                unlock_begin: flag("chapter8UnlockBegin"),
+                unlock_second_phase: flag("chapter8UnlockSecondPhase"),
+                passed: flag("chapter8Passed"),
+                song_unlocked: bits("chapter8SongUnlocked"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    fn save_with(game_record: Option<HashMap<String, HashMap<String, SongRecord>>>) -> GameSave {
+        GameSave {
+            game_key: None,
+            game_progress: None,
+            game_record,
+            settings: None,
+            user: None,
+        }
+    }
+
+    #[test]
+    fn save_without_scores_is_empty_save() {
+        assert!(matches!(
+            save_with(None).played_records(),
+            Err(AppError::EmptySave)
+        ));
+        assert!(matches!(
+            save_with(Some(HashMap::new())).played_records(),
+            Err(AppError::EmptySave)
+        ));
+
+        let played = HashMap::from([(
+            "Glaciaxion.SunsetRay.0".to_string(),
+            HashMap::from([("EZ".to_string(), SongRecord::default())]),
+        )]);
+        assert_eq!(save_with(Some(played)).played_records().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn empty_save_responds_with_404() {
+        let response = AppError::EmptySave.error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = response.into_body().try_into_bytes().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "empty_save");
+    }
+}
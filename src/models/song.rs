@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::str::FromStr;
 use utoipa::ToSchema;
 
+use crate::models::save::SongRecord;
+use crate::utils::error::AppError;
+
 /// 歌曲信息结构体
 /// 包含歌曲的基本信息，如ID、名称、作曲者、插画师和各难度的谱师
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -28,6 +32,40 @@ pub struct SongInfo {
     pub at_charter: Option<String>,
 }
 
+/// 歌曲署名字段
+/// 用于按谱师、作曲者或插画师检索歌曲
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum SongCreditField {
+    /// 谱师（任一难度）
+    Charter,
+    /// 作曲者
+    Composer,
+    /// 插画师
+    Illustrator,
+}
+
+impl SongCreditField {
+    /// 所有可选值，用于错误提示
+    pub const VALUES: [&'static str; 3] = ["charter", "composer", "illustrator"];
+}
+
+impl FromStr for SongCreditField {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "charter" => Ok(SongCreditField::Charter),
+            "composer" => Ok(SongCreditField::Composer),
+            "illustrator" => Ok(SongCreditField::Illustrator),
+            _ => Err(AppError::BadRequest(format!(
+                "无效的检索字段 '{s}'，可选值: {}",
+                SongCreditField::VALUES.join(", ")
+            ))),
+        }
+    }
+}
+
 /// 歌曲难度信息结构体
 /// 包含歌曲各难度的定数信息
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -77,6 +115,40 @@ pub struct SongQuery {
 pub type NicknameMap = HashMap<String, Vec<String>>;
 
 
+/// 搜索候选歌曲的匹配方式，按匹配程度从高到低排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SongMatchType {
+    /// 歌曲ID完全一致
+    Id,
+    /// 曲名完全一致 (不区分大小写)
+    Name,
+    /// 别名完全一致 (不区分大小写)
+    Nickname,
+    /// 曲名以查询开头
+    NamePrefix,
+    /// 曲名包含查询
+    NamePartial,
+    /// 别名包含查询
+    NicknamePartial,
+}
+
+/// 歌曲搜索的候选项
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SongCandidate {
+    /// 歌曲ID，可直接用于其他接口的精确查询
+    pub song_id: String,
+    /// 歌曲名称
+    pub song_name: String,
+    /// 作曲家
+    pub composer: String,
+    /// 匹配方式
+    pub match_type: SongMatchType,
+    /// 通过别名匹配时命中的别名 (小写)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_nickname: Option<String>,
+}
+
 /// 用于在多结果搜索中返回的单个歌曲项，包含关键信息
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AmbiguousSongItem {
@@ -86,4 +158,134 @@ pub struct AmbiguousSongItem {
     pub song_name: String,
     /// 作曲家
     pub composer: String,
+    /// 各难度定数，仅在请求 include_constants=true 时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constants: Option<SongConstants>,
+}
+
+/// 单个难度的定数
+/// 官方定数缺失时以预测定数补充
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChartConstant {
+    /// 官方定数（可选）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constant: Option<f64>,
+    /// 预测定数，仅在官方定数缺失时提供（可选）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub predicted_constant: Option<f32>,
+}
+
+/// 歌曲各难度的定数，没有该难度的谱面时省略
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SongConstants {
+    #[serde(rename = "EZ", skip_serializing_if = "Option::is_none")]
+    pub ez: Option<ChartConstant>,
+    #[serde(rename = "HD", skip_serializing_if = "Option::is_none")]
+    pub hd: Option<ChartConstant>,
+    #[serde(rename = "IN", skip_serializing_if = "Option::is_none")]
+    pub inl: Option<ChartConstant>,
+    #[serde(rename = "AT", skip_serializing_if = "Option::is_none")]
+    pub at: Option<ChartConstant>,
+}
+
+impl SongConstants {
+    /// 各难度中最高的定数，官方定数缺失的难度按预测定数计算
+    pub fn max_constant(&self) -> Option<f64> {
+        [&self.ez, &self.hd, &self.inl, &self.at]
+            .into_iter()
+            .flatten()
+            .filter_map(|chart| chart.constant.or(chart.predicted_constant.map(f64::from)))
+            .reduce(f64::max)
+    }
+}
+
+/// 歌曲列表中的单首歌曲
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SongListItem {
+    /// 歌曲ID
+    pub song_id: String,
+    /// 歌曲名称
+    pub song_name: String,
+    /// 各难度中最高的定数（官方定数缺失时按预测定数），没有任何定数时为 null
+    pub max_constant: Option<f64>,
+    /// 各难度定数
+    pub constants: SongConstants,
+}
+
+/// 分页的歌曲列表
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SongListPage {
+    /// 符合条件的歌曲总数
+    pub total: usize,
+    /// 本页起始位置
+    pub offset: usize,
+    /// 本页最多返回的歌曲数
+    pub limit: usize,
+    /// 本页歌曲，按 info.csv 中的顺序排列
+    pub songs: Vec<SongListItem>,
+}
+
+/// 附带各难度定数的歌曲信息
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SongInfoWithConstants {
+    #[serde(flatten)]
+    pub info: SongInfo,
+    /// 各难度定数
+    pub constants: SongConstants,
+}
+
+/// 单曲详情中的单个难度
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SongChartDetail {
+    /// 难度 (EZ, HD, IN, AT)
+    pub difficulty: String,
+    /// 谱师（可选）
+    pub charter: Option<String>,
+    /// 官方定数（可选）
+    pub constant: Option<f64>,
+    /// 预测定数（可选）
+    pub predicted_constant: Option<f32>,
+    /// 玩家在该难度的成绩，仅在携带用户身份请求且已游玩时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<SongRecord>,
+}
+
+/// 单曲详情：歌曲信息与各难度的定数、成绩
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SongDetail {
+    #[serde(flatten)]
+    pub info: SongInfo,
+    /// 各难度详情，按 EZ、HD、IN、AT 排列，没有该难度的谱面时省略
+    pub charts: Vec<SongChartDetail>,
+}
+
+/// 单个谱面的成绩及推分信息
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChartRecordWithPush {
+    /// 歌曲ID
+    pub song_id: String,
+    /// 难度 (EZ, HD, IN, AT)
+    pub difficulty: String,
+    /// 玩家成绩，附带定数、单曲 RKS、曲名与预测定数
+    pub record: SongRecord,
+    /// 使总 RKS (四舍五入后) 增加 0.01 所需的 ACC；已 AP 或定数无效时为 null，无法推分时为 100
+    pub push_acc: Option<f64>,
+    /// 达到推分 ACC 后该谱面的 RKS
+    pub push_chart_rks: Option<f64>,
+}
+
+/// 存档中出现但数据文件未收录的歌曲
+/// 用于提醒维护者补全 info.csv / difficulty.csv
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UnknownSongReport {
+    /// 歌曲ID
+    pub song_id: String,
+    /// info.csv 中缺失（成绩卡片上无法显示曲名）
+    pub missing_info: bool,
+    /// difficulty.csv 中缺失（无法计算 RKS）
+    pub missing_difficulty: bool,
+    /// 自服务启动以来在解析的存档中出现的次数
+    pub seen_in_saves: u64,
+    /// 存档数据库中拥有该曲当前成绩的玩家数
+    pub archived_players: i64,
 }
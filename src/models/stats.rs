@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 谱面覆盖统计
+/// 统计口径：只计入定数表中存在的谱面，FC 数包含 AP
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CoverageStats {
+    /// 可游玩的谱面总数（来自定数表）
+    pub total_charts: u32,
+    /// 有成绩记录的谱面数
+    pub played: u32,
+    /// 已通过的谱面数（分数不低于 700000）
+    pub cleared: u32,
+    /// Full Combo 的谱面数（含 AP）
+    pub fc: u32,
+    /// All Perfect 的谱面数
+    pub ap: u32,
+    /// 覆盖率，played / total_charts 的百分比，保留两位小数
+    pub coverage: f64,
+}
+
+/// 单个难度的谱面覆盖统计
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DifficultyCoverage {
+    /// 难度 (EZ, HD, IN, AT)
+    pub difficulty: String,
+    /// 该难度的统计数据
+    pub stats: CoverageStats,
+}
+
+/// 谱面覆盖统计响应
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChartCoverageResponse {
+    /// 全部难度汇总
+    pub overall: CoverageStats,
+    /// 按难度拆分的统计，顺序为 EZ, HD, IN, AT
+    pub by_difficulty: Vec<DifficultyCoverage>,
+    /// Legacy 难度的统计，不计入 RKS，也不计入 overall；仅在 include_legacy=true 时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub legacy: Option<LegacyCoverage>,
+}
+
+/// Legacy 难度的成绩统计 (不计入 RKS)
+/// Legacy 谱面已不可游玩且定数表中没有对应条目，因此只统计存档中的记录数，不计算覆盖率
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct LegacyCoverage {
+    /// 有成绩记录的 Legacy 谱面数
+    pub played: u32,
+    /// 已通过的 Legacy 谱面数（分数不低于 700000）
+    pub cleared: u32,
+    /// Full Combo 的 Legacy 谱面数（含 AP）
+    pub fc: u32,
+    /// All Perfect 的 Legacy 谱面数
+    pub ap: u32,
+}
+
+/// 课题模式等级
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChallengeRank {
+    /// 颜色: Green, Blue, Red, Gold, Rainbow
+    pub color: String,
+    /// 等级，即课题三首曲目的难度之和
+    pub level: u32,
+}
+
+/// 存档中的 Data 数量，由 gameProgress 的 money 字段解码，各单位分别计数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DataAmount {
+    pub kb: u64,
+    pub mb: u64,
+    pub gb: u64,
+    pub tb: u64,
+    pub pb: u64,
+    /// 与 BN 图片一致的显示文本，从高位到低位列出非零单位，如 "2 GB, 512 MB"；全部为 0 时为 null
+    pub display: Option<String>,
+}
@@ -102,6 +102,31 @@ pub struct TokenListResponse {
     pub bindings: Vec<PlatformBindingInfo>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenValidateResponse {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+}
+
+/// 绑定预检结果，预检不会修改任何绑定
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BindValidateResponse {
+    /// 是否可以绑定：Token 格式正确且仍然有效
+    pub can_bind: bool,
+    /// Token 格式是否正确
+    pub token_format_valid: bool,
+    /// Token 是否仍然有效，格式错误时不检查，为 null
+    pub token_valid: Option<bool>,
+    /// Token 对应的玩家昵称
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    /// 该平台账号是否已有绑定
+    pub platform_id_bound: bool,
+    /// 已有绑定是否使用同一个 Token；此时绑定不会产生任何变化
+    pub bound_to_same_token: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PlatformBindingInfo {
     pub platform: String,
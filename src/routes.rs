@@ -16,33 +16,77 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         )
         // Binding
         .service(controllers::binding::bind_user) // POST /bind
+        .service(controllers::binding::validate_bind) // POST /bind/validate
         .service(controllers::binding::unbind_user) // POST /unbind
         .service(controllers::binding::list_tokens) // POST /token/list
+        .service(controllers::binding::list_tokens_by_query) // GET /token/list
+        .service(controllers::binding::validate_token) // POST /token/validate
         // Saves
         .service(controllers::save::get_cloud_saves) // POST /get/cloud/saves
         .service(controllers::save::get_cloud_saves_with_difficulty) // POST /get/cloud/saves/with_difficulty
         .service(controllers::save::get_cloud_save_info) // GET /get/cloud/saveInfo
+        .service(controllers::save::get_save_summary) // POST /save/info
+        .service(controllers::save::get_save_settings) // POST /save/settings
+        .service(controllers::save::get_save_progress) // POST /save/progress
         // RKS / BN
         .service(controllers::rks::get_rks) // POST /rks
+        .service(controllers::rks::get_rks_history) // POST /rks/history
+        .service(controllers::rks::get_recent_records) // POST /rks/recent
+        .service(controllers::rks::get_chart_percentile) // POST /rks/percentile
+        .service(controllers::rks::export_records_csv) // POST /rks/export.csv
+        .service(controllers::rks::calculate_rks) // POST /rks/calc
+        .service(controllers::rks::get_tier_push_acc) // POST /rks/push-acc/tier
+        .service(controllers::rks::plan_target_rks) // POST /rks/target
+        .service(controllers::rks::filter_records) // POST /rks/filter
+        .service(controllers::rks::get_rks_ranking) // GET /leaderboard/rks
         .service(controllers::b30::get_b30) // POST /b30
         .service(controllers::rks::get_bn) // POST /bn/{n}
+        .service(controllers::rks::get_bn_text) // POST /bn/{n}/text
         // Song Search (Recommended)
         .service(controllers::song::search_song) // GET /song/search
         .service(controllers::song::search_song_record) // POST /song/search/record
+        .service(controllers::song::get_song_record_full) // POST /song/record/full
         .service(controllers::song::search_song_predictions) // GET /song/search/predictions
+        .service(controllers::song::search_song_by_credit) // GET /song/search/by
+        .service(controllers::song::search_song_candidates) // GET /song/search/candidates
+        .service(controllers::song::list_songs) // GET /songs
+        .service(controllers::song::get_song_cover) // GET /song/{id}/cover
+        .service(controllers::song::get_song_detail) // GET /song/{id}/detail
+        .service(controllers::song::get_song_detail_with_records) // POST /song/{id}/detail
         // Song Search (Old/Compatible)
         .service(controllers::song::get_song_info) // GET /song/info
         .service(controllers::song::get_song_record) // POST /song/record
+        // Compare
+        .service(controllers::image::generate_compare_image) // POST /compare/image
+        // Stats
+        .service(controllers::stats::get_chart_coverage) // POST /stats/coverage
+        .service(controllers::stats::get_challenge_rank) // POST /stats/challenge
+        .service(controllers::stats::get_data_amount) // POST /stats/data
         .service(controllers::status::get_status) // GET /status
-        .service(controllers::health::health_check); // GET /health
+        .service(controllers::health::health_check) // GET /health
+        .service(controllers::health::health_details) // GET /health/details
+        .service(controllers::health::get_metrics) // GET /metrics
+        // Admin
+        .service(controllers::admin::find_duplicate_archives) // GET /admin/archives/duplicates
+        .service(controllers::admin::merge_player_archives) // POST /admin/archives/merge
+        .service(controllers::admin::reset_player_archive) // POST /admin/archives/reset
+        .service(controllers::admin::list_save_snapshots) // GET /admin/archives/{player_id}/snapshots
+        .service(controllers::admin::parse_save_snapshot) // POST /admin/snapshots/{id}/parse
+        .service(controllers::admin::recompute_all_rks) // POST /admin/recompute-all
+        .service(controllers::admin::list_unknown_songs) // GET /admin/songs/unknown
+        .service(controllers::admin::list_caches) // GET /admin/cache
+        .service(controllers::admin::evict_cache); // POST /admin/cache/evict
 
     // 图片路由
     cfg.service(
         web::scope("/image")
             .service(controllers::image::generate_bn_image_from_user_data) // POST /image/bn/user-generated
             .service(controllers::image::generate_bn_image) // POST /image/bn/{n}
+            .service(controllers::image::sign_bn_image) // POST /image/bn/{n}/sign
+            .service(controllers::image::get_signed_bn_image) // GET /image/bn/{n}/signed
             .service(controllers::image::generate_song_image)
             .service(controllers::image::get_rks_leaderboard)
+            .service(controllers::image::generate_ap3_image) // POST /image/ap3
             .service(controllers::image::get_cache_stats)
             .service(controllers::image::get_image_stats)
             .service(controllers::image::get_image_stats_by_type),
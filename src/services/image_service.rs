@@ -1,3 +1,5 @@
+use crate::controllers::image::BnRenderOptions;
+use crate::models::cache::{CacheInfo, CacheName};
 use crate::models::cloud_save::FullSaveData;
 use crate::models::rks::RksRecord;
 use crate::models::user::IdentifierRequest;
@@ -9,42 +11,140 @@ use crate::utils::cover_loader;
 use crate::utils::error::AppError;
 use crate::utils::image_renderer::LeaderboardRenderData;
 use crate::utils::image_renderer::{self, PlayerStats, SongDifficultyScore, SongRenderData};
+use crate::utils::image_renderer::{Ap3RenderData, ComparePlayerData, CompareRenderData};
+use crate::utils::render_pool;
+use crate::utils::request_id;
 use crate::utils::rks_utils;
-use crate::utils::token_helper::resolve_token;
+use crate::utils::save_parser;
+use crate::utils::text::truncate_chars;
+use crate::utils::token_helper::{self, resolve_token, resolve_token_with_binding};
 use actix_web::web;
 use chrono::{DateTime, Utc};
 use moka::future::Cache;
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::{self, sync::Semaphore};
+use tokio::{
+    self,
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
 
 // 添加用于缓存统计的原子计数器
-use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering as AtomicOrdering};
+
+// 正在执行的渲染任务数，供停机时统计
+static ACTIVE_RENDERS: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前正在执行（已获得渲染许可）的图片渲染任务数
+pub fn active_renders() -> usize {
+    ACTIVE_RENDERS.load(AtomicOrdering::Relaxed)
+}
+
+/// 渲染许可：持有期间计入正在执行的渲染任务，随渲染线程结束释放
+struct RenderPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for RenderPermit {
+    fn drop(&mut self) {
+        ACTIVE_RENDERS.fetch_sub(1, AtomicOrdering::Relaxed);
+    }
+}
+
+/// BN 图片缓存键: (N, 存档校验和, 渲染参数)
+type BnCacheKey = (u32, String, BnRenderOptions);
+
+/// 单曲图片缓存键: (歌曲ID, 存档校验和, 编码参数, 是否只渲染已游玩难度, ACC 小数位数, 是否随机背景, 背景模式, 标签语言)
+type SongCacheKey = (
+    String,
+    String,
+    crate::controllers::image::SongImageEncoding,
+    bool,
+    usize,
+    bool,
+    crate::controllers::image::BackgroundMode,
+    crate::controllers::image::ImageLang,
+);
+
+/// 排行榜图片缓存键: (条目数量, 最近一次存档更新时间, 主题, 输出格式, 缩放倍数, 标签语言)
+type LeaderboardCacheKey = (
+    usize,
+    String,
+    crate::controllers::image::Theme,
+    crate::controllers::image::ImageFormat,
+    crate::controllers::image::ImageScale,
+    crate::controllers::image::ImageLang,
+);
+
+/// 双人对比图缓存键: (左侧存档校验和, 右侧存档校验和, 主题, 输出格式, 缩放倍数, 标签语言)
+type CompareCacheKey = (
+    String,
+    String,
+    crate::controllers::image::Theme,
+    crate::controllers::image::ImageFormat,
+    crate::controllers::image::ImageScale,
+    crate::controllers::image::ImageLang,
+);
+
+/// AP Top 3 图缓存键: (存档校验和, 主题, 输出格式, 缩放倍数, 是否允许不足 3 个 AP, 标签语言)
+type Ap3CacheKey = (
+    String,
+    crate::controllers::image::Theme,
+    crate::controllers::image::ImageFormat,
+    crate::controllers::image::ImageScale,
+    bool,
+    crate::controllers::image::ImageLang,
+);
+
+/// 与存档关联的图片渲染结果
+///
+/// `save_updated_at` 为存档的更新时间，供接口写入响应头，客户端可据此判断缓存的图片是否过期。
+/// 外部数据源不请求存档摘要，该字段为空。
+pub struct RenderedImage<T> {
+    pub data: T,
+    pub save_updated_at: Option<String>,
+    /// 实际使用的数据源，内部 Token 失效并回退到外部数据源时为 `external`
+    pub data_source: &'static str,
+}
+
+/// 双人对比图中每位玩家展示的谱面数量
+const COMPARE_TOP_CHARTS: usize = 5;
+
+/// 单曲图片标注与平均 ACC 的差距时，该难度至少需要的其他玩家成绩数
+const SONG_AVERAGE_MIN_PLAYERS: i64 = 5;
+
+/// 玩家尚无任何成绩时，BN 占位图上显示的提示
+const NO_RECORDS_MESSAGE: &str = "暂无成绩记录，游玩任意谱面并同步云存档后即可生成 Best N";
 
 // --- ImageService 结构体定义 ---
 
 pub struct ImageService {
-    bn_image_cache: Cache<(u32, String, crate::controllers::image::Theme), Arc<Vec<u8>>>,
-    song_image_cache: Cache<(String, String), Arc<Vec<u8>>>,
-    leaderboard_image_cache: Cache<(usize, String), Arc<Vec<u8>>>,
+    bn_image_cache: Cache<BnCacheKey, Arc<Vec<u8>>>,
+    // BN 的 SVG 输出与 PNG 分开缓存，SVG 内嵌了 base64 曲绘，体积与 PNG 不同
+    bn_svg_cache: Cache<BnCacheKey, Arc<String>>,
+    song_image_cache: Cache<SongCacheKey, Arc<Vec<u8>>>,
+    leaderboard_image_cache: Cache<LeaderboardCacheKey, Arc<Vec<u8>>>,
+    compare_image_cache: Cache<CompareCacheKey, Arc<Vec<u8>>>,
+    ap3_image_cache: Cache<Ap3CacheKey, Arc<Vec<u8>>>,
     // 添加缓存统计计数器
     bn_cache_hits: AtomicU64,
     bn_cache_misses: AtomicU64,
+    bn_svg_cache_hits: AtomicU64,
+    bn_svg_cache_misses: AtomicU64,
     song_cache_hits: AtomicU64,
     song_cache_misses: AtomicU64,
     leaderboard_cache_hits: AtomicU64,
     leaderboard_cache_misses: AtomicU64,
+    compare_cache_hits: AtomicU64,
+    compare_cache_misses: AtomicU64,
+    ap3_cache_hits: AtomicU64,
+    ap3_cache_misses: AtomicU64,
     // 数据库连接池，用于持久化计数器
     db_pool: Option<sqlx::SqlitePool>,
     // 推分ACC预计算缓存
     push_acc_cache: Cache<(String, String), f64>,
     // 新增：用于限制并发图片渲染任务的信号量
     render_semaphore: Arc<Semaphore>,
-    // 新增：用于限制后台存档更新的并发
-    bg_task_semaphore: Arc<Semaphore>,
 }
 
 impl ImageService {
@@ -52,31 +152,37 @@ impl ImageService {
         &self,
         n: u32,
         identifier: web::Json<IdentifierRequest>,
-        theme: &crate::controllers::image::Theme,
+        options: &BnRenderOptions,
         phigros_service: web::Data<PhigrosService>,
         user_service: web::Data<UserService>,
         player_archive_service: web::Data<PlayerArchiveService>,
-    ) -> Result<String, AppError> {
+    ) -> Result<RenderedImage<String>, AppError> {
         let start_time = std::time::Instant::now();
         log::info!("BN SVG 生成 - 开始处理请求: {:?}", start_time.elapsed());
 
-        let save_checksum = if identifier.data_source.as_deref() == Some("external") {
-            if let Some(api_user_id) = &identifier.api_user_id {
-                format!("external_api_{}", api_user_id)
-            } else {
-                format!(
-                    "external_{}_{}",
-                    identifier.platform.as_deref().unwrap_or(""),
-                    identifier.platform_id.as_deref().unwrap_or("")
-                )
-            }
-        } else {
-            let token = resolve_token(&identifier, &user_service).await?;
-            phigros_service
-                .get_save_checksum(&token)
-                .await
-                .unwrap_or_else(|_| "unknown".to_string())
-        };
+        let (identifier, save_checksum, save_updated_at) =
+            Self::resolve_save_version(identifier, &phigros_service, &user_service).await?;
+        let data_source = token_helper::data_source_of(&identifier);
+        // 背景按存档校验和固定选取，存档不变时图片保持一致
+        let background_seed =
+            (!options.random_background).then(|| image_renderer::background_seed(&save_checksum));
+        let filter = options.filter;
+
+        let cache_key = (n, save_checksum.clone(), options.clone());
+        if let Some(cached) = self.bn_svg_cache.get(&cache_key).await {
+            self.bn_svg_cache_hits.fetch_add(1, AtomicOrdering::Relaxed);
+            log::debug!(
+                "BN SVG缓存命中: n={}, checksum={}",
+                n,
+                truncate_chars(&save_checksum, 8)
+            );
+            log::info!("BN SVG 生成 - 总耗时(缓存命中): {:?}", start_time.elapsed());
+            return Ok(RenderedImage {
+                data: cached.to_string(),
+                save_updated_at,
+                data_source,
+            });
+        }
 
         let (full_data_res, profile_res) = if identifier.data_source.as_deref() == Some("external") {
             tokio::join!(
@@ -91,19 +197,20 @@ impl ImageService {
                 }
             )
         } else {
-            let token = resolve_token(&identifier, &user_service).await?;
-            tokio::join!(
-                phigros_service.get_full_save_data(&token),
-                phigros_service.get_profile(&token)
+            let resolved = resolve_token_with_binding(&identifier, &user_service).await?;
+            let (full_data_res, profile_res) = tokio::join!(
+                phigros_service.get_full_save_data(&resolved.token),
+                phigros_service.get_profile(&resolved.token)
+            );
+            (
+                full_data_res.map_err(|e| resolved.annotate_error(e)),
+                profile_res,
             )
         };
 
         let full_data = full_data_res?;
-        if full_data.rks_result.records.is_empty() {
-            return Err(AppError::Other(format!(
-                "用户无成绩记录，无法生成 B{n} SVG"
-            )));
-        }
+        Self::ensure_bn_records(&full_data.rks_result.records, filter, options.allow_empty)?;
+        let n = Self::clamp_bn_to_records(n, &full_data.rks_result.records, filter);
 
         // 优先从 Profile 提取 objectId 与昵称，避免存档内缺失导致 player_id=unknown
         let (profile_object_id, player_nickname) = match profile_res {
@@ -137,49 +244,33 @@ impl ImageService {
         };
 
         // 后台异步更新玩家存档（不阻塞SVG生成）
-        let player_name_for_archive = player_name.clone();
-        let mut fc_map = std::collections::HashMap::new();
-        if let Some(game_record_map) = &full_data.save.game_record {
-            for (song_id, difficulties) in game_record_map {
-                for (diff_name, record) in difficulties {
-                    if record.fc == Some(true) {
-                        fc_map.insert(format!("{}-{}", song_id, diff_name), true);
-                    }
-                }
-            }
-        }
-        let archive_service_clone = player_archive_service.clone();
-        let player_id_clone = player_id.clone();
-        let player_name_clone = player_name_for_archive.clone();
-        let scores_clone = full_data.rks_result.records.clone();
-        let is_external = identifier.data_source.as_deref() == Some("external");
-        let bg_sem = self.bg_task_semaphore.clone();
-        tokio::spawn(async move {
-            let _permit = bg_sem.acquire_owned().await.ok();
-            if let Err(e) = archive_service_clone
-                .update_player_scores_from_rks_records(
-                    &player_id_clone,
-                    &player_name_clone,
-                    &scores_clone,
-                    &fc_map,
-                    is_external,
-                )
-                .await
-            {
-                log::error!(
-                    "后台更新玩家 {player_name_clone} ({player_id_clone}) 存档失败: {e}"
-                );
-            }
-        });
+        player_archive_service.spawn_update_from_save(
+            player_id.clone(),
+            player_name.clone(),
+            &full_data.save,
+            full_data.rks_result.records.clone(),
+            identifier.data_source.as_deref() == Some("external"),
+        );
 
         // 排序并截取Top N
         let mut sorted_scores = full_data.rks_result.records.clone();
-        sorted_scores.sort_by(|a, b| b.rks.partial_cmp(&a.rks).unwrap_or(std::cmp::Ordering::Equal));
+        sorted_scores.sort_by(rks_utils::compare_records);
         let (exact_rks, _) = crate::utils::rks_utils::calculate_player_rks_details(&sorted_scores);
-        let top_n_scores: Vec<crate::models::rks::RksRecord> =
-            sorted_scores.iter().take(n as usize).cloned().collect();
-
-        // 预计算推分ACC
+        let top_n_scores = Self::select_bn_scores(&sorted_scores, n, filter);
+        let play_dates = Self::load_play_dates(
+            &player_archive_service,
+            &player_id,
+            &sorted_scores,
+            options.show_play_date,
+        )
+        .await;
+
+        // 预计算推分ACC；限定定数范围时筛选后的成绩不代表玩家的真实 RKS，改为参照全部成绩
+        let push_reference = if filter.has_constant_range() {
+            &sorted_scores
+        } else {
+            &top_n_scores
+        };
         let mut push_acc_map: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
         for score in top_n_scores
             .iter()
@@ -189,7 +280,7 @@ impl ImageService {
             if let Some(push_acc) = crate::utils::rks_utils::calculate_target_chart_push_acc(
                 &key,
                 score.difficulty_value,
-                &top_n_scores,
+                push_reference,
             ) {
                 push_acc_map.insert(key, push_acc);
             }
@@ -223,6 +314,9 @@ impl ImageService {
             best_27_avg,
             real_rks: Some(exact_rks),
             player_name: Some(player_name),
+            region: full_data.cloud_summary["results"][0]["region"]
+                .as_str()
+                .map(str::to_string),
             update_time: {
                 let date_str = full_data.cloud_summary["results"][0]["updatedAt"]
                     .as_str()
@@ -233,46 +327,62 @@ impl ImageService {
             },
             n,
             ap_top_3_scores,
-            challenge_rank: if let Some(game_progress) = &full_data.save.game_progress {
-                let rank = game_progress
-                    .get("challengeModeRank")
-                    .and_then(|v| v.as_i64())
-                    .and_then(|rank_num| {
-                        if rank_num <= 0 { return None; }
-                        let rank_str = rank_num.to_string();
-                        if rank_str.is_empty() { return None; }
-                        let (color_char, level_str) = rank_str.split_at(1);
-                        let color = match color_char {
-                            "1" => "Green", "2" => "Blue", "3" => "Red",
-                            "4" => "Gold", "5" => "Rainbow", _ => return None,
-                        };
-                        Some((color.to_string(), level_str.to_string()))
-                    });
-                rank
-            } else { None },
-            data_string: if let Some(game_progress) = &full_data.save.game_progress {
-                game_progress.get("money").and_then(|v| v.as_array()).and_then(|arr| {
-                    let units = ["KB", "MB", "GB", "TB"];
-                    let mut parts: Vec<String> = arr.iter().zip(units.iter()).filter_map(|(val, &unit)| {
-                        val.as_u64().and_then(|u_val| if u_val > 0 { Some(format!("{u_val} {unit}")) } else { None })
-                    }).collect();
-                    parts.reverse();
-                    if parts.is_empty() { None } else { Some(format!("Data: {}", parts.join(", "))) }
-                })
-            } else { None },
+            challenge_rank: full_data
+                .save
+                .game_progress
+                .as_ref()
+                .and_then(save_parser::decode_challenge_rank)
+                .map(|(color, level)| (color.to_string(), level.to_string())),
+            data_string: full_data
+                .save
+                .game_progress
+                .as_ref()
+                .and_then(save_parser::decode_data_amount)
+                .and_then(|amount| amount.display),
             custom_footer_text: Some(app_config.custom_footer_text),
             is_user_generated: false,
+            ap_only: filter.mode == crate::controllers::image::BnMode::Ap,
+            constant_range: filter.constant_range_label(),
+            empty_message: sorted_scores
+                .is_empty()
+                .then(|| NO_RECORDS_MESSAGE.to_string()),
+            acc_decimals: options.acc_decimals,
+            background_seed,
+            background_mode: options.background_mode,
+            player_background: full_data
+                .save
+                .user
+                .as_ref()
+                .and_then(save_parser::player_background_song_id),
+            play_dates,
+            lang: options.lang,
+            layout: options.layout,
         };
 
         let svg_string = image_renderer::generate_svg_string(
             &top_n_scores,
             &stats,
             Some(&push_acc_map),
-            theme,
+            &options.theme,
             true,
+            image_renderer::RenderProfile::default(),
         )?;
 
-        Ok(svg_string)
+        self.bn_svg_cache_misses
+            .fetch_add(1, AtomicOrdering::Relaxed);
+        self.bn_svg_cache
+            .insert(cache_key, Arc::new(svg_string.clone()))
+            .await;
+        log::info!(
+            "BN SVG 生成 - 总耗时(缓存未命中): {:?}",
+            start_time.elapsed()
+        );
+
+        Ok(RenderedImage {
+            data: svg_string,
+            save_updated_at,
+            data_source,
+        })
     }
     pub fn new(max_concurrent_renders: usize) -> Self {
         // 从环境变量读取缓存大小配置（单位：MB），未设置则使用默认值
@@ -297,25 +407,46 @@ impl ImageService {
             // 按字节加权的缓存，限制总内存占用
             // BN 图片缓存：总容量 ~ 400MB，TTL 120s，TTI 60s
             bn_image_cache: Cache::builder()
-                .weigher(|_: &(u32, String, crate::controllers::image::Theme), v: &Arc<Vec<u8>>| v.len() as u32)
+                .weigher(|_: &BnCacheKey, v: &Arc<Vec<u8>>| v.len() as u32)
                 .max_capacity(bn_cache_mb * 1024 * 1024)
                 .time_to_live(Duration::from_secs(60))  // 从 120s 减少到 60s
                 .time_to_idle(Duration::from_secs(30))  // 从 60s 减少到 30s
                 .build(),
+            // BN SVG 缓存：与 BN 图片共用容量配置与过期时间
+            bn_svg_cache: Cache::builder()
+                .weigher(|_: &BnCacheKey, v: &Arc<String>| v.len() as u32)
+                .max_capacity(bn_cache_mb * 1024 * 1024)
+                .time_to_live(Duration::from_secs(60))
+                .time_to_idle(Duration::from_secs(30))
+                .build(),
             // 歌曲图片缓存：总容量 ~ 200MB
             song_image_cache: Cache::builder()
-                .weigher(|_: &(String, String), v: &Arc<Vec<u8>>| v.len() as u32)
+                .weigher(|_: &SongCacheKey, v: &Arc<Vec<u8>>| v.len() as u32)
                 .max_capacity(song_cache_mb * 1024 * 1024)
                 .time_to_live(Duration::from_secs(60))  // 从 120s 减少到 60s
                 .time_to_idle(Duration::from_secs(30))  // 从 60s 减少到 30s
                 .build(),
             // 排行榜图片缓存：总容量 ~ 100MB
             leaderboard_image_cache: Cache::builder()
-                .weigher(|_: &(usize, String), v: &Arc<Vec<u8>>| v.len() as u32)
+                .weigher(|_: &LeaderboardCacheKey, v: &Arc<Vec<u8>>| v.len() as u32)
                 .max_capacity(leaderboard_cache_mb * 1024 * 1024)
                 .time_to_live(Duration::from_secs(120)) // 从 180s 减少到 120s
                 .time_to_idle(Duration::from_secs(60))  // 从 90s 减少到 60s
                 .build(),
+            // 双人对比图缓存：与单曲图共用容量配置
+            compare_image_cache: Cache::builder()
+                .weigher(|_: &CompareCacheKey, v: &Arc<Vec<u8>>| v.len() as u32)
+                .max_capacity(song_cache_mb * 1024 * 1024)
+                .time_to_live(Duration::from_secs(60))
+                .time_to_idle(Duration::from_secs(30))
+                .build(),
+            // AP Top 3 图缓存：与单曲图共用容量配置
+            ap3_image_cache: Cache::builder()
+                .weigher(|_: &Ap3CacheKey, v: &Arc<Vec<u8>>| v.len() as u32)
+                .max_capacity(song_cache_mb * 1024 * 1024)
+                .time_to_live(Duration::from_secs(60))
+                .time_to_idle(Duration::from_secs(30))
+                .build(),
             // 推分ACC缓存：最多缓存10000个计算结果，缓存10分钟
             // 推分ACC计算复杂度高，需要更大的缓存
             push_acc_cache: Cache::builder()
@@ -325,16 +456,20 @@ impl ImageService {
             // 初始化缓存统计计数器
             bn_cache_hits: AtomicU64::new(0),
             bn_cache_misses: AtomicU64::new(0),
+            bn_svg_cache_hits: AtomicU64::new(0),
+            bn_svg_cache_misses: AtomicU64::new(0),
             song_cache_hits: AtomicU64::new(0),
             song_cache_misses: AtomicU64::new(0),
             leaderboard_cache_hits: AtomicU64::new(0),
             leaderboard_cache_misses: AtomicU64::new(0),
+            compare_cache_hits: AtomicU64::new(0),
+            compare_cache_misses: AtomicU64::new(0),
+            ap3_cache_hits: AtomicU64::new(0),
+            ap3_cache_misses: AtomicU64::new(0),
             // 数据库连接池初始化为 None，需要在创建服务时设置
             db_pool: None,
             // 初始化信号量，限制并发渲染数量
             render_semaphore: Arc::new(Semaphore::new(max_concurrent_renders)),
-            // 初始化后台任务并发限制（默认 CPU 核心数）
-            bg_task_semaphore: Arc::new(Semaphore::new(std::cmp::max(2, num_cpus::get()))),
         }
     }
 
@@ -351,46 +486,41 @@ impl ImageService {
         &self,
         n: u32,
         identifier: web::Json<IdentifierRequest>,
-        theme: &crate::controllers::image::Theme,
+        options: &BnRenderOptions,
         phigros_service: web::Data<PhigrosService>,
         user_service: web::Data<UserService>,
         player_archive_service: web::Data<PlayerArchiveService>,
-    ) -> Result<Vec<u8>, AppError> {
+    ) -> Result<RenderedImage<Vec<u8>>, AppError> {
         let start_time = std::time::Instant::now();
         log::info!("BN图片生成 - 开始处理请求: {:?}", start_time.elapsed());
 
         let checksum_start = std::time::Instant::now();
-        let save_checksum = if identifier.data_source.as_deref() == Some("external") {
-            // 外部数据源：使用平台和ID生成唯一校验和
-            if let Some(api_user_id) = &identifier.api_user_id {
-                format!("external_api_{}", api_user_id)
-            } else {
-                format!(
-                    "external_{}_{}",
-                    identifier.platform.as_deref().unwrap_or(""),
-                    identifier.platform_id.as_deref().unwrap_or("")
-                )
-            }
-        } else {
-            // 内部数据源使用token获取校验和
-            let token = resolve_token(&identifier, &user_service).await?;
-            phigros_service
-                .get_save_checksum(&token)
-                .await
-                .unwrap_or_else(|_| "unknown".to_string())
-        };
+        let (identifier, save_checksum, save_updated_at) =
+            Self::resolve_save_version(identifier, &phigros_service, &user_service).await?;
+        let data_source = token_helper::data_source_of(&identifier);
         log::info!(
             "BN图片生成 - 获取存档校验和耗时: {:?}",
             checksum_start.elapsed()
         );
 
-        let cache_key = (n, save_checksum.clone(), theme.clone());
+        let cache_key = (n, save_checksum.clone(), options.clone());
+        let background_seed =
+            (!options.random_background).then(|| image_renderer::background_seed(&save_checksum));
+        let filter = options.filter;
 
         if let Some(cached) = self.bn_image_cache.get(&cache_key).await {
             self.bn_cache_hits.fetch_add(1, AtomicOrdering::Relaxed);
-            log::debug!("BN图片缓存命中: n={}, checksum={}", n, &save_checksum[..8]);
+            log::debug!(
+                "BN图片缓存命中: n={}, checksum={}",
+                n,
+                truncate_chars(&save_checksum, 8)
+            );
             log::info!("BN图片生成 - 总耗时(缓存命中): {:?}", start_time.elapsed());
-            return Ok(cached.to_vec());
+            return Ok(RenderedImage {
+                data: cached.to_vec(),
+                save_updated_at,
+                data_source,
+            });
         }
 
         let compute_fut = self
@@ -410,10 +540,14 @@ impl ImageService {
                     )
                 } else {
                     // 使用内部数据源
-                    let token = resolve_token(&identifier, &user_service).await?;
-                    tokio::join!(
-                        phigros_service.get_full_save_data(&token),
-                        phigros_service.get_profile(&token)
+                    let resolved = resolve_token_with_binding(&identifier, &user_service).await?;
+                    let (full_data_res, profile_res) = tokio::join!(
+                        phigros_service.get_full_save_data(&resolved.token),
+                        phigros_service.get_profile(&resolved.token)
+                    );
+                    (
+                        full_data_res.map_err(|e| resolved.annotate_error(e)),
+                        profile_res,
                     )
                 };
                 log::info!(
@@ -422,11 +556,12 @@ impl ImageService {
                 );
 
                 let full_data = full_data_res?;
-                if full_data.rks_result.records.is_empty() {
-                    return Err(AppError::Other(format!(
-                        "用户无成绩记录，无法生成 B{n} 图片"
-                    )));
-                }
+                Self::ensure_bn_records(
+                    &full_data.rks_result.records,
+                    filter,
+                    options.allow_empty,
+                )?;
+                let n = Self::clamp_bn_to_records(n, &full_data.rks_result.records, filter);
 
                 // 优先从 Profile 提取 objectId 与昵称，避免存档内缺失导致 player_id=unknown
                 let (profile_object_id, player_nickname) = match profile_res {
@@ -462,53 +597,29 @@ impl ImageService {
                 };
 
                 // --- 异步更新玩家存档 ---
-                let player_name_for_archive = player_name.clone();
-                let mut fc_map = HashMap::new();
-                if let Some(game_record_map) = &full_data.save.game_record {
-                    for (song_id, difficulties) in game_record_map {
-                        for (diff_name, record) in difficulties {
-                            if record.fc == Some(true) {
-                                fc_map.insert(format!("{song_id}-{diff_name}"), true);
-                            }
-                        }
-                    }
-                }
-                let archive_service_clone = player_archive_service.clone();
-                let player_id_clone = player_id.clone();
-                let player_name_clone = player_name_for_archive.clone();
-                let scores_clone = full_data.rks_result.records.clone();
-                let is_external = identifier.data_source.as_deref() == Some("external");
-                let bg_sem = self.bg_task_semaphore.clone();
-                tokio::spawn(async move {
-                    let _permit = bg_sem.acquire_owned().await.ok();
-                    if let Err(e) = archive_service_clone
-                        .update_player_scores_from_rks_records(
-                            &player_id_clone,
-                            &player_name_clone,
-                            &scores_clone,
-                            &fc_map,
-                            is_external,
-                        )
-                        .await
-                    {
-                        log::error!(
-                            "后台更新玩家 {player_name_clone} ({player_id_clone}) 存档失败: {e}"
-                        );
-                    }
-                });
+                player_archive_service.spawn_update_from_save(
+                    player_id.clone(),
+                    player_name.clone(),
+                    &full_data.save,
+                    full_data.rks_result.records.clone(),
+                    identifier.data_source.as_deref() == Some("external"),
+                );
 
                 // --- 预计算推分ACC（移至阻塞线程，避免阻塞 Actix worker） ---
                 let push_acc_start = std::time::Instant::now();
                 let scores_for_push = full_data.rks_result.records.clone();
-                let n_for_push = n as usize;
+                let n_for_push = n;
                 let push_acc_map: HashMap<String, f64> = tokio::task::spawn_blocking(move || {
                     let mut sorted_scores_for_push = scores_for_push;
-                    sorted_scores_for_push.sort_by(|a, b| b.rks.partial_cmp(&a.rks).unwrap_or(Ordering::Equal));
-                    let top_n_scores_for_push: Vec<RksRecord> = sorted_scores_for_push
-                        .iter()
-                        .take(n_for_push)
-                        .cloned()
-                        .collect();
+                    sorted_scores_for_push.sort_by(rks_utils::compare_records);
+                    let top_n_scores_for_push =
+                        Self::select_bn_scores(&sorted_scores_for_push, n_for_push, filter);
+                    // 限定定数范围时筛选后的成绩不代表玩家的真实 RKS，改为参照全部成绩
+                    let push_reference = if filter.has_constant_range() {
+                        &sorted_scores_for_push
+                    } else {
+                        &top_n_scores_for_push
+                    };
 
                     let mut map: HashMap<String, f64> = HashMap::new();
                     for score in top_n_scores_for_push
@@ -519,7 +630,7 @@ impl ImageService {
                         if let Some(push_acc) = rks_utils::calculate_target_chart_push_acc(
                             &key0,
                             score.difficulty_value,
-                            &top_n_scores_for_push,
+                            push_reference,
                         ) {
                             map.insert(key0, push_acc);
                         }
@@ -534,24 +645,46 @@ impl ImageService {
                     push_acc_start.elapsed()
                 );
 
+                let play_dates = Self::load_play_dates(
+                    &player_archive_service,
+                    &player_id,
+                    &full_data.rks_result.records,
+                    options.show_play_date,
+                )
+                .await;
+
+                // --- 按需补全缺失的曲绘（需在进入阻塞渲染前完成） ---
+                cover_loader::fetch_missing_covers(&Self::collect_cover_song_ids(
+                    &full_data.rks_result.records,
+                    n,
+                    filter,
+                ))
+                .await;
+
                 // --- 将所有权转移到阻塞任务 ---
                 let render_start = std::time::Instant::now();
-                let theme_clone = theme.clone();
+                let render_options = options.clone();
 
-                let permit = self.render_semaphore.clone().acquire_owned().await.map_err(|e| AppError::InternalError(format!("Failed to acquire semaphore permit: {e}")))?;
+                let permit = self.acquire_render_permit().await?;
 
-                let png_data_result = web::block(move || {
+                // 将请求ID带入渲染线程，使渲染阶段的日志可与请求关联
+                let request_id = request_id::current_request_id();
+                let png_data_result = render_pool::run(move || {
                     let _permit = permit;
-                    Self::_render_bn_image_sync(
-                        full_data,
-                        Some(player_name),
-                        n,
-                        push_acc_map,
-                        theme_clone,
-                    )
+                    request_id::with_request_id(request_id, || {
+                        Self::_render_bn_image_sync(
+                            full_data,
+                            Some(player_name),
+                            n,
+                            push_acc_map,
+                            background_seed,
+                            play_dates,
+                            render_options,
+                        )
+                    })
                 })
                 .await
-                .map_err(|e| AppError::InternalError(format!("Blocking task join error: {e}")))?;
+                .map_err(|e| AppError::InternalError(format!("Render task error: {e}")))?;
 
                 let png_data = png_data_result?;
                 log::info!("BN图片生成 - 渲染总耗时: {:?}", render_start.elapsed());
@@ -562,13 +695,14 @@ impl ImageService {
         let image_bytes_arc = tokio::time::timeout(std::time::Duration::from_secs(20), compute_fut)
             .await
             .map_err(|_| AppError::InternalError("BN 图片生成任务超时".to_string()))?
-            .map_err(|e: Arc<AppError>| AppError::InternalError(e.to_string()))?;
+            // 保留原错误的状态码（如空存档 404、Token 被拒绝 401），便于客户端提示
+            .map_err(AppError::from_shared)?;
 
         self.bn_cache_misses.fetch_add(1, AtomicOrdering::Relaxed);
         log::debug!(
             "BN图片缓存未命中: n={}, checksum={}",
             n,
-            &save_checksum[..8]
+            truncate_chars(&save_checksum, 8)
         );
 
         if let Err(e) = self.increment_counter("bn").await {
@@ -579,7 +713,160 @@ impl ImageService {
             "BN图片生成 - 总耗时(缓存未命中): {:?}",
             start_time.elapsed()
         );
-        Ok(image_bytes_arc.to_vec())
+        Ok(RenderedImage {
+            data: image_bytes_arc.to_vec(),
+            save_updated_at,
+            data_source,
+        })
+    }
+
+    /// 确定存档的缓存校验和与更新时间
+    ///
+    /// 外部数据源不请求存档摘要，以平台或 API 身份生成唯一校验和。内部 Token 被拒绝且允许回退时，
+    /// 返回改用外部数据源的身份信息，后续获取存档随之使用外部数据源。
+    async fn resolve_save_version(
+        identifier: web::Json<IdentifierRequest>,
+        phigros_service: &PhigrosService,
+        user_service: &web::Data<UserService>,
+    ) -> Result<(web::Json<IdentifierRequest>, String, Option<String>), AppError> {
+        let identifier = if identifier.data_source.as_deref() == Some("external") {
+            identifier
+        } else {
+            let token = resolve_token(&identifier, user_service).await?;
+            match phigros_service.get_save_version(&token).await {
+                Ok(version) => return Ok((identifier, version.checksum, version.updated_at)),
+                Err(e) => match token_helper::external_fallback(&identifier, &e) {
+                    Some(fallback) => web::Json(fallback),
+                    // 获取校验和失败时不中断，错误在获取完整存档时报告
+                    None => return Ok((identifier, "unknown".to_string(), None)),
+                },
+            }
+        };
+
+        // 外部数据源：使用平台和ID生成唯一校验和
+        let checksum = if let Some(api_user_id) = &identifier.api_user_id {
+            format!("external_api_{}", api_user_id)
+        } else {
+            format!(
+                "external_{}_{}",
+                identifier.platform.as_deref().unwrap_or(""),
+                identifier.platform_id.as_deref().unwrap_or("")
+            )
+        };
+        Ok((identifier, checksum, None))
+    }
+
+    /// 获取渲染许可，并发渲染数达到上限时排队等待
+    async fn acquire_render_permit(&self) -> Result<RenderPermit, AppError> {
+        let permit = self
+            .render_semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| {
+                AppError::InternalError(format!("Failed to acquire semaphore permit: {e}"))
+            })?;
+        ACTIVE_RENDERS.fetch_add(1, AtomicOrdering::Relaxed);
+        Ok(RenderPermit { _permit: permit })
+    }
+
+    /// 读取成绩的达成时间用于在卡片上标注；未开启或查询失败时返回空表，卡片不标注日期
+    async fn load_play_dates(
+        player_archive_service: &PlayerArchiveService,
+        player_id: &str,
+        records: &[RksRecord],
+        show_play_date: bool,
+    ) -> HashMap<String, DateTime<Utc>> {
+        if !show_play_date {
+            return HashMap::new();
+        }
+        player_archive_service
+            .get_play_dates(player_id, records)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("读取成绩达成时间失败，卡片不标注日期: {e}");
+                HashMap::new()
+            })
+    }
+
+    /// 收集BN图中需要展示曲绘的曲目ID（Best N 与 AP Top 3）
+    fn collect_cover_song_ids(
+        records: &[RksRecord],
+        n: u32,
+        filter: crate::controllers::image::BnFilter,
+    ) -> Vec<String> {
+        let mut sorted = records.to_vec();
+        sorted.sort_by(rks_utils::compare_records);
+
+        let mut song_ids: Vec<String> = Self::select_bn_scores(&sorted, n, filter)
+            .into_iter()
+            .chain(sorted.iter().filter(|r| r.acc >= 100.0).take(3).cloned())
+            .map(|r| r.song_id)
+            .collect();
+        song_ids.sort();
+        song_ids.dedup();
+        song_ids
+    }
+
+    /// 按筛选条件从已按RKS降序排列的成绩中选出前N项（AP 模式仅保留 ACC 100% 的成绩，并可限定定数范围）
+    fn select_bn_scores(
+        sorted_scores: &[RksRecord],
+        n: u32,
+        filter: crate::controllers::image::BnFilter,
+    ) -> Vec<RksRecord> {
+        sorted_scores
+            .iter()
+            .filter(|s| filter.matches(s))
+            .take(n as usize)
+            .cloned()
+            .collect()
+    }
+
+    /// 将 N 收紧到玩家可展示的成绩数量，避免标题显示远超实际的 Best N（无成绩时保持原值）
+    fn clamp_bn_to_records(
+        n: u32,
+        records: &[RksRecord],
+        filter: crate::controllers::image::BnFilter,
+    ) -> u32 {
+        let available = records.iter().filter(|s| filter.matches(s)).count() as u32;
+        if available == 0 {
+            n
+        } else {
+            n.min(available)
+        }
+    }
+
+    /// 校验存档中有可用于 Best N 图片的成绩
+    ///
+    /// 新账号尚无成绩时返回 `EmptySave`，`allow_empty` 时改为渲染占位图。
+    fn ensure_bn_records(
+        records: &[RksRecord],
+        filter: crate::controllers::image::BnFilter,
+        allow_empty: bool,
+    ) -> Result<(), AppError> {
+        if records.is_empty() {
+            return if allow_empty {
+                Ok(())
+            } else {
+                Err(AppError::EmptySave)
+            };
+        }
+        Self::ensure_bn_filter_has_scores(records, filter)
+    }
+
+    /// 筛选后玩家至少需要一条成绩：AP 模式下需要 AP 成绩，限定定数范围时需要范围内的成绩
+    fn ensure_bn_filter_has_scores(
+        records: &[RksRecord],
+        filter: crate::controllers::image::BnFilter,
+    ) -> Result<(), AppError> {
+        if records.iter().any(|r| filter.matches(r)) {
+            return Ok(());
+        }
+        let message = match filter.constant_range_label() {
+            Some(range) => format!("玩家在该定数范围内 ({range}) 没有符合条件的成绩"),
+            None => "玩家没有 AP (ACC 100%) 成绩，无法生成 AP Best N 图片".to_string(),
+        };
+        Err(AppError::BadRequest(message))
     }
 
     /// 同步执行的BN图片渲染函数
@@ -588,16 +875,18 @@ impl ImageService {
         player_name: Option<String>,
         n: u32,
         push_acc_map: HashMap<String, f64>,
-        theme: crate::controllers::image::Theme,
+        background_seed: Option<u64>,
+        play_dates: HashMap<String, DateTime<Utc>>,
+        options: BnRenderOptions,
     ) -> Result<Vec<u8>, AppError> {
         let data_process_start = std::time::Instant::now();
+        let filter = options.filter;
         let mut sorted_scores = full_data.rks_result.records;
-        sorted_scores.sort_by(|a, b| b.rks.partial_cmp(&a.rks).unwrap_or(Ordering::Equal));
+        sorted_scores.sort_by(rks_utils::compare_records);
 
         let (exact_rks, _) = rks_utils::calculate_player_rks_details(&sorted_scores);
 
-        let top_n_scores: Vec<RksRecord> =
-            sorted_scores.iter().take(n as usize).cloned().collect();
+        let top_n_scores = Self::select_bn_scores(&sorted_scores, n, filter);
 
         let ap_scores_ranked: Vec<_> = sorted_scores.iter().filter(|s| s.acc == 100.0).collect();
         let ap_top_3_scores: Vec<RksRecord> =
@@ -624,28 +913,10 @@ impl ImageService {
         };
 
         let (challenge_rank, data_string) = if let Some(game_progress) = &full_data.save.game_progress {
-            let rank = game_progress
-                .get("challengeModeRank")
-                .and_then(|v| v.as_i64())
-                .and_then(|rank_num| {
-                    if rank_num <= 0 { return None; }
-                    let rank_str = rank_num.to_string();
-                    if rank_str.is_empty() { return None; }
-                    let (color_char, level_str) = rank_str.split_at(1);
-                    let color = match color_char {
-                        "1" => "Green", "2" => "Blue", "3" => "Red",
-                        "4" => "Gold", "5" => "Rainbow", _ => return None,
-                    };
-                    Some((color.to_string(), level_str.to_string()))
-                });
-            let money_str = game_progress.get("money").and_then(|v| v.as_array()).and_then(|arr| {
-                let units = ["KB", "MB", "GB", "TB"];
-                let mut parts: Vec<String> = arr.iter().zip(units.iter()).filter_map(|(val, &unit)| {
-                    val.as_u64().and_then(|u_val| if u_val > 0 { Some(format!("{u_val} {unit}")) } else { None })
-                }).collect();
-                parts.reverse();
-                if parts.is_empty() { None } else { Some(format!("Data: {}", parts.join(", "))) }
-            });
+            let rank = save_parser::decode_challenge_rank(game_progress)
+                .map(|(color, level)| (color.to_string(), level.to_string()));
+            let money_str =
+                save_parser::decode_data_amount(game_progress).and_then(|amount| amount.display);
             (rank, money_str)
         } else {
             (None, None)
@@ -659,6 +930,9 @@ impl ImageService {
             best_27_avg,
             real_rks: Some(exact_rks),
             player_name,
+            region: full_data.cloud_summary["results"][0]["region"]
+                .as_str()
+                .map(str::to_string),
             update_time: {
                 let date_str = full_data.cloud_summary["results"][0]["updatedAt"]
                     .as_str()
@@ -673,6 +947,22 @@ impl ImageService {
             data_string,
             custom_footer_text: Some(app_config.custom_footer_text),
             is_user_generated: false, // 官方数据
+            ap_only: filter.mode == crate::controllers::image::BnMode::Ap,
+            constant_range: filter.constant_range_label(),
+            empty_message: sorted_scores
+                .is_empty()
+                .then(|| NO_RECORDS_MESSAGE.to_string()),
+            acc_decimals: options.acc_decimals,
+            background_seed,
+            background_mode: options.background_mode,
+            player_background: full_data
+                .save
+                .user
+                .as_ref()
+                .and_then(save_parser::player_background_song_id),
+            play_dates,
+            lang: options.lang,
+            layout: options.layout,
         };
         log::info!("BN图片生成 - Stats创建耗时: {:?}", stats_creation_start.elapsed());
 
@@ -681,27 +971,35 @@ impl ImageService {
             &top_n_scores,
             &stats,
             Some(&push_acc_map),
-            &theme,
+            &options.theme,
             false,
+            image_renderer::RenderProfile::default(),
         )?;
         log::info!("BN图片生成 - SVG生成耗时: {:?}", svg_gen_start.elapsed());
 
         let png_render_start = std::time::Instant::now();
-        let result = image_renderer::render_svg_to_png(svg_string, false); // 官方数据
+        let result = image_renderer::render_svg_to_png(svg_string, false, options.scale.factor()); // 官方数据
         log::info!("BN图片生成 - PNG渲染耗时: {:?}", png_render_start.elapsed());
         result
     }
 
     // 新增：生成单曲成绩图片的服务逻辑
+    #[allow(clippy::too_many_arguments)]
     pub async fn generate_song_image(
         &self,
         song_query: String,
+        encoding: crate::controllers::image::SongImageEncoding,
+        played_only: bool,
+        acc_decimals: usize,
+        random_background: bool,
+        background_mode: crate::controllers::image::BackgroundMode,
+        lang: crate::controllers::image::ImageLang,
         identifier: web::Json<IdentifierRequest>,
         phigros_service: web::Data<PhigrosService>,
         user_service: web::Data<UserService>,
         song_service: web::Data<SongService>,
         player_archive_service: web::Data<PlayerArchiveService>,
-    ) -> Result<Vec<u8>, AppError> {
+    ) -> Result<RenderedImage<Vec<u8>>, AppError> {
         let start_time = std::time::Instant::now();
         log::info!("歌曲图片生成 - 开始处理请求: {:?}", start_time.elapsed());
 
@@ -725,40 +1023,39 @@ impl ImageService {
         };
         let song_id = song_info.id.clone();
 
-        let save_checksum = if identifier.data_source.as_deref() == Some("external") {
-            // 外部数据源：使用平台和ID生成唯一校验和
-            if let Some(api_user_id) = &identifier.api_user_id {
-                format!("external_api_{}", api_user_id)
-            } else {
-                format!(
-                    "external_{}_{}",
-                    identifier.platform.as_deref().unwrap_or(""),
-                    identifier.platform_id.as_deref().unwrap_or("")
-                )
-            }
-        } else {
-            // 内部数据源使用token获取校验和
-            let token = resolve_token(&identifier, &user_service).await?;
-            phigros_service
-                .get_save_checksum(&token)
-                .await
-                .unwrap_or_else(|_| "unknown".to_string())
-        };
-
-        let cache_key = (song_id.clone(), save_checksum.clone());
+        let (identifier, save_checksum, save_updated_at) =
+            Self::resolve_save_version(identifier, &phigros_service, &user_service).await?;
+        let data_source = token_helper::data_source_of(&identifier);
+
+        let cache_key = (
+            song_id.clone(),
+            save_checksum.clone(),
+            encoding,
+            played_only,
+            acc_decimals,
+            random_background,
+            background_mode,
+            lang,
+        );
+        let background_seed =
+            (!random_background).then(|| image_renderer::background_seed(&save_checksum));
 
         if let Some(cached) = self.song_image_cache.get(&cache_key).await {
             self.song_cache_hits.fetch_add(1, AtomicOrdering::Relaxed);
             log::debug!(
                 "歌曲图片缓存命中: song_id={}, checksum={}",
-                &song_id[..std::cmp::min(20, song_id.len())],
-                &save_checksum[..8]
+                truncate_chars(&song_id, 20),
+                truncate_chars(&save_checksum, 8)
             );
             log::info!(
                 "歌曲图片生成 - 总耗时(缓存命中): {:?}",
                 start_time.elapsed()
             );
-            return Ok(cached.to_vec());
+            return Ok(RenderedImage {
+                data: cached.to_vec(),
+                save_updated_at,
+                data_source,
+            });
         }
 
         let image_bytes_arc = self
@@ -777,10 +1074,14 @@ impl ImageService {
                     )
                 } else {
                     // 使用内部数据源
-                    let token = resolve_token(&identifier, &user_service).await?;
-                    tokio::join!(
-                        phigros_service.get_full_save_data(&token),
-                        phigros_service.get_profile(&token)
+                    let resolved = resolve_token_with_binding(&identifier, &user_service).await?;
+                    let (full_data_res, profile_res) = tokio::join!(
+                        phigros_service.get_full_save_data(&resolved.token),
+                        phigros_service.get_profile(&resolved.token)
+                    );
+                    (
+                        full_data_res.map_err(|e| resolved.annotate_error(e)),
+                        profile_res,
                     )
                 };
 
@@ -818,65 +1119,54 @@ impl ImageService {
                 };
 
                 // --- 异步更新玩家存档 ---
-                let player_name_for_archive = player_name.clone();
-                let fc_map: HashMap<String, bool> =
-                    if let Some(game_record_map) = &full_data.save.game_record {
-                        game_record_map
-                            .iter()
-                            .flat_map(|(song_id, difficulties)| {
-                                difficulties.iter().filter_map(move |(diff_name, record)| {
-                                    if record.fc == Some(true) {
-                                        Some((format!("{song_id}-{diff_name}"), true))
-                                    } else {
-                                        None
-                                    }
-                                })
-                            })
-                            .collect()
-                    } else {
+                player_archive_service.spawn_update_from_save(
+                    player_id.clone(),
+                    player_name.clone(),
+                    &full_data.save,
+                    full_data.rks_result.records.clone(),
+                    identifier.data_source.as_deref() == Some("external"),
+                );
+
+                // --- 其他玩家的平均 ACC，查询失败时不标注 ---
+                let acc_averages = player_archive_service
+                    .get_chart_average_accs(&player_id, &song_info.id, SONG_AVERAGE_MIN_PLAYERS)
+                    .await
+                    .unwrap_or_else(|e| {
+                        log::warn!("查询谱面平均 ACC 失败，单曲图片将不标注与平均值的差距: {e}");
                         HashMap::new()
-                    };
-                let archive_service_clone = player_archive_service.clone();
-                let player_id_clone = player_id.clone();
-                let player_name_clone = player_name_for_archive.clone();
-                let records_clone = full_data.rks_result.records.clone();
-                let is_external = identifier.data_source.as_deref() == Some("external");
-                let bg_sem = self.bg_task_semaphore.clone();
-                tokio::spawn(async move {
-                    let _permit = bg_sem.acquire_owned().await.ok();
-                    if let Err(e) = archive_service_clone
-                        .update_player_scores_from_rks_records(
-                            &player_id_clone,
-                            &player_name_clone,
-                            &records_clone,
-                            &fc_map,
-                            is_external,
-                        )
-                        .await
-                    {
-                        log::error!(
-                            "后台更新玩家 {player_name_clone} ({player_id_clone}) 存档失败: {e}"
-                        );
-                    }
-                });
+                    });
+
+                // --- 按需补全缺失的曲绘（需在进入阻塞渲染前完成） ---
+                cover_loader::fetch_missing_cover(&song_info.id).await;
 
                 // --- 将所有权转移到阻塞任务 ---
                 let render_start = std::time::Instant::now();
                 let song_service_clone = song_service.clone();
 
-                let permit = self.render_semaphore.clone().acquire_owned().await.map_err(|e| AppError::InternalError(format!("Failed to acquire semaphore permit: {e}")))?;
+                let permit = self.acquire_render_permit().await?;
 
-                let png_data_result = web::block(move || {
+                // 将请求ID带入渲染线程，使渲染阶段的日志可与请求关联
+                let request_id = request_id::current_request_id();
+                let png_data_result = render_pool::run(move || {
                     let _permit = permit;
-                    Self::_render_song_image_sync(
-                        full_data,
-                        Some(player_name),
-                        song_info,
-                        song_service_clone,
-                    )
+                    request_id::with_request_id(request_id, || {
+                        Self::_render_song_image_sync(
+                            full_data,
+                            Some(player_name),
+                            song_info,
+                            song_service_clone,
+                            encoding,
+                            played_only,
+                            acc_decimals,
+                            background_seed,
+                            background_mode,
+                            lang,
+                            acc_averages,
+                        )
+                    })
                 })
                 .await
-                .map_err(|e| AppError::InternalError(format!("Blocking task join error: {e}")))?;
+                .map_err(|e| AppError::InternalError(format!("Render task error: {e}")))?;
 
                 let png_data = png_data_result?;
                 log::info!("歌曲图片生成 - 渲染总耗时: {:?}", render_start.elapsed());
@@ -884,13 +1174,13 @@ impl ImageService {
                 Ok(Arc::new(png_data))
             })
             .await
-            .map_err(|e: Arc<AppError>| AppError::InternalError(e.to_string()))?;
+            .map_err(AppError::from_shared)?;
 
         self.song_cache_misses.fetch_add(1, AtomicOrdering::Relaxed);
         log::debug!(
             "歌曲图片缓存未命中: song_id={}, checksum={}",
-            &song_id[..std::cmp::min(20, song_id.len())],
-            &save_checksum[..8]
+            truncate_chars(&song_id, 20),
+            truncate_chars(&save_checksum, 8)
         );
 
         if let Err(e) = self.increment_counter("song").await {
@@ -901,23 +1191,33 @@ impl ImageService {
             "歌曲图片生成 - 总耗时(缓存未命中): {:?}",
             start_time.elapsed()
         );
-        Ok(image_bytes_arc.to_vec())
+        Ok(RenderedImage {
+            data: image_bytes_arc.to_vec(),
+            save_updated_at,
+            data_source,
+        })
     }
 
     /// 同步执行的单曲图片渲染函数
+    #[allow(clippy::too_many_arguments)]
     fn _render_song_image_sync(
         full_data: FullSaveData,
         player_name: Option<String>,
         song_info: crate::models::song::SongInfo,
         song_service: web::Data<SongService>,
+        encoding: crate::controllers::image::SongImageEncoding,
+        played_only: bool,
+        acc_decimals: usize,
+        background_seed: Option<u64>,
+        background_mode: crate::controllers::image::BackgroundMode,
+        lang: crate::controllers::image::ImageLang,
+        acc_averages: HashMap<String, f64>,
     ) -> Result<Vec<u8>, AppError> {
         let data_process_start = std::time::Instant::now();
         let mut all_records_sorted = full_data.rks_result.records;
-        all_records_sorted.sort_by(|a, b| b.rks.partial_cmp(&a.rks).unwrap_or(Ordering::Equal));
+        all_records_sorted.sort_by(rks_utils::compare_records);
 
-        let game_record_map = full_data.save.game_record.ok_or_else(|| {
-            AppError::Other("存档中无成绩记录，无法生成单曲图片".to_string())
-        })?;
+        let game_record_map = full_data.save.played_records()?;
         let song_difficulties_from_save =
             game_record_map.get(&song_info.id).cloned().unwrap_or_default();
 
@@ -962,32 +1262,24 @@ impl ImageService {
                     is_fc: record.and_then(|r| r.fc),
                     is_phi: Some(is_phi),
                     player_push_acc: push_acc,
+                    acc_vs_average: acc
+                        .zip(acc_averages.get(diff_key))
+                        .map(|(acc, avg)| acc - avg),
                 }),
             );
         }
         log::info!("歌曲图片生成 - 数据处理耗时: {:?}", data_process_start.elapsed());
 
         let illustration_process_start = std::time::Instant::now();
-        let illustration_path_png = PathBuf::from(cover_loader::COVERS_DIR)
-            .join("ill")
-            .join(format!("{}.png", song_info.id));
-        let illustration_path_jpg = PathBuf::from(cover_loader::COVERS_DIR)
-            .join("ill")
-            .join(format!("{}.jpg", song_info.id));
-        let illustration_path = if illustration_path_png.exists() {
-            Some(illustration_path_png)
-        } else if illustration_path_jpg.exists() {
-            Some(illustration_path_jpg)
-        } else {
-            None
-        };
+        let illustration_path =
+            cover_loader::find_cover_path(&song_info.id, cover_loader::CoverSize::Full);
         log::info!("歌曲图片生成 - 插画处理耗时: {:?}", illustration_process_start.elapsed());
 
         let render_data_creation_start = std::time::Instant::now();
         let render_data = SongRenderData {
             song_name: song_info.song,
             song_id: song_info.id,
-            player_name: player_name,
+            player_name,
             update_time: {
                 let date_str = full_data.cloud_summary["results"][0]["updatedAt"]
                     .as_str()
@@ -998,16 +1290,36 @@ impl ImageService {
             },
             difficulty_scores: difficulty_scores_map,
             illustration_path,
+            played_only,
+            acc_decimals,
+            background_seed,
+            background_mode,
+            lang,
         };
         log::info!("歌曲图片生成 - RenderData创建耗时: {:?}", render_data_creation_start.elapsed());
 
         let svg_gen_start = std::time::Instant::now();
-        let svg_string = image_renderer::generate_song_svg_string(&render_data, false)?;
+        // 直接输出 SVG 时内嵌曲绘，使文件可以独立显示
+        let embed_images = encoding.format == crate::controllers::image::SongImageFormat::Svg;
+        let svg_string = image_renderer::generate_song_svg_string(&render_data, embed_images)?;
         log::info!("歌曲图片生成 - SVG生成耗时: {:?}", svg_gen_start.elapsed());
 
         let png_render_start = std::time::Instant::now();
-        let result = image_renderer::render_svg_to_png(svg_string, false); // 官方数据
-        log::info!("歌曲图片生成 - PNG渲染耗时: {:?}", png_render_start.elapsed());
+        let result = match encoding.format {
+            crate::controllers::image::SongImageFormat::Svg => Ok(svg_string.into_bytes()),
+            crate::controllers::image::SongImageFormat::Png => {
+                // 官方数据
+                image_renderer::render_svg_to_png(svg_string, false, encoding.scale.factor())
+            }
+            crate::controllers::image::SongImageFormat::Jpeg => image_renderer::render_svg_to_jpeg(
+                svg_string,
+                false,
+                encoding.quality,
+                image_renderer::SONG_IMAGE_BACKGROUND_RGB,
+                encoding.scale.factor(),
+            ),
+        };
+        log::info!("歌曲图片生成 - {:?}渲染耗时: {:?}", encoding.format, png_render_start.elapsed());
         result
     }
 
@@ -1016,10 +1328,14 @@ impl ImageService {
     pub async fn generate_rks_leaderboard_image(
         &self,
         limit: Option<usize>,
+        theme: crate::controllers::image::Theme,
+        format: crate::controllers::image::ImageFormat,
+        scale: crate::controllers::image::ImageScale,
+        lang: crate::controllers::image::ImageLang,
         player_archive_service: web::Data<PlayerArchiveService>,
     ) -> Result<Vec<u8>, AppError> {
         let start_time = std::time::Instant::now();
-        let actual_limit = limit.unwrap_or(20).min(100);
+        let actual_limit = crate::config::CONFIG.leaderboard_limit(limit);
 
         let last_update = player_archive_service
             .get_ref()
@@ -1027,7 +1343,14 @@ impl ImageService {
             .await
             .unwrap_or_else(|_| "unknown".to_string());
 
-        let cache_key = (actual_limit, last_update.clone());
+        let cache_key = (
+            actual_limit,
+            last_update.clone(),
+            theme.clone(),
+            format,
+            scale,
+            lang,
+        );
 
         if let Some(cached) = self.leaderboard_image_cache.get(&cache_key).await {
             self.leaderboard_cache_hits
@@ -1035,7 +1358,7 @@ impl ImageService {
             log::debug!(
                 "排行榜图片缓存命中: limit={}, update_time={}",
                 actual_limit,
-                &last_update[..std::cmp::min(10, last_update.len())]
+                truncate_chars(&last_update, 10)
             );
             log::info!(
                 "排行榜图片生成 - 总耗时(缓存命中): {:?}",
@@ -1052,30 +1375,38 @@ impl ImageService {
                     .get_rks_ranking(actual_limit)
                     .await?;
 
-                let permit = self.render_semaphore.clone().acquire_owned().await.map_err(|e| AppError::InternalError(format!("Failed to acquire semaphore permit: {e}")))?;
+                let permit = self.acquire_render_permit().await?;
 
-                let png_data_result = web::block(move || {
+                // 将请求ID带入渲染线程，使渲染阶段的日志可与请求关联
+                let request_id = request_id::current_request_id();
+                let png_data_result = render_pool::run(move || {
                     let _permit = permit;
-                    Self::_render_rks_leaderboard_image_sync(
-                        top_players,
-                        actual_limit,
-                    )
+                    request_id::with_request_id(request_id, || {
+                        Self::_render_rks_leaderboard_image_sync(
+                            top_players,
+                            actual_limit,
+                            &theme,
+                            format,
+                            scale,
+                            lang,
+                        )
+                    })
                 })
                 .await
-                .map_err(|e| AppError::InternalError(format!("Blocking task join error: {e}")))?;
+                .map_err(|e| AppError::InternalError(format!("Render task error: {e}")))?;
 
                 let png_data = png_data_result?;
                 Ok(Arc::new(png_data))
             })
             .await
-            .map_err(|e: Arc<AppError>| AppError::InternalError(e.to_string()))?;
+            .map_err(AppError::from_shared)?;
 
         self.leaderboard_cache_misses
             .fetch_add(1, AtomicOrdering::Relaxed);
         log::debug!(
             "排行榜图片缓存未命中: limit={}, update_time={}",
             actual_limit,
-            &last_update[..std::cmp::min(10, last_update.len())]
+            truncate_chars(&last_update, 10)
         );
 
         if let Err(e) = self.increment_counter("leaderboard").await {
@@ -1093,21 +1424,392 @@ impl ImageService {
     fn _render_rks_leaderboard_image_sync(
         top_players: Vec<crate::models::player_archive::RKSRankingEntry>,
         actual_limit: usize,
+        theme: &crate::controllers::image::Theme,
+        format: crate::controllers::image::ImageFormat,
+        scale: crate::controllers::image::ImageScale,
+        lang: crate::controllers::image::ImageLang,
     ) -> Result<Vec<u8>, AppError> {
         let render_data = LeaderboardRenderData {
-            title: "RKS 排行榜".to_string(),
             entries: top_players,
             display_count: actual_limit,
             update_time: Utc::now(),
+            lang,
         };
 
-        let svg_string = image_renderer::generate_leaderboard_svg_string(&render_data)?;
-        image_renderer::render_svg_to_png(svg_string, false) // 排行榜不是用户生成的
+        let svg_string = image_renderer::generate_leaderboard_svg_string(&render_data, theme)?;
+        match format {
+            crate::controllers::image::ImageFormat::Svg => Ok(svg_string.into_bytes()),
+            crate::controllers::image::ImageFormat::Png => {
+                // 排行榜不是用户生成的
+                image_renderer::render_svg_to_png(svg_string, false, scale.factor())
+            }
+        }
     }
+
+    // --- 双人对比图相关函数 ---
+
+    /// 生成双人对比图。任一侧获取数据失败时，在该侧绘制错误提示而不是整体报错
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_compare_image(
+        &self,
+        left: &IdentifierRequest,
+        right: &IdentifierRequest,
+        theme: crate::controllers::image::Theme,
+        format: crate::controllers::image::ImageFormat,
+        scale: crate::controllers::image::ImageScale,
+        lang: crate::controllers::image::ImageLang,
+        phigros_service: web::Data<PhigrosService>,
+        user_service: web::Data<UserService>,
+    ) -> Result<Vec<u8>, AppError> {
+        let start_time = std::time::Instant::now();
+
+        let (left_version, right_version) = tokio::join!(
+            Self::resolve_save_version(web::Json(left.clone()), &phigros_service, &user_service),
+            Self::resolve_save_version(web::Json(right.clone()), &phigros_service, &user_service)
+        );
+
+        // 两侧校验和均可用时才走缓存，否则直接渲染（失败的一侧不应被缓存）
+        let cache_key = match (&left_version, &right_version) {
+            (Ok((_, l, _)), Ok((_, r, _))) => {
+                Some((l.clone(), r.clone(), theme.clone(), format, scale, lang))
+            }
+            _ => None,
+        };
+        if let Some(key) = &cache_key {
+            if let Some(cached) = self.compare_image_cache.get(key).await {
+                self.compare_cache_hits
+                    .fetch_add(1, AtomicOrdering::Relaxed);
+                log::info!("对比图生成 - 总耗时(缓存命中): {:?}", start_time.elapsed());
+                return Ok(cached.to_vec());
+            }
+        }
+
+        let load_side = |identifier: Result<IdentifierRequest, AppError>| {
+            let phigros_service = phigros_service.clone();
+            let user_service = user_service.clone();
+            async move {
+                Self::load_compare_player(&identifier?, &phigros_service, &user_service).await
+            }
+        };
+        let (left_data, right_data) = tokio::join!(
+            load_side(left_version.map(|(identifier, _, _)| identifier.into_inner())),
+            load_side(right_version.map(|(identifier, _, _)| identifier.into_inner()))
+        );
+        // 上游错误信息可能包含内部细节，只写入日志，图片中显示通用的错误提示
+        let left_data = left_data
+            .inspect_err(|e| log::warn!("对比图左侧玩家数据获取失败: {e}"))
+            .ok();
+        let right_data = right_data
+            .inspect_err(|e| log::warn!("对比图右侧玩家数据获取失败: {e}"))
+            .ok();
+        let cacheable = left_data.is_some() && right_data.is_some();
+
+        let render_data = CompareRenderData {
+            left: left_data,
+            right: right_data,
+            update_time: Utc::now(),
+            lang,
+        };
+
+        let permit = self.acquire_render_permit().await?;
+        let request_id = request_id::current_request_id();
+        let png_data = render_pool::run(move || {
+            let _permit = permit;
+            request_id::with_request_id(request_id, || {
+                let svg_string = image_renderer::generate_compare_svg_string(&render_data, &theme)?;
+                match format {
+                    crate::controllers::image::ImageFormat::Svg => Ok(svg_string.into_bytes()),
+                    crate::controllers::image::ImageFormat::Png => {
+                        image_renderer::render_svg_to_png(svg_string, false, scale.factor())
+                    }
+                }
+            })
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Render task error: {e}")))??;
+
+        self.compare_cache_misses
+            .fetch_add(1, AtomicOrdering::Relaxed);
+        if let (Some(key), true) = (cache_key, cacheable) {
+            self.compare_image_cache
+                .insert(key, Arc::new(png_data.clone()))
+                .await;
+        }
+
+        if let Err(e) = self.increment_counter("compare").await {
+            log::error!("更新对比图计数器失败: {e}");
+        }
+
+        log::info!(
+            "对比图生成 - 总耗时(缓存未命中): {:?}",
+            start_time.elapsed()
+        );
+        Ok(png_data)
+    }
+
+    /// 获取对比图单侧玩家的 RKS 概况与最高的若干谱面
+    async fn load_compare_player(
+        identifier: &IdentifierRequest,
+        phigros_service: &PhigrosService,
+        user_service: &web::Data<UserService>,
+    ) -> Result<ComparePlayerData, AppError> {
+        let (full_data, player_name) =
+            Self::load_save_with_name(identifier, phigros_service, user_service).await?;
+
+        // RksResult 中的记录已按 RKS 降序排列
+        let records = full_data.rks_result.records;
+        let (exact_rks, _) = rks_utils::calculate_player_rks_details(&records);
+
+        let b27_count = records.len().min(27);
+        let best_27_avg = (b27_count > 0)
+            .then(|| records.iter().take(b27_count).map(|s| s.rks).sum::<f64>() / b27_count as f64);
+
+        let ap_top_3: Vec<f64> = records
+            .iter()
+            .filter(|s| s.acc >= 100.0)
+            .take(3)
+            .map(|s| s.rks)
+            .collect();
+        let ap_top_3_avg = (ap_top_3.len() >= 3).then(|| ap_top_3.iter().sum::<f64>() / 3.0);
+
+        Ok(ComparePlayerData {
+            player_name,
+            rks: exact_rks,
+            best_27_avg,
+            ap_top_3_avg,
+            top_scores: records.into_iter().take(COMPARE_TOP_CHARTS).collect(),
+        })
+    }
+
+    /// 获取完整存档与玩家名称，对比图与 AP Top 3 图共用
+    async fn load_save_with_name(
+        identifier: &IdentifierRequest,
+        phigros_service: &PhigrosService,
+        user_service: &web::Data<UserService>,
+    ) -> Result<(FullSaveData, String), AppError> {
+        if identifier.data_source.as_deref() == Some("external") {
+            let full_data = phigros_service
+                .get_full_save_data_with_source(identifier)
+                .await?;
+            let player_name = full_data.cloud_summary["results"][0]["PlayerId"]
+                .as_str()
+                .unwrap_or("external:unknown")
+                .to_string();
+            Ok((full_data, player_name))
+        } else {
+            let resolved =
+                resolve_token_with_binding(&web::Json(identifier.clone()), user_service).await?;
+            let (full_data, profile) = tokio::join!(
+                phigros_service.get_full_save_data(&resolved.token),
+                phigros_service.get_profile(&resolved.token)
+            );
+            let player_name = profile
+                .map(|p| p.nickname)
+                .unwrap_or_else(|_| "未知玩家".to_string());
+            Ok((
+                full_data.map_err(|e| resolved.annotate_error(e))?,
+                player_name,
+            ))
+        }
+    }
+
+    // --- AP Top 3 图相关函数 ---
+
+    /// 只渲染 AP Top 3 卡片的紧凑图片
+    ///
+    /// AP 成绩不足 3 个时默认返回 `400 Bad Request`，`allow_partial` 为 true 时渲染已有的 AP 成绩；
+    /// 没有任何 AP 成绩时始终返回错误。
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_ap3_image(
+        &self,
+        identifier: &IdentifierRequest,
+        theme: crate::controllers::image::Theme,
+        format: crate::controllers::image::ImageFormat,
+        scale: crate::controllers::image::ImageScale,
+        allow_partial: bool,
+        lang: crate::controllers::image::ImageLang,
+        phigros_service: web::Data<PhigrosService>,
+        user_service: web::Data<UserService>,
+    ) -> Result<Vec<u8>, AppError> {
+        let start_time = std::time::Instant::now();
+
+        let (identifier, checksum, _) = Self::resolve_save_version(
+            web::Json(identifier.clone()),
+            &phigros_service,
+            &user_service,
+        )
+        .await?;
+        let cache_key = (checksum, theme.clone(), format, scale, allow_partial, lang);
+        if let Some(cached) = self.ap3_image_cache.get(&cache_key).await {
+            self.ap3_cache_hits.fetch_add(1, AtomicOrdering::Relaxed);
+            log::info!(
+                "AP Top 3 图生成 - 总耗时(缓存命中): {:?}",
+                start_time.elapsed()
+            );
+            return Ok(cached.to_vec());
+        }
+
+        let (full_data, player_name) =
+            Self::load_save_with_name(&identifier, &phigros_service, &user_service).await?;
+
+        // RksResult 中的记录已按 RKS 降序排列
+        let records = full_data.rks_result.records;
+        let (exact_rks, _) = rks_utils::calculate_player_rks_details(&records);
+        let ap_scores: Vec<RksRecord> = records
+            .into_iter()
+            .filter(|s| s.acc >= 100.0)
+            .take(3)
+            .collect();
+        if ap_scores.is_empty() {
+            return Err(AppError::BadRequest(
+                "玩家没有 AP 成绩，无法生成 AP Top 3 图片".to_string(),
+            ));
+        }
+        if ap_scores.len() < 3 && !allow_partial {
+            return Err(AppError::BadRequest(format!(
+                "玩家只有 {} 个 AP 成绩，不足 3 个；如需渲染已有的 AP 成绩请指定 allow_partial=true",
+                ap_scores.len()
+            )));
+        }
+
+        let render_data = Ap3RenderData {
+            player_name,
+            rks: exact_rks,
+            ap_scores,
+            update_time: Utc::now(),
+            lang,
+        };
+
+        let permit = self.acquire_render_permit().await?;
+        let request_id = request_id::current_request_id();
+        let image_data = render_pool::run(move || {
+            let _permit = permit;
+            request_id::with_request_id(request_id, || {
+                let embed_images = format == crate::controllers::image::ImageFormat::Svg;
+                let svg_string = image_renderer::generate_ap3_svg_string(
+                    &render_data,
+                    &theme,
+                    embed_images,
+                    image_renderer::RenderProfile::default(),
+                )?;
+                match format {
+                    crate::controllers::image::ImageFormat::Svg => Ok(svg_string.into_bytes()),
+                    crate::controllers::image::ImageFormat::Png => {
+                        image_renderer::render_svg_to_png(svg_string, false, scale.factor())
+                    }
+                }
+            })
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Render task error: {e}")))??;
+
+        self.ap3_cache_misses.fetch_add(1, AtomicOrdering::Relaxed);
+        self.ap3_image_cache
+            .insert(cache_key, Arc::new(image_data.clone()))
+            .await;
+
+        if let Err(e) = self.increment_counter("ap3").await {
+            log::error!("更新 AP Top 3 图计数器失败: {e}");
+        }
+
+        log::info!(
+            "AP Top 3 图生成 - 总耗时(缓存未命中): {:?}",
+            start_time.elapsed()
+        );
+        Ok(image_data)
+    }
+}
+
+/// 清除键满足条件的缓存条目，返回清除的条目数
+async fn evict_matching<K, V>(cache: &Cache<K, V>, matches: impl Fn(&K) -> bool) -> u64
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    let keys: Vec<Arc<K>> = cache
+        .iter()
+        .filter(|(key, _)| matches(key))
+        .map(|(key, _)| key)
+        .collect();
+    for key in &keys {
+        cache.invalidate(key.as_ref()).await;
+    }
+    keys.len() as u64
+}
+
+/// 清空整个缓存，返回清空前的条目数
+async fn flush<K, V>(cache: &Cache<K, V>) -> u64
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    cache.run_pending_tasks().await;
+    let count = cache.entry_count();
+    cache.invalidate_all();
+    count
 }
 
 // 添加缓存统计方法
 impl ImageService {
+    /// 各图片缓存的条目数与占用字节数
+    pub async fn image_cache_infos(&self) -> Vec<CacheInfo> {
+        vec![
+            CacheInfo::of(CacheName::Bn, &self.bn_image_cache).await,
+            CacheInfo::of(CacheName::BnSvg, &self.bn_svg_cache).await,
+            CacheInfo::of(CacheName::Song, &self.song_image_cache).await,
+            CacheInfo::of(CacheName::Leaderboard, &self.leaderboard_image_cache).await,
+            CacheInfo::of(CacheName::Compare, &self.compare_image_cache).await,
+            CacheInfo::of(CacheName::Ap3, &self.ap3_image_cache).await,
+        ]
+    }
+
+    /// 清除图片缓存，返回清除的条目数
+    ///
+    /// 指定存档校验和时只清除由该存档渲染的图片，否则清空整个缓存。
+    /// 排行榜图片不对应单个存档，只能整体清除。
+    pub async fn evict_image_cache(
+        &self,
+        cache: CacheName,
+        checksum: Option<&str>,
+    ) -> Result<u64, AppError> {
+        let evicted = match (cache, checksum) {
+            (CacheName::Bn, Some(checksum)) => {
+                evict_matching(&self.bn_image_cache, |key| key.1 == checksum).await
+            }
+            (CacheName::Bn, None) => flush(&self.bn_image_cache).await,
+            (CacheName::BnSvg, Some(checksum)) => {
+                evict_matching(&self.bn_svg_cache, |key| key.1 == checksum).await
+            }
+            (CacheName::BnSvg, None) => flush(&self.bn_svg_cache).await,
+            (CacheName::Song, Some(checksum)) => {
+                evict_matching(&self.song_image_cache, |key| key.1 == checksum).await
+            }
+            (CacheName::Song, None) => flush(&self.song_image_cache).await,
+            (CacheName::Compare, Some(checksum)) => {
+                evict_matching(&self.compare_image_cache, |key| {
+                    key.0 == checksum || key.1 == checksum
+                })
+                .await
+            }
+            (CacheName::Compare, None) => flush(&self.compare_image_cache).await,
+            (CacheName::Ap3, Some(checksum)) => {
+                evict_matching(&self.ap3_image_cache, |key| key.0 == checksum).await
+            }
+            (CacheName::Ap3, None) => flush(&self.ap3_image_cache).await,
+            (CacheName::Leaderboard, Some(_)) => {
+                return Err(AppError::BadRequest(
+                    "排行榜图片缓存不对应单个存档，只能整体清除".to_string(),
+                ))
+            }
+            (CacheName::Leaderboard, None) => flush(&self.leaderboard_image_cache).await,
+            (CacheName::PlayerArchive | CacheName::SongSearch, _) => {
+                return Err(AppError::InternalError(format!("{cache:?} 不是图片缓存")))
+            }
+        };
+        log::info!("已清除 {evicted} 条图片缓存: cache={cache:?}, checksum={checksum:?}");
+        Ok(evicted)
+    }
+
     pub fn get_cache_stats(&self) -> serde_json::Value {
         let bn_hits = self
             .bn_cache_hits
@@ -1124,6 +1826,17 @@ impl ImageService {
             "0.00%".to_string()
         };
 
+        let bn_svg_hits = self.bn_svg_cache_hits.load(AtomicOrdering::Relaxed);
+        let bn_svg_misses = self.bn_svg_cache_misses.load(AtomicOrdering::Relaxed);
+        let bn_svg_hit_rate = if bn_svg_hits + bn_svg_misses > 0 {
+            format!(
+                "{:.2}%",
+                (bn_svg_hits as f64 / (bn_svg_hits + bn_svg_misses) as f64) * 100.0
+            )
+        } else {
+            "0.00%".to_string()
+        };
+
         let song_hits = self
             .song_cache_hits
             .load(std::sync::atomic::Ordering::Relaxed);
@@ -1154,12 +1867,39 @@ impl ImageService {
             "0.00%".to_string()
         };
 
+        let compare_hits = self.compare_cache_hits.load(AtomicOrdering::Relaxed);
+        let compare_misses = self.compare_cache_misses.load(AtomicOrdering::Relaxed);
+        let compare_hit_rate = if compare_hits + compare_misses > 0 {
+            format!(
+                "{:.2}%",
+                (compare_hits as f64 / (compare_hits + compare_misses) as f64) * 100.0
+            )
+        } else {
+            "0.00%".to_string()
+        };
+
+        let ap3_hits = self.ap3_cache_hits.load(AtomicOrdering::Relaxed);
+        let ap3_misses = self.ap3_cache_misses.load(AtomicOrdering::Relaxed);
+        let ap3_hit_rate = if ap3_hits + ap3_misses > 0 {
+            format!(
+                "{:.2}%",
+                (ap3_hits as f64 / (ap3_hits + ap3_misses) as f64) * 100.0
+            )
+        } else {
+            "0.00%".to_string()
+        };
+
         serde_json::json!({
             "bn_image_cache": {
                 "hits": bn_hits,
                 "misses": bn_misses,
                 "hit_rate": bn_hit_rate
             },
+            "bn_svg_cache": {
+                "hits": bn_svg_hits,
+                "misses": bn_svg_misses,
+                "hit_rate": bn_svg_hit_rate
+            },
             "song_image_cache": {
                 "hits": song_hits,
                 "misses": song_misses,
@@ -1169,6 +1909,16 @@ impl ImageService {
                 "hits": leaderboard_hits,
                 "misses": leaderboard_misses,
                 "hit_rate": leaderboard_hit_rate
+            },
+            "compare_image_cache": {
+                "hits": compare_hits,
+                "misses": compare_misses,
+                "hit_rate": compare_hit_rate
+            },
+            "ap3_image_cache": {
+                "hits": ap3_hits,
+                "misses": ap3_misses,
+                "hit_rate": ap3_hit_rate
             }
         })
     }
@@ -1176,10 +1926,12 @@ impl ImageService {
     // 增加图片生成计数
     async fn increment_counter(&self, image_type: &str) -> Result<(), AppError> {
         if let Some(ref pool) = self.db_pool {
-            sqlx::query!(
-                "UPDATE image_counter SET count = count + 1, last_updated = datetime('now') WHERE image_type = ?",
-                image_type
+            // 计数行不存在时（新增的图片类型）自动创建，无需预先写入种子数据
+            sqlx::query(
+                "INSERT INTO image_counter (image_type, count, last_updated) VALUES (?, 1, datetime('now')) \
+                 ON CONFLICT(image_type) DO UPDATE SET count = count + 1, last_updated = datetime('now')",
             )
+            .bind(image_type)
             .execute(pool)
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
@@ -1295,30 +2047,84 @@ impl ImageService {
 
             let dv = difficulty_value.unwrap();
 
-            // 计算RKS
-            let rks = rks_utils::calculate_chart_rks(score.acc, dv);
-
-            let record = RksRecord {
-                song_id: song_info.id.clone(),
-                song_name: song_info.song.clone(),
-                difficulty: score.difficulty.clone(),
-                score: Some(score.score as f64),
-                acc: score.acc,
-                rks,
-                difficulty_value: dv,
-                is_fc: false, // 用户数据不提供FC信息
-            };
-
+            let record = Self::user_score_record(score, &song_info.id, &song_info.song, dv);
             rks_records.push(record);
             song_ids.push(song_info.id);
         }
 
+        let (stats, push_acc_map) =
+            Self::prepare_user_data_render(user_data.player_name, &mut rks_records);
+
+        log::info!("用户数据BN图片生成 - 数据处理耗时: {:?}", start_time.elapsed());
+
+        // 按需补全缺失的曲绘
+        cover_loader::fetch_missing_covers(&song_ids).await;
+
+        // 渲染图片
+        let render_start = std::time::Instant::now();
+        let theme = crate::controllers::image::Theme::default(); // 使用配置的默认主题
+
+        let permit = self.acquire_render_permit().await?;
+
+        // 将请求ID带入渲染线程，使渲染阶段的日志可与请求关联
+        let request_id = request_id::current_request_id();
+        let png_data_result = render_pool::run(move || {
+            let _permit = permit;
+            request_id::with_request_id(request_id, || {
+                Self::_render_bn_image_from_user_data_sync(
+                    rks_records,
+                    stats,
+                    push_acc_map,
+                    theme,
+                )
+            })
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Render task error: {e}")))?;
+
+        let png_data = png_data_result?;
+        log::info!("用户数据BN图片生成 - 渲染总耗时: {:?}", render_start.elapsed());
+
+        // 更新计数器
+        if let Err(e) = self.increment_counter("user-generated").await {
+            log::error!("更新用户生成图片计数器失败: {e}");
+        }
+
+        log::info!("用户数据BN图片生成 - 总耗时: {:?}", start_time.elapsed());
+        Ok(png_data)
+    }
+
+    /// 将一条用户提交的成绩转换为 RksRecord
+    fn user_score_record(
+        score: &crate::controllers::image::UserScoreRecord,
+        song_id: &str,
+        song_name: &str,
+        difficulty_value: f64,
+    ) -> RksRecord {
+        RksRecord {
+            song_id: song_id.to_string(),
+            song_name: song_name.to_string(),
+            difficulty: score.difficulty.clone(),
+            score: Some(score.score as f64),
+            acc: score.acc,
+            rks: rks_utils::calculate_chart_rks(score.acc, difficulty_value),
+            difficulty_value,
+            // AP 必然 FC，未提供 is_fc 时按非 FC 处理
+            is_fc: score.acc == 100.0 || score.is_fc.unwrap_or(false),
+        }
+    }
+
+    /// 将用户成绩按 RKS 排序，并计算渲染所需的统计数据与推分 ACC
+    fn prepare_user_data_render(
+        player_name: String,
+        rks_records: &mut [RksRecord],
+    ) -> (PlayerStats, HashMap<String, f64>) {
         // 按RKS排序
-        rks_records.sort_by(|a, b| b.rks.partial_cmp(&a.rks).unwrap_or(std::cmp::Ordering::Equal));
+        rks_records.sort_by(rks_utils::compare_records);
 
         // 计算统计数据
         let n = rks_records.len() as u32;
-        let (exact_rks, _) = rks_utils::calculate_player_rks_details(&rks_records);
+        let (exact_rks, _) = rks_utils::calculate_player_rks_details(rks_records);
 
         // 计算AP数量和B27平均值
         let ap_scores: Vec<RksRecord> = rks_records.iter().filter(|s| s.acc == 100.0).cloned().collect();
@@ -1371,7 +2177,8 @@ impl ImageService {
             ap_top_3_avg,
             best_27_avg,
             real_rks: Some(exact_rks),
-            player_name: Some(user_data.player_name),
+            player_name: Some(player_name),
+            region: None,
             update_time: Utc::now(),
             n,
             ap_top_3_scores,
@@ -1379,38 +2186,19 @@ impl ImageService {
             data_string: None, // 用户数据不提供数据信息
             custom_footer_text: Some("*由玩家提供数据生成".to_string()), // 标记数据来源
             is_user_generated: true, // 用户数据
+            ap_only: false,
+            constant_range: None,
+            empty_message: None,
+            acc_decimals: image_renderer::DEFAULT_ACC_DECIMALS,
+            background_seed: None, // 用户提供的数据没有存档校验和，背景仍随机选取
+            background_mode: crate::controllers::image::BackgroundMode::default(),
+            player_background: None,
+            play_dates: HashMap::new(),
+            lang: crate::controllers::image::ImageLang::default(),
+            layout: crate::controllers::image::BnLayout::default(),
         };
 
-        log::info!("用户数据BN图片生成 - 数据处理耗时: {:?}", start_time.elapsed());
-
-        // 渲染图片
-        let render_start = std::time::Instant::now();
-        let theme = crate::controllers::image::Theme::Black; // 默认使用黑色主题
-
-        let permit = self.render_semaphore.clone().acquire_owned().await.map_err(|e| AppError::InternalError(format!("Failed to acquire semaphore permit: {e}")))?;
-
-        let png_data_result = web::block(move || {
-            let _permit = permit;
-            Self::_render_bn_image_from_user_data_sync(
-                rks_records,
-                stats,
-                push_acc_map,
-                theme,
-            )
-        })
-        .await
-        .map_err(|e| AppError::InternalError(format!("Blocking task join error: {e}")))?;
-
-        let png_data = png_data_result?;
-        log::info!("用户数据BN图片生成 - 渲染总耗时: {:?}", render_start.elapsed());
-
-        // 更新计数器
-        if let Err(e) = self.increment_counter("user-generated").await {
-            log::error!("更新用户生成图片计数器失败: {e}");
-        }
-
-        log::info!("用户数据BN图片生成 - 总耗时: {:?}", start_time.elapsed());
-        Ok(png_data)
+        (stats, push_acc_map)
     }
 
     /// 同步执行的用户数据BN图片渲染函数
@@ -1427,12 +2215,138 @@ impl ImageService {
             Some(&push_acc_map),
             &theme,
             false,
+            image_renderer::RenderProfile::default(),
         )?;
         log::info!("用户数据BN图片生成 - SVG生成耗时: {:?}", svg_gen_start.elapsed());
 
         let png_render_start = std::time::Instant::now();
-        let result = image_renderer::render_svg_to_png(svg_string, true); // 用户数据
+        let result = image_renderer::render_svg_to_png(svg_string, true, 1.0); // 用户数据
         log::info!("用户数据BN图片生成 - PNG渲染耗时: {:?}", png_render_start.elapsed());
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::image::UserScoreRecord;
+
+    fn user_score(acc: f64, is_fc: Option<bool>) -> UserScoreRecord {
+        UserScoreRecord {
+            song_name: "Test".to_string(),
+            score: 990_000,
+            acc,
+            difficulty: "IN".to_string(),
+            is_fc,
+        }
+    }
+
+    #[test]
+    fn user_data_fc_score_renders_fc_card() {
+        let mut records = vec![
+            ImageService::user_score_record(&user_score(99.5, Some(true)), "fc", "FC", 15.0),
+            ImageService::user_score_record(&user_score(98.0, None), "plain", "Plain", 15.0),
+        ];
+        let (stats, push_acc_map) =
+            ImageService::prepare_user_data_render("Player".to_string(), &mut records);
+        assert!(records.iter().find(|r| r.song_id == "fc").unwrap().is_fc);
+        assert!(!records.iter().find(|r| r.song_id == "plain").unwrap().is_fc);
+
+        let svg = image_renderer::generate_svg_string(
+            &records,
+            &stats,
+            Some(&push_acc_map),
+            &crate::controllers::image::Theme::default(),
+            false,
+            image_renderer::RenderProfile::default(),
+        )
+        .unwrap();
+        assert_eq!(svg.matches(r#"class="card card-fc""#).count(), 1);
+    }
+
+    #[test]
+    fn user_data_full_acc_counts_as_fc() {
+        let record = ImageService::user_score_record(&user_score(100.0, None), "ap", "AP", 15.0);
+        assert!(record.is_fc);
+    }
+
+    #[tokio::test]
+    async fn counter_is_created_on_first_increment() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::utils::schema::migrate(&pool).await.unwrap();
+        let service = ImageService::new(1).with_db_pool(pool.clone());
+
+        service.increment_counter("never-seen").await.unwrap();
+        service.increment_counter("never-seen").await.unwrap();
+
+        let count: i64 =
+            sqlx::query_scalar("SELECT count FROM image_counter WHERE image_type = 'never-seen'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    fn empty_save_data() -> FullSaveData {
+        FullSaveData {
+            rks_result: crate::models::rks::RksResult::new(Vec::new()),
+            save: crate::models::save::GameSave {
+                game_key: None,
+                game_progress: None,
+                game_record: None,
+                settings: None,
+                user: None,
+            },
+            cloud_summary: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn bn_image_of_empty_save_is_empty_save_unless_allowed() {
+        let filter = crate::controllers::image::BnFilter::default();
+        assert!(matches!(
+            ImageService::ensure_bn_records(&[], filter, false),
+            Err(AppError::EmptySave)
+        ));
+        // allow_empty 时渲染占位图
+        assert!(ImageService::ensure_bn_records(&[], filter, true).is_ok());
+    }
+
+    #[test]
+    fn song_image_of_empty_save_is_empty_save() {
+        let song_service = web::Data::new(SongService::new());
+        let song_info = song_service.get_song_by_id("Glaciaxion.SunsetRay").unwrap();
+        let result = ImageService::_render_song_image_sync(
+            empty_save_data(),
+            None,
+            song_info,
+            song_service,
+            crate::controllers::image::SongImageEncoding {
+                format: crate::controllers::image::SongImageFormat::Svg,
+                quality: 0,
+                scale: crate::controllers::image::ImageScale::default(),
+            },
+            false,
+            2,
+            None,
+            crate::controllers::image::BackgroundMode::Gradient,
+            crate::controllers::image::ImageLang::default(),
+            HashMap::new(),
+        );
+        assert!(matches!(result, Err(AppError::EmptySave)));
+    }
+
+    #[test]
+    fn cached_empty_save_error_keeps_404() {
+        // 与 BN / 单曲 / 排行榜图片的缓存计算相同：错误经 Arc 共享后再还原
+        let err = AppError::from_shared(Arc::new(AppError::EmptySave));
+        assert_eq!(
+            actix_web::ResponseError::error_response(&err).status(),
+            actix_web::http::StatusCode::NOT_FOUND
+        );
+    }
+}
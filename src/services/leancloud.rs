@@ -10,20 +10,27 @@ pub struct LeanCloudService {
 
 impl LeanCloudService {
     pub fn new() -> Self {
+        // 请求头取自配置，启动时已通过 AppConfig::validate_leancloud 校验
+        let config = &crate::config::CONFIG;
         let mut phi = reqwest::header::HeaderMap::new();
         phi.append(
             "User-Agent",
-            "LeanCloud-CSharp-SDK/1.0.3"
+            config
+                .leancloud_user_agent
                 .parse()
                 .expect("无法解析User-Agent头"),
         );
         phi.append(
             "X-LC-Id",
-            "rAK3FfdieFob2Nn8Am".parse().expect("无法解析X-LC-Id头"),
+            config
+                .leancloud_client_id
+                .parse()
+                .expect("无法解析X-LC-Id头"),
         );
         phi.append(
             "X-LC-Key",
-            "Qr9AEqtuoSVS3zeD6iVbM4ZC0AtkJcQ89tywVyi0"
+            config
+                .leancloud_app_key
                 .parse()
                 .expect("无法解析X-LC-Key头"),
         );
@@ -61,7 +68,12 @@ impl LeanCloudService {
 
         let response = self
             .client
-            .post("https://rak3ffdi.cloud.tds1.tapapis.cn/1.1/users")
+            .post(format!(
+                "{}/users",
+                crate::config::CONFIG
+                    .leancloud_base_url
+                    .trim_end_matches('/')
+            ))
             .headers(self.phi.clone()) // HeaderMap 的克隆操作相对轻量，且 headers 方法需要所有权
             .body(body)
             .send()
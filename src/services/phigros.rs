@@ -1,27 +1,41 @@
 use crate::models::cloud_save::FullSaveData;
 use crate::models::rks::RksResult;
-use crate::models::save::{GameSave, SongRecord};
+use crate::models::save::{GameSave, SaveSummary, SongRecord};
 use crate::models::user::UserProfile;
+use crate::services::player_archive_service::PlayerArchiveService;
+use crate::utils::circuit_breaker;
+use crate::utils::data_loader::{
+    get_difficulty_by_id, get_predicted_constant, get_song_name_by_id,
+};
 use crate::utils::error::{AppError, AppResult};
-use crate::utils::save_parser::{parse_save, parse_save_with_difficulty};
+use crate::utils::save_parser::{parse_save, parse_save_summary, parse_save_with_difficulty};
+use crate::utils::text::truncate_chars;
+use crate::utils::token_helper;
 use reqwest::Client;
 use std::time::Duration;
 use std::collections::HashMap;
 use serde_json::json;
 
-// Phigros API相关的常量
-const BASE_URL: &str = "https://rak3ffdi.cloud.tds1.tapapis.cn/1.1/";
-const LC_ID: &str = "rAK3FfdieFob2Nn8Am";
-const LC_KEY: &str = "Qr9AEqtuoSVS3zeD6iVbM4ZC0AtkJcQ89tywVyi0";
-const USER_AGENT: &str = "LeanCloud-CSharp-SDK/1.0.3";
-
 // 外部数据源API常量
 const EXTERNAL_API_URL: &str = "http://phib19.top:8080/get/cloud/saves";
+/// 外部数据源在熔断器中的名称
+const EXTERNAL_SOURCE_NAME: &str = "phib19";
+
+/// 云端存档的版本标识，取自存档摘要
+#[derive(Debug, Clone)]
+pub struct SaveVersion {
+    /// 存档校验和，用作图片缓存键
+    pub checksum: String,
+    /// 存档更新时间 (LeanCloud `updatedAt`)，摘要中缺失时为空
+    pub updated_at: Option<String>,
+}
 
 // Phigros服务，管理与Phigros API交互、存档解析等
 #[derive(Clone)]
 pub struct PhigrosService {
     client: Client,
+    // 保存原始存档快照，启用 STORE_SAVE_SNAPSHOTS 时生效
+    snapshot_store: Option<PlayerArchiveService>,
 }
 
 impl PhigrosService {
@@ -37,7 +51,27 @@ impl PhigrosService {
                 log::warn!("构建 HTTP 客户端失败，回退默认设置: {e}");
                 Client::new()
             });
-        Self { client }
+        Self {
+            client,
+            snapshot_store: None,
+        }
+    }
+
+    // 设置原始存档快照的存储位置，每次下载到的存档都会按玩家保存一份
+    pub fn with_snapshot_store(mut self, player_archive_service: PlayerArchiveService) -> Self {
+        self.snapshot_store = Some(player_archive_service);
+        self
+    }
+
+    // 保存下载到的原始存档 (加密状态)，不会保存令牌等认证信息
+    fn store_snapshot(&self, player_id: Option<&str>, save_data: &[u8]) {
+        let Some(store) = &self.snapshot_store else {
+            return;
+        };
+        match player_id {
+            Some(player_id) => store.spawn_save_snapshot(player_id.to_string(), save_data.to_vec()),
+            None => log::debug!("无法确定存档所属的玩家ID，跳过保存存档快照"),
+        }
     }
 
     // 获取存档数据并解析
@@ -46,25 +80,6 @@ impl PhigrosService {
         parse_save(&save_data)
     }
 
-    // 增强版：根据数据源获取存档数据并解析
-    pub async fn get_save_with_source(&self, request: &crate::models::user::IdentifierRequest) -> AppResult<GameSave> {
-        match request.data_source.as_deref() {
-            Some("external") => {
-                // 使用外部数据源
-                let request_data = Self::build_external_request_data(request)?;
-                let (_, save_data) = self.get_external_save_data(request_data).await?;
-                parse_save(&save_data)
-            },
-            _ => {
-                // 使用内部数据源（默认）
-                let token = request.token.as_ref()
-                    .ok_or_else(|| AppError::Other("内部数据源需要token".to_string()))?;
-                let save_data = self.fetch_save(token).await?;
-                parse_save(&save_data)
-            }
-        }
-    }
-
     // 获取存档数据并解析，添加难度和RKS信息
     pub async fn get_save_with_difficulty(&self, token: &str) -> AppResult<GameSave> {
         let save_data = self.fetch_save(token).await?;
@@ -113,6 +128,7 @@ impl PhigrosService {
         log::debug!("进入 get_rks_with_source (重构版) 服务函数");
 
         let full_data = self.get_full_save_data_with_source(request).await?;
+        full_data.save.played_records()?;
 
         let (player_id, player_name) = match request.data_source.as_deref() {
             Some("external") => {
@@ -138,37 +154,6 @@ impl PhigrosService {
         Ok((full_data.rks_result, full_data.save, player_id, player_name))
     }
 
-    // 获取特定歌曲的成绩
-    pub async fn get_song_record(
-        &self,
-        token: &str,
-        song_id: &str,
-        difficulty: Option<&str>,
-    ) -> AppResult<HashMap<String, SongRecord>> {
-        let save = self.get_save_with_difficulty(token).await?;
-
-        let game_record = save
-            .game_record
-            .ok_or_else(|| AppError::Other("没有游戏记录数据".to_string()))?;
-
-        let song_records = game_record
-            .get(song_id)
-            .ok_or_else(|| AppError::SongNotFound(song_id.to_string()))?;
-
-        if let Some(diff) = difficulty {
-            let mut result = HashMap::new();
-
-            let record = song_records.get(diff).ok_or_else(|| {
-                AppError::Other(format!("没有找到歌曲 {song_id} 的 {diff} 难度记录"))
-            })?;
-
-            result.insert(diff.to_string(), record.clone());
-            return Ok(result);
-        }
-
-        Ok(song_records.clone())
-    }
-
     // 增强版：根据数据源获取特定歌曲的成绩
     pub async fn get_song_record_with_source(
         &self,
@@ -177,27 +162,7 @@ impl PhigrosService {
         difficulty: Option<&str>,
     ) -> AppResult<HashMap<String, SongRecord>> {
         let save = self.get_save_with_difficulty_and_source(request).await?;
-
-        let game_record = save
-            .game_record
-            .ok_or_else(|| AppError::Other("没有游戏记录数据".to_string()))?;
-
-        let song_records = game_record
-            .get(song_id)
-            .ok_or_else(|| AppError::SongNotFound(song_id.to_string()))?;
-
-        if let Some(diff) = difficulty {
-            let mut result = HashMap::new();
-
-            let record = song_records.get(diff).ok_or_else(|| {
-                AppError::Other(format!("没有找到歌曲 {song_id} 的 {diff} 难度记录"))
-            })?;
-
-            result.insert(diff.to_string(), record.clone());
-            return Ok(result);
-        }
-
-        Ok(song_records.clone())
+        song_records_from_save(&save, song_id, difficulty)
     }
 
     // 从Phigros云端获取存档数据
@@ -243,23 +208,44 @@ impl PhigrosService {
         }
         log::debug!("存档校验和匹配成功");
 
+        // 存档摘要的 user 字段指向存档所属账号，与内部数据源的玩家ID (objectId) 一致
+        self.store_snapshot(
+            summary["results"][0]["user"]["objectId"].as_str(),
+            &save_data,
+        );
+
         Ok(save_data)
     }
 
+    // 构造携带 LeanCloud 请求头的 GET 请求，地址与请求头均取自配置
+    fn leancloud_get(&self, path: &str, token: &str) -> reqwest::RequestBuilder {
+        let config = &crate::config::CONFIG;
+        self.client
+            .get(format!(
+                "{}/{path}",
+                config.leancloud_base_url.trim_end_matches('/')
+            ))
+            .header("X-LC-Id", &config.leancloud_client_id)
+            .header("X-LC-Key", &config.leancloud_app_key)
+            .header("User-Agent", &config.leancloud_user_agent)
+            .header("Accept", "application/json")
+            .header("X-LC-Session", token)
+    }
+
     // 获取存档摘要信息
     async fn fetch_summary(&self, token: &str) -> AppResult<serde_json::Value> {
         let response = self
-            .client
-            .get(format!("{BASE_URL}classes/_GameSave?limit=1"))
-            .header("X-LC-Id", LC_ID)
-            .header("X-LC-Key", LC_KEY)
-            .header("User-Agent", USER_AGENT)
-            .header("Accept", "application/json")
-            .header("X-LC-Session", token)
+            .leancloud_get("classes/_GameSave?limit=1", token)
             .send()
             .await?;
 
         if !response.status().is_success() {
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(AppError::AuthError(format!(
+                    "Token 无效或已过期，{}",
+                    token_helper::REAUTH_HINT
+                )));
+            }
             return Err(AppError::Other(format!(
                 "获取存档摘要失败: HTTP {}",
                 response.status()
@@ -272,7 +258,12 @@ impl PhigrosService {
 
     // 下载存档数据
     async fn download_save(&self, url: &str) -> AppResult<Vec<u8>> {
-        let response = self.client.get(url).send().await?;
+        let response = self
+            .client
+            .get(url)
+            .header("User-Agent", &crate::config::CONFIG.leancloud_user_agent)
+            .send()
+            .await?;
 
         if !response.status().is_success() {
             return Err(AppError::Other(format!(
@@ -298,16 +289,7 @@ impl PhigrosService {
     // 获取用户Profile信息
     pub async fn get_profile(&self, token: &str) -> AppResult<UserProfile> {
         log::debug!("开始获取用户 Profile 信息...");
-        let response = self
-            .client
-            .get(format!("{BASE_URL}users/me"))
-            .header("X-LC-Id", LC_ID)
-            .header("X-LC-Key", LC_KEY)
-            .header("User-Agent", USER_AGENT)
-            .header("Accept", "application/json")
-            .header("X-LC-Session", token)
-            .send()
-            .await?;
+        let response = self.leancloud_get("users/me", token).send().await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -317,7 +299,10 @@ impl PhigrosService {
                 .unwrap_or_else(|_| "无法读取错误信息".to_string());
             log::error!("获取 Profile 失败: HTTP {status}, 响应: {error_text}");
             if status == reqwest::StatusCode::UNAUTHORIZED {
-                return Err(AppError::AuthError("Token 无效或已过期".to_string()));
+                return Err(AppError::AuthError(format!(
+                    "Token 无效或已过期，{}",
+                    token_helper::REAUTH_HINT
+                )));
             }
             return Err(AppError::Other(format!("获取 Profile 失败: HTTP {status}")));
         }
@@ -341,14 +326,30 @@ impl PhigrosService {
         Ok(summary)
     }
 
-    // 获取存档的校验和，用于作为缓存键的一部分
-    pub async fn get_save_checksum(&self, token: &str) -> AppResult<String> {
+    // 获取并解码云端存档元数据，只请求一次摘要，不下载存档文件
+    pub async fn get_save_summary(&self, token: &str) -> AppResult<SaveSummary> {
+        let summary = self.fetch_summary(token).await?;
+        let save_info = &summary["results"][0];
+        if save_info.is_null() {
+            return Err(AppError::Other("云端没有存档".to_string()));
+        }
+        parse_save_summary(save_info)
+    }
+
+    // 获取存档的校验和与更新时间，只请求一次摘要
+    pub async fn get_save_version(&self, token: &str) -> AppResult<SaveVersion> {
         let summary = self.fetch_summary(token).await?;
         let checksum = summary["results"][0]["gameFile"]["metaData"]["_checksum"]
             .as_str()
             .ok_or_else(|| AppError::Other("无法获取存档校验和".to_string()))?
             .to_string();
-        Ok(checksum)
+        let updated_at = summary["results"][0]["updatedAt"]
+            .as_str()
+            .map(str::to_string);
+        Ok(SaveVersion {
+            checksum,
+            updated_at,
+        })
     }
 
     // 调用外部数据源API - 支持多种认证方式
@@ -356,27 +357,49 @@ impl PhigrosService {
     pub async fn get_external_save_data(&self, request_data: serde_json::Value) -> AppResult<(serde_json::Value, Vec<u8>)> {
         log::debug!("开始调用外部API获取存档数据，请求数据: {}", request_data);
 
-        let response = self
+        // 上游持续故障时直接失败，避免每个请求都等待超时
+        let breaker = circuit_breaker::breaker_for(EXTERNAL_SOURCE_NAME);
+        breaker.check()?;
+
+        let response = match self
             .client
             .post(EXTERNAL_API_URL)
             .json(&request_data)
             .send()
             .await
-            .map_err(|e| AppError::Other(format!("外部API请求失败: {e}")))?;
+        {
+            Ok(response) => response,
+            Err(e) => {
+                breaker.record_failure();
+                return Err(AppError::Other(format!("外部API请求失败: {e}")));
+            }
+        };
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
             log::error!("外部API返回错误状态: HTTP {status}, 响应: {error_text}");
 
+            // 鉴权失败说明上游工作正常，只有服务端错误计入熔断
+            if status.is_server_error() {
+                breaker.record_failure();
+            } else {
+                breaker.record_success();
+            }
             if status == reqwest::StatusCode::BAD_REQUEST {
                 return Err(AppError::AuthError("外部API鉴权失败".to_string()));
             }
             return Err(AppError::Other(format!("外部API错误: HTTP {status}")));
         }
 
-        let external_response: serde_json::Value = response.json().await
-            .map_err(|e| AppError::Other(format!("解析外部API响应失败: {e}")))?;
+        let external_response: serde_json::Value = match response.json().await {
+            Ok(value) => value,
+            Err(e) => {
+                breaker.record_failure();
+                return Err(AppError::Other(format!("解析外部API响应失败: {e}")));
+            }
+        };
+        breaker.record_success();
 
         log::debug!("成功从外部API获取数据");
 
@@ -386,6 +409,12 @@ impl PhigrosService {
             .ok_or_else(|| AppError::Other("外部API响应中没有saveUrl".to_string()))?;
 
         let save_data = self.download_save(save_url).await?;
+        self.store_snapshot(
+            external_response["data"]["saveInfo"]["PlayerId"]
+                .as_str()
+                .or_else(|| external_response["data"]["apiId"].as_str()),
+            &save_data,
+        );
         Ok((external_response, save_data))
     }
 
@@ -431,7 +460,7 @@ impl PhigrosService {
 
         if let Some(token) = &request.token {
             // Token认证 - 使用token的前8位作为PlayerId
-            return Ok(format!("token:{}", &token[..std::cmp::min(8, token.len())]));
+            return Ok(format!("token:{}", truncate_chars(token, 8)));
         }
 
         Err(AppError::Other("无法从请求中提取PlayerId".to_string()))
@@ -496,6 +525,10 @@ impl PhigrosService {
 
                 log::debug!("从外部API响应中提取到玩家名称: {}, PlayerId: {}", player_name, player_id);
 
+                // 头像与地区为可选字段，外部API未返回时保持为 null
+                let avatar = Self::external_profile_field(&external_response, "avatar");
+                let region = Self::external_profile_field(&external_response, "region");
+
                 // 构造云端摘要，包含从外部API获取的真实数据
                 let updated_at = external_response["data"]["saveInfo"]["modifiedAt"]["iso"]
                     .as_str()
@@ -511,7 +544,9 @@ impl PhigrosService {
                         },
                         "updatedAt": updated_at,
                         "PlayerId": player_id,
-                        "nickname": player_name
+                        "nickname": player_name,
+                        "avatar": avatar,
+                        "region": region
                     }]
                 });
 
@@ -530,20 +565,32 @@ impl PhigrosService {
         }
     }
 
+    // 辅助函数：外部API不同版本的返回结构不一致，依次从 saveInfo 与 data 中读取非空字符串字段
+    fn external_profile_field(
+        external_response: &serde_json::Value,
+        field: &str,
+    ) -> Option<String> {
+        [
+            &external_response["data"]["saveInfo"][field],
+            &external_response["data"][field],
+        ]
+        .into_iter()
+        .filter_map(|v| v.as_str())
+        .map(str::trim)
+        .find(|s| !s.is_empty())
+        .map(str::to_string)
+    }
+
     // 辅助函数：从已解析的GameSave中计算RKS
+    // 没有 gameRecord 的存档 (新账号) 视为没有成绩，由调用方决定返回 EmptySave 还是占位结果
     fn calculate_rks_from_save(&self, save: &GameSave) -> AppResult<RksResult> {
-        let game_record = save
-            .game_record
-            .as_ref()
-            .ok_or_else(|| AppError::Other("没有游戏记录数据".to_string()))?;
-
         let mut rks_records = Vec::new();
-        for (song_id, difficulties) in game_record {
+        for (song_id, difficulties) in save.game_record.iter().flatten() {
             let song_name = crate::utils::data_loader::get_song_name_by_id(song_id)
                 .unwrap_or_else(|| song_id.clone());
             for (diff_name, record) in difficulties {
                 if let (Some(acc), Some(difficulty)) = (record.acc, record.difficulty) {
-                    if acc >= 70.0 && difficulty > 0.0 {
+                    if crate::utils::rks_utils::is_rks_eligible_acc(acc) && difficulty > 0.0 {
                         let rks_record = crate::models::rks::RksRecord::new(
                             song_id.clone(),
                             song_name.clone(),
@@ -559,3 +606,106 @@ impl PhigrosService {
         Ok(RksResult::new(rks_records))
     }
 }
+
+/// 从存档中取出指定歌曲的成绩，可按难度过滤
+///
+/// 附带谱面元数据 (曲名、定数与预测定数)，客户端无需再查询。
+fn song_records_from_save(
+    save: &GameSave,
+    song_id: &str,
+    difficulty: Option<&str>,
+) -> AppResult<HashMap<String, SongRecord>> {
+    let song_records = save
+        .played_records()?
+        .get(song_id)
+        .ok_or_else(|| AppError::SongNotFound(song_id.to_string()))?;
+
+    let mut result = if let Some(diff) = difficulty {
+        let record = song_records
+            .get(diff)
+            .ok_or_else(|| AppError::Other(format!("没有找到歌曲 {song_id} 的 {diff} 难度记录")))?;
+        HashMap::from([(diff.to_string(), record.clone())])
+    } else {
+        song_records.clone()
+    };
+
+    let song_name = get_song_name_by_id(song_id);
+    for (diff_name, record) in result.iter_mut() {
+        if record.difficulty.is_none() {
+            record.difficulty = get_difficulty_by_id(song_id, diff_name);
+        }
+        record.song_name = song_name.clone();
+        record.predicted_constant = get_predicted_constant(song_id, diff_name);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save_with_glaciaxion() -> GameSave {
+        let record = |score: f64, acc: f64| SongRecord {
+            score: Some(score),
+            acc: Some(acc),
+            fc: Some(false),
+            ..SongRecord::default()
+        };
+        GameSave {
+            game_key: None,
+            game_progress: None,
+            game_record: Some(HashMap::from([(
+                "Glaciaxion.SunsetRay".to_string(),
+                HashMap::from([
+                    ("HD".to_string(), record(980_000.0, 98.5)),
+                    ("IN".to_string(), record(950_000.0, 96.0)),
+                ]),
+            )])),
+            settings: None,
+            user: None,
+        }
+    }
+
+    #[test]
+    fn song_records_include_chart_metadata() {
+        let records =
+            song_records_from_save(&save_with_glaciaxion(), "Glaciaxion.SunsetRay", None).unwrap();
+        assert_eq!(records.len(), 2);
+        let hd = &records["HD"];
+        assert_eq!(hd.song_name.as_deref(), Some("Glaciaxion"));
+        assert_eq!(hd.difficulty, Some(6.5));
+        assert_eq!(records["IN"].difficulty, Some(12.6));
+
+        // 接口返回的 JSON 中包含曲名与定数
+        let json = serde_json::to_value(&records).unwrap();
+        assert_eq!(json["IN"]["song_name"], "Glaciaxion");
+        assert_eq!(json["IN"]["difficulty"], 12.6);
+    }
+
+    #[test]
+    fn song_records_can_be_filtered_by_difficulty() {
+        let save = save_with_glaciaxion();
+        let records = song_records_from_save(&save, "Glaciaxion.SunsetRay", Some("IN")).unwrap();
+        assert_eq!(records.keys().collect::<Vec<_>>(), ["IN"]);
+        assert_eq!(records["IN"].song_name.as_deref(), Some("Glaciaxion"));
+
+        assert!(song_records_from_save(&save, "Glaciaxion.SunsetRay", Some("AT")).is_err());
+        assert!(matches!(
+            song_records_from_save(&save, "Rrharil.TeamGrimoire", None),
+            Err(AppError::SongNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn song_records_of_empty_save_is_empty_save() {
+        let save = GameSave {
+            game_record: None,
+            ..save_with_glaciaxion()
+        };
+        assert!(matches!(
+            song_records_from_save(&save, "Glaciaxion.SunsetRay", None),
+            Err(AppError::EmptySave)
+        ));
+    }
+}
@@ -1,8 +1,16 @@
+use crate::config::CONFIG;
+use crate::models::cache::{CacheInfo, CacheName};
 use crate::models::player_archive::{
-    ArchiveConfig, ChartScore, ChartScoreHistory, PlayerArchive, RKSRankingEntry,
+    ArchiveConfig, ArchiveMergeResult, ArchiveResetResult, ArchiveWriteQueueStats, ChartPercentile,
+    ChartScore, ChartScoreHistory, DuplicateArchiveCandidate, PlayerArchive, PlayerBasicInfo,
+    RKSRankingEntry, RksGain, RksHistory, RksRecomputeResult, RksSnapshot, SaveSnapshotInfo,
 };
 use crate::models::rks::RksRecord;
+use crate::models::save::GameSave;
+use crate::utils::data_loader;
 use crate::utils::error::AppError;
+use crate::utils::rks_utils;
+use crate::utils::rks_webhook::{self, RksChangePayload};
 use chrono::{DateTime, Utc};
 use futures::TryStreamExt;
 use log;
@@ -10,8 +18,70 @@ use moka::future::Cache;
 use sqlx::Row;
 use sqlx::{query, query_as, SqlitePool};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// 全量重算 RKS 时每批处理的玩家数，每批结束后输出一次进度
+const RECOMPUTE_BATCH_SIZE: usize = 50;
+
+/// 等待写入的成绩增量更新
+struct PendingScoreUpdate {
+    player_name: String,
+    rks_records: Vec<RksRecord>,
+    fc_map: HashMap<String, bool>,
+    is_external: bool,
+}
+
+/// 按玩家ID登记的后台写入任务，值为任务期间到达的最新一次更新
+type InFlightUpdates = Arc<Mutex<HashMap<String, Option<PendingScoreUpdate>>>>;
+
+/// 后台写入任务持有的登记，任务结束时注销
+///
+/// 任务中途 panic 时由 `Drop` 注销，否则该玩家的登记会一直留在表中，之后的更新都只会被合并而不再写入。
+struct InFlightGuard {
+    in_flight: InFlightUpdates,
+    player_id: String,
+    registered: bool,
+}
+
+impl InFlightGuard {
+    fn new(in_flight: InFlightUpdates, player_id: String) -> Self {
+        Self {
+            in_flight,
+            player_id,
+            registered: true,
+        }
+    }
+
+    /// 取出任务期间到达的最新更新；`finish` 为 true 且没有新的更新时注销该玩家的写入任务
+    fn take_pending(&mut self, finish: bool) -> Option<PendingScoreUpdate> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let pending = in_flight.get_mut(&self.player_id).and_then(Option::take);
+        if pending.is_none() && finish {
+            in_flight.remove(&self.player_id);
+            self.registered = false;
+        }
+        pending
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if !self.registered {
+            return;
+        }
+        log::warn!(
+            "玩家 {} 的后台写入任务异常结束，注销写入登记",
+            self.player_id
+        );
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.player_id);
+    }
+}
 
 #[derive(Clone)]
 pub struct PlayerArchiveService {
@@ -19,6 +89,14 @@ pub struct PlayerArchiveService {
     config: ArchiveConfig,
     // 使用 moka 作为高性能并发缓存
     cache: Cache<String, Arc<PlayerArchive>>,
+    // 限制后台写入任务的并发，避免突发请求占满 SQLite 连接池
+    write_semaphore: Arc<Semaphore>,
+    // 正在等待写入许可的任务数
+    queued_writes: Arc<AtomicUsize>,
+    // 按玩家ID登记正在排队或执行的写入任务，值为任务期间到达的最新一次更新
+    in_flight_updates: InFlightUpdates,
+    // 因同一玩家已有写入任务而被合并的更新次数
+    coalesced_writes: Arc<AtomicU64>,
 }
 
 impl PlayerArchiveService {
@@ -31,11 +109,297 @@ impl PlayerArchiveService {
             .time_to_live(Duration::from_secs(300))
             .build();
 
+        let config = config.unwrap_or_default();
+        let write_semaphore = Arc::new(Semaphore::new(config.max_concurrent_writes.max(1)));
+
         Self {
             pool,
-            config: config.unwrap_or_default(),
+            config,
             cache,
+            write_semaphore,
+            queued_writes: Arc::new(AtomicUsize::new(0)),
+            in_flight_updates: Arc::new(Mutex::new(HashMap::new())),
+            coalesced_writes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 存档缓存的条目数
+    pub async fn cache_info(&self) -> CacheInfo {
+        CacheInfo::of(CacheName::PlayerArchive, &self.cache).await
+    }
+
+    /// 清除存档缓存，返回清除的条目数；指定玩家ID时只清除该玩家的缓存
+    pub async fn evict_cache(&self, player_id: Option<&str>) -> u64 {
+        let evicted = match player_id {
+            Some(player_id) => {
+                let existed = self.cache.contains_key(player_id);
+                self.cache.invalidate(player_id).await;
+                u64::from(existed)
+            }
+            None => {
+                self.cache.run_pending_tasks().await;
+                let count = self.cache.entry_count();
+                self.cache.invalidate_all();
+                count
+            }
+        };
+        log::info!("已清除 {evicted} 条存档缓存: player_id={player_id:?}");
+        evicted
+    }
+
+    /// 获取后台写入许可，许可不足时排队等待
+    async fn acquire_write_permit(&self) -> Option<OwnedSemaphorePermit> {
+        self.queued_writes.fetch_add(1, Ordering::Relaxed);
+        let permit = self.write_semaphore.clone().acquire_owned().await.ok();
+        self.queued_writes.fetch_sub(1, Ordering::Relaxed);
+        permit
+    }
+
+    /// 后台存档写入队列状态
+    pub fn write_queue_stats(&self) -> ArchiveWriteQueueStats {
+        let max_concurrent = self.config.max_concurrent_writes.max(1);
+        ArchiveWriteQueueStats {
+            max_concurrent,
+            running: max_concurrent.saturating_sub(self.write_semaphore.available_permits()),
+            queued: self.queued_writes.load(Ordering::Relaxed),
+            coalesced: self.coalesced_writes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 按存档在后台更新玩家成绩：从存档中取出 FC 状态后调用 [`Self::spawn_score_update`]
+    pub fn spawn_update_from_save(
+        &self,
+        player_id: String,
+        player_name: String,
+        save: &GameSave,
+        rks_records: Vec<RksRecord>,
+        is_external: bool,
+    ) {
+        let fc_map = save
+            .game_record
+            .iter()
+            .flatten()
+            .flat_map(|(song_id, difficulties)| {
+                difficulties
+                    .iter()
+                    .filter(|(_, record)| record.fc == Some(true))
+                    .map(move |(diff_name, _)| (format!("{song_id}-{diff_name}"), true))
+            })
+            .collect();
+        self.spawn_score_update(player_id, player_name, rks_records, fc_map, is_external);
+    }
+
+    /// 在后台排队执行成绩增量更新，同时执行的写入任务数受 `max_concurrent_writes` 限制
+    ///
+    /// 每个玩家同一时间只有一个写入任务。任务排队或执行期间到达的更新不再另起任务，
+    /// 只保留最新的一次，由该任务在当前写入完成后接着写入，避免突发请求重复写入同一份存档。
+    pub fn spawn_score_update(
+        &self,
+        player_id: String,
+        player_name: String,
+        rks_records: Vec<RksRecord>,
+        fc_map: HashMap<String, bool>,
+        is_external: bool,
+    ) {
+        let update = PendingScoreUpdate {
+            player_name,
+            rks_records,
+            fc_map,
+            is_external,
+        };
+        {
+            let mut in_flight = self.in_flight_updates.lock().unwrap();
+            if let Some(pending) = in_flight.get_mut(&player_id) {
+                // 尚未写入的旧更新直接被替换
+                *pending = Some(update);
+                self.coalesced_writes.fetch_add(1, Ordering::Relaxed);
+                log::debug!("玩家 {player_id} 已有后台写入任务，合并本次存档更新");
+                return;
+            }
+            in_flight.insert(player_id.clone(), None);
+        }
+
+        let service = self.clone();
+        let mut guard = InFlightGuard::new(self.in_flight_updates.clone(), player_id.clone());
+        tokio::spawn(async move {
+            let mut update = update;
+            loop {
+                let permit = service.acquire_write_permit().await;
+                // 等待许可期间到达了更新的数据时，直接写入最新的一次
+                if let Some(latest) = guard.take_pending(false) {
+                    update = latest;
+                }
+                if let Err(e) = service
+                    .update_player_scores_from_rks_records(
+                        &player_id,
+                        &update.player_name,
+                        &update.rks_records,
+                        &update.fc_map,
+                        update.is_external,
+                    )
+                    .await
+                {
+                    log::error!(
+                        "后台更新玩家 {} ({player_id}) 存档失败: {e}",
+                        update.player_name
+                    );
+                }
+                drop(permit);
+
+                match guard.take_pending(true) {
+                    Some(next) => update = next,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// 在后台按固定间隔清理全库超出保留数量的历史成绩，启动后立即执行一次
+    pub fn spawn_history_pruning(&self, interval: Duration) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let _permit = service.acquire_write_permit().await;
+                if let Err(e) = service.prune_history().await {
+                    log::error!("定期清理历史成绩失败: {e}");
+                }
+            }
+        });
+    }
+
+    /// 清理全库超出保留数量的历史成绩，每个玩家的每个谱面只保留最近 `history_max_records` 条
+    ///
+    /// 成绩更新时只清理本次有变化的玩家，调小保留数量后遗留的旧记录由此统一清理。
+    pub async fn prune_history(&self) -> Result<u64, AppError> {
+        let deleted = query(
+            "DELETE FROM chart_scores WHERE id IN (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER(PARTITION BY player_id, song_id, difficulty ORDER BY play_time DESC) AS history_rank
+                    FROM chart_scores
+                    WHERE is_current = 0
+                ) WHERE history_rank > ?
+            )",
+        )
+        .bind(self.config.history_max_records as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("清理历史成绩失败: {e}")))?
+        .rows_affected();
+
+        if deleted > 0 {
+            // 存档缓存中包含历史记录，清理后统一失效
+            self.cache.invalidate_all();
+            log::info!("已清理 {deleted} 条超出保留数量的历史成绩");
+        } else {
+            log::debug!("没有需要清理的历史成绩");
+        }
+        Ok(deleted)
+    }
+
+    /// 在后台保存一份原始云存档快照，未启用 `STORE_SAVE_SNAPSHOTS` 时不做任何事
+    ///
+    /// 只保存下载到的加密存档文件本身，不包含令牌等认证信息；
+    /// 超过 `SAVE_SNAPSHOT_MAX_BYTES` 的存档不保存，同一玩家校验和相同的存档只保存一次。
+    pub fn spawn_save_snapshot(&self, player_id: String, save_data: Vec<u8>) {
+        if !CONFIG.store_save_snapshots {
+            return;
+        }
+        if save_data.len() > CONFIG.save_snapshot_max_bytes {
+            log::warn!(
+                "玩家 {player_id} 的存档大小 {} 字节超过快照上限 {} 字节，跳过保存",
+                save_data.len(),
+                CONFIG.save_snapshot_max_bytes
+            );
+            return;
+        }
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            let _permit = service.acquire_write_permit().await;
+            if let Err(e) = service.store_save_snapshot(&player_id, &save_data).await {
+                log::error!("保存玩家 {player_id} 的存档快照失败: {e}");
+            }
+        });
+    }
+
+    /// 保存原始存档快照，并删除该玩家超出 `SAVE_SNAPSHOT_RETENTION` 的旧快照
+    async fn store_save_snapshot(&self, player_id: &str, save_data: &[u8]) -> Result<(), AppError> {
+        use md5::{Digest, Md5};
+
+        let checksum = format!("{:x}", Md5::digest(save_data));
+        let inserted = query(
+            "INSERT OR IGNORE INTO save_snapshots (player_id, checksum, size, data, created_at)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(player_id)
+        .bind(&checksum)
+        .bind(save_data.len() as i64)
+        .bind(save_data)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("写入存档快照失败: {e}")))?
+        .rows_affected();
+        if inserted == 0 {
+            log::debug!("玩家 {player_id} 的存档快照 {checksum} 已存在");
+            return Ok(());
         }
+
+        let pruned = query(
+            "DELETE FROM save_snapshots WHERE player_id = ? AND id NOT IN (
+                SELECT id FROM save_snapshots WHERE player_id = ? ORDER BY id DESC LIMIT ?
+            )",
+        )
+        .bind(player_id)
+        .bind(player_id)
+        .bind(CONFIG.save_snapshot_retention as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("清理存档快照失败: {e}")))?
+        .rows_affected();
+        log::debug!("已保存玩家 {player_id} 的存档快照 {checksum}，清理旧快照 {pruned} 份");
+        Ok(())
+    }
+
+    /// 列出玩家的原始存档快照，按保存时间倒序
+    pub async fn list_save_snapshots(
+        &self,
+        player_id: &str,
+    ) -> Result<Vec<SaveSnapshotInfo>, AppError> {
+        query_as(
+            "SELECT id, player_id, checksum, size, created_at FROM save_snapshots
+             WHERE player_id = ? ORDER BY id DESC",
+        )
+        .bind(player_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询存档快照失败: {e}")))
+    }
+
+    /// 读取原始存档快照的元数据与存档内容
+    pub async fn get_save_snapshot(
+        &self,
+        snapshot_id: i64,
+    ) -> Result<(SaveSnapshotInfo, Vec<u8>), AppError> {
+        let row = query(
+            "SELECT id, player_id, checksum, size, created_at, data FROM save_snapshots WHERE id = ?",
+        )
+        .bind(snapshot_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询存档快照失败: {e}")))?
+        .ok_or_else(|| AppError::UserNotFound(format!("存档快照不存在: {snapshot_id}")))?;
+
+        let info = SaveSnapshotInfo {
+            id: row.get("id"),
+            player_id: row.get("player_id"),
+            checksum: row.get("checksum"),
+            size: row.get("size"),
+            created_at: row.get("created_at"),
+        };
+        Ok((info, row.get("data")))
     }
 
     /// 获取玩家存档 (已重构)
@@ -196,9 +560,10 @@ ORDER BY rs.play_time DESC;
 
         // 获取BestN成绩
         all_current_scores.sort_by(|a, b| {
-            b.rks
-                .partial_cmp(&a.rks)
-                .unwrap_or(std::cmp::Ordering::Equal)
+            rks_utils::compare_rank(
+                (a.rks, a.acc, &a.song_id, &a.difficulty),
+                (b.rks, b.acc, &b.song_id, &b.difficulty),
+            )
         });
         all_current_scores.truncate(self.config.best_n_count as usize);
 
@@ -294,6 +659,14 @@ ORDER BY rs.play_time DESC;
             "玩家[{player_id}]RKS计算: Best{best_n_count}平均={best_n_avg:.4}, AP Top {ap_count}平均={ap_avg:.4}, 最终RKS={final_rks:.4}"
         );
 
+        // 读取更新前的RKS，用于变化通知
+        let previous: Option<(String, f64)> =
+            sqlx::query_as("SELECT player_name, rks FROM player_archives WHERE player_id = ?")
+                .bind(player_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("查询玩家原RKS失败: {e}")))?;
+
         // 更新玩家RKS
         let update_time_str = Utc::now().to_rfc3339();
         query!(
@@ -306,12 +679,35 @@ ORDER BY rs.play_time DESC;
         .await
         .map_err(|e| AppError::DatabaseError(format!("更新玩家RKS失败: {e}")))?;
 
+        // 记录当日 RKS 快照，同一天内多次更新只保留最后一次
+        sqlx::query(
+            "INSERT INTO rks_snapshots (player_id, snapshot_date, rks) VALUES (?, ?, ?)
+             ON CONFLICT(player_id, snapshot_date) DO UPDATE SET rks = excluded.rks",
+        )
+        .bind(player_id)
+        .bind(Utc::now().format("%Y-%m-%d").to_string())
+        .bind(final_rks)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("写入RKS快照失败: {e}")))?;
+
+        // 首次建档时原RKS为占位的0，不视为变化
+        if let Some((player_name, old_rks)) = previous.filter(|(_, rks)| *rks > 0.0) {
+            rks_webhook::notify_rks_change(RksChangePayload {
+                player_id: player_id.to_string(),
+                player_name,
+                old_rks,
+                new_rks: final_rks,
+            });
+        }
+
         Ok(final_rks)
     }
 
-    /// (已重构) 从RKS记录批量更新玩家成绩。
+    /// (已重构) 从RKS记录增量更新玩家成绩。
     /// - 使用事务保证操作的原子性。
-    /// - 放弃手动拼接SQL，改用循环执行预处理语句的方式进行批量插入，更安全高效。
+    /// - 先读取当前成绩，与新记录逐谱面比对，只写入发生变化的谱面。
+    /// - 成绩变化时保留旧记录作为历史 (is_current = 0)，定数/曲名变化时原地更新。
     pub async fn update_player_scores_from_rks_records(
         &self,
         player_id: &str,
@@ -321,7 +717,7 @@ ORDER BY rs.play_time DESC;
         is_external: bool,
     ) -> Result<(), AppError> {
         log::info!(
-            "批量更新玩家[{}] ({}) 的成绩, 共{}条记录",
+            "增量更新玩家[{}] ({}) 的成绩, 共{}条记录",
             player_id,
             player_name,
             rks_records.len()
@@ -356,24 +752,100 @@ ORDER BY rs.play_time DESC;
             return Ok(());
         }
 
-        // 2. 批量删除该玩家所有旧成绩记录（比UPDATE更高效）
-        query!("DELETE FROM chart_scores WHERE player_id = ?", player_id)
+        // 2. 读取当前成绩，构建 "歌曲ID-难度" -> 当前记录 的映射
+        let current_rows: Vec<CurrentScoreRow> = query_as(
+            "SELECT id, song_id, song_name, difficulty, difficulty_value, score, acc, rks, is_fc
+             FROM chart_scores
+             WHERE player_id = ? AND is_current = 1",
+        )
+        .bind(player_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询当前成绩失败: {e}")))?;
+
+        let mut current_map: HashMap<String, CurrentScoreRow> = current_rows
+            .into_iter()
+            .map(|row| (format!("{}-{}", row.song_id, row.difficulty), row))
+            .collect();
+
+        // 3. 逐谱面比对，划分为：新增/成绩变化 (插入新记录) 和 仅元数据变化 (原地更新)
+        let mut to_insert: Vec<(&RksRecord, i32)> = Vec::new();
+        let mut to_retire: Vec<i64> = Vec::new();
+        let mut to_refresh: Vec<(i64, &RksRecord)> = Vec::new();
+
+        for record in rks_records {
+            let key = format!("{}-{}", record.song_id, record.difficulty);
+            let is_fc = fc_map.get(&key).copied().unwrap_or(false) as i32;
+            let score = record.score.unwrap_or(0.0);
+
+            match current_map.remove(&key) {
+                None => to_insert.push((record, is_fc)),
+                Some(existing) => {
+                    let result_changed = (existing.score - score).abs() > f64::EPSILON
+                        || (existing.acc - record.acc).abs() > f64::EPSILON
+                        || existing.is_fc != is_fc;
+                    if result_changed {
+                        to_retire.push(existing.id);
+                        to_insert.push((record, is_fc));
+                    } else if (existing.difficulty_value - record.difficulty_value).abs()
+                        > f64::EPSILON
+                        || (existing.rks - record.rks).abs() > f64::EPSILON
+                        || existing.song_name != record.song_name
+                    {
+                        to_refresh.push((existing.id, record));
+                    }
+                }
+            }
+        }
+
+        // 存档中已不存在的谱面，不再作为当前成绩
+        to_retire.extend(current_map.values().map(|row| row.id));
+
+        let inserted = to_insert.len();
+        let retired = to_retire.len();
+        let refreshed = to_refresh.len();
+        let unchanged = rks_records.len() - inserted - refreshed;
+
+        // 4. 将被替换或已移除的旧成绩标记为历史记录
+        if !to_retire.is_empty() {
+            let mut query_builder =
+                sqlx::QueryBuilder::new("UPDATE chart_scores SET is_current = 0 WHERE id IN (");
+            let mut separated = query_builder.separated(", ");
+            for id in &to_retire {
+                separated.push_bind(id);
+            }
+            separated.push_unseparated(")");
+            query_builder
+                .build()
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("标记历史成绩失败: {e}")))?;
+        }
+
+        // 5. 原地更新定数、RKS或曲名发生变化的当前成绩
+        for (id, record) in &to_refresh {
+            sqlx::query(
+                "UPDATE chart_scores SET song_name = ?, difficulty_value = ?, rks = ? WHERE id = ?",
+            )
+            .bind(&record.song_name)
+            .bind(record.difficulty_value)
+            .bind(record.rks)
+            .bind(id)
             .execute(&mut *tx)
             .await
-            .map_err(|e| AppError::DatabaseError(format!("删除旧成绩记录失败: {e}")))?;
+            .map_err(|e| AppError::DatabaseError(format!("更新成绩记录失败: {e}")))?;
+        }
 
-        // 3. 批量插入新的当前成绩（使用事务优化性能）
-        log::debug!("开始批量插入 {} 条新成绩记录...", rks_records.len());
+        // 6. 批量插入新增或成绩变化的谱面
+        if !to_insert.is_empty() {
+            log::debug!("开始批量插入 {inserted} 条新成绩记录...");
 
-        if !rks_records.is_empty() {
             // 使用 sqlx::QueryBuilder 进行批量插入
             let mut query_builder = sqlx::QueryBuilder::new(
                 "INSERT INTO chart_scores (player_id, song_id, song_name, difficulty, difficulty_value, score, acc, rks, is_fc, is_phi, play_time, is_current)"
             );
 
-            query_builder.push_values(rks_records.iter(), |mut b, record| {
-                let key = format!("{}-{}", record.song_id, record.difficulty);
-                let is_fc = fc_map.get(&key).copied().unwrap_or(false) as i32;
+            query_builder.push_values(to_insert.iter(), |mut b, (record, is_fc)| {
                 let is_phi = (record.acc >= 100.0) as i32;
 
                 b.push_bind(player_id)
@@ -384,7 +856,7 @@ ORDER BY rs.play_time DESC;
                     .push_bind(record.score.unwrap_or(0.0))
                     .push_bind(record.acc)
                     .push_bind(record.rks)
-                    .push_bind(is_fc)
+                    .push_bind(*is_fc)
                     .push_bind(is_phi)
                     .push_bind(update_time)
                     .push_bind(1i32); // is_current = 1
@@ -397,8 +869,24 @@ ORDER BY rs.play_time DESC;
                 .map_err(|e| AppError::DatabaseError(format!("批量插入成绩失败: {e}")))?;
 
             log::debug!("批量插入完成");
-        } else {
-            log::debug!("没有成绩记录需要插入");
+        }
+
+        // 7. 清理超出保留数量的历史记录
+        if !to_retire.is_empty() {
+            sqlx::query(
+                "DELETE FROM chart_scores WHERE id IN (
+                    SELECT id FROM (
+                        SELECT id, ROW_NUMBER() OVER(PARTITION BY song_id, difficulty ORDER BY play_time DESC) AS history_rank
+                        FROM chart_scores
+                        WHERE player_id = ?1 AND is_current = 0
+                    ) WHERE history_rank > ?2
+                )",
+            )
+            .bind(player_id)
+            .bind(self.config.history_max_records as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("清理历史成绩失败: {e}")))?;
         }
 
         // 提交事务
@@ -406,29 +894,39 @@ ORDER BY rs.play_time DESC;
             .await
             .map_err(|e| AppError::DatabaseError(format!("提交事务失败: {e}")))?;
 
-        // 4. 在所有数据库操作完成后，异步计算并更新玩家RKS和推分ACC
-        let self_clone = self.clone();
-        let player_id_clone = player_id.to_string();
-        let player_name_clone = player_name.to_string();
-        tokio::spawn(async move {
-            log::info!("成绩批量更新完成，开始异步重新计算玩家[{player_id_clone}] ({player_name_clone}) 的 RKS...");
-            if let Err(e) = self_clone.recalculate_player_rks(&player_id_clone).await {
-                log::error!(
-                    "异步重新计算玩家[{player_id_clone}] ({player_name_clone}) RKS 失败: {e}"
-                );
-            }
+        log::info!(
+            "玩家[{player_id}]成绩增量更新完成: 新增/变化={inserted}, 转为历史={retired}, 原地更新={refreshed}, 未变化={unchanged}"
+        );
 
-            if self_clone.config.store_push_acc {
-                log::info!(
-                    "开始异步重新计算玩家[{player_id_clone}] ({player_name_clone}) 的推分 ACC..."
-                );
-                if let Err(e) = self_clone.recalculate_push_acc(&player_id_clone).await {
-                    log::error!("异步重新计算玩家[{player_id_clone}] ({player_name_clone}) 推分 ACC 失败: {e}");
+        // 8. 在所有数据库操作完成后，异步计算并更新玩家RKS和推分ACC
+        // 成绩没有任何变化时，RKS 与推分 ACC 不会改变，无需重新计算
+        if inserted == 0 && retired == 0 && refreshed == 0 {
+            log::debug!("玩家[{player_id}]成绩无变化，跳过 RKS 与推分 ACC 重新计算");
+        } else {
+            let self_clone = self.clone();
+            let player_id_clone = player_id.to_string();
+            let player_name_clone = player_name.to_string();
+            tokio::spawn(async move {
+                let _permit = self_clone.acquire_write_permit().await;
+                log::info!("成绩增量更新完成，开始异步重新计算玩家[{player_id_clone}] ({player_name_clone}) 的 RKS...");
+                if let Err(e) = self_clone.recalculate_player_rks(&player_id_clone).await {
+                    log::error!(
+                        "异步重新计算玩家[{player_id_clone}] ({player_name_clone}) RKS 失败: {e}"
+                    );
                 }
-            }
-        });
 
-        // 5. 清除缓存 (仅限内部数据源)
+                if self_clone.config.store_push_acc {
+                    log::info!(
+                        "开始异步重新计算玩家[{player_id_clone}] ({player_name_clone}) 的推分 ACC..."
+                    );
+                    if let Err(e) = self_clone.recalculate_push_acc(&player_id_clone).await {
+                        log::error!("异步重新计算玩家[{player_id_clone}] ({player_name_clone}) 推分 ACC 失败: {e}");
+                    }
+                }
+            });
+        }
+
+        // 9. 清除缓存 (仅限内部数据源)
         if !is_external {
             self.cache.invalidate(player_id).await;
             log::debug!("玩家[{player_id}] ({player_name}) 缓存已清除");
@@ -439,7 +937,7 @@ ORDER BY rs.play_time DESC;
 
     /// 计算并更新推分ACC
     pub async fn recalculate_push_acc(&self, player_id: &str) -> Result<(), AppError> {
-        use crate::utils::rks_utils::calculate_target_chart_push_acc;
+        use crate::utils::rks_utils::calculate_push_acc_batch;
         log::info!("重新计算玩家[{player_id}]推分ACC");
 
         // 直接从数据库获取所有当前成绩,避免调用get_player_archive造成缓存锁竞争
@@ -476,11 +974,7 @@ ORDER BY rs.play_time DESC;
 
         // 按RKS排序
         let mut sorted_records = rks_records.clone();
-        sorted_records.sort_by(|a, b| {
-            b.rks
-                .partial_cmp(&a.rks)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        sorted_records.sort_by(rks_utils::compare_records);
 
         // 开始事务
         let mut tx = self
@@ -495,25 +989,14 @@ ORDER BY rs.play_time DESC;
             .await
             .map_err(|e| AppError::DatabaseError(format!("清除旧推分ACC记录失败: {e}")))?;
 
-        // (优化后) 计算并批量插入推分ACC
+        // 一次性计算所有谱面的推分ACC，并批量插入
         let mut records_to_insert = Vec::new();
-        for score in &all_scores {
-            if score.acc >= 100.0 || score.difficulty_value <= 0.0 {
-                continue;
-            }
-            let target_chart_id = format!("{}-{}", score.song_id, score.difficulty);
-            if let Some(push_acc) = calculate_target_chart_push_acc(
-                &target_chart_id,
-                score.difficulty_value,
-                &sorted_records,
-            ) {
-                if push_acc > score.acc {
-                    records_to_insert.push((
-                        score.song_id.clone(),
-                        score.difficulty.clone(),
-                        push_acc,
-                    ));
-                }
+        for (score, push_acc) in sorted_records
+            .iter()
+            .zip(calculate_push_acc_batch(&sorted_records))
+        {
+            if let Some(push_acc) = push_acc.filter(|push_acc| *push_acc > score.acc) {
+                records_to_insert.push((score.song_id.clone(), score.difficulty.clone(), push_acc));
             }
         }
 
@@ -625,6 +1108,613 @@ ORDER BY rs.play_time DESC;
         Ok(result.unwrap_or_else(|| chrono::Utc::now().to_rfc3339()))
     }
 
+    /// 查找疑似重复的玩家存档
+    /// 外部数据源以 `platform:id` 或 `external:unknown` 作为 player_id，同一玩家可能同时存在多条存档。
+    /// 这里将玩家名称相同且 RKS 差值不超过 `rks_tolerance` 的存档两两列出。
+    pub async fn find_duplicate_archives(
+        &self,
+        rks_tolerance: f64,
+    ) -> Result<Vec<DuplicateArchiveCandidate>, AppError> {
+        let pairs: Vec<DuplicateArchivePairRow> = query_as(
+            "SELECT a.player_name AS player_name,
+                    a.player_id AS a_id, a.rks AS a_rks, a.update_time AS a_update_time,
+                    b.player_id AS b_id, b.rks AS b_rks, b.update_time AS b_update_time
+             FROM player_archives a
+             JOIN player_archives b
+               ON a.player_name = b.player_name AND a.player_id < b.player_id
+             WHERE ABS(a.rks - b.rks) <= ?
+             ORDER BY a.player_name, a.player_id, b.player_id",
+        )
+        .bind(rks_tolerance)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询重复存档失败: {e}")))?;
+
+        let candidates: Vec<DuplicateArchiveCandidate> = pairs
+            .into_iter()
+            .map(|row| {
+                let a = PlayerBasicInfo {
+                    player_id: row.a_id,
+                    player_name: row.player_name.clone(),
+                    rks: row.a_rks,
+                    update_time: row.a_update_time,
+                };
+                let b = PlayerBasicInfo {
+                    player_id: row.b_id,
+                    player_name: row.player_name.clone(),
+                    rks: row.b_rks,
+                    update_time: row.b_update_time,
+                };
+                // 优先保留内部ID（非外部ID），两者同类时保留最近更新的
+                let prefer_a = match (
+                    is_external_player_id(&a.player_id),
+                    is_external_player_id(&b.player_id),
+                ) {
+                    (false, true) => true,
+                    (true, false) => false,
+                    _ => a.update_time >= b.update_time,
+                };
+                let suggested_primary_id = if prefer_a {
+                    a.player_id.clone()
+                } else {
+                    b.player_id.clone()
+                };
+                DuplicateArchiveCandidate {
+                    player_name: row.player_name,
+                    rks_diff: (a.rks - b.rks).abs(),
+                    suggested_primary_id,
+                    archives: vec![a, b],
+                }
+            })
+            .collect();
+
+        log::info!(
+            "重复存档检测完成: RKS容差={rks_tolerance}, 疑似重复={}",
+            candidates.len()
+        );
+        Ok(candidates)
+    }
+
+    /// 将 `alias_id` 的存档合并到 `primary_id`
+    /// - 成绩记录与推分ACC迁移到主存档，同一谱面的当前成绩只保留分数更高者，其余转为历史。
+    /// - 与主存档完全重复（同谱面同游玩时间）的记录直接丢弃。
+    /// - 删除别名存档后重新计算主存档的 RKS 与推分ACC，并清除两者的缓存。
+    pub async fn merge_player_archives(
+        &self,
+        primary_id: &str,
+        alias_id: &str,
+    ) -> Result<ArchiveMergeResult, AppError> {
+        if primary_id == alias_id {
+            return Err(AppError::BadRequest(
+                "primary_id 与 alias_id 不能相同".to_string(),
+            ));
+        }
+        log::info!("开始合并玩家存档: [{alias_id}] -> [{primary_id}]");
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("开始事务失败: {e}")))?;
+
+        for id in [primary_id, alias_id] {
+            let exists: Option<i64> =
+                sqlx::query_scalar("SELECT 1 FROM player_archives WHERE player_id = ?")
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| AppError::DatabaseError(format!("查询玩家存档失败: {e}")))?;
+            if exists.is_none() {
+                return Err(AppError::UserNotFound(format!("玩家存档不存在: {id}")));
+            }
+        }
+
+        // 1. 同一谱面两边都有当前成绩时，只保留分数更高者（同分优先主存档）
+        let retired_scores = query(
+            "UPDATE chart_scores SET is_current = 0
+             WHERE id IN (
+                 SELECT id FROM (
+                     SELECT id, ROW_NUMBER() OVER (
+                         PARTITION BY song_id, difficulty
+                         ORDER BY score DESC, acc DESC, (player_id = ?) DESC, play_time DESC
+                     ) AS rn
+                     FROM chart_scores
+                     WHERE player_id IN (?, ?) AND is_current = 1
+                 ) WHERE rn > 1
+             )",
+        )
+        .bind(primary_id)
+        .bind(primary_id)
+        .bind(alias_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("合并当前成绩失败: {e}")))?
+        .rows_affected();
+
+        // 2. 迁移成绩记录，与主存档冲突（同谱面同游玩时间）的记录保留在原处，随后删除
+        let moved_scores =
+            query("UPDATE OR IGNORE chart_scores SET player_id = ? WHERE player_id = ?")
+                .bind(primary_id)
+                .bind(alias_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("迁移成绩记录失败: {e}")))?
+                .rows_affected();
+
+        let dropped_scores = query("DELETE FROM chart_scores WHERE player_id = ?")
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除重复成绩记录失败: {e}")))?
+            .rows_affected();
+
+        // 3. 迁移推分ACC（主存档已有的保留，稍后统一重新计算）
+        query("UPDATE OR IGNORE push_acc SET player_id = ? WHERE player_id = ?")
+            .bind(primary_id)
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("迁移推分ACC失败: {e}")))?;
+        query("DELETE FROM push_acc WHERE player_id = ?")
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除推分ACC失败: {e}")))?;
+
+        // 4. 迁移原始存档快照（主存档已有相同校验和的保留）
+        query("UPDATE OR IGNORE save_snapshots SET player_id = ? WHERE player_id = ?")
+            .bind(primary_id)
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("迁移存档快照失败: {e}")))?;
+        query("DELETE FROM save_snapshots WHERE player_id = ?")
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除存档快照失败: {e}")))?;
+
+        // 5. 删除别名存档及其 RKS 快照（主存档的快照历史保持不变）
+        query("DELETE FROM rks_snapshots WHERE player_id = ?")
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除RKS快照失败: {e}")))?;
+        query("DELETE FROM player_archives WHERE player_id = ?")
+            .bind(alias_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除玩家存档失败: {e}")))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("提交事务失败: {e}")))?;
+
+        // 6. 重新计算主存档并清除缓存
+        let rks = self.recalculate_player_rks(primary_id).await?;
+        if self.config.store_push_acc {
+            self.recalculate_push_acc(primary_id).await?;
+        }
+        self.cache.invalidate(primary_id).await;
+        self.cache.invalidate(alias_id).await;
+
+        log::info!(
+            "玩家存档合并完成: [{alias_id}] -> [{primary_id}], 迁移={moved_scores}, 丢弃={dropped_scores}, 转为历史={retired_scores}, RKS={rks:.4}"
+        );
+
+        Ok(ArchiveMergeResult {
+            primary_id: primary_id.to_string(),
+            alias_id: alias_id.to_string(),
+            moved_scores,
+            dropped_scores,
+            retired_scores,
+            rks,
+        })
+    }
+
+    /// 重置玩家存档：在事务中删除其成绩记录、推分ACC、RKS 快照与存档本身，并清除缓存。
+    /// 玩家下次查询时会按最新云存档重新建立存档。
+    pub async fn reset_player_archive(
+        &self,
+        player_id: &str,
+    ) -> Result<ArchiveResetResult, AppError> {
+        log::info!("开始重置玩家存档: [{player_id}]");
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("开始事务失败: {e}")))?;
+
+        let deleted_scores = query("DELETE FROM chart_scores WHERE player_id = ?")
+            .bind(player_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除成绩记录失败: {e}")))?
+            .rows_affected();
+
+        let deleted_push_acc = query("DELETE FROM push_acc WHERE player_id = ?")
+            .bind(player_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除推分ACC失败: {e}")))?
+            .rows_affected();
+
+        let deleted_archives = query("DELETE FROM player_archives WHERE player_id = ?")
+            .bind(player_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除玩家存档失败: {e}")))?
+            .rows_affected();
+
+        let deleted_snapshots = query("DELETE FROM rks_snapshots WHERE player_id = ?")
+            .bind(player_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除RKS快照失败: {e}")))?
+            .rows_affected();
+
+        let deleted_save_snapshots = query("DELETE FROM save_snapshots WHERE player_id = ?")
+            .bind(player_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("删除存档快照失败: {e}")))?
+            .rows_affected();
+
+        if deleted_scores == 0
+            && deleted_push_acc == 0
+            && deleted_archives == 0
+            && deleted_snapshots == 0
+            && deleted_save_snapshots == 0
+        {
+            return Err(AppError::UserNotFound(format!(
+                "玩家存档不存在: {player_id}"
+            )));
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("提交事务失败: {e}")))?;
+
+        self.cache.invalidate(player_id).await;
+
+        log::info!(
+            "玩家存档重置完成: [{player_id}], 成绩={deleted_scores}, 推分ACC={deleted_push_acc}, 存档={deleted_archives}, 快照={deleted_snapshots}, 存档快照={deleted_save_snapshots}"
+        );
+
+        Ok(ArchiveResetResult {
+            player_id: player_id.to_string(),
+            deleted_scores,
+            deleted_push_acc,
+            deleted_archives,
+            deleted_snapshots,
+            deleted_save_snapshots,
+        })
+    }
+
+    /// 按当前定数表重算全部玩家的 RKS，用于更新 difficulty.csv 之后修正已存档的数据。
+    /// - 逐个玩家在事务中更新定数或 RKS 发生变化的当前成绩，历史记录保持不变。
+    /// - 定数表中找不到定数的谱面保留原值。
+    /// - 有谱面被更新的玩家重新计算总 RKS 与推分ACC，并清除缓存。
+    pub async fn recompute_all_rks(&self) -> Result<RksRecomputeResult, AppError> {
+        let player_ids: Vec<String> =
+            sqlx::query_scalar("SELECT player_id FROM player_archives ORDER BY player_id")
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("查询玩家列表失败: {e}")))?;
+
+        let total = player_ids.len();
+        log::info!("开始按当前定数重算RKS，共 {total} 名玩家");
+
+        let mut result = RksRecomputeResult {
+            players_total: total as u64,
+            ..Default::default()
+        };
+        let mut processed = 0;
+        for batch in player_ids.chunks(RECOMPUTE_BATCH_SIZE) {
+            for player_id in batch {
+                let (updated, without_constant) =
+                    self.recompute_player_chart_rks(player_id).await?;
+                result.charts_updated += updated;
+                result.charts_without_constant += without_constant;
+
+                if updated > 0 {
+                    self.recalculate_player_rks(player_id).await?;
+                    self.recalculate_push_acc(player_id).await?;
+                    self.cache.invalidate(player_id).await;
+                    result.players_updated += 1;
+                }
+            }
+            processed += batch.len();
+            log::info!(
+                "RKS重算进度: {processed}/{total} 名玩家, 已更新玩家 {}, 已更新谱面 {}",
+                result.players_updated,
+                result.charts_updated
+            );
+        }
+
+        log::info!(
+            "RKS重算完成: 玩家 {total}, 更新玩家 {}, 更新谱面 {}, 缺少定数的谱面 {}",
+            result.players_updated,
+            result.charts_updated,
+            result.charts_without_constant
+        );
+        Ok(result)
+    }
+
+    /// 按当前定数更新单个玩家的当前成绩，返回 (更新的谱面数, 缺少定数的谱面数)
+    async fn recompute_player_chart_rks(&self, player_id: &str) -> Result<(u64, u64), AppError> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("开始事务失败: {e}")))?;
+
+        let scores: Vec<(i64, String, String, f64, f64, f64)> = query_as(
+            "SELECT id, song_id, difficulty, difficulty_value, acc, rks FROM chart_scores
+             WHERE player_id = ? AND is_current = 1",
+        )
+        .bind(player_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询当前成绩失败: {e}")))?;
+
+        let mut updated = 0;
+        let mut without_constant = 0;
+        for (id, song_id, difficulty, old_constant, acc, old_rks) in scores {
+            let Some(constant) = data_loader::get_difficulty_by_id(&song_id, &difficulty) else {
+                without_constant += 1;
+                continue;
+            };
+            let rks = rks_utils::calculate_chart_rks(acc, constant);
+            if (constant - old_constant).abs() < f64::EPSILON && (rks - old_rks).abs() < 1e-9 {
+                continue;
+            }
+
+            query("UPDATE chart_scores SET difficulty_value = ?, rks = ? WHERE id = ?")
+                .bind(constant)
+                .bind(rks)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("更新成绩RKS失败: {e}")))?;
+            updated += 1;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("提交事务失败: {e}")))?;
+
+        if updated > 0 {
+            log::debug!("玩家[{player_id}]按当前定数更新了 {updated} 条成绩");
+        }
+        Ok((updated, without_constant))
+    }
+
+    /// 统计存档数据库中未被数据文件完整收录的歌曲，返回 歌曲ID -> 拥有当前成绩的玩家数
+    pub async fn count_unknown_archived_songs(&self) -> Result<HashMap<String, i64>, AppError> {
+        let rows: Vec<(String, i64)> = query_as(
+            "SELECT song_id, COUNT(DISTINCT player_id) FROM chart_scores
+             WHERE is_current = 1
+             GROUP BY song_id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("统计存档歌曲失败: {e}")))?;
+
+        Ok(rows
+            .into_iter()
+            .filter(|(song_id, _)| !data_loader::is_song_fully_known(song_id))
+            .collect())
+    }
+
+    /// 查询玩家最近有成绩变化的谱面，按成绩被存档的时间 (`play_time`) 倒序，时间相同时按单曲 RKS 降序
+    pub async fn get_recent_chart_scores(
+        &self,
+        player_id: &str,
+        limit: usize,
+    ) -> Result<Vec<ChartScore>, AppError> {
+        query_as(
+            "SELECT song_id, song_name, difficulty, difficulty_value, score, acc, rks, is_fc, is_phi, play_time
+             FROM chart_scores
+             WHERE player_id = ? AND is_current = 1
+             ORDER BY play_time DESC, rks DESC
+             LIMIT ?",
+        )
+        .bind(player_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询最近成绩失败: {e}")))
+    }
+
+    /// 查询玩家的 RKS 历史，并计算最近 7 天与 30 天的 RKS 变化
+    pub async fn get_rks_history(&self, player_id: &str) -> Result<RksHistory, AppError> {
+        let current_rks: f64 =
+            sqlx::query_scalar("SELECT rks FROM player_archives WHERE player_id = ?")
+                .bind(player_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("查询玩家RKS失败: {e}")))?
+                .ok_or_else(|| AppError::UserNotFound(format!("玩家存档不存在: {player_id}")))?;
+
+        let today = Utc::now().date_naive();
+        let gained_7d = self
+            .rks_gain_since(player_id, current_rks, today, 7)
+            .await?;
+        let gained_30d = self
+            .rks_gain_since(player_id, current_rks, today, 30)
+            .await?;
+
+        let snapshots: Vec<RksSnapshot> = sqlx::query_as(
+            "SELECT snapshot_date, rks FROM rks_snapshots
+             WHERE player_id = ? AND snapshot_date >= ?
+             ORDER BY snapshot_date ASC",
+        )
+        .bind(player_id)
+        .bind(
+            (today - chrono::Duration::days(30))
+                .format("%Y-%m-%d")
+                .to_string(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询RKS快照失败: {e}")))?;
+
+        Ok(RksHistory {
+            player_id: player_id.to_string(),
+            current_rks,
+            gained_7d,
+            gained_30d,
+            snapshots,
+        })
+    }
+
+    /// 查询成绩的达成时间，返回 "歌曲ID-难度" -> 存档中记录该成绩的时间
+    ///
+    /// 仅当存档中的当前成绩与 `records` 中的分数一致时才返回；成绩刚刷新、存档尚未更新的谱面不包含在结果中。
+    pub async fn get_play_dates(
+        &self,
+        player_id: &str,
+        records: &[RksRecord],
+    ) -> Result<HashMap<String, DateTime<Utc>>, AppError> {
+        let rows: Vec<(String, String, f64, DateTime<Utc>)> = query_as(
+            "SELECT song_id, difficulty, score, play_time FROM chart_scores
+             WHERE player_id = ? AND is_current = 1",
+        )
+        .bind(player_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询成绩达成时间失败: {e}")))?;
+
+        let archived: HashMap<String, (f64, DateTime<Utc>)> = rows
+            .into_iter()
+            .map(|(song_id, difficulty, score, play_time)| {
+                (format!("{song_id}-{difficulty}"), (score, play_time))
+            })
+            .collect();
+
+        Ok(records
+            .iter()
+            .filter_map(|record| {
+                let key = format!("{}-{}", record.song_id, record.difficulty);
+                let (score, play_time) = archived.get(&key)?;
+                (*score == record.score.unwrap_or(0.0)).then_some((key, *play_time))
+            })
+            .collect())
+    }
+
+    /// 计算玩家在某个谱面上超过了多少已存档玩家
+    ///
+    /// 以 `record` 中的 ACC 与其他玩家在该谱面的当前成绩比较，不计入玩家自己的存档。
+    pub async fn get_chart_percentile(
+        &self,
+        player_id: &str,
+        record: &RksRecord,
+    ) -> Result<ChartPercentile, AppError> {
+        let (total_players, players_below, players_above): (i64, i64, i64) = query_as(
+            "SELECT COUNT(*), COALESCE(SUM(acc < ?), 0), COALESCE(SUM(acc > ?), 0)
+             FROM chart_scores
+             WHERE song_id = ? AND difficulty = ? AND is_current = 1 AND player_id != ?",
+        )
+        .bind(record.acc)
+        .bind(record.acc)
+        .bind(&record.song_id)
+        .bind(&record.difficulty)
+        .bind(player_id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询谱面成绩分布失败: {e}")))?;
+
+        let percentile = (total_players > 0)
+            .then(|| (players_below as f64 / total_players as f64 * 10000.0).round() / 100.0);
+
+        Ok(ChartPercentile {
+            player_id: player_id.to_string(),
+            song_id: record.song_id.clone(),
+            song_name: record.song_name.clone(),
+            difficulty: record.difficulty.clone(),
+            acc: record.acc,
+            score: record.score,
+            total_players,
+            players_below,
+            rank: players_above + 1,
+            percentile,
+        })
+    }
+
+    /// 统计其他已存档玩家在某首歌各难度上的平均 ACC，键为难度
+    ///
+    /// 不计入玩家自己的存档；成绩不足 `min_players` 人的难度不返回，避免样本过少时平均值失真。
+    pub async fn get_chart_average_accs(
+        &self,
+        player_id: &str,
+        song_id: &str,
+        min_players: i64,
+    ) -> Result<HashMap<String, f64>, AppError> {
+        let rows: Vec<(String, f64, i64)> = query_as(
+            "SELECT difficulty, AVG(acc), COUNT(*)
+             FROM chart_scores
+             WHERE song_id = ? AND is_current = 1 AND player_id != ?
+             GROUP BY difficulty",
+        )
+        .bind(song_id)
+        .bind(player_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询谱面平均 ACC 失败: {e}")))?;
+
+        Ok(rows
+            .into_iter()
+            .filter(|(_, _, count)| *count >= min_players)
+            .map(|(difficulty, avg_acc, _)| (difficulty, avg_acc))
+            .collect())
+    }
+
+    /// 计算最近 `days` 天的 RKS 变化
+    /// 基准为统计起点当天或之前最近的快照，没有时取最早的快照；没有任何快照时返回 None
+    async fn rks_gain_since(
+        &self,
+        player_id: &str,
+        current_rks: f64,
+        today: chrono::NaiveDate,
+        days: u32,
+    ) -> Result<Option<RksGain>, AppError> {
+        let cutoff = (today - chrono::Duration::days(i64::from(days)))
+            .format("%Y-%m-%d")
+            .to_string();
+
+        let mut baseline: Option<RksSnapshot> = sqlx::query_as(
+            "SELECT snapshot_date, rks FROM rks_snapshots
+             WHERE player_id = ? AND snapshot_date <= ?
+             ORDER BY snapshot_date DESC LIMIT 1",
+        )
+        .bind(player_id)
+        .bind(&cutoff)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("查询RKS快照失败: {e}")))?;
+
+        if baseline.is_none() {
+            baseline = sqlx::query_as(
+                "SELECT snapshot_date, rks FROM rks_snapshots
+                 WHERE player_id = ?
+                 ORDER BY snapshot_date ASC LIMIT 1",
+            )
+            .bind(player_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("查询RKS快照失败: {e}")))?;
+        }
+
+        Ok(baseline.map(|snapshot| RksGain {
+            days,
+            gained: current_rks - snapshot.rks,
+            baseline_date: snapshot.snapshot_date,
+            baseline_rks: snapshot.rks,
+        }))
+    }
+
     /// 辅助函数：获取推分ACC
     #[allow(dead_code)]
     async fn get_push_acc_map(
@@ -682,3 +1772,295 @@ struct CombinedScoreRecord {
     // 推分ACC (由于是LEFT JOIN, 可能为NULL)
     push_acc: Option<f64>,
 }
+
+// 增量更新时用于比对的当前成绩记录
+#[derive(sqlx::FromRow)]
+struct CurrentScoreRow {
+    id: i64,
+    song_id: String,
+    song_name: String,
+    difficulty: String,
+    difficulty_value: f64,
+    score: f64,
+    acc: f64,
+    rks: f64,
+    is_fc: i32,
+}
+
+// 重复存档检测查询的行结构
+#[derive(sqlx::FromRow)]
+struct DuplicateArchivePairRow {
+    player_name: String,
+    a_id: String,
+    a_rks: f64,
+    a_update_time: DateTime<Utc>,
+    b_id: String,
+    b_rks: f64,
+    b_update_time: DateTime<Utc>,
+}
+
+// 外部数据源写入的 player_id 形如 `platform:id` 或 `external:unknown`
+fn is_external_player_id(player_id: &str) -> bool {
+    player_id.contains(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    /// 使用已迁移的内存数据库创建存档服务；只保留一个连接，内存数据库不会随连接关闭而丢失
+    async fn memory_service(config: ArchiveConfig) -> PlayerArchiveService {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::utils::schema::migrate(&pool).await.unwrap();
+        PlayerArchiveService::new(
+            pool,
+            Some(ArchiveConfig {
+                store_push_acc: false,
+                ..config
+            }),
+        )
+    }
+
+    fn score(song_id: &str, acc: f64) -> RksRecord {
+        RksRecord {
+            song_id: song_id.to_string(),
+            song_name: song_id.to_string(),
+            difficulty: "IN".to_string(),
+            difficulty_value: 15.0,
+            acc,
+            score: Some(acc * 10_000.0),
+            rks: rks_utils::calculate_chart_rks(acc, 15.0),
+            is_fc: false,
+        }
+    }
+
+    /// 按 (歌曲ID, 记录ID, ACC, 是否为当前成绩) 列出玩家的全部成绩行
+    async fn score_rows(service: &PlayerArchiveService) -> Vec<(String, i64, f64, i64)> {
+        query_as(
+            "SELECT song_id, id, acc, is_current FROM chart_scores
+             WHERE player_id = 'player' ORDER BY song_id, id",
+        )
+        .fetch_all(&service.pool)
+        .await
+        .unwrap()
+    }
+
+    fn registered(player_id: &str) -> InFlightUpdates {
+        let in_flight: InFlightUpdates = Arc::new(Mutex::new(HashMap::new()));
+        in_flight
+            .lock()
+            .unwrap()
+            .insert(player_id.to_string(), None);
+        in_flight
+    }
+
+    #[test]
+    fn panicking_write_task_releases_its_registration() {
+        let in_flight = registered("player");
+        let guard = InFlightGuard::new(in_flight.clone(), "player".to_string());
+
+        let result = catch_unwind(AssertUnwindSafe(move || {
+            let _guard = guard;
+            panic!("写入任务 panic");
+        }));
+        assert!(result.is_err());
+        assert!(in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn finished_task_does_not_release_a_newer_registration() {
+        let in_flight = registered("player");
+        let mut guard = InFlightGuard::new(in_flight.clone(), "player".to_string());
+        assert!(guard.take_pending(true).is_none());
+        assert!(in_flight.lock().unwrap().is_empty());
+
+        // 注销后到达的更新登记了新的任务，旧任务的 guard 随后才被释放
+        in_flight.lock().unwrap().insert("player".to_string(), None);
+        drop(guard);
+        assert!(in_flight.lock().unwrap().contains_key("player"));
+    }
+
+    #[tokio::test]
+    async fn incremental_update_only_writes_changed_charts() {
+        let service = memory_service(ArchiveConfig::default()).await;
+        let fc_map = HashMap::new();
+        let update = |records: Vec<RksRecord>| {
+            let service = service.clone();
+            let fc_map = fc_map.clone();
+            async move {
+                service
+                    .update_player_scores_from_rks_records(
+                        "player", "Player", &records, &fc_map, false,
+                    )
+                    .await
+                    .unwrap();
+            }
+        };
+
+        update(vec![score("a", 95.0), score("b", 96.0)]).await;
+        let first = score_rows(&service).await;
+        assert_eq!(first.len(), 2);
+
+        // b 的成绩提升：a 的记录保持原样，b 的旧记录转为历史
+        update(vec![score("a", 95.0), score("b", 98.0)]).await;
+        let second = score_rows(&service).await;
+        assert_eq!(second.len(), 3);
+        assert_eq!(second[0], first[0]);
+        assert_eq!(second[1], ("b".to_string(), first[1].1, 96.0, 0));
+        assert_eq!((second[2].2, second[2].3), (98.0, 1));
+
+        // 成绩没有变化时不写入任何谱面
+        update(vec![score("a", 95.0), score("b", 98.0)]).await;
+        assert_eq!(score_rows(&service).await, second);
+
+        // 存档中已不存在的谱面转为历史，不再作为当前成绩
+        update(vec![score("b", 98.0)]).await;
+        let fourth = score_rows(&service).await;
+        assert_eq!(fourth[0], ("a".to_string(), first[0].1, 95.0, 0));
+        assert_eq!(fourth[1..], second[1..]);
+    }
+
+    /// 改为增量更新之前的写入方式：删除玩家全部成绩后重新插入
+    async fn full_rewrite(service: &PlayerArchiveService, records: &[RksRecord]) {
+        let mut tx = service.pool.begin().await.unwrap();
+        query("DELETE FROM chart_scores WHERE player_id = 'player'")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "INSERT INTO chart_scores (player_id, song_id, song_name, difficulty, difficulty_value, score, acc, rks, is_fc, is_phi, play_time, is_current)",
+        );
+        query_builder.push_values(records, |mut b, record| {
+            b.push_bind("player")
+                .push_bind(&record.song_id)
+                .push_bind(&record.song_name)
+                .push_bind(&record.difficulty)
+                .push_bind(record.difficulty_value)
+                .push_bind(record.score.unwrap_or(0.0))
+                .push_bind(record.acc)
+                .push_bind(record.rks)
+                .push_bind(0)
+                .push_bind(0)
+                .push_bind("2025-01-01T00:00:00Z")
+                .push_bind(1);
+        });
+        query_builder.build().execute(&mut *tx).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    /// 900 个谱面中 10 个成绩变化时，对比整表重写与增量更新的写入行数和耗时
+    ///
+    /// 运行：`cargo test --release archive_write_benchmark -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn archive_write_benchmark() {
+        const CHARTS: usize = 900;
+        const CHANGED: usize = 10;
+        const ROUNDS: u32 = 20;
+        let before: Vec<RksRecord> = (0..CHARTS)
+            .map(|i| score(&format!("song{i:03}"), 95.0))
+            .collect();
+        let mut after = before.clone();
+        for record in after.iter_mut().take(CHANGED) {
+            *record = score(&record.song_id, 97.0);
+        }
+
+        let service = memory_service(ArchiveConfig::default()).await;
+        let start = std::time::Instant::now();
+        for round in 0..ROUNDS {
+            full_rewrite(&service, if round % 2 == 0 { &after } else { &before }).await;
+        }
+        let rewrite_time = start.elapsed() / ROUNDS;
+
+        let service = memory_service(ArchiveConfig {
+            history_max_records: ROUNDS as usize,
+            ..ArchiveConfig::default()
+        })
+        .await;
+        let fc_map = HashMap::new();
+        service
+            .update_player_scores_from_rks_records("player", "Player", &before, &fc_map, false)
+            .await
+            .unwrap();
+        let rows_before = score_rows(&service).await.len();
+        let start = std::time::Instant::now();
+        for round in 0..ROUNDS {
+            let records = if round % 2 == 0 { &after } else { &before };
+            service
+                .update_player_scores_from_rks_records("player", "Player", records, &fc_map, false)
+                .await
+                .unwrap();
+        }
+        let incremental_time = start.elapsed() / ROUNDS;
+        // 每轮只有变化的谱面插入新记录
+        assert_eq!(
+            score_rows(&service).await.len(),
+            rows_before + CHANGED * ROUNDS as usize
+        );
+
+        println!(
+            "{CHARTS} 个谱面 / {CHANGED} 个变化: 整表重写 {} 行 {rewrite_time:?}/次, 增量更新 {} 行 {incremental_time:?}/次",
+            CHARTS * 2,
+            CHANGED * 2
+        );
+    }
+
+    #[tokio::test]
+    async fn pruning_keeps_only_the_newest_history_per_chart() {
+        let service = memory_service(ArchiveConfig {
+            history_max_records: 2,
+            ..ArchiveConfig::default()
+        })
+        .await;
+        // a: 1 条当前成绩 + 4 条历史；b: 1 条历史
+        let rows = [
+            ("a", 95.0, "2025-01-05T00:00:00Z", 1),
+            ("a", 94.0, "2025-01-04T00:00:00Z", 0),
+            ("a", 93.0, "2025-01-03T00:00:00Z", 0),
+            ("a", 92.0, "2025-01-02T00:00:00Z", 0),
+            ("a", 91.0, "2025-01-01T00:00:00Z", 0),
+            ("b", 90.0, "2025-01-01T00:00:00Z", 0),
+        ];
+        for (song_id, acc, play_time, is_current) in rows {
+            query(
+                "INSERT INTO chart_scores (player_id, song_id, song_name, difficulty, difficulty_value, score, acc, rks, is_fc, is_phi, play_time, is_current)
+                 VALUES ('player', ?, ?, 'IN', 15.0, 0, ?, 0, 0, 0, ?, ?)",
+            )
+            .bind(song_id)
+            .bind(song_id)
+            .bind(acc)
+            .bind(play_time)
+            .bind(is_current)
+            .execute(&service.pool)
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(service.prune_history().await.unwrap(), 2);
+        let remaining: Vec<(String, f64, i64)> = score_rows(&service)
+            .await
+            .into_iter()
+            .map(|(song_id, _, acc, is_current)| (song_id, acc, is_current))
+            .collect();
+        assert_eq!(
+            remaining,
+            [
+                ("a".to_string(), 95.0, 1),
+                ("a".to_string(), 94.0, 0),
+                ("a".to_string(), 93.0, 0),
+                ("b".to_string(), 90.0, 0),
+            ]
+        );
+
+        // 已在保留数量内时不再删除
+        assert_eq!(service.prune_history().await.unwrap(), 0);
+    }
+}
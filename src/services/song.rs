@@ -1,8 +1,46 @@
-use crate::models::song::{SongDifficulty, SongInfo};
-use crate::utils::data_loader::{DIFFICULTY_MAP, SONG_INFO, SONG_NICKNAMES};
+use crate::models::cache::{CacheInfo, CacheName};
+use crate::models::song::{
+    ChartConstant, SongCandidate, SongChartDetail, SongConstants, SongCreditField, SongDetail,
+    SongDifficulty, SongInfo, SongListItem, SongMatchType,
+};
+use crate::utils::data_loader::{
+    get_predicted_constant, DIFFICULTY_MAP, SONG_INFO, SONG_NICKNAMES,
+};
 use crate::utils::error::{AppError, AppResult};
+use moka::sync::Cache;
 use std::collections::HashSet;
 
+/// 每种歌曲查询缓存最多保存的查询条数
+const SONG_SEARCH_CACHE_CAPACITY: u64 = 4096;
+
+/// 缓存的查询结果。`AppError` 无法克隆，未找到与歧义两种错误以消息保存，命中时重新构造
+#[derive(Clone)]
+enum CachedSearch<T> {
+    Found(T),
+    NotFound(String),
+    Ambiguous(String),
+}
+
+impl<T: Clone> CachedSearch<T> {
+    /// 只缓存由歌曲数据决定的结果，其他错误原样返回且不缓存
+    fn from_result(result: &AppResult<T>) -> Option<Self> {
+        match result {
+            Ok(value) => Some(Self::Found(value.clone())),
+            Err(AppError::SongNotFound(message)) => Some(Self::NotFound(message.clone())),
+            Err(AppError::AmbiguousSongName(message)) => Some(Self::Ambiguous(message.clone())),
+            Err(_) => None,
+        }
+    }
+
+    fn into_result(self) -> AppResult<T> {
+        match self {
+            Self::Found(value) => Ok(value),
+            Self::NotFound(message) => Err(AppError::SongNotFound(message)),
+            Self::Ambiguous(message) => Err(AppError::AmbiguousSongName(message)),
+        }
+    }
+}
+
 // 歌曲服务，提供歌曲信息查询
 #[derive(Clone)]
 pub struct SongService {
@@ -12,6 +50,13 @@ pub struct SongService {
     name_to_song: std::collections::HashMap<String, SongInfo>,
     // 别名到歌曲ID的映射（小写）
     nickname_to_id: std::collections::HashMap<String, String>,
+    // 署名字段 -> (小写署名 -> SONG_INFO 中的歌曲下标)
+    credit_index:
+        std::collections::HashMap<SongCreditField, std::collections::HashMap<String, Vec<usize>>>,
+    // search_song 的查询缓存（键为去除首尾空白后的查询）
+    song_cache: Cache<String, CachedSearch<SongInfo>>,
+    // search_songs 的查询缓存（键同上）
+    songs_cache: Cache<String, CachedSearch<Vec<SongInfo>>>,
 }
 
 impl SongService {
@@ -45,20 +90,136 @@ impl SongService {
             }
         }
 
+        // 构建署名索引，同一署名只保留一份，检索时只需遍历去重后的署名
+        let mut credit_index = std::collections::HashMap::new();
+        for (idx, song_info) in SONG_INFO.iter().enumerate() {
+            let charters = [
+                &song_info.ez_charter,
+                &song_info.hd_charter,
+                &song_info.in_charter,
+                &song_info.at_charter,
+            ];
+            let entries = charters
+                .into_iter()
+                .flatten()
+                .map(|charter| (SongCreditField::Charter, charter.as_str()))
+                .chain(std::iter::once((
+                    SongCreditField::Composer,
+                    song_info.composer.as_str(),
+                )))
+                .chain(
+                    song_info
+                        .illustrator
+                        .as_deref()
+                        .map(|illustrator| (SongCreditField::Illustrator, illustrator)),
+                );
+
+            for (field, credit) in entries {
+                let credit = credit.trim();
+                if credit.is_empty() {
+                    continue;
+                }
+                let songs: &mut Vec<usize> = credit_index
+                    .entry(field)
+                    .or_insert_with(std::collections::HashMap::new)
+                    .entry(credit.to_lowercase())
+                    .or_default();
+                // 同一首歌多个难度谱师相同时避免重复
+                if songs.last() != Some(&idx) {
+                    songs.push(idx);
+                }
+            }
+        }
+
+        // 查询结果只取决于上面构建的映射。歌曲数据只在启动时加载一次，缓存与服务同生命周期；
+        // 替换数据文件后需重启服务，缓存随新建的服务一并清空
         Self {
             id_to_song,
             name_to_song,
             nickname_to_id,
+            credit_index,
+            song_cache: Cache::new(SONG_SEARCH_CACHE_CAPACITY),
+            songs_cache: Cache::new(SONG_SEARCH_CACHE_CAPACITY),
+        }
+    }
+
+    // 歌曲查询缓存的占用（两种查询合计）
+    pub fn search_cache_info(&self) -> CacheInfo {
+        self.song_cache.run_pending_tasks();
+        self.songs_cache.run_pending_tasks();
+        let entry_count = self.song_cache.entry_count() + self.songs_cache.entry_count();
+        CacheInfo {
+            name: CacheName::SongSearch,
+            entry_count,
+            weighted_size: entry_count,
         }
     }
 
+    // 清除歌曲查询缓存，指定查询时只清除该查询的结果，返回清除的条目数
+    pub fn evict_search_cache(&self, query: Option<&str>) -> u64 {
+        match query {
+            Some(query) => {
+                let query = query.trim();
+                u64::from(self.song_cache.remove(query).is_some())
+                    + u64::from(self.songs_cache.remove(query).is_some())
+            }
+            None => {
+                self.song_cache.run_pending_tasks();
+                self.songs_cache.run_pending_tasks();
+                let evicted = self.song_cache.entry_count() + self.songs_cache.entry_count();
+                self.song_cache.invalidate_all();
+                self.songs_cache.invalidate_all();
+                evicted
+            }
+        }
+    }
+
+    // 按署名字段检索歌曲（子串匹配，不区分大小写），结果按歌曲列表原始顺序返回
+    pub fn search_songs_by_credit(
+        &self,
+        field: SongCreditField,
+        query: &str,
+    ) -> AppResult<Vec<SongInfo>> {
+        let query_lower = query.trim().to_lowercase();
+        if query_lower.is_empty() {
+            return Err(AppError::BadRequest("查询参数 q 不能为空".to_string()));
+        }
+
+        let Some(index) = self.credit_index.get(&field) else {
+            return Ok(Vec::new());
+        };
+
+        let matched: std::collections::BTreeSet<usize> = index
+            .iter()
+            .filter(|(credit, _)| credit.contains(&query_lower))
+            .flat_map(|(_, songs)| songs.iter().copied())
+            .collect();
+
+        Ok(matched
+            .into_iter()
+            .filter_map(|idx| SONG_INFO.get(idx).cloned())
+            .collect())
+    }
+
     // 统一搜索函数：自动判断输入是ID、歌曲名还是别名
     pub fn search_song(&self, initial_query: &str) -> AppResult<SongInfo> {
         if initial_query.is_empty() {
             return Err(AppError::SongNotFound("输入为空".to_string()));
         }
 
+        // ID 匹配区分大小写，缓存键只去除首尾空白
         let query = initial_query.trim();
+        if let Some(cached) = self.song_cache.get(query) {
+            return cached.into_result();
+        }
+        let result = self.search_song_uncached(query);
+        if let Some(cached) = CachedSearch::from_result(&result) {
+            self.song_cache.insert(query.to_string(), cached);
+        }
+        result
+    }
+
+    fn search_song_uncached(&self, query: &str) -> AppResult<SongInfo> {
         let query_lower = query.to_lowercase();
         log::info!("统一搜索歌曲: '{query}'");
 
@@ -148,18 +309,29 @@ impl SongService {
 
     // 新的统一搜索函数，返回所有可能的匹配项
     pub fn search_songs(&self, query: &str) -> AppResult<Vec<SongInfo>> {
-        let mut results: Vec<SongInfo> = Vec::new();
-        let mut found_ids: HashSet<String> = HashSet::new();
         let query_trimmed = query.trim();
-        let query_lower = query_trimmed.to_lowercase();
-
         if query_trimmed.is_empty() {
             return Err(AppError::SongNotFound("输入为空".to_string()));
         }
 
+        if let Some(cached) = self.songs_cache.get(query_trimmed) {
+            return cached.into_result();
+        }
+        let result = self.search_songs_uncached(query_trimmed);
+        if let Some(cached) = CachedSearch::from_result(&result) {
+            self.songs_cache.insert(query_trimmed.to_string(), cached);
+        }
+        result
+    }
+
+    fn search_songs_uncached(&self, query: &str) -> AppResult<Vec<SongInfo>> {
+        let mut results: Vec<SongInfo> = Vec::new();
+        let mut found_ids: HashSet<String> = HashSet::new();
+        let query_lower = query.to_lowercase();
+
         // 1. 精确匹配
         // 按 ID 匹配
-        if let Some(song_info) = self.id_to_song.get(query_trimmed) {
+        if let Some(song_info) = self.id_to_song.get(query) {
             if found_ids.insert(song_info.id.clone()) {
                 results.push(song_info.clone());
             }
@@ -212,6 +384,68 @@ impl SongService {
         }
     }
 
+    // 列出搜索候选：匹配集合与 search_songs 相同，按匹配方式、曲名长度、曲名与ID排序，无匹配时为空列表
+    pub fn search_song_candidates(&self, query: &str) -> AppResult<Vec<SongCandidate>> {
+        let songs = match self.search_songs(query) {
+            Ok(songs) => songs,
+            Err(AppError::SongNotFound(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let query = query.trim();
+        let query_lower = query.to_lowercase();
+        let mut candidates: Vec<SongCandidate> = songs
+            .into_iter()
+            .map(|info| {
+                let name_lower = info.song.to_lowercase();
+                let mut matched_nickname = None;
+                let match_type = if info.id == query {
+                    SongMatchType::Id
+                } else if name_lower == query_lower {
+                    SongMatchType::Name
+                } else if self.nickname_to_id.get(&query_lower) == Some(&info.id) {
+                    matched_nickname = Some(query_lower.clone());
+                    SongMatchType::Nickname
+                } else if name_lower.starts_with(&query_lower) {
+                    SongMatchType::NamePrefix
+                } else if name_lower.contains(&query_lower) {
+                    SongMatchType::NamePartial
+                } else {
+                    // 同一首歌可能有多个别名命中，取字典序最小的一个以保证结果稳定
+                    matched_nickname = self
+                        .nickname_to_id
+                        .iter()
+                        .filter(|(nickname, id)| **id == info.id && nickname.contains(&query_lower))
+                        .map(|(nickname, _)| nickname)
+                        .min()
+                        .cloned();
+                    SongMatchType::NicknamePartial
+                };
+                SongCandidate {
+                    song_id: info.id,
+                    song_name: info.song,
+                    composer: info.composer,
+                    match_type,
+                    matched_nickname,
+                }
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            a.match_type
+                .cmp(&b.match_type)
+                .then_with(|| {
+                    a.song_name
+                        .chars()
+                        .count()
+                        .cmp(&b.song_name.chars().count())
+                })
+                .then_with(|| a.song_name.cmp(&b.song_name))
+                .then_with(|| a.song_id.cmp(&b.song_id))
+        });
+        Ok(candidates)
+    }
+
     // 根据统一查询找ID
     pub fn get_song_id(&self, query: &str) -> AppResult<String> {
         if query.is_empty() {
@@ -230,6 +464,101 @@ impl SongService {
             .ok_or_else(|| AppError::SongNotFound(id.to_string()))
     }
 
+    // 获取歌曲各难度定数，官方定数缺失时以预测定数补充
+    pub fn get_song_constants(&self, id: &str) -> SongConstants {
+        let official = DIFFICULTY_MAP.get(id);
+        let chart = |level: &str, constant: Option<f64>| {
+            let predicted_constant = match constant {
+                Some(_) => None,
+                None => get_predicted_constant(id, level),
+            };
+            (constant.is_some() || predicted_constant.is_some()).then_some(ChartConstant {
+                constant,
+                predicted_constant,
+            })
+        };
+        SongConstants {
+            ez: chart("EZ", official.and_then(|d| d.ez)),
+            hd: chart("HD", official.and_then(|d| d.hd)),
+            inl: chart("IN", official.and_then(|d| d.inl)),
+            at: chart("AT", official.and_then(|d| d.at)),
+        }
+    }
+
+    // 按 info.csv 的顺序列出歌曲及各难度定数
+    // 提供 q 时只保留 ID、曲名或任一别名包含 q 的歌曲（不区分大小写）
+    pub fn list_songs(&self, q: Option<&str>) -> Vec<SongListItem> {
+        let q = q
+            .map(str::trim)
+            .filter(|q| !q.is_empty())
+            .map(str::to_lowercase);
+        let nickname_ids: HashSet<&str> = match &q {
+            Some(q) => self
+                .nickname_to_id
+                .iter()
+                .filter(|(nickname, _)| nickname.contains(q.as_str()))
+                .map(|(_, id)| id.as_str())
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        SONG_INFO
+            .iter()
+            .filter(|info| match &q {
+                Some(q) => {
+                    info.id.to_lowercase().contains(q.as_str())
+                        || info.song.to_lowercase().contains(q.as_str())
+                        || nickname_ids.contains(info.id.as_str())
+                }
+                None => true,
+            })
+            .map(|info| {
+                let constants = self.get_song_constants(&info.id);
+                SongListItem {
+                    song_id: info.id.clone(),
+                    song_name: info.song.clone(),
+                    max_constant: constants.max_constant(),
+                    constants,
+                }
+            })
+            .collect()
+    }
+
+    // 获取单曲详情：歌曲信息及各难度的谱师、官方定数与预测定数（不含玩家成绩）
+    pub fn get_song_detail(&self, id: &str) -> AppResult<SongDetail> {
+        let info = self
+            .id_to_song
+            .get(id)
+            .cloned()
+            .ok_or_else(|| AppError::SongNotFound(id.to_string()))?;
+        // 新曲可能只有预测定数，官方定数缺失不视为错误
+        let (ez, hd, inl, at) = self
+            .get_song_difficulty(id)
+            .map(|d| (d.ez, d.hd, d.inl, d.at))
+            .unwrap_or_default();
+
+        let charts = [
+            ("EZ", info.ez_charter.clone(), ez),
+            ("HD", info.hd_charter.clone(), hd),
+            ("IN", info.in_charter.clone(), inl),
+            ("AT", info.at_charter.clone(), at),
+        ]
+        .into_iter()
+        .filter_map(|(level, charter, constant)| {
+            let predicted_constant = get_predicted_constant(id, level);
+            (constant.is_some() || predicted_constant.is_some()).then(|| SongChartDetail {
+                difficulty: level.to_string(),
+                charter,
+                constant,
+                predicted_constant,
+                record: None,
+            })
+        })
+        .collect();
+
+        Ok(SongDetail { info, charts })
+    }
+
     // 获取所有歌曲信息
     #[allow(dead_code)]
     pub fn get_all_songs(&self) -> Vec<SongInfo> {
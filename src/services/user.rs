@@ -315,6 +315,36 @@ impl UserService {
         }
     }
 
+    // 在后台按固定间隔清理过期的解绑验证码，启动后立即执行一次
+    // 过期验证码平时只在再次校验时删除，生成后未再使用的会一直残留
+    pub fn spawn_verification_code_cleanup(&self, interval: std::time::Duration) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = service.delete_expired_verification_codes().await {
+                    log::error!("定期清理过期验证码失败: {e}");
+                }
+            }
+        });
+    }
+
+    // 删除所有已过期的解绑验证码，返回删除的条数
+    pub async fn delete_expired_verification_codes(&self) -> AppResult<u64> {
+        let deleted = sqlx::query("DELETE FROM unbind_verification_codes WHERE expires_at < ?")
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("清理过期验证码时出错: {e}")))?
+            .rows_affected();
+
+        if deleted > 0 {
+            log::info!("已清理 {deleted} 条过期的解绑验证码");
+        }
+        Ok(deleted)
+    }
+
     async fn delete_verification_code(&self, platform: &str, platform_id: &str) -> AppResult<()> {
         let platform = platform.to_lowercase();
 
@@ -365,3 +395,44 @@ impl UserService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cleanup_removes_only_expired_codes() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::utils::schema::migrate(&pool).await.unwrap();
+        let service = UserService::new(pool.clone());
+
+        // 生成后从未再校验的过期验证码
+        sqlx::query(
+            "INSERT INTO unbind_verification_codes (platform, platform_id, code, expires_at)
+             VALUES ('qq', 'expired', 'ABCDEFGH', ?)",
+        )
+        .bind(Utc::now() - Duration::minutes(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+        service
+            .generate_and_store_verification_code("qq", "valid")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            service.delete_expired_verification_codes().await.unwrap(),
+            1
+        );
+        let remaining: Vec<String> =
+            sqlx::query_scalar("SELECT platform_id FROM unbind_verification_codes")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(remaining, ["valid"]);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::config::CONFIG;
+use crate::utils::error::{AppError, AppResult};
+use actix_web::HttpRequest;
+
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// 校验管理接口的访问权限
+/// 未配置 `ADMIN_TOKEN` 时所有管理接口均不可用；否则要求请求头 `X-Admin-Token` 与之完全一致
+pub fn require_admin(req: &HttpRequest) -> AppResult<()> {
+    let Some(expected) = CONFIG.admin_token.as_deref() else {
+        return Err(AppError::Forbidden(
+            "管理接口未启用，请配置 ADMIN_TOKEN".to_string(),
+        ));
+    };
+
+    let provided = req
+        .headers()
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        log::warn!("管理接口鉴权失败: {} {}", req.method(), req.path());
+        return Err(AppError::AuthError("管理员令牌无效".to_string()));
+    }
+    Ok(())
+}
+
+// 定长比较，避免通过响应时间逐字节猜测令牌
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
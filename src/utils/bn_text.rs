@@ -0,0 +1,136 @@
+use std::fmt::Write;
+
+use crate::controllers::rks::BnTextFormat;
+use crate::models::rks::RksRecord;
+use crate::utils::image_renderer::{format_acc, format_push_acc, DEFAULT_ACC_DECIMALS};
+use crate::utils::rks_utils;
+
+/// 文本输出的最大字符数，适配常见聊天平台的单条消息上限
+pub const MAX_TEXT_CHARS: usize = 2000;
+
+/// 生成 Best N 成绩的文本摘要。
+///
+/// `sorted_records` 须为按 RKS 降序排列的全部成绩（推分 ACC 需要参考全部成绩），
+/// 只输出前 `lines` 条；超出 [`MAX_TEXT_CHARS`] 时截断并注明省略的条数。
+pub fn render_bn_text(
+    player_name: &str,
+    sorted_records: &[RksRecord],
+    n: u32,
+    lines: usize,
+    format: BnTextFormat,
+) -> String {
+    let (exact_rks, _) = rks_utils::calculate_player_rks_details(sorted_records);
+
+    let mut out = String::new();
+    match format {
+        BnTextFormat::Text => {
+            let _ = writeln!(out, "{player_name} · RKS {exact_rks:.4} · Best {n}");
+        }
+        BnTextFormat::Markdown => {
+            let _ = writeln!(
+                out,
+                "**{}** · RKS {exact_rks:.4} · Best {n}\n",
+                escape_markdown(player_name)
+            );
+            out.push_str("| # | 曲目 | 难度 | ACC | RKS | 推分 |\n");
+            out.push_str("|---|---|---|---|---|---|\n");
+        }
+    }
+
+    let shown: Vec<&RksRecord> = sorted_records.iter().take(lines).collect();
+    for (index, record) in shown.iter().enumerate() {
+        let push_hint = push_hint(record, sorted_records);
+        let line = match format {
+            BnTextFormat::Text => format!(
+                "#{} {} [{} {:.1}] {}% RKS {:.4} {}\n",
+                index + 1,
+                record.song_name,
+                record.difficulty,
+                record.difficulty_value,
+                format_acc(record.acc, DEFAULT_ACC_DECIMALS),
+                record.rks,
+                push_hint
+            ),
+            BnTextFormat::Markdown => format!(
+                "| {} | {} | {} {:.1} | {}% | {:.4} | {} |\n",
+                index + 1,
+                escape_markdown(&record.song_name),
+                record.difficulty,
+                record.difficulty_value,
+                format_acc(record.acc, DEFAULT_ACC_DECIMALS),
+                record.rks,
+                push_hint
+            ),
+        };
+
+        // 为省略提示预留空间，超出上限时停止追加
+        let remaining = shown.len() - index;
+        let omitted_note = format!("…(剩余 {remaining} 条已省略)");
+        if out.chars().count() + line.chars().count() + omitted_note.chars().count()
+            > MAX_TEXT_CHARS
+        {
+            out.push_str(&omitted_note);
+            out.push('\n');
+            break;
+        }
+        out.push_str(&line);
+    }
+
+    out
+}
+
+/// 推分提示：AP 成绩标注 AP，否则给出使总 RKS 提升 0.01 所需的 ACC
+fn push_hint(record: &RksRecord, sorted_records: &[RksRecord]) -> String {
+    if record.acc >= 100.0 {
+        return "AP".to_string();
+    }
+    if record.difficulty_value <= 0.0 {
+        return "-".to_string();
+    }
+    let chart_id = format!("{}-{}", record.song_id, record.difficulty);
+    let push_acc = rks_utils::calculate_target_chart_push_acc(
+        &chart_id,
+        record.difficulty_value,
+        sorted_records,
+    )
+    .unwrap_or(100.0);
+    // 与 BN 图片的显示规则一致
+    format!(
+        "-> {}%",
+        format_push_acc(push_acc, record.acc, DEFAULT_ACC_DECIMALS)
+    )
+}
+
+/// 转义 Markdown 表格中有特殊含义的字符
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '|' | '*' | '_' | '`' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_hint_follows_image_display_rules() {
+        assert_eq!(
+            format_push_acc(99.996, 99.0, DEFAULT_ACC_DECIMALS),
+            "100.00"
+        );
+        // 取整后与当前 ACC 相同时多保留一位小数
+        assert_eq!(
+            format_push_acc(98.503, 98.501, DEFAULT_ACC_DECIMALS),
+            "98.503"
+        );
+        assert_eq!(format_push_acc(98.7, 98.0, DEFAULT_ACC_DECIMALS), "98.70");
+        // 高精度模式的阈值随小数位数变化
+        assert_eq!(format_push_acc(99.99996, 99.0, 4), "100.0000");
+        assert_eq!(format_push_acc(99.996, 99.0, 4), "99.9960");
+    }
+}
@@ -0,0 +1,206 @@
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::config::CONFIG;
+use crate::utils::error::{AppError, AppResult};
+
+lazy_static! {
+    // 按数据源名称登记的熔断器，新增外部数据源时以其名称取用即可
+    static ref BREAKERS: Mutex<BTreeMap<&'static str, Arc<CircuitBreaker>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// 获取指定数据源的熔断器，首次使用时按配置创建
+pub fn breaker_for(source: &'static str) -> Arc<CircuitBreaker> {
+    BREAKERS
+        .lock()
+        .unwrap()
+        .entry(source)
+        .or_insert_with(|| {
+            Arc::new(CircuitBreaker::new(
+                source,
+                CONFIG.external_breaker_threshold,
+                Duration::from_secs(CONFIG.external_breaker_cooldown_secs),
+            ))
+        })
+        .clone()
+}
+
+/// 所有已使用过的数据源的熔断器状态，按名称排序
+pub fn all_states() -> Vec<BreakerState> {
+    BREAKERS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|breaker| breaker.state())
+        .collect()
+}
+
+/// 外部数据源的熔断器
+///
+/// 连续失败达到阈值后断开，冷却期内的请求直接失败而不再等待上游超时；
+/// 冷却期结束后放行请求试探，成功即恢复，失败则重新断开。
+pub struct CircuitBreaker {
+    source: &'static str,
+    failure_threshold: usize,
+    cooldown: Duration,
+    inner: Mutex<BreakerInner>,
+}
+
+#[derive(Default)]
+struct BreakerInner {
+    consecutive_failures: usize,
+    open_until: Option<Instant>,
+}
+
+/// 熔断器的当前状态
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BreakerState {
+    /// 数据源名称
+    pub source: String,
+    /// closed (正常)、open (断开，请求直接失败) 或 half_open (冷却结束，等待试探结果)
+    pub state: &'static str,
+    /// 连续失败次数
+    pub consecutive_failures: usize,
+    /// 断开状态下距离恢复试探的秒数
+    pub retry_after_secs: Option<u64>,
+}
+
+impl CircuitBreaker {
+    pub fn new(source: &'static str, failure_threshold: usize, cooldown: Duration) -> Self {
+        Self {
+            source,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerInner::default()),
+        }
+    }
+
+    /// 请求上游前调用，断开状态下返回 `ServiceUnavailable`
+    pub fn check(&self) -> AppResult<()> {
+        let inner = self.inner.lock().unwrap();
+        match inner.open_until {
+            Some(open_until) if Instant::now() < open_until => {
+                let retry_after = open_until.saturating_duration_since(Instant::now());
+                Err(AppError::ServiceUnavailable(format!(
+                    "外部数据源 {} 暂时不可用，请在 {} 秒后重试",
+                    self.source,
+                    retry_after.as_secs().max(1)
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 上游正常响应时调用
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.open_until.is_some() {
+            log::info!("外部数据源 {} 已恢复，熔断器关闭", self.source);
+        }
+        *inner = BreakerInner::default();
+    }
+
+    /// 上游连接失败、超时或返回服务端错误时调用
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        if inner.consecutive_failures >= self.failure_threshold {
+            inner.open_until = Some(Instant::now() + self.cooldown);
+            log::warn!(
+                "外部数据源 {} 连续失败 {} 次，熔断 {} 秒",
+                self.source,
+                inner.consecutive_failures,
+                self.cooldown.as_secs()
+            );
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        let (state, retry_after_secs) = match inner.open_until {
+            Some(open_until) if now < open_until => (
+                "open",
+                Some(open_until.saturating_duration_since(now).as_secs().max(1)),
+            ),
+            Some(_) => ("half_open", None),
+            None => ("closed", None),
+        };
+        BreakerState {
+            source: self.source.to_string(),
+            state,
+            consecutive_failures: inner.consecutive_failures,
+            retry_after_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn opens_after_threshold_failures() {
+        let breaker = CircuitBreaker::new("test", 3, COOLDOWN);
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.state().state, "closed");
+
+        breaker.record_failure();
+        assert!(matches!(
+            breaker.check(),
+            Err(AppError::ServiceUnavailable(_))
+        ));
+        let state = breaker.state();
+        assert_eq!(state.state, "open");
+        assert_eq!(state.consecutive_failures, 3);
+        assert!(state.retry_after_secs.is_some());
+    }
+
+    #[test]
+    fn success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new("test", 2, COOLDOWN);
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.state().consecutive_failures, 1);
+    }
+
+    #[test]
+    fn half_open_after_cooldown_and_closes_on_success() {
+        let breaker = CircuitBreaker::new("test", 1, COOLDOWN);
+        breaker.record_failure();
+        assert!(breaker.check().is_err());
+
+        // 冷却结束后放行试探请求
+        std::thread::sleep(COOLDOWN * 2);
+        assert_eq!(breaker.state().state, "half_open");
+        assert!(breaker.check().is_ok());
+
+        breaker.record_success();
+        let state = breaker.state();
+        assert_eq!(state.state, "closed");
+        assert_eq!(state.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn failed_probe_reopens_the_breaker() {
+        let breaker = CircuitBreaker::new("test", 1, COOLDOWN);
+        breaker.record_failure();
+        std::thread::sleep(COOLDOWN * 2);
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure();
+        assert!(breaker.check().is_err());
+        assert_eq!(breaker.state().state, "open");
+    }
+}
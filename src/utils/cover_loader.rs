@@ -1,14 +1,37 @@
+use crate::config::CONFIG;
 use crate::utils::error::{AppError, AppResult};
 use git2::Repository;
 use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use lazy_static::lazy_static;
+use moka::future::Cache;
+use reqwest::{Client, StatusCode};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub const COVERS_DIR: &str = "resources/covers";
 const GIT_REPO_URL: &str = "https://gitee.com/Steveeee-e/phi-plugin-ill.git";
+// 远程确实不存在的曲绘在负缓存中保留的时间
+const MISSING_COVER_TTL_SECS: u64 = 3600;
 #[allow(dead_code)]
 const PLACEHOLDER_COLOR: Rgba<u8> = Rgba([100, 100, 100, 255]); // 灰色占位符
 
+lazy_static! {
+    // 远程曲绘源的负缓存：记录确认不存在的曲目ID，避免每次渲染都重复请求
+    static ref MISSING_COVER_CACHE: Cache<String, ()> = Cache::builder()
+        .max_capacity(4096)
+        .time_to_live(Duration::from_secs(MISSING_COVER_TTL_SECS))
+        .build();
+    static ref COVER_HTTP_CLIENT: Client = Client::builder()
+        .connect_timeout(Duration::from_secs(3))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_else(|e| {
+            log::warn!("构建曲绘下载 HTTP 客户端失败，回退默认设置: {e}");
+            Client::new()
+        });
+}
+
 // 确保本地曲绘目录存在且包含内容，否则尝试克隆
 pub fn ensure_covers_available() -> AppResult<()> {
     let covers_path = Path::new(COVERS_DIR);
@@ -67,28 +90,136 @@ fn clone_repo(target_path: &Path) -> AppResult<()> {
     }
 }
 
+// 曲绘尺寸：Low 对应 illLow 目录的缩略图，Full 对应 ill 目录的原图
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverSize {
+    Low,
+    Full,
+}
+
+impl CoverSize {
+    fn sub_dir(self) -> &'static str {
+        match self {
+            CoverSize::Low => "illLow",
+            CoverSize::Full => "ill",
+        }
+    }
+}
+
+// 曲目ID会拼接进URL和文件路径，拒绝空ID及包含路径分隔符的ID
+pub fn is_safe_song_id(song_id: &str) -> bool {
+    !song_id.is_empty() && !song_id.contains(['/', '\\']) && !song_id.contains("..")
+}
+
+// 查找本地曲绘文件（依次尝试 .png 与 .jpg），找不到时返回 None
+pub fn find_cover_path(song_id: &str, size: CoverSize) -> Option<PathBuf> {
+    if !is_safe_song_id(song_id) {
+        return None;
+    }
+    let dir = PathBuf::from(COVERS_DIR).join(size.sub_dir());
+    ["png", "jpg"]
+        .iter()
+        .map(|ext| dir.join(format!("{song_id}.{ext}")))
+        .find(|path| path.exists())
+}
+
+// 检查本地 ill 或 illLow 目录中是否已有该曲目的曲绘
+pub fn has_local_cover(song_id: &str) -> bool {
+    find_cover_path(song_id, CoverSize::Low).is_some()
+        || find_cover_path(song_id, CoverSize::Full).is_some()
+}
+
+// 为一批曲目按需拉取缺失的曲绘（未配置 COVER_CDN_BASE_URL 时不做任何事）
+pub async fn fetch_missing_covers(song_ids: &[String]) {
+    if CONFIG.cover_cdn_base_url.is_none() {
+        return;
+    }
+    futures::future::join_all(song_ids.iter().map(|id| fetch_missing_cover(id))).await;
+}
+
+// 本地缺少曲绘时，从配置的曲绘源下载到 ill / illLow 目录
+// 返回值表示调用结束后本地是否存在该曲绘
+pub async fn fetch_missing_cover(song_id: &str) -> bool {
+    if has_local_cover(song_id) {
+        return true;
+    }
+    let Some(base_url) = CONFIG.cover_cdn_base_url.as_deref() else {
+        return false;
+    };
+    if !is_safe_song_id(song_id) {
+        return false;
+    }
+    if MISSING_COVER_CACHE.contains_key(song_id) {
+        return false;
+    }
+
+    let mut fetched = false;
+    let mut all_not_found = true;
+    // 按需下载时写入的曲绘子目录与曲绘仓库结构一致
+    for sub_dir in [CoverSize::Full.sub_dir(), CoverSize::Low.sub_dir()] {
+        match download_cover(base_url, sub_dir, song_id).await {
+            Ok(true) => {
+                fetched = true;
+                all_not_found = false;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                all_not_found = false;
+                log::warn!("下载曲绘失败 '{song_id}' ({sub_dir}): {e}");
+            }
+        }
+    }
+
+    if fetched {
+        log::info!("已从远程曲绘源补全曲绘: {song_id}");
+    } else if all_not_found {
+        log::debug!("远程曲绘源中不存在曲绘 '{song_id}'，加入负缓存");
+        MISSING_COVER_CACHE.insert(song_id.to_string(), ()).await;
+    }
+    fetched
+}
+
+// 下载单张曲绘，返回 Ok(false) 表示远程不存在
+async fn download_cover(base_url: &str, sub_dir: &str, song_id: &str) -> AppResult<bool> {
+    let url = format!("{}/{sub_dir}/{song_id}.png", base_url.trim_end_matches('/'));
+    let response = COVER_HTTP_CLIENT.get(&url).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    if !response.status().is_success() {
+        return Err(AppError::Other(format!(
+            "曲绘源返回异常状态 {}: {url}",
+            response.status()
+        )));
+    }
+
+    let bytes = response.bytes().await?;
+    if image::guess_format(&bytes).is_err() {
+        return Err(AppError::Other(format!("曲绘源返回的不是有效图片: {url}")));
+    }
+
+    // 先写入临时文件再重命名，避免渲染线程读到写了一半的图片
+    let dir = PathBuf::from(COVERS_DIR).join(sub_dir);
+    tokio::fs::create_dir_all(&dir).await?;
+    let target = dir.join(format!("{song_id}.png"));
+    let tmp = dir.join(format!("{song_id}.png.part"));
+    tokio::fs::write(&tmp, &bytes).await?;
+    tokio::fs::rename(&tmp, &target).await?;
+    Ok(true)
+}
+
 // 加载本地曲绘图片，如果找不到则返回占位图
 #[allow(dead_code)]
 pub fn load_local_cover(song_id: &str, size: (u32, u32)) -> RgbaImage {
-    // 假设克隆后的仓库结构为 resources/covers/illLow/{song_id}.png
-    // 如果不是，需要调整此路径
-    let path_png = PathBuf::from(COVERS_DIR)
-        .join("illLow")
-        .join(format!("{song_id}.png"));
-    let path_jpg = PathBuf::from(COVERS_DIR)
-        .join("illLow")
-        .join(format!("{song_id}.jpg"));
-
-    let img_result: Result<DynamicImage, image::ImageError> = if path_png.exists() {
-        image::open(&path_png)
-    } else if path_jpg.exists() {
-        image::open(&path_jpg)
-    } else {
-        Err(image::ImageError::IoError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Cover not found",
-        )))
-    };
+    let img_result: Result<DynamicImage, image::ImageError> =
+        if let Some(path) = find_cover_path(song_id, CoverSize::Low) {
+            image::open(&path)
+        } else {
+            Err(image::ImageError::IoError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Cover not found",
+            )))
+        };
 
     match img_result {
         Ok(img) => {
@@ -1,6 +1,9 @@
 use aes::Aes256;
 use base64::{engine::general_purpose, Engine as _};
-use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use cbc::cipher::{
+    block_padding::{NoPadding, Pkcs7},
+    BlockDecryptMut, BlockEncryptMut, KeyIvInit,
+};
 use cbc::{Decryptor, Encryptor};
 use md5::{Digest, Md5};
 use once_cell::sync::Lazy; // 引入 once_cell 来实现单次初始化
@@ -25,7 +28,6 @@ static AES_IV: Lazy<[u8; 16]> = Lazy::new(|| {
     iv_vec.try_into().expect("配置中的AES IV长度必须是16字节")
 });
 
-#[allow(dead_code)]
 pub fn encrypt(data: &[u8]) -> AppResult<Vec<u8>> {
     // 直接使用已经初始化好的静态 KEY 和 IV
     // new_from_slices 已经隐式地验证了长度，因为我们用了固定长度数组 [u8; 32]
@@ -59,6 +61,90 @@ pub fn calculate_md5(data: &[u8]) -> String {
     format!("{result:x}")
 }
 
+// --- 启动自检 ---
+// NIST SP 800-38A F.2.5 (CBC-AES256) 的前两个分组，用于确认 AES-256-CBC 实现本身正确，与配置无关
+const NIST_KEY_HEX: &str = "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+const NIST_IV_HEX: &str = "000102030405060708090a0b0c0d0e0f";
+const NIST_PLAINTEXT_HEX: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+const NIST_CIPHERTEXT_HEX: &str =
+    "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d";
+
+// 存档密钥/IV 的已知向量：由 OpenSSL 独立生成，而非本模块的 encrypt，
+// 密钥或 IV 被改错时解密结果会对不上，从而在启动时就暴露问题，而不是等到第一次拉取存档才报错。
+// printf 'Phi-Backend AES self-test' | openssl enc -aes-256-cbc -K <密钥hex> -iv <IV hex> | base64
+const SELF_TEST_PLAINTEXT: &[u8] = b"Phi-Backend AES self-test";
+const SELF_TEST_CIPHERTEXT_BASE64: &str = "WqAFz19dd+d8LbR8RZFum2eL5LGSsOeFQzZGa0vJ5T8=";
+
+/// 使用 NIST 已知向量校验 AES-256-CBC 的加解密实现
+fn nist_known_answer_test() -> AppResult<()> {
+    let decode =
+        |s: &str| hex::decode(s).map_err(|e| AppError::AesError(format!("NIST 向量解码失败: {e}")));
+    let (key, iv) = (decode(NIST_KEY_HEX)?, decode(NIST_IV_HEX)?);
+    let (plaintext, ciphertext) = (decode(NIST_PLAINTEXT_HEX)?, decode(NIST_CIPHERTEXT_HEX)?);
+
+    let encrypted = Encryptor::<Aes256>::new_from_slices(&key, &iv)
+        .map_err(|e| AppError::AesError(format!("AES加密器初始化失败: {e}")))?
+        .encrypt_padded_vec_mut::<NoPadding>(&plaintext);
+    let decrypted = Decryptor::<Aes256>::new_from_slices(&key, &iv)
+        .map_err(|e| AppError::AesError(format!("AES解密器初始化失败: {e}")))?
+        .decrypt_padded_vec_mut::<NoPadding>(&ciphertext)
+        .map_err(|e| AppError::AesError(format!("NIST 向量解密失败: {e}")))?;
+    if encrypted != ciphertext || decrypted != plaintext {
+        return Err(AppError::AesError(
+            "AES-256-CBC 实现与 NIST 已知向量不一致".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 使用已知向量校验 AES 密钥与 IV 配置是否正确
+///
+/// 先用 NIST 向量确认加解密实现正确，再以不会 panic 的方式检查 Base64 与长度，
+/// 最后分别验证解密和加密结果与 OpenSSL 生成的已知向量一致。
+pub fn self_test() -> AppResult<()> {
+    nist_known_answer_test()?;
+
+    let key_len = general_purpose::STANDARD
+        .decode(AES_KEY_BASE64)
+        .map_err(|e| AppError::AesError(format!("AES密钥 (AES_KEY_BASE64) 不是有效的Base64: {e}")))?
+        .len();
+    if key_len != 32 {
+        return Err(AppError::AesError(format!(
+            "AES密钥长度必须是32字节，当前为 {key_len} 字节"
+        )));
+    }
+    let iv_len = general_purpose::STANDARD
+        .decode(AES_IV_BASE64)
+        .map_err(|e| AppError::AesError(format!("AES IV (AES_IV_BASE64) 不是有效的Base64: {e}")))?
+        .len();
+    if iv_len != 16 {
+        return Err(AppError::AesError(format!(
+            "AES IV长度必须是16字节，当前为 {iv_len} 字节"
+        )));
+    }
+
+    let ciphertext = general_purpose::STANDARD
+        .decode(SELF_TEST_CIPHERTEXT_BASE64)
+        .map_err(|e| AppError::AesError(format!("自检向量解码失败: {e}")))?;
+
+    let decrypted = decrypt(&ciphertext).map_err(|e| {
+        AppError::AesError(format!("已知向量解密失败，AES密钥或IV可能配置错误: {e}"))
+    })?;
+    if decrypted != SELF_TEST_PLAINTEXT {
+        return Err(AppError::AesError(
+            "已知向量解密结果不一致，AES密钥或IV配置错误".to_string(),
+        ));
+    }
+
+    if encrypt(SELF_TEST_PLAINTEXT)? != ciphertext {
+        return Err(AppError::AesError(
+            "已知向量加密结果不一致，AES密钥或IV配置错误".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 // --- session token 验证 ---
 pub fn validate_session_token(token: &str) -> bool {
     if token.len() != 25 {
@@ -66,3 +152,32 @@ pub fn validate_session_token(token: &str) -> bool {
     }
     token.chars().all(|c| c.is_ascii_alphanumeric())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nist_vector_matches() {
+        nist_known_answer_test().unwrap();
+    }
+
+    #[test]
+    fn save_key_decrypts_openssl_vector() {
+        let ciphertext =
+            hex::decode("5aa005cf5f5d77e77c2db47c45916e9b678be4b192b0e7854336466b4bc9e53f")
+                .unwrap();
+        assert_eq!(decrypt(&ciphertext).unwrap(), SELF_TEST_PLAINTEXT);
+        assert_eq!(encrypt(SELF_TEST_PLAINTEXT).unwrap(), ciphertext);
+        self_test().unwrap();
+    }
+
+    #[test]
+    fn tampered_ciphertext_does_not_decrypt_to_plaintext() {
+        let mut ciphertext = general_purpose::STANDARD
+            .decode(SELF_TEST_CIPHERTEXT_BASE64)
+            .unwrap();
+        ciphertext[0] ^= 0xff;
+        assert!(decrypt(&ciphertext).map_or(true, |plain| plain != SELF_TEST_PLAINTEXT));
+    }
+}
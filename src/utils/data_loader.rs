@@ -4,7 +4,10 @@ use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use chrono::{DateTime, Utc};
 
 use crate::models::predictions::PredictedConstants;
 use crate::models::song::{NicknameMap, SongDifficulty, SongInfo};
@@ -17,6 +20,12 @@ fn get_data_path(env_var: &str, default_value: &str) -> PathBuf {
         .unwrap_or_else(|_| PathBuf::from(default_value))
 }
 
+// difficulty.csv 中重复歌曲ID的数量（在加载 SONG_DIFFICULTY 时统计）
+static DIFFICULTY_DUPLICATE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// 歌曲数据的加载时间（在加载 SONG_INFO 时记录），数据文件只在启动后加载一次
+static DATA_LOADED_AT: OnceLock<DateTime<Utc>> = OnceLock::new();
+
 lazy_static! {
     static ref INFO_DATA_PATH_BUF: PathBuf = get_data_path("INFO_DATA_PATH", "info");
     static ref INFO_FILE_PATH: PathBuf =
@@ -29,6 +38,7 @@ lazy_static! {
         env::var("PREDICTIONS_FILE").unwrap_or_else(|_| "chart_predictions_wide.csv".to_string())
     );
     pub static ref SONG_INFO: Arc<Vec<SongInfo>> = Arc::new({
+        DATA_LOADED_AT.get_or_init(Utc::now);
         match load_song_info(&INFO_FILE_PATH) {
             Ok(info) => {
                 log::info!("已加载 {} 条歌曲信息", info.len());
@@ -191,14 +201,27 @@ fn load_song_info(path: &Path) -> AppResult<Vec<SongInfo>> {
 fn load_song_difficulty(path: &Path) -> AppResult<Vec<SongDifficulty>> {
     log::debug!("正在加载歌曲难度，路径: {}", path.display());
     let mut rdr = csv::Reader::from_path(path)?;
-    let mut difficulties = Vec::new();
+    let mut difficulties: Vec<SongDifficulty> = Vec::new();
+    // 歌曲ID -> 首次出现的行号，用于检测重复ID
+    let mut first_seen: HashMap<String, usize> = HashMap::new();
+    let mut duplicates: Vec<String> = Vec::new();
 
     for (index, result) in rdr.deserialize().enumerate() {
         let line_num = index + 2;
         log::trace!("处理 difficulty.csv 第 {line_num} 行...");
         match result {
             Ok(record) => {
+                let record: SongDifficulty = record;
                 log::trace!("成功解析第 {line_num} 行: {record:?}");
+                // 重复ID只保留首次出现的记录，避免后面的行静默覆盖定数
+                if let Some(first_line) = first_seen.get(&record.id) {
+                    duplicates.push(format!(
+                        "'{}' (第 {first_line} 行与第 {line_num} 行)",
+                        record.id
+                    ));
+                    continue;
+                }
+                first_seen.insert(record.id.clone(), line_num);
                 difficulties.push(record);
             }
             Err(e) => {
@@ -207,6 +230,15 @@ fn load_song_difficulty(path: &Path) -> AppResult<Vec<SongDifficulty>> {
         }
     }
 
+    if !duplicates.is_empty() {
+        log::warn!(
+            "difficulty.csv 中存在 {} 个重复的歌曲ID，已保留首次出现的记录: {}",
+            duplicates.len(),
+            duplicates.join(", ")
+        );
+    }
+    DIFFICULTY_DUPLICATE_COUNT.store(duplicates.len(), Ordering::Relaxed);
+
     log::debug!("歌曲难度加载完成，共 {} 条", difficulties.len());
     Ok(difficulties)
 }
@@ -253,6 +285,43 @@ fn load_predicted_constants(path: &Path) -> AppResult<HashMap<String, PredictedC
     Ok(predictions)
 }
 
+/// 歌曲数据的加载时间，可作为歌曲列表等只依赖数据文件的响应的版本
+pub fn data_loaded_at() -> DateTime<Utc> {
+    lazy_static::initialize(&SONG_INFO);
+    *DATA_LOADED_AT.get_or_init(Utc::now)
+}
+
+/// 获取 difficulty.csv 中重复歌曲ID的数量（会触发难度数据加载）
+pub fn get_difficulty_duplicate_count() -> usize {
+    lazy_static::initialize(&SONG_DIFFICULTY);
+    DIFFICULTY_DUPLICATE_COUNT.load(Ordering::Relaxed)
+}
+
+lazy_static! {
+    // 自启动以来存档中出现、但 info.csv 或 difficulty.csv 未收录的歌曲ID -> 出现次数
+    static ref UNKNOWN_SAVE_SONGS: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+/// 歌曲ID是否同时收录于 info.csv 与 difficulty.csv
+pub fn is_song_fully_known(id: &str) -> bool {
+    SONG_ID_TO_NAME.contains_key(id) && DIFFICULTY_MAP.contains_key(id)
+}
+
+/// 记录存档中出现的未收录歌曲ID，供管理员报告使用
+pub fn record_unknown_save_songs<'a>(song_ids: impl IntoIterator<Item = &'a String>) {
+    let mut unknown = UNKNOWN_SAVE_SONGS.lock().unwrap();
+    for id in song_ids {
+        if !is_song_fully_known(id) {
+            *unknown.entry(id.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// 获取自启动以来记录到的未收录歌曲ID及其在存档中出现的次数
+pub fn unknown_save_songs() -> HashMap<String, u64> {
+    UNKNOWN_SAVE_SONGS.lock().unwrap().clone()
+}
+
 pub fn get_song_name_by_id(id: &str) -> Option<String> {
     let result = SONG_ID_TO_NAME.get(id).cloned();
     if result.is_none() {
@@ -261,7 +330,6 @@ pub fn get_song_name_by_id(id: &str) -> Option<String> {
     result
 }
 
-#[allow(dead_code)]
 pub fn get_song_id_by_name(name: &str) -> Option<String> {
     SONG_NAME_TO_ID.get(name).cloned()
 }
@@ -1,5 +1,7 @@
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::error::JsonPayloadError;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
 use serde::Serialize;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -20,6 +22,15 @@ pub enum AppError {
     #[error("找不到歌曲: {0}")]
     SongNotFound(String),
 
+    #[error("存档中没有任何成绩记录，请先游玩任意谱面并同步云存档")]
+    EmptySave,
+
+    #[error("找不到成绩记录: {0}")]
+    RecordNotFound(String),
+
+    #[error("找不到曲绘: {0}")]
+    CoverNotFound(String),
+
     #[error("查询匹配到多个歌曲: {0}")]
     AmbiguousSongName(String),
 
@@ -83,6 +94,9 @@ pub enum AppError {
     #[error("认证错误: {0}")]
     AuthError(String),
 
+    #[error("禁止访问: {0}")]
+    Forbidden(String),
+
     #[error("存档解密错误: {0}")]
     SaveDecryptError(String),
 
@@ -97,10 +111,72 @@ pub enum AppError {
 
     #[error("请求超时")]
     Timeout,
+
+    #[error("服务暂不可用: {0}")]
+    ServiceUnavailable(String),
 }
 
 pub type AppResult<T> = Result<T, AppError>;
 
+impl AppError {
+    /// 从缓存共享的错误 (`moka` 的 `try_get_with` 返回 `Arc<AppError>`) 还原出独立的错误
+    ///
+    /// 只有一个持有者时直接取出原错误；否则按变体重建，包装外部库错误的变体
+    /// 转为状态码相同的字符串变体，保证客户端看到的状态码与错误类型不因缓存而改变。
+    pub fn from_shared(err: Arc<AppError>) -> AppError {
+        let err = match Arc::try_unwrap(err) {
+            Ok(err) => return err,
+            Err(shared) => shared,
+        };
+        match err.as_ref() {
+            AppError::AesError(msg) => AppError::AesError(msg.clone()),
+            AppError::InvalidSessionToken => AppError::InvalidSessionToken,
+            AppError::InvalidSaveSize(size) => AppError::InvalidSaveSize(*size),
+            AppError::ChecksumMismatch { expected, actual } => AppError::ChecksumMismatch {
+                expected: expected.clone(),
+                actual: actual.clone(),
+            },
+            AppError::SongNotFound(msg) => AppError::SongNotFound(msg.clone()),
+            AppError::EmptySave => AppError::EmptySave,
+            AppError::RecordNotFound(msg) => AppError::RecordNotFound(msg.clone()),
+            AppError::CoverNotFound(msg) => AppError::CoverNotFound(msg.clone()),
+            AppError::AmbiguousSongName(msg) => AppError::AmbiguousSongName(msg.clone()),
+            AppError::UserBindingNotFound(msg) => AppError::UserBindingNotFound(msg.clone()),
+            AppError::UserNotFound(msg) => AppError::UserNotFound(msg.clone()),
+            AppError::BindingAlreadyExists(msg) => AppError::BindingAlreadyExists(msg.clone()),
+            AppError::ProfileVerificationFailed(msg) => {
+                AppError::ProfileVerificationFailed(msg.clone())
+            }
+            AppError::TokenVerificationFailed(msg) => {
+                AppError::TokenVerificationFailed(msg.clone())
+            }
+            AppError::VerificationCodeExpired => AppError::VerificationCodeExpired,
+            AppError::VerificationCodeInvalid => AppError::VerificationCodeInvalid,
+            AppError::VerificationCodeNotFound => AppError::VerificationCodeNotFound,
+            AppError::DatabaseError(msg) => AppError::DatabaseError(msg.clone()),
+            AppError::BadRequest(msg) => AppError::BadRequest(msg.clone()),
+            AppError::DecodeError(e) => AppError::DecodeError(e.clone()),
+            AppError::IoError(e) => AppError::IoError(std::io::Error::new(e.kind(), e.to_string())),
+            AppError::DbError(e) => AppError::DatabaseError(e.to_string()),
+            AppError::SerdeJsonError(_) | AppError::SerdeYamlError(_) => {
+                AppError::BadRequest(err.to_string())
+            }
+            AppError::ZipError(_) | AppError::ReqwestError(_) | AppError::CsvError(_) => {
+                AppError::InternalError(err.to_string())
+            }
+            AppError::Other(msg) => AppError::Other(msg.clone()),
+            AppError::AuthError(msg) => AppError::AuthError(msg.clone()),
+            AppError::Forbidden(msg) => AppError::Forbidden(msg.clone()),
+            AppError::SaveDecryptError(msg) => AppError::SaveDecryptError(msg.clone()),
+            AppError::ConfigError(msg) => AppError::ConfigError(msg.clone()),
+            AppError::ValidationError(msg) => AppError::ValidationError(msg.clone()),
+            AppError::InternalError(msg) => AppError::InternalError(msg.clone()),
+            AppError::Timeout => AppError::Timeout,
+            AppError::ServiceUnavailable(msg) => AppError::ServiceUnavailable(msg.clone()),
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
@@ -127,6 +203,13 @@ impl ResponseError for AppError {
                 "checksum_mismatch",
             ),
             AppError::SongNotFound(_) => (actix_web::http::StatusCode::NOT_FOUND, "song_not_found"),
+            AppError::EmptySave => (actix_web::http::StatusCode::NOT_FOUND, "empty_save"),
+            AppError::RecordNotFound(_) => {
+                (actix_web::http::StatusCode::NOT_FOUND, "record_not_found")
+            }
+            AppError::CoverNotFound(_) => {
+                (actix_web::http::StatusCode::NOT_FOUND, "cover_not_found")
+            }
             AppError::AmbiguousSongName(_) => (
                 actix_web::http::StatusCode::BAD_REQUEST,
                 "ambiguous_song_name",
@@ -198,6 +281,7 @@ impl ResponseError for AppError {
                 actix_web::http::StatusCode::UNAUTHORIZED,
                 "authentication_error",
             ),
+            AppError::Forbidden(_) => (actix_web::http::StatusCode::FORBIDDEN, "forbidden"),
             AppError::SaveDecryptError(_) => {
                 (actix_web::http::StatusCode::BAD_REQUEST, "decryption_error")
             }
@@ -216,6 +300,10 @@ impl ResponseError for AppError {
                 actix_web::http::StatusCode::GATEWAY_TIMEOUT,
                 "request_timeout",
             ),
+            AppError::ServiceUnavailable(_) => (
+                actix_web::http::StatusCode::SERVICE_UNAVAILABLE,
+                "service_unavailable",
+            ),
         };
 
         HttpResponse::build(status_code).json(ErrorResponse {
@@ -224,3 +312,63 @@ impl ResponseError for AppError {
         })
     }
 }
+
+/// JSON 请求体的大小上限 (字节)
+///
+/// 所有接口的请求体都只包含身份信息或少量成绩，64 KiB 足以容纳
+/// `/image/bn/user-generated` 的 100 条成绩，超出的请求在反序列化前即被拒绝。
+pub const JSON_BODY_LIMIT: usize = 64 * 1024;
+
+/// JSON 请求体解析失败时统一返回 `400 Bad Request`，响应格式与其他错误一致
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let message = match &err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            format!("请求体过大，不能超过 {} KiB", JSON_BODY_LIMIT / 1024)
+        }
+        _ => format!("请求体无效: {err}"),
+    };
+    AppError::BadRequest(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn shared_empty_save_keeps_404() {
+        // 缓存中的其他请求仍持有同一个错误时也要保留 empty_save
+        let shared = Arc::new(AppError::EmptySave);
+        let _other_waiter = shared.clone();
+        let err = AppError::from_shared(shared);
+        assert!(matches!(err, AppError::EmptySave));
+        assert_eq!(err.error_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn shared_errors_keep_their_status_code() {
+        let cases = vec![
+            AppError::BadRequest("无 AP 成绩".to_string()),
+            AppError::AuthError("Token 已失效".to_string()),
+            AppError::SongNotFound("Glaciaxion".to_string()),
+            AppError::InvalidSessionToken,
+            AppError::Timeout,
+            AppError::DbError(sqlx::Error::RowNotFound),
+            AppError::IoError(std::io::Error::other("磁盘错误")),
+            AppError::SerdeJsonError(serde_json::from_str::<u32>("x").unwrap_err()),
+        ];
+        for original in cases {
+            let expected = original.error_response().status();
+            let shared = Arc::new(original);
+            let _other_waiter = shared.clone();
+            let err = AppError::from_shared(shared);
+            assert_eq!(err.error_response().status(), expected, "{err}");
+        }
+    }
+
+    #[test]
+    fn sole_owner_gets_the_original_error() {
+        let err = AppError::from_shared(Arc::new(AppError::DbError(sqlx::Error::RowNotFound)));
+        assert!(matches!(err, AppError::DbError(_)));
+    }
+}
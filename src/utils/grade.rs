@@ -0,0 +1,175 @@
+use lazy_static::lazy_static;
+use std::str::FromStr;
+
+use crate::config::CONFIG;
+
+/// 满分，达到即为 φ
+const PHI_SCORE: f64 = 1_000_000.0;
+
+lazy_static! {
+    // 由 `GRADE_THRESHOLDS` 配置的分数线，未配置或无效时使用游戏内的默认值
+    static ref THRESHOLDS: GradeThresholds = CONFIG.grade_thresholds.parse().unwrap_or_default();
+}
+
+/// 成绩评级 (φ/V/S/A/B/C/F)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    Phi,
+    V,
+    S,
+    A,
+    B,
+    C,
+    F,
+}
+
+impl Grade {
+    /// 图片中显示的评级文字
+    pub fn label(self) -> &'static str {
+        match self {
+            Grade::Phi => "φ",
+            Grade::V => "V",
+            Grade::S => "S",
+            Grade::A => "A",
+            Grade::B => "B",
+            Grade::C => "C",
+            Grade::F => "F",
+        }
+    }
+
+    /// 评级文字对应的 SVG 样式类
+    pub fn css_class(self) -> &'static str {
+        match self {
+            Grade::Phi => "rank-phi",
+            Grade::V => "rank-v",
+            Grade::S => "rank-s",
+            Grade::A => "rank-a",
+            Grade::B => "rank-b",
+            Grade::C => "rank-c",
+            Grade::F => "rank-f",
+        }
+    }
+}
+
+/// V/S/A/B/C 各评级的最低分数，低于 C 的分数线为 F
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GradeThresholds([u32; 5]);
+
+impl Default for GradeThresholds {
+    fn default() -> Self {
+        Self([960_000, 920_000, 880_000, 820_000, 700_000])
+    }
+}
+
+impl FromStr for GradeThresholds {
+    type Err = String;
+
+    /// 解析逗号分隔的 V,S,A,B,C 分数线，如 `960000,920000,880000,820000,700000`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| {
+                v.trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("无效的分数线: '{}'", v.trim()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let thresholds: [u32; 5] = values
+            .try_into()
+            .map_err(|v: Vec<u32>| format!("需要 5 个分数线 (V,S,A,B,C)，实际为 {} 个", v.len()))?;
+        if thresholds[0] as f64 >= PHI_SCORE {
+            return Err(format!("V 的分数线必须小于 {PHI_SCORE}"));
+        }
+        if !thresholds.windows(2).all(|pair| pair[0] > pair[1]) {
+            return Err("分数线必须按 V,S,A,B,C 严格递减".to_string());
+        }
+        Ok(Self(thresholds))
+    }
+}
+
+impl GradeThresholds {
+    /// 按分数计算评级，满分为 φ
+    pub fn grade(&self, score: f64) -> Grade {
+        if score >= PHI_SCORE {
+            return Grade::Phi;
+        }
+        let [v, s, a, b, c] = self.0.map(f64::from);
+        match score {
+            score if score >= v => Grade::V,
+            score if score >= s => Grade::S,
+            score if score >= a => Grade::A,
+            score if score >= b => Grade::B,
+            score if score >= c => Grade::C,
+            _ => Grade::F,
+        }
+    }
+}
+
+/// 按配置的分数线计算成绩评级
+///
+/// ACC 100% 直接视为 φ；其他情况需要分数，没有分数时返回 `None`。
+pub fn grade_of(score: Option<f64>, acc: f64) -> Option<Grade> {
+    if acc >= 100.0 {
+        return Some(Grade::Phi);
+    }
+    score.map(|score| THRESHOLDS.grade(score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_thresholds_grade_on_boundaries() {
+        let thresholds = GradeThresholds::default();
+        let cases = [
+            (1_000_000.0, Grade::Phi),
+            (999_999.0, Grade::V),
+            (960_000.0, Grade::V),
+            (959_999.0, Grade::S),
+            (920_000.0, Grade::S),
+            (919_999.0, Grade::A),
+            (880_000.0, Grade::A),
+            (879_999.0, Grade::B),
+            (820_000.0, Grade::B),
+            (819_999.0, Grade::C),
+            (700_000.0, Grade::C),
+            (699_999.0, Grade::F),
+            (0.0, Grade::F),
+        ];
+        for (score, expected) in cases {
+            assert_eq!(thresholds.grade(score), expected, "score = {score}");
+        }
+    }
+
+    #[test]
+    fn custom_thresholds_are_parsed_and_applied() {
+        let thresholds: GradeThresholds = " 950000, 900000,850000 ,800000,600000".parse().unwrap();
+        assert_eq!(thresholds.grade(950_000.0), Grade::V);
+        assert_eq!(thresholds.grade(949_999.0), Grade::S);
+        assert_eq!(thresholds.grade(600_000.0), Grade::C);
+        assert_eq!(thresholds.grade(599_999.0), Grade::F);
+        assert_eq!(thresholds.grade(1_000_000.0), Grade::Phi);
+    }
+
+    #[test]
+    fn invalid_thresholds_are_rejected() {
+        for invalid in [
+            "960000,920000,880000,820000",
+            "960000,920000,880000,820000,700000,600000",
+            "960000,920000,abc,820000,700000",
+            "1000000,920000,880000,820000,700000",
+            "960000,960000,880000,820000,700000",
+            "700000,820000,880000,920000,960000",
+        ] {
+            assert!(invalid.parse::<GradeThresholds>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn ap_is_phi_regardless_of_score() {
+        assert_eq!(grade_of(None, 100.0), Some(Grade::Phi));
+        assert_eq!(grade_of(Some(0.0), 100.0), Some(Grade::Phi));
+        assert_eq!(grade_of(None, 99.5), None);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::controllers::image::ImageLang;
+
+/// 图片中的文字标签，BN、单曲、排行榜、对比与 AP Top 3 图共用
+///
+/// 模板中的 `{value}` 替换为对应的数值或文本，`{n}` 替换为 Best N 的数量。
+pub struct ImageLabels {
+    pub ap_top_3_avg: &'static str,
+    pub ap_best_n_avg: &'static str,
+    pub best_27_avg: &'static str,
+    pub region: &'static str,
+    pub constant: &'static str,
+    pub data: &'static str,
+    pub challenge: &'static str,
+    pub updated_at: &'static str,
+    pub generated_at: &'static str,
+    /// 单曲图的玩家名称
+    pub player: &'static str,
+    /// 单曲图中谱面不存在的难度
+    pub no_chart: &'static str,
+    /// 单曲图页脚
+    pub generated_by: &'static str,
+    pub leaderboard_title: &'static str,
+    pub compare_title: &'static str,
+    /// 对比图页脚
+    pub rendered_at: &'static str,
+    /// 对比图中某一侧玩家数据获取失败
+    pub load_failed: &'static str,
+}
+
+const EN: ImageLabels = ImageLabels {
+    ap_top_3_avg: "AP Top 3 Avg: {value}",
+    ap_best_n_avg: "AP Best {n} Avg: {value}",
+    best_27_avg: "Best 27 Avg: {value}",
+    region: "Region: {value}",
+    constant: "Constant: {value}",
+    data: "Data: {value}",
+    challenge: "Challenge: {value}",
+    updated_at: "Updated at {value} UTC",
+    generated_at: "Generated by Phi-Backend at {value} UTC+8",
+    player: "Player: {value}",
+    no_chart: "No Chart",
+    generated_by: "Generated by Phi-Backend | {value} UTC+8",
+    leaderboard_title: "RKS Leaderboard",
+    compare_title: "Player Comparison",
+    rendered_at: "Generated at {value} UTC",
+    load_failed: "Failed to load data",
+};
+
+const ZH: ImageLabels = ImageLabels {
+    ap_top_3_avg: "AP Top 3 平均: {value}",
+    ap_best_n_avg: "AP Best {n} 平均: {value}",
+    best_27_avg: "Best 27 平均: {value}",
+    region: "地区: {value}",
+    constant: "定数: {value}",
+    data: "Data: {value}",
+    challenge: "课题: {value}",
+    updated_at: "更新于 {value} UTC",
+    generated_at: "由 Phi-Backend 生成于 {value} UTC+8",
+    player: "玩家: {value}",
+    no_chart: "无谱面",
+    generated_by: "由 Phi-Backend 生成 | {value} UTC+8",
+    leaderboard_title: "RKS 排行榜",
+    compare_title: "玩家对比",
+    rendered_at: "生成时间: {value} UTC",
+    load_failed: "数据获取失败",
+};
+
+const JA: ImageLabels = ImageLabels {
+    ap_top_3_avg: "AP Top 3 平均: {value}",
+    ap_best_n_avg: "AP Best {n} 平均: {value}",
+    best_27_avg: "Best 27 平均: {value}",
+    region: "地域: {value}",
+    constant: "譜面定数: {value}",
+    data: "Data: {value}",
+    challenge: "課題モード: {value}",
+    updated_at: "更新日時 {value} UTC",
+    generated_at: "Phi-Backend により生成 {value} UTC+8",
+    player: "プレイヤー: {value}",
+    no_chart: "譜面なし",
+    generated_by: "Phi-Backend により生成 | {value} UTC+8",
+    leaderboard_title: "RKS ランキング",
+    compare_title: "プレイヤー比較",
+    rendered_at: "生成日時 {value} UTC",
+    load_failed: "データの取得に失敗しました",
+};
+
+/// 获取指定语言的图片标签
+pub fn labels(lang: ImageLang) -> &'static ImageLabels {
+    match lang {
+        ImageLang::En => &EN,
+        ImageLang::Zh => &ZH,
+        ImageLang::Ja => &JA,
+    }
+}
+
+/// 将值填入标签模板
+pub fn fill(template: &str, value: &str) -> String {
+    template.replace("{value}", value)
+}
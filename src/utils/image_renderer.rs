@@ -1,8 +1,13 @@
+use crate::config::CONFIG;
 use crate::models::player_archive::RKSRankingEntry;
 use crate::models::rks::RksRecord;
 use crate::utils::cover_loader;
+use crate::utils::data_loader;
 use crate::utils::error::AppError;
+use crate::utils::grade;
+use crate::utils::image_labels;
 use crate::utils::rks_utils;
+use crate::utils::text::ellipsize;
 use base64::{engine::general_purpose::STANDARD as base64_engine, Engine as _}; // Added
 use chrono::{DateTime, FixedOffset, Utc};
 use lru::LruCache;
@@ -19,19 +24,77 @@ use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
 
+/// BN 图片的渲染档位
+///
+/// 卡片阴影、FC/AP 发光与背景模糊滤镜是 resvg 渲染中开销最大的部分。
+/// fast 档位去掉这些滤镜：卡片不带阴影与发光 (仍保留 FC/AP 描边颜色)，
+/// 背景图直接使用 illBlur 中已模糊的曲绘而不再叠加高斯模糊。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderProfile {
+    /// 默认效果，保留全部滤镜
+    Pretty,
+    /// 不使用滤镜，优先渲染吞吐量
+    Fast,
+}
+
+impl RenderProfile {
+    /// 所有可用的渲染档位名称
+    pub const VALUES: [&'static str; 2] = ["pretty", "fast"];
+
+    fn uses_filters(self) -> bool {
+        self == RenderProfile::Pretty
+    }
+}
+
+impl Default for RenderProfile {
+    /// 由 `RENDER_PROFILE` 配置决定，未配置或无效时为 pretty
+    fn default() -> Self {
+        CONFIG
+            .render_profile
+            .parse()
+            .unwrap_or(RenderProfile::Pretty)
+    }
+}
+
+impl std::str::FromStr for RenderProfile {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pretty" => Ok(RenderProfile::Pretty),
+            "fast" => Ok(RenderProfile::Fast),
+            _ => Err(AppError::ConfigError(format!(
+                "无效的渲染档位 '{s}'，可选值: {}",
+                RenderProfile::VALUES.join(", ")
+            ))),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct PlayerStats {
     pub ap_top_3_avg: Option<f64>,
     pub best_27_avg: Option<f64>,
     pub real_rks: Option<f64>,
     pub player_name: Option<String>,
+    pub region: Option<String>, // 玩家地区（仅外部数据源可能提供）
     pub update_time: DateTime<Utc>,
     pub n: u32,                                   // 请求的 Best N 数量
     pub ap_top_3_scores: Vec<RksRecord>,          // 添加 AP Top 3 的具体成绩
     pub challenge_rank: Option<(String, String)>, // 新增：课题等级 (颜色, 等级)
-    pub data_string: Option<String>,              // 新增：格式化后的Data字符串
+    pub data_string: Option<String>,              // 新增：格式化后的Data数值（不含标签）
     pub custom_footer_text: Option<String>,
-    pub is_user_generated: bool, // 新增：标记是否为用户生成
+    pub is_user_generated: bool,        // 新增：标记是否为用户生成
+    pub ap_only: bool,                  // 仅展示AP成绩（AP Best N），此时不再单独绘制 AP Top 3 区域
+    pub constant_range: Option<String>, // 限定的定数范围（展示文本），未限定时为 None
+    pub empty_message: Option<String>,  // 无成绩时在卡片区域居中显示的提示（占位图）
+    pub acc_decimals: usize,            // ACC 显示的小数位数
+    pub background_seed: Option<u64>,   // 背景选取种子，None 时随机选取
+    pub background_mode: crate::controllers::image::BackgroundMode, // 背景来源
+    pub player_background: Option<String>, // 玩家在游戏中选择的背景对应的歌曲ID，无法对应到已收录曲目时为 None
+    pub play_dates: HashMap<String, DateTime<Utc>>, // 成绩达成时间 ("歌曲ID-难度")，为空时卡片不标注日期
+    pub lang: crate::controllers::image::ImageLang, // 文字标签的语言
+    pub layout: crate::controllers::image::BnLayout, // 主卡片区布局
 }
 
 // 新增：单曲成绩渲染所需数据结构
@@ -44,6 +107,7 @@ pub struct SongDifficultyScore {
     pub is_fc: Option<bool>,          // 可选：是否 Full Combo
     pub is_phi: Option<bool>,         // 可选：是否 Phi (ACC 100%)
     pub player_push_acc: Option<f64>, // 新增：玩家总RKS推分ACC
+    pub acc_vs_average: Option<f64>,  // 与其他已存档玩家平均 ACC 之差 (百分点)，数据不足时为 None
 }
 
 #[derive(Debug)]
@@ -56,23 +120,65 @@ pub struct SongRenderData {
     pub difficulty_scores: HashMap<String, Option<SongDifficultyScore>>,
     // 歌曲插画路径 (用于渲染)
     pub illustration_path: Option<PathBuf>,
+    // 是否只渲染玩家有成绩的难度
+    pub played_only: bool,
+    // ACC 显示的小数位数
+    pub acc_decimals: usize,
+    // 背景选取种子（曲绘缺失时使用），None 时随机选取
+    pub background_seed: Option<u64>,
+    // 背景来源
+    pub background_mode: crate::controllers::image::BackgroundMode,
+    // 文字标签的语言
+    pub lang: crate::controllers::image::ImageLang,
 }
 
 /// 排行榜渲染数据
 #[allow(dead_code)]
 pub struct LeaderboardRenderData {
-    pub title: String,
     pub update_time: DateTime<Utc>,
     pub entries: Vec<RKSRankingEntry>,
     pub display_count: usize,
+    pub lang: crate::controllers::image::ImageLang, // 文字标签的语言
+}
+
+/// AP Top 3 单独成图的渲染数据
+pub struct Ap3RenderData {
+    pub player_name: String,
+    pub rks: f64,
+    pub ap_scores: Vec<RksRecord>, // RKS 最高的至多 3 个 AP 成绩
+    pub update_time: DateTime<Utc>,
+    pub lang: crate::controllers::image::ImageLang, // 文字标签的语言
+}
+
+/// 玩家对比图中单个玩家的数据
+pub struct ComparePlayerData {
+    pub player_name: String,
+    pub rks: f64,
+    pub best_27_avg: Option<f64>,
+    pub ap_top_3_avg: Option<f64>,
+    pub top_scores: Vec<RksRecord>, // RKS 最高的若干谱面
+}
+
+/// 玩家对比图渲染数据，某一侧获取失败时为 `None`，该侧只显示通用的错误提示
+pub struct CompareRenderData {
+    pub left: Option<ComparePlayerData>,
+    pub right: Option<ComparePlayerData>,
+    pub update_time: DateTime<Utc>,
+    pub lang: crate::controllers::image::ImageLang, // 文字标签的语言
 }
 
 // 常量定义
 const FONTS_DIR: &str = "resources/fonts";
 const MAIN_FONT_NAME: &str = "思源黑体 CN";
+/// 内置的 CJK 兜底字体，系统与 resources/fonts 中都没有支持中日文的字体时加载
+const CJK_FALLBACK_FONT_FILE: &str = "Source Han Sans & Saira Hybrid-Regular #5446 (1).ttf";
+/// 用于判断字体是否支持中日文的字符
+const CJK_PROBE_CHARS: [char; 2] = ['中', 'あ'];
 const COVER_ASPECT_RATIO: f64 = 512.0 / 270.0;
-#[allow(dead_code)]
-const SONG_ILLUST_ASPECT_RATIO: f64 = 1.0; // 假设单曲图的插画是方形的
+const SONG_ILLUST_ASPECT_RATIO: f64 = 2048.0 / 1080.0; // 单曲图曲绘的默认宽高比，无法读取实际尺寸时使用
+const UNKNOWN_SONG_MARKER: &str = " (未收录)"; // info.csv 中缺失的曲目在成绩卡片上的标记
+/// 单曲图背景渐变起始色 (#141826)，JPEG 输出时用于合成透明像素
+pub const SONG_IMAGE_BACKGROUND_RGB: [u8; 3] = [0x14, 0x18, 0x26];
 
 // 全局字体数据库单例
 static GLOBAL_FONT_DB: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
@@ -89,12 +195,14 @@ const BACKGROUND_CACHE_SIZE: usize = 10; // 缓存10张背景图片
 const COVER_METADATA_CACHE_SIZE: usize = 10000; // 缓存封面元数据
 
 // 背景主色反色缓存（避免重复解码大图）
-static INVERSE_COLOR_CACHE: OnceLock<std::sync::Mutex<LruCache<PathBuf, String>>> = OnceLock::new();
-
-fn get_inverse_color_cache() -> &'static std::sync::Mutex<LruCache<PathBuf, String>> {
-    INVERSE_COLOR_CACHE.get_or_init(|| {
-        std::sync::Mutex::new(LruCache::new(NonZeroUsize::new(256).unwrap()))
-    })
+// 每个路径对应一个 OnceLock 槽位：同一背景的并发渲染只扫描一次像素，解码失败 (None) 也会被缓存
+type InverseColorSlot = Arc<OnceLock<Option<String>>>;
+static INVERSE_COLOR_CACHE: OnceLock<std::sync::Mutex<LruCache<PathBuf, InverseColorSlot>>> =
+    OnceLock::new();
+
+fn get_inverse_color_cache() -> &'static std::sync::Mutex<LruCache<PathBuf, InverseColorSlot>> {
+    INVERSE_COLOR_CACHE
+        .get_or_init(|| std::sync::Mutex::new(LruCache::new(NonZeroUsize::new(256).unwrap())))
 }
 
 /// 初始化全局字体数据库
@@ -120,9 +228,73 @@ fn init_global_font_db() -> Arc<fontdb::Database> {
         }
     }
 
+    if find_cjk_family(&font_db).is_none() {
+        load_cjk_fallback_font(&mut font_db);
+    }
+
     Arc::new(font_db)
 }
 
+/// 查找支持中日文字符的字体，返回第一个匹配的字体族名称
+fn find_cjk_family(font_db: &fontdb::Database) -> Option<String> {
+    font_db.faces().find_map(|face| {
+        let covered = font_db.with_face_data(face.id, |data, index| {
+            ttf_parser::Face::parse(data, index).is_ok_and(|parsed| {
+                CJK_PROBE_CHARS
+                    .iter()
+                    .all(|c| parsed.glyph_index(*c).is_some())
+            })
+        })?;
+        covered.then(|| {
+            face.families
+                .first()
+                .map(|(name, _)| name.clone())
+                .unwrap_or_else(|| face.post_script_name.clone())
+        })
+    })
+}
+
+/// 加载内置的 CJK 兜底字体
+///
+/// 依次在工作目录、可执行文件所在目录与编译时的项目目录下查找，工作目录不是项目根目录时也能找到。
+fn load_cjk_fallback_font(font_db: &mut fontdb::Database) {
+    let mut dirs = vec![PathBuf::from(FONTS_DIR)];
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        dirs.push(exe_dir.join(FONTS_DIR));
+    }
+    dirs.push(Path::new(env!("CARGO_MANIFEST_DIR")).join(FONTS_DIR));
+
+    for path in dirs.iter().map(|dir| dir.join(CJK_FALLBACK_FONT_FILE)) {
+        if !path.is_file() {
+            continue;
+        }
+        match font_db.load_font_file(&path) {
+            Ok(()) => {
+                log::warn!(
+                    "未找到支持中日文的字体，已加载内置兜底字体 '{}'",
+                    path.display()
+                );
+                return;
+            }
+            Err(e) => log::error!("加载兜底字体失败 '{}': {}", path.display(), e),
+        }
+    }
+}
+
+/// 检查字体数据库中是否有支持中日文的字体，返回其字体族名称
+///
+/// 缺少 CJK 字体时渲染不会报错，但中文曲名会显示为方框，因此启动时调用，失败时终止启动。
+pub fn check_cjk_font() -> Result<String, String> {
+    find_cjk_family(&get_global_font_db()).ok_or_else(|| {
+        format!(
+            "系统与 {FONTS_DIR} 中均未找到支持中日文的字体，内置兜底字体 '{CJK_FALLBACK_FONT_FILE}' 缺失或无法加载"
+        )
+    })
+}
+
 /// 获取全局字体数据库
 pub fn get_global_font_db() -> Arc<fontdb::Database> {
     GLOBAL_FONT_DB.get_or_init(init_global_font_db).clone()
@@ -132,9 +304,9 @@ pub fn get_global_font_db() -> Arc<fontdb::Database> {
 fn init_background_and_cover_cache() -> BackgroundAndCoverCache {
     log::info!("初始化背景图片缓存和封面文件列表");
 
-    // 初始化 LRU 缓存
+    // 初始化 LRU 缓存，启用启动预热时容量至少能容纳预热的曲绘，避免预热结果被立即淘汰
     let cache = std::sync::Mutex::new(LruCache::new(
-        NonZeroUsize::new(BACKGROUND_CACHE_SIZE).unwrap(),
+        NonZeroUsize::new(BACKGROUND_CACHE_SIZE.max(CONFIG.cover_warm_count)).unwrap(),
     ));
 
     // 初始化封面元数据缓存
@@ -187,6 +359,8 @@ fn init_background_and_cover_cache() -> BackgroundAndCoverCache {
         }
     }
 
+    // 排序以保证按种子选取背景时结果稳定，不受目录遍历顺序影响
+    cover_files.sort();
     log::info!("初始化完成，共找到 {} 个封面文件", cover_files.len());
 
     // 预构建封面元数据，避免运行时文件系统调用
@@ -245,6 +419,55 @@ pub fn get_cover_metadata_cache() -> &'static std::sync::Mutex<HashMap<String, S
 
 
 
+/// 查找成绩卡片使用的曲绘路径
+fn resolve_cover_href(song_id: &str) -> Option<String> {
+    // 使用预构建的封面元数据缓存，避免运行时文件系统调用
+    get_cover_metadata_cache()
+        .lock()
+        .unwrap()
+        .get(song_id)
+        .cloned()
+        .or_else(|| {
+            // 回退检查：直接使用文件系统检查（优先缩略图，其次原图）
+            cover_loader::find_cover_path(song_id, cover_loader::CoverSize::Low)
+                .or_else(|| cover_loader::find_cover_path(song_id, cover_loader::CoverSize::Full))
+                .map(|path| path.to_string_lossy().into_owned())
+        })
+}
+
+/// 启动时预热曲绘缓存，返回实际载入缓存的曲绘数量
+///
+/// 按曲目最高定数从高到低选取至多 `count` 首曲目（最常出现在 Best N 中），
+/// 以渲染时相同的方式读取并编码其曲绘。超过 256KB 的大图本就不进入缓存，不计入数量。
+/// 该函数会进行磁盘 IO，应在阻塞线程中调用。
+pub fn warm_cover_cache(count: usize) -> usize {
+    let mut songs: Vec<(&String, f64)> = data_loader::DIFFICULTY_MAP
+        .iter()
+        .map(|(id, song)| {
+            let max_constant = [song.ez, song.hd, song.inl, song.at]
+                .into_iter()
+                .flatten()
+                .fold(0.0, f64::max);
+            (id, max_constant)
+        })
+        .collect();
+    songs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut warmed = 0;
+    for (song_id, _) in songs {
+        if warmed >= count {
+            break;
+        }
+        let Some(href) = resolve_cover_href(song_id) else {
+            continue;
+        };
+        if get_background_image(&PathBuf::from(href)).is_some_and(|h| h.starts_with("data:")) {
+            warmed += 1;
+        }
+    }
+    warmed
+}
+
 /// 从缓存或磁盘加载背景图片
 /// 注意：现在只缓存小图（<256KB），大图直接返回路径
 fn get_background_image(path: &PathBuf) -> Option<String> {
@@ -295,6 +518,59 @@ fn get_image_href(path: &PathBuf, embed_images: bool) -> Option<String> {
     get_background_image(path)
 }
 
+/// 由存档校验和等稳定标识计算背景选取种子 (FNV-1a)，跨进程与版本保持一致
+pub fn background_seed(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// 选取背景图：有种子时按种子固定选取，否则随机选取
+fn pick_background<T>(candidates: &[T], seed: Option<u64>) -> Option<&T> {
+    match seed {
+        Some(seed) if !candidates.is_empty() => {
+            candidates.get((seed % candidates.len() as u64) as usize)
+        }
+        Some(_) => None,
+        None => candidates.choose(&mut rand::rng()),
+    }
+}
+
+/// ACC 默认显示的小数位数
+pub const DEFAULT_ACC_DECIMALS: usize = 2;
+/// 高精度模式下 ACC 显示的小数位数（接近推分阈值时更直观）
+pub const PRECISE_ACC_DECIMALS: usize = 4;
+
+/// 根据是否请求高精度 ACC 返回显示的小数位数
+pub fn acc_decimals(precise: bool) -> usize {
+    if precise {
+        PRECISE_ACC_DECIMALS
+    } else {
+        DEFAULT_ACC_DECIMALS
+    }
+}
+
+/// 统一的 ACC 格式化（不含百分号），BN 与单曲图共用
+/// 仅在显示时取整，底层计算始终使用原始值
+pub fn format_acc(acc: f64, decimals: usize) -> String {
+    format!("{acc:.decimals$}")
+}
+
+/// 推分 ACC 的显示文本（不含百分号），BN 图片与 `/bn/{n}/text` 共用
+///
+/// 取整后为 100% 时直接显示 100；与当前 ACC 取整后相同时多保留一位小数。
+pub fn format_push_acc(push_acc: f64, current_acc: f64, decimals: usize) -> String {
+    // 显示精度的半个单位，用于判断取整后是否会与当前ACC或100%重合
+    let half_unit = 0.5 / 10f64.powi(decimals as i32);
+    if push_acc > 100.0 - half_unit {
+        format_acc(100.0, decimals)
+    } else if (push_acc - current_acc).abs() < half_unit {
+        format_acc(push_acc, decimals + 1)
+    } else {
+        format_acc(push_acc, decimals)
+    }
+}
+
 // Helper function to generate a single score card SVG group
 struct CardRenderInfo<'a> {
     svg: &'a mut String,
@@ -310,6 +586,32 @@ struct CardRenderInfo<'a> {
     theme: &'a crate::controllers::image::Theme,
     is_user_generated: bool, // 新增
     embed_images: bool,
+    acc_decimals: usize,
+    play_date: Option<DateTime<Utc>>, // 成绩达成时间，None 时不标注
+}
+
+/// 判断字符是否为全角（主要针对中日韩字符）
+fn is_full_width(ch: char) -> bool {
+    // 这个范围覆盖了常见的中日韩统一表意文字、平假名、片假名和全角符号
+    ('\u{4E00}'..='\u{9FFF}').contains(&ch) || // CJK Unified Ideographs
+    ('\u{3040}'..='\u{30FF}').contains(&ch) || // Hiragana and Katakana
+    ('\u{FF00}'..='\u{FFEF}').contains(&ch) // Full-width forms
+}
+
+/// 估算曲名渲染后的大致宽度，用于判断是否需要 textLength 压缩
+///
+/// 全角字符宽度约等于字号，半角字符宽度约为一半。默认值按内置字体调校，
+/// 替换 resources/fonts 中的字体后可通过 SONG_NAME_FULL_WIDTH_PX / SONG_NAME_HALF_WIDTH_PX 调整。
+fn estimate_song_name_width(text: &str, full_width_char_px: f64, half_width_char_px: f64) -> f64 {
+    text.chars()
+        .map(|ch| {
+            if is_full_width(ch) {
+                full_width_char_px
+            } else {
+                half_width_char_px
+            }
+        })
+        .sum()
 }
 
 fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
@@ -330,6 +632,8 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
         theme: _theme,
         is_user_generated,
         embed_images,
+        acc_decimals,
+        play_date,
     } = info;
 
     // --- Card Dimensions & Layout ---
@@ -378,40 +682,7 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
     writeln!(svg, "<defs><clipPath id=\"{clip_path_id}\"><rect x=\"{cover_x}\" y=\"{cover_y}\" width=\"{cover_size_w:.1}\" height=\"{cover_size_h:.1}\" rx=\"4\" ry=\"4\" /></clipPath></defs>").map_err(fmt_err)?;
 
     // Cover Image or Placeholder
-    // 使用预构建的封面元数据缓存，避免运行时文件系统调用
-    let metadata_cache = get_cover_metadata_cache();
-    let cover_href = metadata_cache
-        .lock()
-        .unwrap()
-        .get(&score.song_id)
-        .cloned()
-        .or_else(|| {
-            // 回退检查：尝试从缓存文件列表中查找（直接使用文件系统检查，避免HashSet内存占用）
-            let cover_path_low_png = PathBuf::from(cover_loader::COVERS_DIR)
-                .join("illLow")
-                .join(format!("{}.png", score.song_id));
-            let cover_path_low_jpg = PathBuf::from(cover_loader::COVERS_DIR)
-                .join("illLow")
-                .join(format!("{}.jpg", score.song_id));
-            let cover_path_std_png = PathBuf::from(cover_loader::COVERS_DIR)
-                .join("ill")
-                .join(format!("{}.png", score.song_id));
-            let cover_path_std_jpg = PathBuf::from(cover_loader::COVERS_DIR)
-                .join("ill")
-                .join(format!("{}.jpg", score.song_id));
-
-            if cover_path_low_png.exists() {
-                Some(cover_path_low_png.to_string_lossy().into_owned())
-            } else if cover_path_low_jpg.exists() {
-                Some(cover_path_low_jpg.to_string_lossy().into_owned())
-            } else if cover_path_std_png.exists() {
-                Some(cover_path_std_png.to_string_lossy().into_owned())
-            } else if cover_path_std_jpg.exists() {
-                Some(cover_path_std_jpg.to_string_lossy().into_owned())
-            } else {
-                None
-            }
-        });
+    let cover_href = resolve_cover_href(&score.song_id);
 
     if let Some(href) = cover_href {
         let final_href = if embed_images {
@@ -440,32 +711,41 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
 
     // --- Song Name (智能判断是否需要压缩) ---
 
-    // 1. 定义一个简单的函数来判断字符是否为全角（主要针对中日韩字符）
-    fn is_full_width(ch: char) -> bool {
-        // 这个范围覆盖了常见的中日韩统一表意文字、平假名、片假名和全角符号
-        ('\u{4E00}'..='\u{9FFF}').contains(&ch) || // CJK Unified Ideographs
-    ('\u{3040}'..='\u{30FF}').contains(&ch) || // Hiragana and Katakana
-    ('\u{FF00}'..='\u{FFEF}').contains(&ch) // Full-width forms
-    }
+    // 1. 估算文本渲染后的大致宽度
+    // info.csv 未收录的新曲名称会回落为原始ID，改为展示ID中的曲名部分并附加淡色“未收录”标记
+    let unknown_song = !data_loader::SONG_ID_TO_NAME.contains_key(&score.song_id);
+    let display_name = if unknown_song {
+        score
+            .song_id
+            .split('.')
+            .next()
+            .unwrap_or(&score.song_id)
+            .to_string()
+    } else {
+        score.song_name.clone()
+    };
+    let display_width_text = if unknown_song {
+        format!("{display_name}{UNKNOWN_SONG_MARKER}")
+    } else {
+        display_name.clone()
+    };
 
-    // 2. 估算文本渲染后的大致宽度
-    let mut estimated_width = 0.0;
-    // 根据CSS样式，.text-songname 的 font-size 是 19px。
-    // 全角字符宽度约等于字号，半角字符宽度约为一半。这里我们用稍大的值做估算。
-    let full_width_char_px = 19.0;
-    let half_width_char_px = 10.5; // 英文、数字等半角字符的平均宽度估值
+    let estimated_width = estimate_song_name_width(
+        &display_width_text,
+        CONFIG.song_name_full_width_px,
+        CONFIG.song_name_half_width_px,
+    );
 
-    for ch in score.song_name.chars() {
-        if is_full_width(ch) {
-            estimated_width += full_width_char_px;
-        } else {
-            estimated_width += half_width_char_px;
-        }
+    // 2. 根据估算结果，决定是否启用SVG压缩
+    let mut song_name_escaped = escape_xml(&display_name);
+    if unknown_song {
+        write!(
+            song_name_escaped,
+            "<tspan fill-opacity='0.55'>{UNKNOWN_SONG_MARKER}</tspan>"
+        )
+        .map_err(fmt_err)?;
     }
 
-    // 3. 根据估算结果，决定是否启用SVG压缩
-    let song_name_escaped = escape_xml(&score.song_name);
-
     if estimated_width > text_width {
         // 估算宽度超过了可用空间，启用 textLength 进行压缩
         writeln!(
@@ -488,6 +768,17 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
     )
     .map_err(fmt_err)?;
 
+    // 成绩达成日期（存档中记录的时间，UTC），与分数同一行靠右显示
+    if let Some(play_date) = play_date {
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{score_y:.1}" class="text-play-date">{}</text>"#,
+            (card_width as f64) - card_padding,
+            play_date.format("%Y/%m/%d")
+        )
+        .map_err(fmt_err)?;
+    }
+
     // 如果是用户生成的数据，在分数旁边添加 "U" 标签
     if is_user_generated {
         // 方案: 将 "U" 标签放在序号的左边
@@ -522,28 +813,14 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
             .unwrap_or(100.0) // 如果计算失败（比如格式错误），则默认为100
         };
 
-        // 如果推分acc非常接近100，直接显示 -> 100.00%
-        if push_acc > 99.995 {
-            format!(
-                "Acc: {:.2}% <tspan class='push-acc'>-> 100.00%</tspan>",
-                score.acc
-            )
-        }
-        // 如果两者差值非常小(小于0.005，对应四舍五入后两位不变)，则展示三位小数
-        else if (push_acc - score.acc).abs() < 0.005 {
-            format!(
-                "Acc: {:.2}% <tspan class='push-acc'>-> {:.3}%</tspan>",
-                score.acc, push_acc
-            )
-        } else {
-            format!(
-                "Acc: {:.2}% <tspan class='push-acc'>-> {:.2}%</tspan>",
-                score.acc, push_acc
-            )
-        }
+        format!(
+            "Acc: {}% <tspan class='push-acc'>-> {}%</tspan>",
+            format_acc(score.acc, acc_decimals),
+            format_push_acc(push_acc, score.acc, acc_decimals)
+        )
     } else {
         // AP或者已满分或者定数为0，只显示当前acc
-        format!("Acc: {:.2}%", score.acc)
+        format!("Acc: {}%", format_acc(score.acc, acc_decimals))
     };
     writeln!(
         svg,
@@ -553,13 +830,7 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
 
     // Level & RKS
     // 获取难度标签文本和颜色
-    let (difficulty_text, difficulty_color) = match &score.difficulty {
-        diff if diff.eq_ignore_ascii_case("EZ") => ("EZ", "#51AF44"), // 绿色
-        diff if diff.eq_ignore_ascii_case("HD") => ("HD", "#3173B3"), // 蓝色
-        diff if diff.eq_ignore_ascii_case("IN") => ("IN", "#BE2D23"), // 红色
-        diff if diff.eq_ignore_ascii_case("AT") => ("AT", "#383838"), // 深灰色
-        _ => ("??", "#888888"),                                       // 默认灰色
-    };
+    let (difficulty_text, difficulty_color) = difficulty_badge(&score.difficulty);
 
     // 难度标签尺寸
     let badge_width = 36.0;
@@ -617,6 +888,26 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
         writeln!(svg, r#"<text x="{ap_badge_text_x:.1}" y="{ap_badge_text_y:.1}" class="text-fc-ap-badge" text-anchor="middle" fill="black">AP</text>"#).map_err(fmt_err)?;
     }
 
+    // 评级标签（在FC/AP标签右侧）
+    if let Some(grade) = grade::grade_of(score.score, score.acc) {
+        let preceding_badges = usize::from(score.is_fc) + usize::from(score.acc == 100.0);
+        let grade_badge_width = 22.0;
+        let grade_badge_x = badge_x
+            + badge_width
+            + fc_ap_badge_spacing
+            + preceding_badges as f64 * (fc_ap_badge_width + fc_ap_badge_spacing);
+        writeln!(svg, r#"<rect x="{grade_badge_x:.1}" y="{badge_y:.1}" width="{grade_badge_width:.1}" height="{fc_ap_badge_height:.1}" rx="{fc_ap_badge_radius:.1}" ry="{fc_ap_badge_radius:.1}" fill="rgba(0,0,0,0.6)" />"#).map_err(fmt_err)?;
+        writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" class="text-grade {}" text-anchor="middle">{}</text>"#,
+            grade_badge_x + grade_badge_width / 2.0,
+            badge_y + fc_ap_badge_height / 2.0 + 5.0,
+            grade.css_class(),
+            grade.label()
+        )
+        .map_err(fmt_err)?;
+    }
+
     // 恢复等级和RKS的简单字符串拼接
     let level_text = format!("Lv.{} -> {:.2}", score.difficulty_value, score.rks);
     writeln!(
@@ -643,145 +934,121 @@ fn generate_card_svg(info: CardRenderInfo) -> Result<(), AppError> {
     Ok(())
 }
 
-// --- SVG 生成函数 ---
+/// 难度标签文本和颜色
+fn difficulty_badge(difficulty: &str) -> (&'static str, &'static str) {
+    match difficulty {
+        diff if diff.eq_ignore_ascii_case("EZ") => ("EZ", "#51AF44"), // 绿色
+        diff if diff.eq_ignore_ascii_case("HD") => ("HD", "#3173B3"), // 蓝色
+        diff if diff.eq_ignore_ascii_case("IN") => ("IN", "#BE2D23"), // 红色
+        diff if diff.eq_ignore_ascii_case("AT") => ("AT", "#383838"), // 深灰色
+        _ => ("??", "#888888"),                                       // 默认灰色
+    }
+}
 
-pub fn generate_svg_string(
+/// 按难度分组时每个分区标题占用的高度
+const SECTION_TITLE_HEIGHT: u32 = 36;
+
+/// 主卡片区的分区: (难度标签, 成绩在 scores 中的下标)
+///
+/// 平铺布局只有一个无标题分区；按难度分组时依次为 EZ/HD/IN/AT 的非空分区，
+/// 组内沿用 scores 的 RKS 顺序。
+fn main_card_sections(
     scores: &[RksRecord],
-    stats: &PlayerStats,
-    push_acc_map: Option<&HashMap<String, f64>>, // 新增：预先计算的推分ACC映射，键为"曲目ID-难度"
-    theme: &crate::controllers::image::Theme,    // 新增：主题参数
-    embed_images: bool,
-) -> Result<String, AppError> {
-    let _start_time = std::time::Instant::now();
-    // ... (width, height calculations etc. - keep these as they were) ...
-    let width = 1200;
-    let header_height = 120;
-    let _ap_title_height = 50; // Prefix unused variable
-    let footer_height = 50;
-    let main_card_padding_outer = 12;
-    let ap_card_padding_outer = 12;
-    let columns = 3;
+    layout: crate::controllers::image::BnLayout,
+) -> Vec<(Option<&'static str>, Vec<usize>)> {
+    if layout == crate::controllers::image::BnLayout::Flat || scores.is_empty() {
+        return vec![(None, (0..scores.len()).collect())];
+    }
+    ["EZ", "HD", "IN", "AT", "??"]
+        .into_iter()
+        .map(|label| {
+            let indices = scores
+                .iter()
+                .enumerate()
+                .filter(|(_, score)| difficulty_badge(&score.difficulty).0 == label)
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            (Some(label), indices)
+        })
+        .filter(|(_, indices)| !indices.is_empty())
+        .collect()
+}
 
-    let main_card_width = (width - main_card_padding_outer * (columns + 1)) / columns;
-    let card_padding_inner = 10.0;
-    let text_line_height_song = 22.0;
-    let text_line_height_score = 30.0;
-    let text_line_height_acc = 18.0;
-    let text_line_height_level = 18.0;
-    let text_block_spacing = 4.0;
-    let text_block_height = text_line_height_song
-        + text_line_height_score
-        + text_line_height_acc
-        + text_line_height_level
-        + text_block_spacing * 3.0;
-    let calculated_card_height = (text_block_height + card_padding_inner * 2.0) as u32;
-    let ap_card_start_y = ap_card_padding_outer;
-    let ap_section_height = if !stats.ap_top_3_scores.is_empty() {
-        ap_card_start_y + calculated_card_height + ap_card_padding_outer
-    } else {
-        0
-    };
-    let rows = (scores.len() as u32).div_ceil(columns);
-    let content_height = (calculated_card_height + main_card_padding_outer) * rows.max(1);
-    let total_height = header_height + ap_section_height + content_height + footer_height + 10;
+// --- SVG 生成函数 ---
+
+/// BN 图与 AP Top 3 图共用的主题配色
+struct CardPalette {
+    bg_color: &'static str,
+    text_color: &'static str,
+    card_bg_color: &'static str,
+    card_stroke_color: &'static str,
+    text_secondary_color: &'static str,
+    fc_stroke_color: &'static str,
+    ap_stroke_color: &'static str,
+    ap_card_fill: &'static str,
+    fc_card_fill: &'static str,
+}
 
-    // 根据主题定义颜色变量
-    let (
+// 根据主题定义颜色变量
+fn card_palette(theme: &crate::controllers::image::Theme) -> CardPalette {
+    match theme {
+        crate::controllers::image::Theme::White => CardPalette {
+            bg_color: "#FFFFFF",
+            text_color: "#000000",
+            card_bg_color: "#F0F0F0",
+            card_stroke_color: "#DDDDDD",
+            text_secondary_color: "#666666",
+            fc_stroke_color: "#4682B4",
+            ap_stroke_color: "url(#ap-gradient)",
+            ap_card_fill: "#FFFBEB",
+            fc_card_fill: "#E6F2FF",
+        },
+        crate::controllers::image::Theme::Transparent => CardPalette {
+            bg_color: "none",
+            text_color: "#FFFFFF",
+            card_bg_color: "#1A1E2A",
+            card_stroke_color: "#333848",
+            text_secondary_color: "#BBBBBB",
+            fc_stroke_color: "#87CEEB",
+            ap_stroke_color: "url(#ap-gradient)",
+            ap_card_fill: "#1A1E2A",
+            fc_card_fill: "#1A1E2A",
+        },
+        crate::controllers::image::Theme::Black => CardPalette {
+            bg_color: "#141826",
+            text_color: "#FFFFFF",
+            card_bg_color: "#1A1E2A",
+            card_stroke_color: "#333848",
+            text_secondary_color: "#BBBBBB",
+            fc_stroke_color: "#87CEEB",
+            ap_stroke_color: "url(#ap-gradient)",
+            ap_card_fill: "#1A1E2A",
+            fc_card_fill: "#1A1E2A",
+        },
+    }
+}
+
+/// 写入成绩卡片所需的 `<defs>` (背景渐变、滤镜、样式与边框渐变)，返回背景图使用的模糊滤镜属性
+fn write_card_defs(
+    svg: &mut String,
+    theme: &crate::controllers::image::Theme,
+    palette: &CardPalette,
+    normal_card_stroke_color: &str,
+    profile: RenderProfile,
+) -> Result<&'static str, AppError> {
+    let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
+    let CardPalette {
         bg_color,
         text_color,
         card_bg_color,
-        card_stroke_color,
+        card_stroke_color: _,
         text_secondary_color,
         fc_stroke_color,
         ap_stroke_color,
-    ) = match theme {
-        crate::controllers::image::Theme::White => (
-            "#FFFFFF",
-            "#000000",
-            "#F0F0F0",
-            "#DDDDDD",
-            "#666666",
-            "#4682B4",
-            "url(#ap-gradient)",
-        ),
-        crate::controllers::image::Theme::Black => (
-            "#141826",
-            "#FFFFFF",
-            "#1A1E2A",
-            "#333848",
-            "#BBBBBB",
-            "#87CEEB",
-            "url(#ap-gradient)",
-        ),
-    };
-    let (ap_card_fill, fc_card_fill) = match theme {
-        crate::controllers::image::Theme::White => ("#FFFBEB".to_string(), "#E6F2FF".to_string()),
-        crate::controllers::image::Theme::Black => {
-            (card_bg_color.to_string(), card_bg_color.to_string())
-        }
-    };
-
-    let mut normal_card_stroke_color = match theme {
-        crate::controllers::image::Theme::White => "url(#normal-card-stroke-gradient)".to_string(),
-        crate::controllers::image::Theme::Black => "#252A38".to_string(), // Weaker border for black theme
-    };
-    let mut svg = String::new();
-    let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
-
-    // --- 获取随机背景图 ---
-    let mut background_image_href = None;
-    let _background_fill = "url(#bg-gradient)".to_string(); // Prefix unused variable
-
-    // 使用预先缓存的封面文件列表来获取背景图片，避免重复读取目录
-    let background_files = get_cover_files();
-    let background_base_path = PathBuf::from(cover_loader::COVERS_DIR).join("illBlur");
-    let filtered_background_files: Vec<&PathBuf> = background_files
-        .iter()
-        .filter(|path| {
-            // 检查路径是否在 illBlur 目录下且是图片文件
-            path.starts_with(&background_base_path)
-                && (path.extension() == Some("png".as_ref())
-                    || path.extension() == Some("jpg".as_ref()))
-        })
-        .collect();
-
-    if !filtered_background_files.is_empty() {
-        let mut rng = rand::rng();
-        if let Some(random_path) = filtered_background_files.choose(&mut rng) {
-            // 随机选择一个路径
-            // --- 新增：计算背景主色的反色 ---
-            if let crate::controllers::image::Theme::White = theme {
-                if let Some(inverse_color) = get_inverse_color_from_path_cached(random_path) {
-                    normal_card_stroke_color = inverse_color;
-                    log::info!("使用背景反色作为卡片边框: {normal_card_stroke_color}");
-                }
-            }
-            // --- 结束新增 ---
+        ap_card_fill,
+        fc_card_fill,
+    } = palette;
 
-            // 使用缓存函数获取背景图片
-            if let Some(image_href) = get_image_href(random_path, embed_images) {
-                background_image_href = Some(image_href);
-                log::info!("使用随机背景图: {}", random_path.display());
-            } else {
-                log::error!("获取背景图片失败: {}", random_path.display());
-                // 获取失败则回退到渐变
-            }
-        } else {
-            log::warn!("无法从背景文件列表中随机选择一个");
-            // Fallback to gradient if choose fails (shouldn't happen with non-empty list)
-        }
-    } else {
-        log::warn!("找不到任何背景文件用于随机背景");
-        // Fallback to gradient if directory is empty or read failed
-    }
-    // --- 背景图获取结束 ---
-
-    writeln!(
-        svg,
-        r#"<svg width="{width}" height="{total_height}" viewBox="0 0 {width} {total_height}" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">"#
-    ).map_err(fmt_err)?;
-
-    // --- Definitions (Styles, Gradients, Filters, Font) ---
     writeln!(svg, "<defs>").map_err(fmt_err)?;
 
     // Background Gradient (Fallback)
@@ -789,26 +1056,39 @@ pub fn generate_svg_string(
         crate::controllers::image::Theme::White => {
             writeln!(svg, r#"<linearGradient id="bg-gradient" x1="0%" y1="0%" x2="100%" y2="100%"><stop offset="0%" style="stop-color:#FFFFFF" /><stop offset="100%" style="stop-color:#F0F0F0" /></linearGradient>"#).map_err(fmt_err)?;
         }
-        crate::controllers::image::Theme::Black => {
+        crate::controllers::image::Theme::Black | crate::controllers::image::Theme::Transparent => {
             writeln!(svg, r#"<linearGradient id="bg-gradient" x1="0%" y1="0%" x2="100%" y2="100%"><stop offset="0%" style="stop-color:#141826" /><stop offset="100%" style="stop-color:#252E48" /></linearGradient>"#).map_err(fmt_err)?;
         }
     }
 
-    // Shadow Filter Definition
-    writeln!(svg, r#"<filter id="card-shadow" x="-10%" y="-10%" width="120%" height="130%"><feDropShadow dx="0" dy="3" stdDeviation="3" flood-color="rgba(0,0,0,0.25)" flood-opacity="0.25" /></filter>"#).map_err(fmt_err)?;
+    // fast 档位不定义滤镜，下方样式与背景图也不再引用
+    // (引用不存在的滤镜会导致元素不被渲染)
+    let (card_filter, fc_filter, ap_filter, bg_filter) = if profile.uses_filters() {
+        // Shadow Filter Definition
+        writeln!(svg, r#"<filter id="card-shadow" x="-10%" y="-10%" width="120%" height="130%"><feDropShadow dx="0" dy="3" stdDeviation="3" flood-color="rgba(0,0,0,0.25)" flood-opacity="0.25" /></filter>"#).map_err(fmt_err)?;
 
-    // FC Glow Filter Definition
-    writeln!(svg, r#"<filter id="fc-glow" x="-50%" y="-50%" width="200%" height="200%"><feDropShadow dx="0" dy="0" stdDeviation="4" flood-color="{fc_stroke_color}" flood-opacity="0.8" /></filter>"#).map_err(fmt_err)?;
+        // FC Glow Filter Definition
+        writeln!(svg, r#"<filter id="fc-glow" x="-50%" y="-50%" width="200%" height="200%"><feDropShadow dx="0" dy="0" stdDeviation="4" flood-color="{fc_stroke_color}" flood-opacity="0.8" /></filter>"#).map_err(fmt_err)?;
 
-    writeln!(svg, r#"<filter id="ap-glow" x="-50%" y="-50%" width="200%" height="200%"><feDropShadow dx="0" dy="0" stdDeviation="4" flood-color="{fc_stroke_color}" flood-opacity="0.8" /></filter>"#).map_err(fmt_err)?;
+        writeln!(svg, r#"<filter id="ap-glow" x="-50%" y="-50%" width="200%" height="200%"><feDropShadow dx="0" dy="0" stdDeviation="4" flood-color="{fc_stroke_color}" flood-opacity="0.8" /></filter>"#).map_err(fmt_err)?;
 
-    // AP Text Shadow Filter Definition (Removed)
+        // AP Text Shadow Filter Definition (Removed)
 
-    // Gaussian Blur Filter Definition
-    writeln!(svg, r#"<filter id="bg-blur">"#).map_err(fmt_err)?;
-    // 调整 stdDeviation 控制模糊程度, 10 是一个比较强的模糊效果
-    writeln!(svg, r#"<feGaussianBlur stdDeviation="10" />"#).map_err(fmt_err)?;
-    writeln!(svg, r#"</filter>"#).map_err(fmt_err)?;
+        // Gaussian Blur Filter Definition
+        writeln!(svg, r#"<filter id="bg-blur">"#).map_err(fmt_err)?;
+        // 调整 stdDeviation 控制模糊程度, 10 是一个比较强的模糊效果
+        writeln!(svg, r#"<feGaussianBlur stdDeviation="10" />"#).map_err(fmt_err)?;
+        writeln!(svg, r#"</filter>"#).map_err(fmt_err)?;
+
+        (
+            "filter: url(#card-shadow);",
+            "filter: url(#fc-glow);",
+            "filter: url(#ap-glow);",
+            r#" filter="url(#bg-blur)""#,
+        )
+    } else {
+        ("", "", "", "")
+    };
 
     // Font style ... (保持不变) ...
     writeln!(svg, "<style>").map_err(fmt_err)?;
@@ -821,20 +1101,20 @@ pub fn generate_svg_string(
             fill: {card_bg_color};
             stroke: {normal_card_stroke_color};
             stroke-width: 1.5;
-            filter: url(#card-shadow);
+            {card_filter}
             transition: all 0.3s ease;
         }}
         .card-ap {{
           fill: {ap_card_fill};
           stroke: {ap_stroke_color};
           stroke-width: 2.5;
-          filter: url(#ap-glow);
+          {ap_filter}
         }}
         .card-fc {{
           fill: {fc_card_fill};
           stroke: {fc_stroke_color}; /* Light Sky Blue */
           stroke-width: 2.5;
-          filter: url(#fc-glow);
+          {fc_filter}
         }}
         /* ... (其他样式保持不变) ... */
         .text-title {{ font-size: 34px; fill: {text_color}; /* font-weight: bold; */ text-shadow: 0px 2px 4px rgba(0, 0, 0, 0.4); }}
@@ -847,8 +1127,17 @@ pub fn generate_svg_string(
         .text-acc {{ font-size: 14px; fill: #999999; font-weight: 400; }}
         .text-level {{ font-size: 14px; fill: #999999; font-weight: 400; }}
         .text-rank {{ font-size: 14px; fill: #AAAAAA; font-weight: 400; text-anchor: end; }}
+        .text-play-date {{ font-size: 12px; fill: #999999; font-weight: 400; text-anchor: end; }}
         .text-difficulty-badge {{ font-size: 12px; font-weight: 700; }} /* 难度标签文本样式 */
         .text-fc-ap-badge {{ font-size: 11px; font-weight: 700; }} /* FC/AP标签文本样式 */
+        .text-grade {{ font-size: 14px; font-weight: 700; }} /* 评级标签文本样式 */
+        .rank-phi {{ fill: gold; }}
+        .rank-v {{ fill: silver; }}
+        .rank-s {{ fill: #FF6B6B; }}
+        .rank-a {{ fill: #4FC3F7; }}
+        .rank-b {{ fill: #81C784; }}
+        .rank-c {{ fill: #BA68C8; }}
+        .rank-f {{ fill: #9E9E9E; }}
         .push-acc {{ fill: #4CAF50; font-weight: 600; }}
         .text-rank-tag {{ font-size: 13px; fill: {text_secondary_color}; text-anchor: end; font-weight: 700; }}
         .text-section-title {{ font-size: 21px; fill: {text_color}; /* font-weight: bold; */ }}
@@ -892,12 +1181,178 @@ pub fn generate_svg_string(
 
     writeln!(svg, "</defs>").map_err(fmt_err)?;
 
+    Ok(bg_filter)
+}
+
+pub fn generate_svg_string(
+    scores: &[RksRecord],
+    stats: &PlayerStats,
+    push_acc_map: Option<&HashMap<String, f64>>, // 新增：预先计算的推分ACC映射，键为"曲目ID-难度"
+    theme: &crate::controllers::image::Theme,    // 新增：主题参数
+    embed_images: bool,
+    profile: RenderProfile,
+) -> Result<String, AppError> {
+    let _start_time = std::time::Instant::now();
+    // ... (width, height calculations etc. - keep these as they were) ...
+    let width = 1200;
+    let header_height = 120;
+    let _ap_title_height = 50; // Prefix unused variable
+    let footer_height = 50;
+    let main_card_padding_outer = 12;
+    let ap_card_padding_outer = 12;
+    let columns = 3;
+
+    let main_card_width = (width - main_card_padding_outer * (columns + 1)) / columns;
+    let card_padding_inner = 10.0;
+    let text_line_height_song = 22.0;
+    let text_line_height_score = 30.0;
+    let text_line_height_acc = 18.0;
+    let text_line_height_level = 18.0;
+    let text_block_spacing = 4.0;
+    let text_block_height = text_line_height_song
+        + text_line_height_score
+        + text_line_height_acc
+        + text_line_height_level
+        + text_block_spacing * 3.0;
+    let calculated_card_height = (text_block_height + card_padding_inner * 2.0) as u32;
+    let ap_card_start_y = ap_card_padding_outer;
+    let ap_section_height = if !stats.ap_only && !stats.ap_top_3_scores.is_empty() {
+        ap_card_start_y + calculated_card_height + ap_card_padding_outer
+    } else {
+        0
+    };
+    let sections = main_card_sections(scores, stats.layout);
+    let content_height: u32 = sections
+        .iter()
+        .map(|(label, indices)| {
+            let rows = (indices.len() as u32).div_ceil(columns);
+            label.map_or(0, |_| SECTION_TITLE_HEIGHT)
+                + (calculated_card_height + main_card_padding_outer) * rows.max(1)
+        })
+        .sum();
+    let total_height = header_height + ap_section_height + content_height + footer_height + 10;
+
+    let palette = card_palette(theme);
+    let mut normal_card_stroke_color = match theme {
+        crate::controllers::image::Theme::White => "url(#normal-card-stroke-gradient)".to_string(),
+        crate::controllers::image::Theme::Black | crate::controllers::image::Theme::Transparent => {
+            "#252A38".to_string() // Weaker border for black theme
+        }
+    };
+    let mut svg = String::new();
+    let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
+
+    // --- 获取随机背景图 ---
+    let mut background_image_href = None;
+    let _background_fill = "url(#bg-gradient)".to_string(); // Prefix unused variable
+
+    // 使用预先缓存的封面文件列表来获取背景图片，避免重复读取目录
+    let background_files = get_cover_files();
+    let background_base_path = PathBuf::from(cover_loader::COVERS_DIR).join("illBlur");
+    let is_background_file = |path: &&PathBuf| {
+        // 检查路径是否在 illBlur 目录下且是图片文件
+        path.starts_with(&background_base_path)
+            && (path.extension() == Some("png".as_ref())
+                || path.extension() == Some("jpg".as_ref()))
+    };
+
+    let find_song_background = |song_id: &str| {
+        background_files
+            .iter()
+            .filter(is_background_file)
+            .find(|path| path.file_stem().and_then(|stem| stem.to_str()) == Some(song_id))
+    };
+    let pick_random_background = || {
+        let filtered_background_files: Vec<&PathBuf> =
+            background_files.iter().filter(is_background_file).collect();
+        if filtered_background_files.is_empty() {
+            log::warn!("找不到任何背景文件用于随机背景");
+            // Fallback to gradient if directory is empty or read failed
+            None
+        } else {
+            // 按种子或随机选择一个路径
+            let path = pick_background(&filtered_background_files, stats.background_seed).copied();
+            if path.is_none() {
+                log::warn!("无法从背景文件列表中随机选择一个");
+            }
+            path
+        }
+    };
+
+    // 按背景模式确定背景图路径，None 时使用渐变
+    let background_path: Option<&PathBuf> = match stats.background_mode {
+        _ if *theme == crate::controllers::image::Theme::Transparent => {
+            // 透明主题不需要背景图
+            None
+        }
+        crate::controllers::image::BackgroundMode::Gradient => None,
+        crate::controllers::image::BackgroundMode::SongCover => {
+            // 取 RKS 最高成绩的模糊曲绘，缺失时回退到渐变
+            let top_song_id = scores.first().map(|record| record.song_id.as_str());
+            let path = top_song_id.and_then(find_song_background);
+            if path.is_none() {
+                log::warn!("找不到最高成绩曲目的模糊曲绘，使用渐变背景");
+            }
+            path
+        }
+        crate::controllers::image::BackgroundMode::PlayerBackground => {
+            // 玩家在游戏中选择的背景，没有对应的模糊曲绘时回退到随机背景
+            let path = stats
+                .player_background
+                .as_deref()
+                .and_then(find_song_background);
+            if path.is_none() {
+                log::debug!(
+                    "玩家背景 {:?} 没有对应的模糊曲绘，使用随机背景",
+                    stats.player_background
+                );
+            }
+            path.or_else(pick_random_background)
+        }
+        crate::controllers::image::BackgroundMode::Random => pick_random_background(),
+    };
+
+    if let Some(background_path) = background_path {
+        // --- 新增：计算背景主色的反色 ---
+        if let crate::controllers::image::Theme::White = theme {
+            if let Some(inverse_color) = get_inverse_color_from_path_cached(background_path) {
+                normal_card_stroke_color = inverse_color;
+                log::info!("使用背景反色作为卡片边框: {normal_card_stroke_color}");
+            }
+        }
+        // --- 结束新增 ---
+
+        // 使用缓存函数获取背景图片
+        if let Some(image_href) = get_image_href(background_path, embed_images) {
+            background_image_href = Some(image_href);
+            log::info!("使用背景图: {}", background_path.display());
+        } else {
+            log::error!("获取背景图片失败: {}", background_path.display());
+            // 获取失败则回退到渐变
+        }
+    }
+    // --- 背景图获取结束 ---
+
+    writeln!(
+        svg,
+        r#"<svg width="{width}" height="{total_height}" viewBox="0 0 {width} {total_height}" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">"#
+    ).map_err(fmt_err)?;
+
+    // --- Definitions (Styles, Gradients, Filters, Font) ---
+    let bg_filter = write_card_defs(
+        &mut svg,
+        theme,
+        &palette,
+        &normal_card_stroke_color,
+        profile,
+    )?;
+
     // --- Background ---
     // 如果找到了背景图，则使用<image>并应用模糊，否则使用原来的<rect>和渐变
     if let Some(href) = background_image_href {
         writeln!(svg,
             // 使用 href (Base64 data URI), preserveAspectRatio 保证图片覆盖并居中裁剪, filter 应用模糊
-            r#"<image href="{href}" x="0" y="0" width="100%" height="100%" preserveAspectRatio="xMidYMid slice" filter="url(#bg-blur)" />"#
+            r#"<image href="{href}" x="0" y="0" width="100%" height="100%" preserveAspectRatio="xMidYMid slice"{bg_filter} />"#
         ).map_err(fmt_err)?;
         // 可选：在模糊背景上加一层半透明叠加层，使前景文字更清晰
         // 调整 rgba 最后一个值 (alpha) 控制透明度, 0.7 = 70% 不透明
@@ -909,7 +1364,8 @@ pub fn generate_svg_string(
                 )
                 .map_err(fmt_err)?;
             }
-            crate::controllers::image::Theme::Black => {
+            crate::controllers::image::Theme::Black
+            | crate::controllers::image::Theme::Transparent => {
                 writeln!(
                     svg,
                     r#"<rect width="100%" height="100%" fill="rgba(20, 24, 38, 0.7)" />"#
@@ -917,6 +1373,8 @@ pub fn generate_svg_string(
                 .map_err(fmt_err)?;
             }
         }
+    } else if *theme == crate::controllers::image::Theme::Transparent {
+        // 透明主题：不绘制任何背景，保留 PNG 的 Alpha 通道
     } else {
         // 回退到渐变背景
         writeln!(
@@ -928,6 +1386,7 @@ pub fn generate_svg_string(
     // --- 背景结束 ---
 
     // --- Header ---
+    let labels = image_labels::labels(stats.lang);
     let player_name = stats.player_name.as_deref().unwrap_or("Phigros Player");
     let real_rks = stats.real_rks.unwrap_or(0.0);
     writeln!(
@@ -937,9 +1396,20 @@ pub fn generate_svg_string(
         real_rks
     )
     .map_err(fmt_err)?;
-    let ap_text = match stats.ap_top_3_avg {
-        Some(avg) => format!("AP Top 3 Avg: {avg:.4}"),
-        None => "AP Top 3 Avg: N/A".to_string(),
+    let ap_text = if stats.ap_only {
+        // AP Best N 模式下整张图都是 AP 成绩，改为展示所列成绩的平均 RKS
+        let template = labels.ap_best_n_avg.replace("{n}", &stats.n.to_string());
+        if scores.is_empty() {
+            image_labels::fill(&template, "N/A")
+        } else {
+            let avg = scores.iter().map(|s| s.rks).sum::<f64>() / scores.len() as f64;
+            image_labels::fill(&template, &format!("{avg:.4}"))
+        }
+    } else {
+        let avg_str = stats
+            .ap_top_3_avg
+            .map_or("N/A".to_string(), |avg| format!("{avg:.4}"));
+        image_labels::fill(labels.ap_top_3_avg, &avg_str)
     };
     writeln!(
         svg,
@@ -949,15 +1419,46 @@ pub fn generate_svg_string(
     let b27_avg_str = stats
         .best_27_avg
         .map_or("N/A".to_string(), |avg| format!("{avg:.4}"));
-    let bn_text = format!("Best 27 Avg: {b27_avg_str}");
+    let bn_text = image_labels::fill(labels.best_27_avg, &b27_avg_str);
     writeln!(
         svg,
         r#"<text x="40" y="110" class="text-stat">{bn_text}</text>"#
     )
     .map_err(fmt_err)?;
 
-    // --- Right-aligned info (Data, Challenge, Time) ---
-    let mut info_y = 65.0; // Starting Y position for the top-right info block
+    // --- Right-aligned info (Region, Data, Challenge, Time) ---
+    // 有地区信息或定数范围时每项多出一行，整体上移以保持在 Header 内
+    let extra_info_lines = [stats.region.is_some(), stats.constant_range.is_some()]
+        .into_iter()
+        .filter(|present| *present)
+        .count();
+    let mut info_y = 65.0 - 20.0 * extra_info_lines as f64; // Starting Y position for the top-right info block
+
+    // Region
+    if let Some(region) = &stats.region {
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{}" class="text-info">{}</text>"#,
+            width - 30,
+            info_y,
+            escape_xml(&image_labels::fill(labels.region, region))
+        )
+        .map_err(fmt_err)?;
+        info_y += 20.0;
+    }
+
+    // Constant Range
+    if let Some(range) = &stats.constant_range {
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{}" class="text-info">{}</text>"#,
+            width - 30,
+            info_y,
+            escape_xml(&image_labels::fill(labels.constant, range))
+        )
+        .map_err(fmt_err)?;
+        info_y += 20.0;
+    }
 
     // Data String
     if let Some(data_str) = &stats.data_string {
@@ -966,7 +1467,7 @@ pub fn generate_svg_string(
             r#"<text x="{}" y="{}" class="text-info">{}</text>"#,
             width - 30,
             info_y,
-            escape_xml(data_str)
+            escape_xml(&image_labels::fill(labels.data, data_str))
         )
         .map_err(fmt_err)?;
         info_y += 20.0; // Increment Y for the next line
@@ -980,17 +1481,27 @@ pub fn generate_svg_string(
             "Red" => "#BE2D23",
             "Gold" => "#D1913C",
             "Rainbow" => "url(#ap-gradient)", // Use existing gold gradient for rainbow for now
-            _ => text_secondary_color,
+            _ => palette.text_secondary_color,
         };
-        writeln!(svg, r#"<text x="{}" y="{}" class="text-info">Challenge: <tspan fill="{}">{}</tspan> {}</text>"#,
-                 width - 30, info_y, color_hex, color, level).map_err(fmt_err)?;
+        let challenge_text = image_labels::fill(
+            &escape_xml(labels.challenge),
+            &format!(r#"<tspan fill="{color_hex}">{color}</tspan> {level}"#),
+        );
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{}" class="text-info">{}</text>"#,
+            width - 30,
+            info_y,
+            challenge_text
+        )
+        .map_err(fmt_err)?;
         info_y += 20.0; // Increment Y for the next line
     }
 
     // Update Time (always displayed)
-    let update_time = format!(
-        "Updated at {} UTC",
-        stats.update_time.format("%Y/%m/%d %H:%M:%S")
+    let update_time = image_labels::fill(
+        labels.updated_at,
+        &stats.update_time.format("%Y/%m/%d %H:%M:%S").to_string(),
     );
     writeln!(
         svg,
@@ -1007,13 +1518,13 @@ pub fn generate_svg_string(
         header_height,
         width - 40,
         header_height,
-        card_stroke_color
+        palette.card_stroke_color
     )
     .map_err(fmt_err)?;
 
     // --- AP Top 3 Section --- (保持不变) ...
     let ap_section_start_y = header_height + 15;
-    if !stats.ap_top_3_scores.is_empty() {
+    if !stats.ap_only && !stats.ap_top_3_scores.is_empty() {
         writeln!(
             svg,
             r#"<g id="ap-top-3-section" transform="translate(0, {ap_section_start_y})">"#
@@ -1043,43 +1554,97 @@ pub fn generate_svg_string(
                 theme,
                 is_user_generated: stats.is_user_generated,
                 embed_images,
+                acc_decimals: stats.acc_decimals,
+                play_date: stats
+                    .play_dates
+                    .get(&format!("{}-{}", score.song_id, score.difficulty))
+                    .copied(),
+            })?
+        }
+        writeln!(svg, r#"</g>"#).map_err(fmt_err)?;
+    }
+
+    // --- Main Score Cards Section --- (保持不变) ...
+    let main_content_start_y = header_height + ap_section_height + 15;
+    let mut section_start_y = main_content_start_y;
+    for (label, indices) in &sections {
+        // 分区标题: 难度色条 + "难度 · 数量"
+        if let Some(label) = label {
+            let (_, color) = difficulty_badge(label);
+            writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="6" height="22" rx="3" fill="{color}" />"#,
+                main_card_padding_outer,
+                section_start_y + main_card_padding_outer + 6
+            )
+            .map_err(fmt_err)?;
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{}" class="text-section-title">{label} · {}</text>"#,
+                main_card_padding_outer + 14,
+                section_start_y + main_card_padding_outer + 24,
+                indices.len()
+            )
+            .map_err(fmt_err)?;
+            section_start_y += SECTION_TITLE_HEIGHT;
+        }
+
+        for (position, &index) in indices.iter().enumerate() {
+            let score = &scores[index];
+            let row = position as u32 / columns;
+            let col = position as u32 % columns;
+            let x = main_card_padding_outer + col * (main_card_width + main_card_padding_outer);
+            let y = section_start_y
+                + main_card_padding_outer
+                + row * (calculated_card_height + main_card_padding_outer);
+            let is_ap_score = score.acc >= 100.0;
+
+            // 获取预计算的推分ACC（如果有）
+            let push_acc = push_acc_map.and_then(|map| {
+                let key = format!("{}-{}", score.song_id, score.difficulty);
+                map.get(&key).copied()
+            });
+
+            generate_card_svg(CardRenderInfo {
+                svg: &mut svg,
+                score,
+                index,
+                card_x: x,
+                card_y: y,
+                card_width: main_card_width,
+                is_ap_card: false,
+                is_ap_score,
+                pre_calculated_push_acc: push_acc,
+                all_sorted_records: scores,
+                theme,
+                is_user_generated: stats.is_user_generated,
+                embed_images,
+                acc_decimals: stats.acc_decimals,
+                play_date: stats
+                    .play_dates
+                    .get(&format!("{}-{}", score.song_id, score.difficulty))
+                    .copied(),
             })?
         }
-        writeln!(svg, r#"</g>"#).map_err(fmt_err)?;
-    }
 
-    // --- Main Score Cards Section --- (保持不变) ...
-    let main_content_start_y = header_height + ap_section_height + 15;
-    for (index, score) in scores.iter().enumerate() {
-        let row = index as u32 / columns;
-        let col = index as u32 % columns;
-        let x = main_card_padding_outer + col * (main_card_width + main_card_padding_outer);
-        let y = main_content_start_y
-            + main_card_padding_outer
-            + row * (calculated_card_height + main_card_padding_outer);
-        let is_ap_score = score.acc >= 100.0;
-
-        // 获取预计算的推分ACC（如果有）
-        let push_acc = push_acc_map.and_then(|map| {
-            let key = format!("{}-{}", score.song_id, score.difficulty);
-            map.get(&key).copied()
-        });
+        let rows = (indices.len() as u32).div_ceil(columns);
+        section_start_y += (calculated_card_height + main_card_padding_outer) * rows.max(1);
+    }
 
-        generate_card_svg(CardRenderInfo {
-            svg: &mut svg,
-            score,
-            index,
-            card_x: x,
-            card_y: y,
-            card_width: main_card_width,
-            is_ap_card: false,
-            is_ap_score,
-            pre_calculated_push_acc: push_acc,
-            all_sorted_records: scores,
-            theme,
-            is_user_generated: stats.is_user_generated,
-            embed_images,
-        })?
+    // --- 无成绩占位提示（沿用页眉与页脚，仅替换卡片区域） ---
+    if scores.is_empty() {
+        if let Some(message) = &stats.empty_message {
+            let message_y =
+                main_content_start_y + main_card_padding_outer + calculated_card_height / 2;
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{}" class="text-stat" text-anchor="middle" dominant-baseline="middle">{}</text>"#,
+                width / 2,
+                message_y,
+                escape_xml(message)
+            )
+            .map_err(fmt_err)?;
+        }
     }
 
     // --- Footer ---
@@ -1090,9 +1655,9 @@ pub fn generate_svg_string(
     let now_utc = Utc::now();
     let offset = FixedOffset::east_opt(8 * 3600).unwrap();
     let now_utc8 = now_utc.with_timezone(&offset);
-    let generated_text = format!(
-        "Generated by Phi-Backend at {} UTC+8",
-        now_utc8.format("%Y/%m/%d %H:%M:%S")
+    let generated_text = image_labels::fill(
+        labels.generated_at,
+        &now_utc8.format("%Y/%m/%d %H:%M:%S").to_string(),
     );
     writeln!(svg, r#"<text x="{footer_padding}" y="{footer_y:.1}" class="text-footer" text-anchor="start">{generated_text}</text>"#).map_err(fmt_err)?;
 
@@ -1115,9 +1680,121 @@ pub fn generate_svg_string(
     Ok(svg)
 }
 
+/// 只渲染 AP Top 3 卡片的紧凑图片，卡片与 BN 图中的 AP Top 3 区域一致
+pub fn generate_ap3_svg_string(
+    data: &Ap3RenderData,
+    theme: &crate::controllers::image::Theme,
+    embed_images: bool,
+    profile: RenderProfile,
+) -> Result<String, AppError> {
+    let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
+
+    // 卡片尺寸与 BN 图一致
+    let width = 1200;
+    let header_height = 90;
+    let card_padding_outer = 12;
+    let columns = 3;
+    let card_width = (width - card_padding_outer * (columns + 1)) / columns;
+    let text_block_height = 22.0 + 30.0 + 18.0 + 18.0 + 4.0 * 3.0;
+    let card_height = (text_block_height + 10.0 * 2.0) as u32;
+    let total_height = header_height + card_height + card_padding_outer * 2;
+
+    let palette = card_palette(theme);
+    let normal_card_stroke_color = match theme {
+        crate::controllers::image::Theme::White => "url(#normal-card-stroke-gradient)",
+        crate::controllers::image::Theme::Black | crate::controllers::image::Theme::Transparent => {
+            "#252A38"
+        }
+    };
+
+    let mut svg = String::with_capacity(16000);
+    writeln!(
+        svg,
+        r#"<svg width="{width}" height="{total_height}" viewBox="0 0 {width} {total_height}" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">"#
+    ).map_err(fmt_err)?;
+    write_card_defs(&mut svg, theme, &palette, normal_card_stroke_color, profile)?;
+
+    // 透明主题不绘制背景，其余主题使用渐变背景
+    if *theme != crate::controllers::image::Theme::Transparent {
+        writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="url(#bg-gradient)"/>"#
+        )
+        .map_err(fmt_err)?;
+    }
+
+    // --- Header: 玩家名称与 RKS、AP Top 3 平均与更新时间 ---
+    let labels = image_labels::labels(data.lang);
+    writeln!(
+        svg,
+        r#"<text x="40" y="50" class="text-title">{}({:.6})</text>"#,
+        escape_xml(&data.player_name),
+        data.rks
+    )
+    .map_err(fmt_err)?;
+    let avg_str = if data.ap_scores.len() >= 3 {
+        let avg = data.ap_scores.iter().take(3).map(|s| s.rks).sum::<f64>() / 3.0;
+        format!("{avg:.4}")
+    } else {
+        "N/A".to_string()
+    };
+    writeln!(
+        svg,
+        r#"<text x="{}" y="45" class="text-info">{}</text>"#,
+        width - 30,
+        image_labels::fill(labels.ap_top_3_avg, &avg_str)
+    )
+    .map_err(fmt_err)?;
+    let update_time = image_labels::fill(
+        labels.updated_at,
+        &data.update_time.format("%Y/%m/%d %H:%M:%S").to_string(),
+    );
+    writeln!(
+        svg,
+        r#"<text x="{}" y="70" class="text-time">{}</text>"#,
+        width - 30,
+        update_time
+    )
+    .map_err(fmt_err)?;
+
+    // --- AP Top 3 卡片 ---
+    let card_y = header_height + card_padding_outer;
+    for (idx, score) in data.ap_scores.iter().take(3).enumerate() {
+        let x_pos = card_padding_outer + idx as u32 * (card_width + card_padding_outer);
+        generate_card_svg(CardRenderInfo {
+            svg: &mut svg,
+            score,
+            index: idx,
+            card_x: x_pos,
+            card_y,
+            card_width,
+            is_ap_card: true,
+            is_ap_score: true,
+            pre_calculated_push_acc: None,
+            all_sorted_records: &data.ap_scores,
+            theme,
+            is_user_generated: false,
+            embed_images,
+            acc_decimals: DEFAULT_ACC_DECIMALS,
+            play_date: None,
+        })?
+    }
+
+    writeln!(svg, "</svg>").map_err(fmt_err)?;
+
+    Ok(svg)
+}
+
 // ... (render_svg_to_png function - unchanged) ...
-pub fn render_svg_to_png(svg_data: String, is_user_generated: bool) -> Result<Vec<u8>, AppError> {
-    // 分段计时，定位瓶颈
+/// 栅格化输出的像素数上限（约 200MB RGBA），缩放后超出时自动降低缩放倍数
+const MAX_RASTER_PIXELS: f32 = 50_000_000.0;
+
+// 将 SVG 解析并按 `scale` 倍栅格化为 Pixmap（像素为预乘 Alpha），返回 (Pixmap, 解析耗时)
+fn rasterize_svg(
+    svg_data: &str,
+    is_user_generated: bool,
+    scale: f32,
+) -> Result<(Pixmap, std::time::Duration), AppError> {
     let t0 = std::time::Instant::now();
 
     // 字体数据库（全局复用）
@@ -1142,12 +1819,26 @@ pub fn render_svg_to_png(svg_data: String, is_user_generated: bool) -> Result<Ve
         .map_err(|e| AppError::InternalError(format!("Failed to parse SVG: {e}")))?;
     let t_parse = t0.elapsed();
 
-    let pixmap_size = tree.size().to_int_size();
+    // 长图 (如 N 较大的 BN 图) 放大后可能占用过多内存，按像素上限收紧缩放倍数
+    let base_size = tree.size().to_int_size();
+    let base_pixels = base_size.width() as f32 * base_size.height() as f32;
+    let max_scale = (MAX_RASTER_PIXELS / base_pixels).sqrt().max(1.0);
+    if scale > max_scale {
+        log::warn!("图片尺寸过大，缩放倍数由 {scale:.2} 降低为 {max_scale:.2}");
+    }
+    let scale = scale.min(max_scale);
+
+    let pixmap_size = base_size
+        .scale_by(scale)
+        .ok_or_else(|| AppError::InternalError("Invalid pixmap size".to_string()))?;
     let mut pixmap = Pixmap::new(pixmap_size.width(), pixmap_size.height())
         .ok_or_else(|| AppError::InternalError("Failed to create pixmap".to_string()))?;
 
-    render(&tree, Transform::default(), &mut pixmap.as_mut());
-    let t_raster = t0.elapsed();
+    render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
 
     // 用户数据添加隐式水印：直接修改未编码像素，避免解/编码开销
     if is_user_generated {
@@ -1156,10 +1847,35 @@ pub fn render_svg_to_png(svg_data: String, is_user_generated: bool) -> Result<Ve
         }
     }
 
+    Ok((pixmap, t_parse))
+}
+
+/// 将 SVG 渲染为 PNG，`scale` 为输出尺寸相对 SVG 尺寸的倍数
+pub fn render_svg_to_png(
+    svg_data: String,
+    is_user_generated: bool,
+    scale: f32,
+) -> Result<Vec<u8>, AppError> {
+    // 分段计时，定位瓶颈
+    let t0 = std::time::Instant::now();
+    let (mut pixmap, t_parse) = rasterize_svg(&svg_data, is_user_generated, scale)?;
+    let t_raster = t0.elapsed();
+
+    // tiny-skia 的像素为预乘 Alpha，PNG 需要非预乘数据；
+    // 不透明图片不受影响，透明背景的半透明像素需还原颜色
+    for px in pixmap.data_mut().chunks_exact_mut(4) {
+        let a = px[3];
+        if a != 0 && a != 255 {
+            for c in &mut px[..3] {
+                *c = ((u16::from(*c) * 255 + u16::from(a) / 2) / u16::from(a)).min(255) as u8;
+            }
+        }
+    }
+
     // 使用 png crate 进行快速编码
-    let mut out = Vec::with_capacity((pixmap_size.width() * pixmap_size.height() * 4) as usize);
+    let mut out = Vec::with_capacity((pixmap.width() * pixmap.height() * 4) as usize);
     {
-        let mut encoder = png::Encoder::new(&mut out, pixmap_size.width(), pixmap_size.height());
+        let mut encoder = png::Encoder::new(&mut out, pixmap.width(), pixmap.height());
         encoder.set_color(png::ColorType::Rgba);
         encoder.set_depth(png::BitDepth::Eight);
         encoder.set_compression(png::Compression::Fast);
@@ -1188,6 +1904,49 @@ pub fn render_svg_to_png(svg_data: String, is_user_generated: bool) -> Result<Ve
     Ok(out)
 }
 
+/// 将 SVG 渲染为 JPEG。JPEG 不支持透明通道，半透明像素会被合成到 `background` (RGB) 上。
+pub fn render_svg_to_jpeg(
+    svg_data: String,
+    is_user_generated: bool,
+    quality: u8,
+    background: [u8; 3],
+    scale: f32,
+) -> Result<Vec<u8>, AppError> {
+    let t0 = std::time::Instant::now();
+    let (pixmap, t_parse) = rasterize_svg(&svg_data, is_user_generated, scale)?;
+    let t_raster = t0.elapsed();
+
+    // 预乘 Alpha 下的合成: out = c + bg * (255 - a) / 255
+    let mut rgb = Vec::with_capacity((pixmap.width() * pixmap.height() * 3) as usize);
+    for px in pixmap.data().chunks_exact(4) {
+        let inv_a = 255 - u16::from(px[3]);
+        for (c, bg) in px[..3].iter().zip(background) {
+            rgb.push((u16::from(*c) + (u16::from(bg) * inv_a + 127) / 255).min(255) as u8);
+        }
+    }
+
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100))
+        .encode(
+            &rgb,
+            pixmap.width(),
+            pixmap.height(),
+            image::ExtendedColorType::Rgb8,
+        )
+        .map_err(|e| AppError::InternalError(format!("JPEG encode error: {e}")))?;
+    let t_encode = t0.elapsed();
+
+    log::info!(
+        "JPEG渲染内部分段: 解析={:?}, 栅格化={:?}, 编码={:?}, 总计={:?}",
+        t_parse,
+        t_raster - t_parse,
+        t_encode - t_raster,
+        t_encode
+    );
+
+    Ok(out)
+}
+
 // ... (escape_xml function - unchanged) ...
 fn escape_xml(input: &str) -> String {
     input
@@ -1241,25 +2000,32 @@ fn calculate_inverse_color_from_path(path: &Path) -> Option<String> {
 }
 
 /// 带缓存的反色计算，避免重复解码大图
+///
+/// 取出槽位后立即释放缓存锁，解码在锁外进行，不阻塞其他路径的查询；
+/// 同一路径的并发请求等待第一个请求的结果，而不是各自重新扫描像素。
 fn get_inverse_color_from_path_cached(path: &Path) -> Option<String> {
-    let key = PathBuf::from(path);
-    {
-        let mut cache = get_inverse_color_cache().lock().ok()?;
-        if let Some(c) = cache.get(&key) {
-            return Some(c.clone());
-        }
-    }
+    let slot = get_inverse_color_cache()
+        .lock()
+        .ok()?
+        .get_or_insert(PathBuf::from(path), || Arc::new(OnceLock::new()))
+        .clone();
+    slot.get_or_init(|| calculate_inverse_color_from_path(path))
+        .clone()
+}
 
-    let color = calculate_inverse_color_from_path(path)?;
-    if let Ok(mut cache) = get_inverse_color_cache().lock() {
-        cache.put(key, color.clone());
-    }
-    Some(color)
+/// 读取曲绘的实际宽高比，只解析图片头部而不解码像素；没有曲绘或读取失败时使用默认比例
+fn illustration_aspect_ratio(path: Option<&Path>) -> f64 {
+    path.and_then(|path| image::image_dimensions(path).ok())
+        .filter(|(w, h)| *w > 0 && *h > 0)
+        .map_or(SONG_ILLUST_ASPECT_RATIO, |(w, h)| {
+            f64::from(w) / f64::from(h)
+        })
 }
 
 // --- 新增：生成单曲成绩 SVG ---
 pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Result<String, AppError> {
     let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
+    let labels = image_labels::labels(data.lang);
 
     // --- 整体布局与尺寸（横版）---
     let width = 1400; // 图片宽度，从1200增加到1400
@@ -1269,9 +2035,9 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
     // 玩家信息区域高度
     let player_info_height = 78.0; // 原来是70.0，增加8px (上下各4px)
 
-    // 曲绘尺寸 - 保持2048x1080的比例，但整体缩小
+    // 曲绘尺寸 - 按曲绘的实际宽高比预留空间 (通常为2048x1080)，但整体缩小
     let illust_height = height as f64 - padding * 3.0 - player_info_height - 80.0; // 给标题、页脚和曲目名称留出空间
-    let illust_width = illust_height * (2048.0 / 1080.0); // 保持2048x1080的比例
+    let illust_width = illust_height * illustration_aspect_ratio(data.illustration_path.as_deref());
 
     // 确保曲绘不会超过整体宽度的60%
     let illust_width = (illust_width).min(width as f64 * 0.60);
@@ -1305,7 +2071,9 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
 
     // 优先尝试使用当前曲目的曲绘作为背景
     // 使用预先缓存的封面文件列表来检查文件是否存在，避免重复的文件系统调用
-    if cover_files.contains(&current_song_ill_path_png) {
+    if data.background_mode == crate::controllers::image::BackgroundMode::Gradient {
+        // 渐变背景模式不读取任何背景图
+    } else if cover_files.contains(&current_song_ill_path_png) {
         if let Some(image_href) = get_image_href(&current_song_ill_path_png, embed_images) {
             background_image_href = Some(image_href);
             log::info!(
@@ -1321,11 +2089,12 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
                 current_song_ill_path_jpg.display()
             );
         }
+    } else if data.background_mode == crate::controllers::image::BackgroundMode::SongCover {
+        log::warn!("找不到当前曲目的曲绘，使用渐变背景");
     } else {
         // 如果找不到当前曲目的曲绘，则随机选一个
         if !cover_files.is_empty() {
-            let mut rng = rand::rng();
-            if let Some(random_path) = cover_files.as_slice().choose(&mut rng) {
+            if let Some(random_path) = pick_background(cover_files, data.background_seed) {
                 if let Some(image_href) = get_image_href(random_path, embed_images) {
                     background_image_href = Some(image_href);
                     log::info!("使用随机背景图: {}", random_path.display());
@@ -1357,6 +2126,7 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
         .text-acc {{ font-size: 18px; fill: #B0B0B0; }} /* 参考Bn图调整ACC字体 */
         .text-rks {{ font-size: 18px; fill: #E0E0E0; }} /* 参考Bn图调整RKS字体 */
         .text-push-acc {{ font-size: 18px; font-weight: bold; }} /* 参考Bn图调整推分ACC字体 */
+        .text-vs-avg {{ font-size: 16px; }}
         .text-songname {{ font-size: 24px; font-weight: bold; fill: #FFFFFF; text-anchor: middle; }}
         .text-player-info {{ font-size: 22px; font-weight: bold; fill: #FFFFFF; }}
         .text-player-rks {{ font-size: 20px; fill: #E0E0E0; }}
@@ -1373,9 +2143,14 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
         .difficulty-card-phi {{ fill: url(#card-gradient); stroke: gold; stroke-width: 3; }} /* Phi卡片使用渐变填充 */
         .song-name-card {{ fill: rgba(40, 45, 60, 0.8); stroke: rgba(100, 100, 100, 0.4); stroke-width: 1; }}
         .constants-card {{ fill: rgba(40, 45, 60, 0.8); stroke: rgba(100, 100, 100, 0.4); stroke-width: 1; }}
+        .text-grade {{ font-size: 28px; font-weight: bold; }}
         .rank-phi {{ fill: gold; }}
         .rank-v {{ fill: silver; }}
         .rank-s {{ fill: #FF6B6B; }}
+        .rank-a {{ fill: #4FC3F7; }}
+        .rank-b {{ fill: #81C784; }}
+        .rank-c {{ fill: #BA68C8; }}
+        .rank-f {{ fill: #9E9E9E; }}
     "#).map_err(fmt_err)?;
     writeln!(svg, "</style>").map_err(fmt_err)?;
 
@@ -1424,10 +2199,10 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
     let player_name_display = data.player_name.as_deref().unwrap_or("Player");
     writeln!(
         svg,
-        r#"<text x="{}" y="{}" class="text text-player-info">Player: {}</text>"#,
+        r#"<text x="{}" y="{}" class="text text-player-info">{}</text>"#,
         player_info_x + 20.0,
         player_info_y + 49.0,
-        player_name_display
+        escape_xml(&image_labels::fill(labels.player, player_name_display))
     )
     .map_err(fmt_err)?;
 
@@ -1495,13 +2270,29 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
     .map_err(fmt_err)?;
 
     // --- 难度卡片（右侧垂直排列）---
-    let difficulties = ["EZ", "HD", "IN", "AT"]; // 难度顺序
+    let all_difficulties = ["EZ", "HD", "IN", "AT"]; // 难度顺序
+    let played_difficulties: Vec<&str> = all_difficulties
+        .into_iter()
+        .filter(|diff_key| {
+            data.difficulty_scores
+                .get(*diff_key)
+                .is_some_and(|opt| opt.as_ref().is_some_and(|score| score.acc.is_some()))
+        })
+        .collect();
+    // 仅渲染已游玩难度时，若一个成绩都没有则回退为完整的四难度布局
+    let difficulties = if data.played_only && !played_difficulties.is_empty() {
+        played_difficulties
+    } else {
+        all_difficulties.to_vec()
+    };
 
-    // 计算右侧卡片区域的起始位置
+    // 计算右侧卡片区域的起始位置，卡片数量不足4张时在曲绘高度内垂直居中
     let cards_start_x = illust_x + illust_width + padding;
-    let cards_start_y = illust_y; // 与曲绘顶部对齐
+    let cards_total_height = difficulty_card_height * difficulties.len() as f64
+        + difficulty_card_spacing * (difficulties.len() as f64 - 1.0);
+    let cards_start_y = illust_y + (illust_height - cards_total_height) / 2.0;
 
-    // 渲染四个难度卡片
+    // 渲染难度卡片
     for (i, &diff_key) in difficulties.iter().enumerate() {
         let pos_x = cards_start_x;
         let pos_y = cards_start_y + (difficulty_card_height + difficulty_card_spacing) * i as f64;
@@ -1590,22 +2381,40 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
                 let acc_y = pos_y + 65.0;
                 let rks_y = pos_y + 88.0;
 
-                // 分数
-                writeln!(svg, r#"<text x="{text_x}" y="{score_y}" class="text text-score" text-anchor="start">{score_text}</text>"#).map_err(fmt_err)?;
+                // 分数，评级紧跟在分数之后
+                let grade_tspan =
+                    grade::grade_of(score_data.score, acc_value).map_or(String::new(), |grade| {
+                        format!(
+                            r#"<tspan class="text-grade {}" dx="12">{}</tspan>"#,
+                            grade.css_class(),
+                            grade.label()
+                        )
+                    });
+                writeln!(svg, r#"<text x="{text_x}" y="{score_y}" class="text text-score" text-anchor="start">{score_text}{grade_tspan}</text>"#).map_err(fmt_err)?;
+
+                // 与平均 ACC 的差值，右对齐在分数同一行
+                if let Some(diff) = score_data.acc_vs_average {
+                    let vs_avg_x = pos_x + difficulty_card_width - content_padding;
+                    let vs_avg_color = if diff >= 0.0 { "#77DD77" } else { "#FF6961" };
+                    writeln!(svg, r#"<text x="{vs_avg_x}" y="{score_y}" class="text text-vs-avg" style="fill: {vs_avg_color};" text-anchor="end">{diff:+.1}% vs avg</text>"#).map_err(fmt_err)?;
+                }
 
                 // ACC -> 推分
-                let mut acc_text = format!("Acc: {acc_value:.2}%");
+                let mut acc_text = format!("Acc: {}%", format_acc(acc_value, data.acc_decimals));
                 if let Some(push_acc) = score_data.player_push_acc {
                     let push_acc_display = if push_acc >= 100.0 {
                         if score_data.is_phi == Some(true) {
                             "<tspan class='text-push-acc' fill='gold'> (已 Phi)</tspan>".to_string()
                         } else {
-                            "<tspan class='text-push-acc' fill='gold'> -> 100.00%</tspan>"
-                                .to_string()
+                            format!(
+                                "<tspan class='text-push-acc' fill='gold'> -> {}%</tspan>",
+                                format_acc(100.0, data.acc_decimals)
+                            )
                         }
                     } else {
                         format!(
-                            r#"<tspan class='text-push-acc' fill='url(#rks-gradient-push)'> -> {push_acc:.2}%</tspan>"#
+                            r#"<tspan class='text-push-acc' fill='url(#rks-gradient-push)'> -> {}%</tspan>"#,
+                            format_acc(push_acc, data.acc_decimals)
                         )
                     };
                     acc_text.push_str(&push_acc_display);
@@ -1625,15 +2434,23 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
             // 没有数据时，显示"无谱面"
             let no_data_x = right_area_center;
             let no_data_y = pos_y + difficulty_card_height / 2.0 + 5.0; // 垂直居中
-            writeln!(svg, r#"<text x="{no_data_x}" y="{no_data_y}" class="text text-acc" text-anchor="middle" dominant-baseline="middle">无谱面</text>"#).map_err(fmt_err)?;
+            let no_chart = labels.no_chart;
+            writeln!(svg, r#"<text x="{no_data_x}" y="{no_data_y}" class="text text-acc" text-anchor="middle" dominant-baseline="middle">{no_chart}</text>"#).map_err(fmt_err)?;
         }
     }
 
     // --- Footer ---
     let footer_y = height as f64 - padding / 2.0;
     let footer_x = width as f64 - padding;
-    let time_str = local_time.format("%Y-%m-%d %H:%M:%S UTC+8").to_string(); // 使用UTC+8表示时区
-    writeln!(svg, r#"<text x="{footer_x}" y="{footer_y}" class="text text-footer">Generated by Phi-Backend | {time_str}</text>"#).map_err(fmt_err)?;
+    let footer_text = image_labels::fill(
+        labels.generated_by,
+        &local_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+    ); // 使用UTC+8表示时区
+    writeln!(
+        svg,
+        r#"<text x="{footer_x}" y="{footer_y}" class="text text-footer">{footer_text}</text>"#
+    )
+    .map_err(fmt_err)?;
 
     // --- End SVG ---
     writeln!(svg, "</svg>").map_err(fmt_err)?;
@@ -1642,10 +2459,14 @@ pub fn generate_song_svg_string(data: &SongRenderData, embed_images: bool) -> Re
 }
 
 /// 生成排行榜SVG字符串
-pub fn generate_leaderboard_svg_string(data: &LeaderboardRenderData) -> Result<String, AppError> {
+pub fn generate_leaderboard_svg_string(
+    data: &LeaderboardRenderData,
+    theme: &crate::controllers::image::Theme,
+) -> Result<String, AppError> {
     // -- 定义 fmt_err 闭包 --
     let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
     // -- 结束定义 --
+    let labels = image_labels::labels(data.lang);
 
     let width = 1200;
     let row_height = 60;
@@ -1653,79 +2474,96 @@ pub fn generate_leaderboard_svg_string(data: &LeaderboardRenderData) -> Result<S
     let footer_height = 40;
     let total_height = header_height + (data.entries.len() as i32 * row_height) + footer_height;
 
+    // 主题配色: (背景渐变起点, 背景渐变终点, 主文字, 页脚文字, 表头分隔线, 行分隔线)
+    // 透明主题不绘制背景，其余配色沿用黑色主题
+    let (bg_start, bg_end, text_color, footer_color, header_line_color, row_line_color) =
+        match theme {
+            crate::controllers::image::Theme::White => (
+                "#FFFFFF", "#F0F0F0", "#1A1A1A", "#666666", "#CBD5E0", "#E2E8F0",
+            ),
+            crate::controllers::image::Theme::Black
+            | crate::controllers::image::Theme::Transparent => (
+                "#1a1a2e", "#16213e", "white", "#aaaaaa", "#4a5568", "#2d3748",
+            ),
+        };
+
     let mut svg = String::with_capacity(20000);
     svg.push_str(&format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{total_height}" viewBox="0 0 {width} {total_height}">"#));
 
     // 添加渐变背景和样式
+    // 字体使用本地加载的主字体，resvg 无法加载远程 @font-face
     // 使用 r##"..."## 来避免 # 颜色值与原始字符串分隔符冲突
-    svg.push_str(r##"
+    write!(
+        svg,
+        r##"
     <defs>
         <linearGradient id="bg-gradient" x1="0%" y1="0%" x2="100%" y2="100%">
-            <stop offset="0%" stop-color="#1a1a2e" />
-            <stop offset="100%" stop-color="#16213e" />
+            <stop offset="0%" stop-color="{bg_start}" />
+            <stop offset="100%" stop-color="{bg_end}" />
         </linearGradient>
         <style>
-            @font-face {
-                font-family: 'NotoSansSC';
-                src: url('https://fonts.gstatic.com/s/notosanssc/v36/k3kXo84MPvpLmixcA63oeALhLIiP-Q-87KaAavc.woff2') format('woff2');
-            }
-            .header-text {
-                font-family: 'NotoSansSC', sans-serif;
+            .header-text {{
+                font-family: '{MAIN_FONT_NAME}', sans-serif;
                 font-size: 48px;
-                fill: white;
+                fill: {text_color};
                 text-anchor: middle;
                 font-weight: bold; /* 加粗标题 */
-            }
-            .rank-text {
-                font-family: 'NotoSansSC', sans-serif;
+            }}
+            .rank-text {{
+                font-family: '{MAIN_FONT_NAME}', sans-serif;
                 font-size: 32px;
-                fill: white;
+                fill: {text_color};
                 text-anchor: middle;
                 font-weight: bold;
-            }
-            .name-text {
-                font-family: 'NotoSansSC', sans-serif;
+            }}
+            .name-text {{
+                font-family: '{MAIN_FONT_NAME}', sans-serif;
                 font-size: 32px;
-                fill: white;
+                fill: {text_color};
                 text-anchor: start;
-            }
-            .rks-text {
-                font-family: 'NotoSansSC', sans-serif;
+            }}
+            .rks-text {{
+                font-family: '{MAIN_FONT_NAME}', sans-serif;
                 font-size: 32px;
-                fill: white;
+                fill: {text_color};
                 text-anchor: end;
                 font-weight: bold;
-            }
-            .footer-text {
-                font-family: 'NotoSansSC', sans-serif;
+            }}
+            .footer-text {{
+                font-family: '{MAIN_FONT_NAME}', sans-serif;
                 font-size: 20px;
-                fill: #aaaaaa;
+                fill: {footer_color};
                 text-anchor: end;
-            }
+            }}
         </style>
     </defs>
-"##); // <--- 修正结束符的位置，紧跟在 </defs> 之后
+"##
+    )
+    .map_err(fmt_err)?;
 
     // 绘制背景
-    svg.push_str(&format!(
-        r#"<rect width="{width}" height="{total_height}" fill="url(#bg-gradient)" />"#
-    ));
+    if *theme != crate::controllers::image::Theme::Transparent {
+        svg.push_str(&format!(
+            r#"<rect width="{width}" height="{total_height}" fill="url(#bg-gradient)" />"#
+        ));
+    }
 
     // 绘制标题
     svg.push_str(&format!(
         r#"<text x="{}" y="{}" class="header-text">{}</text>"#,
         width / 2,
         header_height / 2 + 16,
-        data.title
+        labels.leaderboard_title
     ));
 
     // 绘制表头分隔线
     write!(
         svg,
-        r##"<line x1="20" y1="{}" x2="{}" y2="{}" stroke="#4a5568" stroke-width="2" />"##,
+        r##"<line x1="20" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="2" />"##,
         header_height,
         width - 20,
-        header_height
+        header_height,
+        header_line_color
     )
     .map_err(fmt_err)?;
 
@@ -1771,10 +2609,11 @@ pub fn generate_leaderboard_svg_string(data: &LeaderboardRenderData) -> Result<S
             let line_y = y_pos + row_height; // Cast here
             write!(
                 svg,
-                r##"<line x1="100" y1="{}" x2="{}" y2="{}" stroke="#2d3748" stroke-width="1" />"##,
+                r##"<line x1="100" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="1" />"##,
                 line_y,
                 width - 100,
-                line_y
+                line_y,
+                row_line_color
             )
             .map_err(fmt_err)?;
         }
@@ -1783,12 +2622,266 @@ pub fn generate_leaderboard_svg_string(data: &LeaderboardRenderData) -> Result<S
     // 绘制底部更新时间
     let time_str = data.update_time.format("%Y-%m-%d %H:%M:%S").to_string();
     svg.push_str(&format!(
-        r#"<text x="{}" y="{}" class="footer-text">更新时间: {} UTC</text>"#,
+        r#"<text x="{}" y="{}" class="footer-text">{}</text>"#,
         width - 60,
         total_height - 15,
-        time_str
+        image_labels::fill(labels.updated_at, &time_str)
     ));
 
     svg.push_str("</svg>");
     Ok(svg)
 }
+
+/// 生成双人对比图的 SVG：左右各占一半，展示玩家名、RKS、B27 平均、AP Top 3 平均及最高的若干谱面
+pub fn generate_compare_svg_string(
+    data: &CompareRenderData,
+    theme: &crate::controllers::image::Theme,
+) -> Result<String, AppError> {
+    let fmt_err = |e| AppError::InternalError(format!("SVG formatting error: {e}"));
+    let labels = image_labels::labels(data.lang);
+
+    let width = 1200;
+    let half_width = width / 2;
+    let header_height = 100;
+    let stats_height = 190;
+    let row_height = 64;
+    let footer_height = 50;
+    let max_rows = [&data.left, &data.right]
+        .iter()
+        .filter_map(|side| side.as_ref())
+        .map(|player| player.top_scores.len())
+        .max()
+        .unwrap_or(0)
+        .max(1) as i32;
+    let total_height = header_height + stats_height + max_rows * row_height + footer_height;
+
+    // 主题配色: (背景渐变起点, 背景渐变终点, 主文字, 次要文字, 分隔线, 错误提示)
+    // 透明主题不绘制背景，其余配色沿用黑色主题
+    let (bg_start, bg_end, text_color, muted_color, line_color, error_color) = match theme {
+        crate::controllers::image::Theme::White => (
+            "#FFFFFF", "#F0F0F0", "#1A1A1A", "#666666", "#CBD5E0", "#C53030",
+        ),
+        crate::controllers::image::Theme::Black | crate::controllers::image::Theme::Transparent => {
+            (
+                "#1a1a2e", "#16213e", "white", "#aaaaaa", "#4a5568", "#FC8181",
+            )
+        }
+    };
+
+    let mut svg = String::with_capacity(16000);
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{total_height}" viewBox="0 0 {width} {total_height}">"#
+    )
+    .map_err(fmt_err)?;
+
+    write!(
+        svg,
+        r##"
+    <defs>
+        <linearGradient id="bg-gradient" x1="0%" y1="0%" x2="100%" y2="100%">
+            <stop offset="0%" stop-color="{bg_start}" />
+            <stop offset="100%" stop-color="{bg_end}" />
+        </linearGradient>
+        <style>
+            text {{ font-family: '{MAIN_FONT_NAME}', sans-serif; }}
+            .header-text {{ font-size: 44px; fill: {text_color}; text-anchor: middle; font-weight: bold; }}
+            .name-text {{ font-size: 34px; fill: {text_color}; font-weight: bold; }}
+            .rks-text {{ font-size: 56px; fill: {text_color}; font-weight: bold; }}
+            .stat-text {{ font-size: 22px; fill: {muted_color}; }}
+            .song-text {{ font-size: 24px; fill: {text_color}; }}
+            .detail-text {{ font-size: 18px; fill: {muted_color}; }}
+            .rank-text {{ font-size: 24px; fill: {muted_color}; font-weight: bold; }}
+            .error-title {{ font-size: 30px; fill: {error_color}; font-weight: bold; }}
+            .footer-text {{ font-size: 18px; fill: {muted_color}; text-anchor: end; }}
+        </style>
+    </defs>
+"##
+    )
+    .map_err(fmt_err)?;
+
+    if *theme != crate::controllers::image::Theme::Transparent {
+        write!(
+            svg,
+            r#"<rect width="{width}" height="{total_height}" fill="url(#bg-gradient)" />"#
+        )
+        .map_err(fmt_err)?;
+    }
+
+    write!(
+        svg,
+        r#"<text x="{}" y="{}" class="header-text">{}</text>"#,
+        half_width,
+        header_height / 2 + 16,
+        labels.compare_title
+    )
+    .map_err(fmt_err)?;
+    write!(
+        svg,
+        r##"<line x1="20" y1="{header_height}" x2="{}" y2="{header_height}" stroke="{line_color}" stroke-width="2" />"##,
+        width - 20
+    )
+    .map_err(fmt_err)?;
+    write!(
+        svg,
+        r##"<line x1="{half_width}" y1="{}" x2="{half_width}" y2="{}" stroke="{line_color}" stroke-width="2" />"##,
+        header_height + 20,
+        total_height - footer_height - 10
+    )
+    .map_err(fmt_err)?;
+
+    for (index, side) in [&data.left, &data.right].into_iter().enumerate() {
+        let x = index as i32 * half_width + 40;
+        let top = header_height;
+
+        let Some(player) = side else {
+            write!(
+                svg,
+                r#"<text x="{x}" y="{}" class="error-title">{}</text>"#,
+                top + 60,
+                labels.load_failed
+            )
+            .map_err(fmt_err)?;
+            continue;
+        };
+
+        write!(
+            svg,
+            r#"<text x="{x}" y="{}" class="name-text">{}</text>"#,
+            top + 50,
+            escape_xml(&ellipsize(&player.player_name, 18))
+        )
+        .map_err(fmt_err)?;
+        write!(
+            svg,
+            r#"<text x="{x}" y="{}" class="rks-text">{:.4}</text>"#,
+            top + 115,
+            player.rks
+        )
+        .map_err(fmt_err)?;
+
+        let fmt_avg = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |v| format!("{v:.4}"));
+        write!(
+            svg,
+            r#"<text x="{x}" y="{}" class="stat-text">{}　{}</text>"#,
+            top + 155,
+            image_labels::fill(labels.best_27_avg, &fmt_avg(player.best_27_avg)),
+            image_labels::fill(labels.ap_top_3_avg, &fmt_avg(player.ap_top_3_avg))
+        )
+        .map_err(fmt_err)?;
+
+        for (rank, score) in player.top_scores.iter().enumerate() {
+            let row_top = top + stats_height + rank as i32 * row_height;
+            write!(
+                svg,
+                r#"<text x="{x}" y="{}" class="rank-text">#{}</text>"#,
+                row_top + 26,
+                rank + 1
+            )
+            .map_err(fmt_err)?;
+            write!(
+                svg,
+                r#"<text x="{}" y="{}" class="song-text">{}</text>"#,
+                x + 50,
+                row_top + 26,
+                escape_xml(&ellipsize(&score.song_name, 20))
+            )
+            .map_err(fmt_err)?;
+            write!(
+                svg,
+                r#"<text x="{}" y="{}" class="detail-text">{} {:.1}　ACC {}%　RKS {:.4}</text>"#,
+                x + 50,
+                row_top + 52,
+                score.difficulty,
+                score.difficulty_value,
+                format_acc(score.acc, DEFAULT_ACC_DECIMALS),
+                score.rks
+            )
+            .map_err(fmt_err)?;
+        }
+    }
+
+    let time_str = data.update_time.format("%Y-%m-%d %H:%M:%S").to_string();
+    write!(
+        svg,
+        r#"<text x="{}" y="{}" class="footer-text">{}</text>"#,
+        width - 40,
+        total_height - 18,
+        image_labels::fill(labels.rendered_at, &time_str)
+    )
+    .map_err(fmt_err)?;
+
+    svg.push_str("</svg>");
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn song_name_compression_follows_configured_char_widths() {
+        // 卡片中曲名的可用宽度约 300px
+        let available = 300.0;
+        let latin = "Dignity of the Unbreakable"; // 26 个半角字符
+        let cjk = "光と影の物語は終わらない"; // 12 个全角字符
+
+        // 内置字体的默认估值下两者都放得下
+        assert!(estimate_song_name_width(latin, 19.0, 10.5) <= available);
+        assert!(estimate_song_name_width(cjk, 19.0, 10.5) <= available);
+
+        // 换用较宽的拉丁字体后，只有英文曲名需要压缩
+        assert!(estimate_song_name_width(latin, 19.0, 12.5) > available);
+        assert!(estimate_song_name_width(cjk, 19.0, 12.5) <= available);
+
+        // 换用较宽的 CJK 字体后，只有中日文曲名需要压缩
+        assert!(estimate_song_name_width(latin, 26.0, 10.5) <= available);
+        assert!(estimate_song_name_width(cjk, 26.0, 10.5) > available);
+    }
+
+    #[test]
+    fn inverse_color_is_computed_once_per_background() {
+        let path = std::env::temp_dir().join(format!("phi-inverse-bg-{}.png", std::process::id()));
+        image::RgbImage::from_pixel(8, 8, image::Rgb([255, 255, 255]))
+            .save(&path)
+            .unwrap();
+        let first = get_inverse_color_from_path_cached(&path);
+        assert!(first.is_some());
+
+        // 文件被删除后仍返回缓存的颜色，说明第二次渲染没有重新打开文件
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(get_inverse_color_from_path_cached(&path), first);
+    }
+
+    #[test]
+    fn undecodable_background_is_cached_as_missing() {
+        let path = std::env::temp_dir().join(format!("phi-broken-bg-{}.png", std::process::id()));
+        std::fs::write(&path, b"not an image").unwrap();
+        assert_eq!(get_inverse_color_from_path_cached(&path), None);
+
+        // 解码失败的结果同样被缓存，换成有效图片后也不会重新扫描
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+        assert_eq!(get_inverse_color_from_path_cached(&path), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn square_cover_uses_its_real_aspect_ratio() {
+        let path =
+            std::env::temp_dir().join(format!("phi-square-cover-{}.png", std::process::id()));
+        image::RgbImage::new(64, 64).save(&path).unwrap();
+        let ratio = illustration_aspect_ratio(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ratio, 1.0);
+    }
+
+    #[test]
+    fn missing_cover_falls_back_to_default_ratio() {
+        assert_eq!(illustration_aspect_ratio(None), SONG_ILLUST_ASPECT_RATIO);
+        let missing = std::env::temp_dir().join("phi-missing-cover.png");
+        assert_eq!(
+            illustration_aspect_ratio(Some(&missing)),
+            SONG_ILLUST_ASPECT_RATIO
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use aes::Aes256;
+use base64::{engine::general_purpose, Engine as _};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use cbc::{Decryptor, Encryptor};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::config::CONFIG;
+use crate::models::user::IdentifierRequest;
+use crate::utils::error::{AppError, AppResult};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名链接默认有效期（秒）
+pub const DEFAULT_EXPIRES_IN: u64 = 3600;
+/// 签名链接允许的最长有效期（秒），即 7 天
+pub const MAX_EXPIRES_IN: u64 = 7 * 24 * 3600;
+
+/// 读取签名密钥，未配置 `IMAGE_SIGNING_SECRET` 时签名相关接口不可用
+pub fn signing_secret() -> AppResult<&'static str> {
+    CONFIG.image_signing_secret.as_deref().ok_or_else(|| {
+        AppError::Forbidden("图片签名接口未启用，请配置 IMAGE_SIGNING_SECRET".to_string())
+    })
+}
+
+/// 将身份信息加密为可放入 URL 的不透明字符串
+///
+/// 链接会出现在网页源码与聊天记录中，因此令牌等字段不能以明文出现在查询参数里。
+/// 格式为 base64url(随机 IV || AES-256-CBC 密文)，密钥由签名密钥派生。
+pub fn seal_identifier(secret: &str, identifier: &IdentifierRequest) -> AppResult<String> {
+    let plaintext = serde_json::to_vec(identifier)
+        .map_err(|e| AppError::InternalError(format!("序列化身份信息失败: {e}")))?;
+    let mut iv = [0u8; 16];
+    rand::rng().fill(&mut iv);
+
+    let cipher = Encryptor::<Aes256>::new_from_slices(&encryption_key(secret), &iv)
+        .map_err(|e| AppError::AesError(format!("AES加密器初始化失败: {e}")))?;
+    let mut sealed = iv.to_vec();
+    sealed.extend(cipher.encrypt_padded_vec_mut::<Pkcs7>(&plaintext));
+    Ok(general_purpose::URL_SAFE_NO_PAD.encode(sealed))
+}
+
+/// 解密 [`seal_identifier`] 生成的身份信息，调用前须已通过签名校验
+pub fn open_identifier(secret: &str, sealed: &str) -> AppResult<IdentifierRequest> {
+    let invalid = || AppError::AuthError("签名链接中的身份信息无效".to_string());
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(sealed)
+        .map_err(|_| invalid())?;
+    if bytes.len() <= 16 {
+        return Err(invalid());
+    }
+    let (iv, ciphertext) = bytes.split_at(16);
+
+    let cipher = Decryptor::<Aes256>::new_from_slices(&encryption_key(secret), iv)
+        .map_err(|e| AppError::AesError(format!("AES解密器初始化失败: {e}")))?;
+    let plaintext = cipher
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|_| invalid())?;
+    serde_json::from_slice(&plaintext).map_err(|_| invalid())
+}
+
+/// 计算 Best N 图片链接的签名（十六进制 HMAC-SHA256）
+///
+/// 签名覆盖身份信息、N、主题与过期时间，任一参数被改动都会导致校验失败。
+pub fn sign_bn(secret: &str, sealed_identifier: &str, n: u32, theme: &str, expires: i64) -> String {
+    hex::encode(
+        bn_mac(secret, sealed_identifier, n, theme, expires)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// 校验 Best N 图片链接：先检查是否过期，再以定长比较校验签名
+pub fn verify_bn(
+    secret: &str,
+    sealed_identifier: &str,
+    n: u32,
+    theme: &str,
+    expires: i64,
+    sig: &str,
+) -> AppResult<()> {
+    if expires < chrono::Utc::now().timestamp() {
+        return Err(AppError::AuthError("签名链接已过期".to_string()));
+    }
+    let sig = hex::decode(sig).map_err(|_| AppError::AuthError("签名无效".to_string()))?;
+    bn_mac(secret, sealed_identifier, n, theme, expires)
+        .verify_slice(&sig)
+        .map_err(|_| AppError::AuthError("签名无效".to_string()))
+}
+
+fn bn_mac(secret: &str, sealed_identifier: &str, n: u32, theme: &str, expires: i64) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC 可以接受任意长度的密钥");
+    // 以换行分隔各字段，身份信息为 base64url 且主题不含换行，拼接结果不会产生歧义
+    mac.update(format!("bn\n{n}\n{theme}\n{expires}\n{sealed_identifier}").as_bytes());
+    mac
+}
+
+// 加密与签名使用不同的密钥，避免同一密钥用于两种用途
+fn encryption_key(secret: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"phi-backend image link identifier\n");
+    hasher.update(secret.as_bytes());
+    hasher.finalize().into()
+}
@@ -1,12 +1,23 @@
+pub mod admin_auth;
 pub mod aes_decrypt;
+pub mod bn_text;
+pub mod circuit_breaker;
 pub mod config;
 pub mod cover_loader;
 pub mod crypto;
 pub mod data_loader;
 pub mod error;
+pub mod grade;
+pub mod image_labels;
 pub mod image_renderer;
+pub mod image_signing;
+pub mod render_pool;
+pub mod request_id;
 pub mod rks_utils;
+pub mod rks_webhook;
 pub mod save_parser;
+pub mod schema;
+pub mod text;
 pub mod token_helper;
 
 // Remove unused re-exports
@@ -0,0 +1,61 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tokio::sync::oneshot;
+
+/// 图片渲染专用的线程池
+///
+/// resvg 渲染与 PNG/WebP 编码耗时较长，若放在 Actix 的阻塞线程池 (`web::block`) 中执行，
+/// 渲染高峰会占满该线程池，使其他阻塞任务排队等待。渲染任务统一在此线程池中执行，
+/// 同时执行的渲染数仍由 `ImageService` 的渲染信号量限制。
+static RENDER_POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+/// 渲染任务在返回结果前异常退出 (发生 panic)
+#[derive(Debug)]
+pub struct RenderTaskAborted;
+
+impl fmt::Display for RenderTaskAborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "渲染线程异常退出")
+    }
+}
+
+/// 按指定线程数创建渲染线程池，返回实际的线程数；只在启动时调用一次，重复调用不生效
+pub fn init(threads: usize) -> usize {
+    RENDER_POOL
+        .get_or_init(|| build(threads))
+        .current_num_threads()
+}
+
+fn build(threads: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .thread_name(|index| format!("render-{index}"))
+        // 渲染任务 panic 时只记录日志，调用方会收到 RenderTaskAborted，而不是终止进程
+        .panic_handler(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            log::error!("渲染任务 panic: {message}");
+        })
+        .build()
+        .expect("创建渲染线程池失败")
+}
+
+/// 在渲染线程池中执行任务并等待结果，未调用 `init` 时按 CPU 核心数创建线程池
+pub async fn run<F, R>(task: F) -> Result<R, RenderTaskAborted>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    RENDER_POOL
+        .get_or_init(|| build(num_cpus::get()))
+        .spawn(move || {
+            let _ = tx.send(task());
+        });
+    rx.await.map_err(|_| RenderTaskAborted)
+}
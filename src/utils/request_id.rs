@@ -0,0 +1,81 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use std::io::Write;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// 客户端传入的请求ID最大长度，超过则重新生成
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    // 当前请求的追踪ID，仅在请求处理任务内可见
+    static REQUEST_ID: String;
+}
+
+/// 获取当前请求的追踪ID（不在请求上下文中时返回 None）
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// 在同步上下文（如阻塞渲染线程）中以指定请求ID执行闭包
+pub fn with_request_id<R>(request_id: Option<String>, f: impl FnOnce() -> R) -> R {
+    match request_id {
+        Some(id) => REQUEST_ID.sync_scope(id, f),
+        None => f(),
+    }
+}
+
+// 仅接受长度合理、由可见 ASCII 字符组成的请求ID，避免日志注入
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// 请求ID中间件：沿用客户端传入的 X-Request-Id，缺失时生成 UUID，
+/// 在请求处理期间写入 task-local 供日志使用，并回写到响应头。
+pub async fn request_id_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let mut res = REQUEST_ID.scope(request_id.clone(), next.call(req)).await?;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(res)
+}
+
+/// 带请求ID的日志格式，在默认格式的基础上追加 `[请求ID]`
+pub fn format_log_record(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    let level_style = buf.default_level_style(record.level());
+    let timestamp = buf.timestamp();
+    match current_request_id() {
+        Some(id) => writeln!(
+            buf,
+            "[{timestamp} {level_style}{:<5}{level_style:#} {}] [{id}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+        None => writeln!(
+            buf,
+            "[{timestamp} {level_style}{:<5}{level_style:#} {}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+    }
+}
@@ -1,4 +1,5 @@
-use crate::models::rks::RksRecord;
+use crate::config::CONFIG;
+use crate::models::rks::{RksRecord, RksTargetPlan, RksTargetStep};
 use once_cell::sync::Lazy;
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -33,10 +34,43 @@ pub fn calculate_player_rks_details(records: &[RksRecord]) -> (f64, f64) {
     (final_exact_rks, final_rounded_rks)
 }
 
+/// 成绩排名的比较规则：单曲 RKS 降序，相同时依次按 ACC 降序、歌曲 ID 升序、难度升序。
+///
+/// 存档中的成绩来自 HashMap，遍历顺序不固定；只按 RKS 排序时并列的谱面会在请求之间交换位置，
+/// 进而改变 Best N 的截断与图片中的顺序。参数依次为 (RKS, ACC, 歌曲 ID, 难度)。
+pub fn compare_rank(a: (f64, f64, &str, &str), b: (f64, f64, &str, &str)) -> Ordering {
+    b.0.total_cmp(&a.0)
+        .then_with(|| b.1.total_cmp(&a.1))
+        .then_with(|| a.2.cmp(b.2))
+        .then_with(|| a.3.cmp(b.3))
+}
+
+/// 按 [`compare_rank`] 比较两条成绩，可直接用于 `sort_by`
+pub fn compare_records(a: &RksRecord, b: &RksRecord) -> Ordering {
+    compare_rank(
+        (a.rks, a.acc, &a.song_id, &a.difficulty),
+        (b.rks, b.acc, &b.song_id, &b.difficulty),
+    )
+}
+
+/// 计入 RKS 的最低 ACC（由 RKS_MIN_ACC 配置，默认 70.0）。
+pub fn rks_min_acc() -> f64 {
+    CONFIG.rks_min_acc
+}
+
+/// 判断某个 ACC 是否满足计入 RKS 的条件。
+pub fn is_rks_eligible_acc(acc_percent: f64) -> bool {
+    acc_percent >= rks_min_acc()
+}
+
 /// 计算指定谱面的 RKS 值。
 pub fn calculate_chart_rks(acc_percent: f64, constant: f64) -> f64 {
-    // 逻辑不变，此函数是正确的。
-    if acc_percent < 70.0 {
+    chart_rks_with_min_acc(acc_percent, constant, rks_min_acc())
+}
+
+/// 按指定的最低 ACC 计算谱面的 RKS 值，ACC 低于 `min_acc` 时为 0
+fn chart_rks_with_min_acc(acc_percent: f64, constant: f64, min_acc: f64) -> f64 {
+    if acc_percent < min_acc {
         return 0.0;
     }
     let acc_factor = ((acc_percent - 55.0) / 45.0).powi(2); // 使用 .powi(2) 更高效
@@ -45,46 +79,162 @@ pub fn calculate_chart_rks(acc_percent: f64, constant: f64) -> f64 {
 
 // --- 推分 ACC 计算 ---
 
-/// 模拟计算将指定谱面提升到某个 ACC 后的精确 RKS 值。
-fn simulate_rks_increase_simplified(
-    target_chart_id_full: &str,
-    target_chart_constant: f64,
-    test_acc: f64,
-    all_sorted_records: &[RksRecord],
-) -> f64 {
-    let parts: Vec<&str> = target_chart_id_full.rsplitn(2, '-').collect();
-    if parts.len() != 2 {
-        return 0.0;
-    } // 格式错误
-    let (song_id, difficulty) = (parts[1], parts[0]);
+/// 推分模拟所需的 RKS 前沿：按 RKS 降序排列的前 28 条成绩与前 4 条 AP 成绩。
+///
+/// 模拟时最多移除目标谱面这一条记录，因此 B27 只会用到前 28 条、AP3 只会用到前 4 条，
+/// 每次模拟只需遍历这几十条记录，而不必复制并重新排序全部成绩。
+/// 同一玩家的所有谱面可以共用一个前沿，见 [`calculate_push_acc_batch`]。
+struct PushAccFrontier<'a> {
+    current_exact_rks: f64,
+    target_rks_threshold: f64,
+    top: Vec<&'a RksRecord>,
+    top_ap: Vec<&'a RksRecord>,
+}
 
-    // 1. 计算模拟后的谱面RKS
-    let simulated_chart_rks = calculate_chart_rks(test_acc, target_chart_constant);
+impl<'a> PushAccFrontier<'a> {
+    fn new(all_sorted_records: &'a [RksRecord]) -> Self {
+        let (current_exact_rks, _current_rounded_rks) =
+            calculate_player_rks_details(all_sorted_records);
+
+        // 目标精确 RKS 阈值：四舍五入后增加 0.01 所需达到的最小值
+        let target_rks_threshold = {
+            let third_decimal_ge_5 = (current_exact_rks * 1000.0) % 10.0 >= 5.0;
+            if third_decimal_ge_5 {
+                (current_exact_rks * 100.0).floor() / 100.0 + 0.015
+            } else {
+                (current_exact_rks * 100.0).floor() / 100.0 + 0.005
+            }
+        };
+
+        // 稳定排序，保证并列记录的先后顺序与逐条模拟时一致
+        let mut ranked: Vec<&RksRecord> = all_sorted_records.iter().collect();
+        ranked.sort_by(|a, b| compare_records(a, b));
+        let top_ap = ranked
+            .iter()
+            .filter(|r| r.acc >= 100.0)
+            .take(4)
+            .copied()
+            .collect();
+        ranked.truncate(28);
+
+        Self {
+            current_exact_rks,
+            target_rks_threshold,
+            top: ranked,
+            top_ap,
+        }
+    }
 
-    // 2. 创建一个新的、临时的记录列表进行模拟，避免复杂的逻辑判断
-    // 我们只关心RKS和是否为AP，所以用元组 (rks, is_ap) 来模拟，更轻量
-    let mut simulated_records: Vec<(f64, bool)> = all_sorted_records
-        .iter()
-        .filter(|r| !(r.song_id == song_id && r.difficulty == difficulty)) // 排除旧记录
-        .map(|r| (r.rks, r.acc >= 100.0))
-        .collect();
+    /// 模拟计算将指定谱面提升到某个 ACC 后的精确 RKS 值。
+    fn simulate(&self, song_id: &str, difficulty: &str, constant: f64, test_acc: f64) -> f64 {
+        let simulated_chart_rks = calculate_chart_rks(test_acc, constant);
+        let is_target = |r: &&&RksRecord| r.song_id == song_id && r.difficulty == difficulty;
 
-    // 3. 插入新记录
-    simulated_records.push((simulated_chart_rks, test_acc >= 100.0));
+        let b27_sum = top_k_sum_with(
+            self.top.iter().filter(|r| !is_target(r)).map(|r| r.rks),
+            Some(simulated_chart_rks),
+            27,
+        );
+        let ap3_sum = top_k_sum_with(
+            self.top_ap.iter().filter(|r| !is_target(r)).map(|r| r.rks),
+            (test_acc >= 100.0).then_some(simulated_chart_rks),
+            3,
+        );
 
-    // 4. 重新排序 (这是最关键的简化，虽然有排序开销，但比之前复杂的逻辑更不易出错且通常足够快)
-    simulated_records.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        (b27_sum + ap3_sum) / 30.0
+    }
 
-    // 5. 重新计算 B27 和 AP3
-    let b27_sum: f64 = simulated_records.iter().take(27).map(|(rks, _)| rks).sum();
-    let ap3_sum: f64 = simulated_records
-        .iter()
-        .filter(|(_, is_ap)| *is_ap)
-        .take(3)
-        .map(|(rks, _)| rks)
-        .sum();
+    /// 二分查找使总 RKS (四舍五入后) 增加 0.01 所需的最小 ACC。
+    ///
+    /// 无需推分或 ACC 100% 仍无法达到目标时返回 `None`，这类结果不进入缓存。
+    fn push_acc(
+        &self,
+        song_id: &str,
+        difficulty: &str,
+        constant: f64,
+        current_acc: f64,
+    ) -> Option<f64> {
+        if self.current_exact_rks >= self.target_rks_threshold {
+            log::debug!("无需推分，当前 RKS 已达标");
+            return None;
+        }
+
+        // 边界检查: 检查ACC 100%时是否能达到目标
+        if self.simulate(song_id, difficulty, constant, 100.0) < self.target_rks_threshold {
+            log::debug!("无法推分，ACC 100% 仍无法达到目标");
+            return None;
+        }
+
+        // 二分查找最小达标ACC - 基于精度动态决定迭代次数
+        let mut low = current_acc;
+        let mut high = 100.0;
+        log::debug!("开始二分查找推分ACC, 区间: [{low:.4}, {high:.4}]");
+
+        // 精度要求：xx.xxxxx% (1e-7)
+        const ACC_PRECISION: f64 = 1e-7; // 精度为0.00001%
+        const MAX_ITERATIONS: usize = 50; // 最大迭代次数，避免无限循环
+
+        let mut iteration = 0;
+        while (high - low) > ACC_PRECISION && iteration < MAX_ITERATIONS {
+            iteration += 1;
+            let mid = low + (high - low) / 2.0;
+            if self.simulate(song_id, difficulty, constant, mid) >= self.target_rks_threshold {
+                high = mid; // mid 满足条件，尝试更低的 acc
+            } else {
+                low = mid; // mid 不满足条件，需要更高的 acc
+            }
+
+            log::debug!(
+                "迭代 {iteration}: 区间 [{low:.8}, {high:.8}], 区间长度: {:.8}",
+                high - low
+            );
+        }
+
+        log::debug!(
+            "二分查找结束, 迭代次数: {iteration}, 最终区间长度: {:.8}, 结果 high = {high:.8}",
+            high - low
+        );
 
-    (b27_sum + ap3_sum) / 30.0
+        // 格式化结果，确保推分ACC大于当前ACC
+        let result_acc = high.max(current_acc);
+
+        let final_acc = if result_acc <= current_acc {
+            log::debug!(
+                "推分ACC计算结果({result_acc:.6})不大于当前ACC({current_acc:.6})，返回100.0"
+            );
+            100.0
+        } else {
+            // 向上取整到小数点后3位，平衡精度和性能
+            (result_acc * 1000.0).ceil() / 1000.0
+        };
+
+        Some(final_acc.min(100.0))
+    }
+}
+
+/// 按降序合并已排序的 `existing` 与新记录 `new` 后，求前 `k` 项之和。
+///
+/// 新记录排在与其 RKS 相同的已有记录之后，与稳定排序时追加到末尾的结果一致。
+fn top_k_sum_with(existing: impl Iterator<Item = f64>, new: Option<f64>, k: usize) -> f64 {
+    let mut pending = new;
+    let mut merged = Vec::with_capacity(k);
+    for rks in existing {
+        if merged.len() == k {
+            break;
+        }
+        if let Some(new_rks) = pending.filter(|new_rks| *new_rks > rks) {
+            merged.push(new_rks);
+            pending = None;
+            if merged.len() == k {
+                break;
+            }
+        }
+        merged.push(rks);
+    }
+    if let Some(new_rks) = pending.filter(|_| merged.len() < k) {
+        merged.push(new_rks);
+    }
+    merged.into_iter().sum()
 }
 
 /// 推分ACC计算结果缓存
@@ -93,7 +243,51 @@ static PUSH_ACC_CACHE: Lazy<std::sync::RwLock<HashMap<String, f64>>> =
 
 const PUSH_ACC_CACHE_SIZE: usize = 5000; // 缓存5000个推分ACC计算结果
 
+/// 推分ACC缓存的键：谱面 ID、玩家当前的精确 RKS 与该谱面的 ACC
+///
+/// 推分ACC取决于玩家的全部成绩，只以谱面 ID 为键会把一名玩家的结果返回给另一名玩家；
+/// 加入精确 RKS 后缓存按玩家的成绩区分，成绩变化后旧的结果也不会再被命中。
+fn push_acc_cache_key(chart_id: &str, current_exact_rks: f64, current_acc: f64) -> String {
+    format!(
+        "{chart_id}@{:x}@{:x}",
+        current_exact_rks.to_bits(),
+        current_acc.to_bits()
+    )
+}
+
+/// 读取缓存的推分ACC，缓存值已不大于当前ACC时视为失效并清除
+fn cached_push_acc(cache_key: &str, current_acc: f64) -> Option<f64> {
+    let cached_result = *PUSH_ACC_CACHE.read().unwrap().get(cache_key)?;
+    log::debug!("推分ACC缓存命中: {cache_key}");
+
+    if cached_result > current_acc {
+        Some(cached_result)
+    } else {
+        log::debug!("缓存的推分ACC({cached_result:.6})已不大于当前ACC({current_acc:.6})，清除缓存");
+        PUSH_ACC_CACHE.write().unwrap().remove(cache_key);
+        None
+    }
+}
+
+/// 缓存推分ACC，只有当推分ACC确实大于当前ACC时才缓存
+fn store_push_acc(cache_key: &str, result: f64, current_acc: f64) {
+    if result <= current_acc {
+        log::debug!("推分ACC({result:.6})不大于当前ACC({current_acc:.6})，不存入缓存");
+        return;
+    }
+    let mut cache = PUSH_ACC_CACHE.write().unwrap();
+    if cache.len() >= PUSH_ACC_CACHE_SIZE {
+        // 简单LRU：删除最旧的键
+        if let Some(first_key) = cache.keys().next().cloned() {
+            cache.remove(&first_key);
+        }
+    }
+    cache.insert(cache_key.to_string(), result);
+}
+
 /// (优化后) 计算指定谱面需要达到多少 ACC 才能使玩家总 RKS (四舍五入后) 增加 0.01
+///
+/// 需要为同一玩家的多张谱面计算时，请使用 [`calculate_push_acc_batch`]，避免重复构建 RKS 前沿。
 pub fn calculate_target_chart_push_acc(
     target_chart_id_full: &str,
     target_chart_constant: f64,
@@ -101,135 +295,545 @@ pub fn calculate_target_chart_push_acc(
 ) -> Option<f64> {
     log::debug!("开始计算推分ACC: 目标谱面={target_chart_id_full}");
 
-    // 1. 检查缓存
-    {
-        if let Some(cached_result) = PUSH_ACC_CACHE.read().unwrap().get(target_chart_id_full) {
-            log::debug!("推分ACC缓存命中: {target_chart_id_full}");
-
-            // 获取当前谱面的ACC，确保缓存的值仍然有效
-            let parts: Vec<&str> = target_chart_id_full.rsplitn(2, '-').collect();
-            if parts.len() == 2 {
-                let (song_id, difficulty) = (parts[1], parts[0]);
-                let current_acc = all_sorted_records
-                    .iter()
-                    .find(|r| r.song_id == song_id && r.difficulty == difficulty)
-                    .map_or(70.0, |r| r.acc);
-
-                // 验证缓存的值是否仍然有效
-                if *cached_result > current_acc {
-                    return Some(*cached_result);
-                } else {
-                    log::debug!("缓存的推分ACC({cached_result:.6})已不大于当前ACC({current_acc:.6})，清除缓存");
-                    // 清除无效缓存
-                    PUSH_ACC_CACHE.write().unwrap().remove(target_chart_id_full);
-                }
-            }
+    let Some((song_id, difficulty)) = target_chart_id_full.rsplit_once('-') else {
+        // 格式错误的谱面 ID 无法参与模拟，视为无法推分
+        return Some(100.0);
+    };
+
+    // 获取当前谱面的 ACC，未游玩时从计入 RKS 的最低 ACC 开始查找
+    let current_acc = all_sorted_records
+        .iter()
+        .find(|r| r.song_id == song_id && r.difficulty == difficulty)
+        .map_or(rks_min_acc(), |r| r.acc);
+
+    let frontier = PushAccFrontier::new(all_sorted_records);
+    let cache_key = push_acc_cache_key(
+        target_chart_id_full,
+        frontier.current_exact_rks,
+        current_acc,
+    );
+    if let Some(cached_result) = cached_push_acc(&cache_key, current_acc) {
+        return Some(cached_result);
+    }
+
+    match frontier.push_acc(song_id, difficulty, target_chart_constant, current_acc) {
+        Some(result) => {
+            store_push_acc(&cache_key, result, current_acc);
+            Some(result)
         }
+        None => Some(100.0),
     }
+}
 
-    // 2. 计算当前 RKS 详情
-    let (current_exact_rks, _current_rounded_rks) =
-        calculate_player_rks_details(all_sorted_records);
+/// 批量计算玩家所有谱面的推分ACC，结果与 `all_sorted_records` 按下标一一对应。
+///
+/// 与逐条调用 [`calculate_target_chart_push_acc`] 结果一致，但 RKS 前沿只构建一次，
+/// 每次模拟的开销与成绩总数无关。AP 或定数无效的谱面没有推分ACC，对应位置为 `None`。
+pub fn calculate_push_acc_batch(all_sorted_records: &[RksRecord]) -> Vec<Option<f64>> {
+    let frontier = PushAccFrontier::new(all_sorted_records);
 
-    // 3. 计算目标精确 RKS 阈值
-    let target_rks_threshold = {
-        let third_decimal_ge_5 = (current_exact_rks * 1000.0) % 10.0 >= 5.0;
-        if third_decimal_ge_5 {
-            (current_exact_rks * 100.0).floor() / 100.0 + 0.015
-        } else {
-            (current_exact_rks * 100.0).floor() / 100.0 + 0.005
+    all_sorted_records
+        .iter()
+        .map(|record| {
+            if record.acc >= 100.0 || record.difficulty_value <= 0.0 {
+                return None;
+            }
+            let cache_key = push_acc_cache_key(
+                &format!("{}-{}", record.song_id, record.difficulty),
+                frontier.current_exact_rks,
+                record.acc,
+            );
+            if let Some(cached_result) = cached_push_acc(&cache_key, record.acc) {
+                return Some(cached_result);
+            }
+            let result = frontier.push_acc(
+                &record.song_id,
+                &record.difficulty,
+                record.difficulty_value,
+                record.acc,
+            );
+            if let Some(result) = result {
+                store_push_acc(&cache_key, result, record.acc);
+            }
+            Some(result.unwrap_or(100.0))
+        })
+        .collect()
+}
+
+// --- 目标 RKS 规划 ---
+
+/// 目标 RKS 规划最多执行的推分次数，每次使总 RKS 至少增加 0.01
+const MAX_TARGET_PLAN_ITERATIONS: usize = 2000;
+
+/// 使单曲 RKS 达到 `chart_rks` 所需的 ACC ([`calculate_chart_rks`] 的反函数)，超过定数时为 `None`
+fn acc_for_chart_rks(chart_rks: f64, constant: f64) -> Option<f64> {
+    (chart_rks <= constant).then(|| 55.0 + 45.0 * (chart_rks.max(0.0) / constant).sqrt())
+}
+
+/// 选出规划中下一次提升的谱面，返回其在 `records` 中的下标与目标 ACC。
+///
+/// 优先选择推分ACC与当前 ACC 相差最小的谱面，相差相同时取排名靠前的。推分ACC需要二分查找，
+/// 因此先按下界从小到大逐个计算，下界已超过当前最优时停止：未 AP 时谱面对 B27 的贡献最多增加
+/// 其单曲 RKS 的增量，总 RKS 要增加 `d` 则单曲 RKS 至少要增加 `30 * d`。
+/// 若没有任何谱面能单独使总 RKS 增加 0.01，则改为将 AP 后总 RKS 提升最多的谱面推到 AP。
+fn next_target_push(records: &[RksRecord], frontier: &PushAccFrontier) -> Option<(usize, f64)> {
+    let required_chart_gain = (frontier.target_rks_threshold - frontier.current_exact_rks) * 30.0;
+    let mut candidates: Vec<(usize, f64)> = records
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.acc < 100.0 && r.difficulty_value > 0.0)
+        .map(|(index, r)| {
+            let min_acc =
+                acc_for_chart_rks(r.rks + required_chart_gain, r.difficulty_value).unwrap_or(100.0);
+            (index, min_acc - r.acc)
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+    // (下标, 推分ACC, 与当前 ACC 的差)
+    let mut best: Option<(usize, f64, f64)> = None;
+    for &(index, min_gap) in &candidates {
+        if best.is_some_and(|(_, _, best_gap)| min_gap > best_gap + 1e-9) {
+            break;
+        }
+        let r = &records[index];
+        let Some(push_acc) =
+            frontier.push_acc(&r.song_id, &r.difficulty, r.difficulty_value, r.acc)
+        else {
+            continue;
+        };
+        let gap = push_acc - r.acc;
+        let better = best.is_none_or(|(best_index, _, best_gap)| {
+            gap < best_gap || (gap == best_gap && index < best_index)
+        });
+        if better {
+            best = Some((index, push_acc, gap));
         }
+    }
+    if let Some((index, push_acc, _)) = best {
+        return Some((index, push_acc));
+    }
+
+    candidates
+        .iter()
+        .map(|&(index, _)| {
+            let r = &records[index];
+            let gain = frontier.simulate(&r.song_id, &r.difficulty, r.difficulty_value, 100.0)
+                - frontier.current_exact_rks;
+            (index, gain)
+        })
+        .filter(|(_, gain)| *gain > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| (index, 100.0))
+}
+
+/// 规划从当前成绩达到目标 RKS (四舍五入后) 需要提升哪些谱面、提升到多少 ACC。
+///
+/// 反复进行推分模拟：每轮按 [`next_target_push`] 选出一张谱面提升到推分ACC，直到达到目标。
+/// 同一谱面的多次提升合并为一项。提升后的谱面只会在排名中前移，因此每轮只需将其移到新位置，
+/// 不必重新排序全部成绩。模拟中的成绩不是玩家的真实成绩，因此不读写推分ACC缓存。
+pub fn plan_target_rks(all_sorted_records: &[RksRecord], target_rks: f64) -> RksTargetPlan {
+    let (_, current_rks) = calculate_player_rks_details(all_sorted_records);
+    let reached = |rounded_rks: f64| rounded_rks + 1e-9 >= target_rks;
+
+    // 现有谱面全部 AP 时的 RKS 为可达上限
+    let mut all_ap: Vec<RksRecord> = all_sorted_records
+        .iter()
+        .filter(|r| r.difficulty_value > 0.0)
+        .cloned()
+        .map(|mut r| {
+            r.acc = 100.0;
+            r.rks = calculate_chart_rks(100.0, r.difficulty_value);
+            r
+        })
+        .collect();
+    all_ap.sort_by(compare_records);
+    let (_, max_rks) = calculate_player_rks_details(&all_ap);
+
+    let mut plan = RksTargetPlan {
+        current_rks,
+        target_rks,
+        max_rks,
+        planned_rks: current_rks,
+        reachable: reached(max_rks),
+        iteration_limit_reached: false,
+        steps: Vec::new(),
     };
+    if !plan.reachable || reached(current_rks) {
+        return plan;
+    }
 
-    if current_exact_rks >= target_rks_threshold {
-        log::debug!("无需推分，当前 RKS 已达标");
-        return Some(100.0);
+    let mut records = all_sorted_records.to_vec();
+    for iteration in 0.. {
+        if reached(plan.planned_rks) {
+            break;
+        }
+        if iteration == MAX_TARGET_PLAN_ITERATIONS {
+            log::warn!("目标 RKS 规划达到推分次数上限 {MAX_TARGET_PLAN_ITERATIONS}，停止规划");
+            plan.iteration_limit_reached = true;
+            break;
+        }
+
+        let frontier = PushAccFrontier::new(&records);
+        let Some((index, push_acc)) = next_target_push(&records, &frontier) else {
+            log::warn!("目标 RKS 规划没有可提升的谱面，停止规划");
+            break;
+        };
+
+        let mut record = records.remove(index);
+        let pushed_rks = calculate_chart_rks(push_acc, record.difficulty_value);
+        match plan
+            .steps
+            .iter_mut()
+            .find(|step| step.song_id == record.song_id && step.difficulty == record.difficulty)
+        {
+            Some(step) => {
+                step.target_acc = push_acc;
+                step.target_chart_rks = pushed_rks;
+            }
+            None => plan.steps.push(RksTargetStep {
+                song_id: record.song_id.clone(),
+                song_name: record.song_name.clone(),
+                difficulty: record.difficulty.clone(),
+                difficulty_value: record.difficulty_value,
+                acc: record.acc,
+                target_acc: push_acc,
+                rks: record.rks,
+                target_chart_rks: pushed_rks,
+            }),
+        }
+        record.acc = push_acc;
+        record.rks = pushed_rks;
+        let position =
+            records[..index].partition_point(|r| compare_records(r, &record) == Ordering::Less);
+        records.insert(position, record);
+
+        plan.planned_rks = calculate_player_rks_details(&records).1;
     }
 
-    // 4. 边界检查: 检查ACC 100%时是否能达到目标
-    let rks_at_100 = simulate_rks_increase_simplified(
-        target_chart_id_full,
-        target_chart_constant,
-        100.0,
-        all_sorted_records,
-    );
+    plan
+}
 
-    if rks_at_100 < target_rks_threshold {
-        log::debug!("无法推分，ACC 100% 仍无法达到目标");
-        return Some(100.0);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{parse_rks_min_acc, DEFAULT_RKS_MIN_ACC};
+
+    fn record(song_id: &str, difficulty_value: f64, acc: f64) -> RksRecord {
+        RksRecord {
+            song_id: song_id.to_string(),
+            song_name: song_id.to_string(),
+            difficulty: "IN".to_string(),
+            difficulty_value,
+            acc,
+            score: None,
+            rks: calculate_chart_rks(acc, difficulty_value),
+            is_fc: acc >= 100.0,
+        }
     }
 
-    // 5. 获取当前谱面的 ACC
-    let parts: Vec<&str> = target_chart_id_full.rsplitn(2, '-').collect();
-    if parts.len() != 2 {
-        return None;
+    /// 以固定种子生成的成绩，定数 1.0-16.9、ACC 70-100，约一成为 AP
+    fn generated_records(count: usize) -> Vec<RksRecord> {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64
+        };
+        let mut records: Vec<RksRecord> = (0..count)
+            .map(|i| {
+                let constant = 1.0 + (next() * 160.0).floor() / 10.0;
+                let acc = if next() < 0.1 {
+                    100.0
+                } else {
+                    70.0 + (next() * 30_000.0).floor() / 1000.0
+                };
+                record(&format!("song{i:04}"), constant, acc)
+            })
+            .collect();
+        records.sort_by(compare_records);
+        records
     }
-    let (song_id, difficulty) = (parts[1], parts[0]);
 
-    let current_acc = all_sorted_records
-        .iter()
-        .find(|r| r.song_id == song_id && r.difficulty == difficulty)
-        .map_or(70.0, |r| r.acc);
-
-    // 6. 二分查找最小达标ACC - 基于精度动态决定迭代次数
-    let mut low = current_acc;
-    let mut high = 100.0;
-    log::debug!("开始二分查找推分ACC, 区间: [{low:.4}, {high:.4}]");
-
-    // 精度要求：xx.xxxxx% (1e-7)
-    const ACC_PRECISION: f64 = 1e-7; // 精度为0.00001%
-    const MAX_ITERATIONS: usize = 50; // 最大迭代次数，避免无限循环
-
-    let mut iteration = 0;
-    while (high - low) > ACC_PRECISION && iteration < MAX_ITERATIONS {
-        iteration += 1;
-        let mid = low + (high - low) / 2.0;
-        let simulated_rks = simulate_rks_increase_simplified(
-            target_chart_id_full,
-            target_chart_constant,
-            mid,
-            all_sorted_records,
-        );
+    /// 优化前的规划：每轮对所有候选谱面计算推分ACC并重新排序全部成绩
+    fn reference_plan_steps(
+        all_sorted_records: &[RksRecord],
+        target_rks: f64,
+    ) -> Vec<(String, f64)> {
+        let mut steps: Vec<(String, f64)> = Vec::new();
+        let mut records = all_sorted_records.to_vec();
+        while calculate_player_rks_details(&records).1 + 1e-9 < target_rks {
+            let frontier = PushAccFrontier::new(&records);
+            let candidates = records
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| r.acc < 100.0 && r.difficulty_value > 0.0);
+            let next = candidates
+                .clone()
+                .filter_map(|(index, r)| {
+                    frontier
+                        .push_acc(&r.song_id, &r.difficulty, r.difficulty_value, r.acc)
+                        .map(|push_acc| (index, push_acc, push_acc - r.acc))
+                })
+                .min_by(|a, b| a.2.total_cmp(&b.2))
+                .map(|(index, push_acc, _)| (index, push_acc))
+                .or_else(|| {
+                    candidates
+                        .map(|(index, r)| {
+                            let gain = frontier.simulate(
+                                &r.song_id,
+                                &r.difficulty,
+                                r.difficulty_value,
+                                100.0,
+                            ) - frontier.current_exact_rks;
+                            (index, gain)
+                        })
+                        .filter(|(_, gain)| *gain > 0.0)
+                        .max_by(|a, b| a.1.total_cmp(&b.1))
+                        .map(|(index, _)| (index, 100.0))
+                });
+            let Some((index, push_acc)) = next else {
+                break;
+            };
+            let record = &mut records[index];
+            match steps.iter_mut().find(|(id, _)| *id == record.song_id) {
+                Some(step) => step.1 = push_acc,
+                None => steps.push((record.song_id.clone(), push_acc)),
+            }
+            record.acc = push_acc;
+            record.rks = calculate_chart_rks(push_acc, record.difficulty_value);
+            records.sort_by(compare_records);
+        }
+        steps
+    }
 
-        if simulated_rks >= target_rks_threshold {
-            high = mid; // mid 满足条件，尝试更低的 acc
+    /// 优化前的推分ACC：每次模拟都复制全部成绩、替换目标谱面并重新排序
+    fn reference_push_acc(all_sorted_records: &[RksRecord], index: usize) -> Option<f64> {
+        let target = &all_sorted_records[index];
+        let simulate = |test_acc: f64| {
+            let mut simulated: Vec<(f64, bool)> = all_sorted_records
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != index)
+                .map(|(_, r)| (r.rks, r.acc >= 100.0))
+                .chain([(
+                    calculate_chart_rks(test_acc, target.difficulty_value),
+                    test_acc >= 100.0,
+                )])
+                .collect();
+            simulated.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let b27: f64 = simulated.iter().take(27).map(|(rks, _)| rks).sum();
+            let ap3: f64 = simulated
+                .iter()
+                .filter(|(_, is_ap)| *is_ap)
+                .take(3)
+                .map(|(rks, _)| rks)
+                .sum();
+            (b27 + ap3) / 30.0
+        };
+
+        let (current_rks, _) = calculate_player_rks_details(all_sorted_records);
+        let threshold = if (current_rks * 1000.0) % 10.0 >= 5.0 {
+            (current_rks * 100.0).floor() / 100.0 + 0.015
         } else {
-            low = mid; // mid 不满足条件，需要更高的 acc
+            (current_rks * 100.0).floor() / 100.0 + 0.005
+        };
+        if current_rks >= threshold || simulate(100.0) < threshold {
+            return Some(100.0);
         }
 
-        log::debug!("迭代 {iteration}: 区间 [{low:.8}, {high:.8}], 区间长度: {:.8}", high - low);
+        let (mut low, mut high) = (target.acc, 100.0);
+        let mut iteration = 0;
+        while high - low > 1e-7 && iteration < 50 {
+            iteration += 1;
+            let mid = low + (high - low) / 2.0;
+            if simulate(mid) >= threshold {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        let result = high.max(target.acc);
+        Some(if result <= target.acc {
+            100.0
+        } else {
+            ((result * 1000.0).ceil() / 1000.0).min(100.0)
+        })
     }
 
-    log::debug!("二分查找结束, 迭代次数: {iteration}, 最终区间长度: {:.8}, 结果 high = {high:.8}", high - low);
+    #[test]
+    fn push_acc_batch_matches_per_chart_calculation_on_a_large_save() {
+        let records = generated_records(900);
+        let batch = calculate_push_acc_batch(&records);
+        assert_eq!(batch.len(), records.len());
+        // 确保比较的不只是无法推分时的 100.0
+        assert!(batch.iter().any(|p| matches!(p, Some(acc) if *acc < 100.0)));
+
+        for (index, (record, push_acc)) in records.iter().zip(&batch).enumerate() {
+            if record.acc >= 100.0 {
+                assert_eq!(*push_acc, None);
+                continue;
+            }
+            let key = format!("{}-{}", record.song_id, record.difficulty);
+            assert_eq!(
+                *push_acc,
+                calculate_target_chart_push_acc(&key, record.difficulty_value, &records),
+                "{key}"
+            );
+            // 优化前的实现每次都重新排序全部成绩，较慢，只抽查部分谱面
+            if index % 9 == 0 {
+                assert_eq!(*push_acc, reference_push_acc(&records, index), "{key}");
+            }
+        }
+    }
 
-    // 7. 格式化结果，确保推分ACC大于当前ACC
-    let result_acc = high.max(current_acc);
+    /// 对比优化前逐谱面重新排序的实现与批量计算在大存档上的耗时
+    ///
+    /// 运行：`cargo test --release push_acc_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn push_acc_benchmark() {
+        // 与其他测试的存档不同，避免命中推分ACC缓存
+        let records = generated_records(899);
+
+        let start = std::time::Instant::now();
+        let batch = calculate_push_acc_batch(&records);
+        let batch_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let reference: Vec<Option<f64>> = (0..records.len())
+            .map(|index| {
+                (records[index].acc < 100.0)
+                    .then(|| reference_push_acc(&records, index))
+                    .flatten()
+            })
+            .collect();
+        let reference_time = start.elapsed();
+
+        assert_eq!(batch, reference);
+        println!(
+            "{} 个谱面: 逐谱面计算 {reference_time:?}, 批量计算 {batch_time:?}",
+            records.len()
+        );
+    }
 
-    // 检查推分ACC是否真的大于当前ACC
-    let final_acc = if result_acc <= current_acc {
-        log::debug!("推分ACC计算结果({result_acc:.6})不大于当前ACC({current_acc:.6})，返回100.0");
-        100.0
-    } else {
-        // 向上取整到小数点后3位，平衡精度和性能
-        (result_acc * 1000.0).ceil() / 1000.0
-    };
+    #[test]
+    fn push_acc_cache_is_not_shared_between_players() {
+        // 两名玩家在同一谱面的 ACC 相同，其余成绩不同
+        let player = |constant: f64| {
+            let mut records: Vec<RksRecord> = (0..30)
+                .map(|i| record(&format!("other{i:02}"), constant, 99.0))
+                .chain([record("shared", 16.0, 95.0)])
+                .collect();
+            records.sort_by(compare_records);
+            records
+        };
+        let strong = player(15.0);
+        let weak = player(12.0);
+
+        let strong_push = calculate_target_chart_push_acc("shared-IN", 16.0, &strong);
+        let weak_push = calculate_target_chart_push_acc("shared-IN", 16.0, &weak);
+        assert_ne!(strong_push, weak_push);
+        let weak_index = weak.iter().position(|r| r.song_id == "shared").unwrap();
+        assert_eq!(calculate_push_acc_batch(&weak)[weak_index], weak_push);
+        // 再次计算时命中各自的缓存
+        assert_eq!(
+            calculate_target_chart_push_acc("shared-IN", 16.0, &strong),
+            strong_push
+        );
+    }
 
-    // 8. 存入缓存
-    let result = final_acc.min(100.0);
+    #[test]
+    fn target_plan_matches_unpruned_search() {
+        let records = generated_records(150);
+        let (_, current_rks) = calculate_player_rks_details(&records);
+        let target_rks = ((current_rks + 0.3) * 100.0).round() / 100.0;
+
+        let plan = plan_target_rks(&records, target_rks);
+        assert!(plan.reachable);
+        assert!(!plan.iteration_limit_reached);
+        assert!(plan.planned_rks + 1e-9 >= target_rks);
+        let steps: Vec<(String, f64)> = plan
+            .steps
+            .iter()
+            .map(|step| (step.song_id.clone(), step.target_acc))
+            .collect();
+        assert_eq!(steps, reference_plan_steps(&records, target_rks));
+    }
 
-    // 只有当推分ACC确实大于当前ACC时才缓存
-    if result > current_acc {
-        let mut cache = PUSH_ACC_CACHE.write().unwrap();
-        if cache.len() >= PUSH_ACC_CACHE_SIZE {
-            // 简单LRU：删除最旧的键
-            if let Some(first_key) = cache.keys().next().cloned() {
-                cache.remove(&first_key);
-            }
-        }
-        cache.insert(target_chart_id_full.to_string(), result);
-    } else {
-        log::debug!("推分ACC({result:.6})不大于当前ACC({current_acc:.6})，不存入缓存");
+    #[test]
+    fn target_plan_reports_unreachable_target_without_steps() {
+        let records = generated_records(40);
+        let plan = plan_target_rks(&records, 99.0);
+        assert!(!plan.reachable);
+        assert!(!plan.iteration_limit_reached);
+        assert!(plan.steps.is_empty());
+    }
+
+    #[test]
+    fn target_plan_to_max_rks_finishes_on_a_large_save() {
+        let records = generated_records(2000);
+        let (_, current_rks) = calculate_player_rks_details(&records);
+        let plan = plan_target_rks(&records, plan_target_rks(&records, 0.0).max_rks);
+        assert!(plan.reachable);
+        assert!(!plan.iteration_limit_reached);
+        assert!(plan.planned_rks >= plan.max_rks - 1e-9);
+        assert!(plan.planned_rks > current_rks);
     }
 
-    Some(result)
-}
\ No newline at end of file
+    #[test]
+    fn low_acc_is_excluded_at_default_and_included_when_lowered() {
+        assert_eq!(chart_rks_with_min_acc(69.0, 15.0, DEFAULT_RKS_MIN_ACC), 0.0);
+        let lowered = chart_rks_with_min_acc(69.0, 15.0, parse_rks_min_acc(Some("65")));
+        assert!((lowered - ((69.0 - 55.0) / 45.0f64).powi(2) * 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn min_acc_below_55_cannot_reward_lower_acc() {
+        let min_acc = parse_rks_min_acc(Some("30"));
+        assert_eq!(min_acc, 55.0);
+        assert_eq!(chart_rks_with_min_acc(40.0, 15.0, min_acc), 0.0);
+        assert!(
+            chart_rks_with_min_acc(60.0, 15.0, min_acc)
+                > chart_rks_with_min_acc(56.0, 15.0, min_acc)
+        );
+    }
+
+    #[test]
+    fn equal_rks_ties_are_ordered_by_acc_then_song_then_difficulty() {
+        let mut hd = record("b", 15.0, 99.0);
+        hd.difficulty = "HD".to_string();
+        // 定数与 ACC 不同但单曲 RKS 相同
+        let mut lower_acc = record("c", 16.0, 98.0);
+        lower_acc.rks = hd.rks;
+        let mut records = [
+            lower_acc.clone(),
+            record("b", 15.0, 99.0),
+            hd.clone(),
+            record("a", 15.0, 99.0),
+            record("z", 16.0, 100.0),
+        ];
+        records.sort_by(compare_records);
+
+        let order: Vec<(&str, &str)> = records
+            .iter()
+            .map(|r| (r.song_id.as_str(), r.difficulty.as_str()))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                ("z", "IN"),
+                ("a", "IN"),
+                ("b", "HD"),
+                ("b", "IN"),
+                ("c", "IN")
+            ]
+        );
+        // 输入顺序不影响结果
+        records.reverse();
+        records.sort_by(compare_records);
+        assert_eq!(records[1].song_id, "a");
+        assert_eq!(records[4].song_id, "c");
+    }
+}
@@ -0,0 +1,70 @@
+use crate::config::CONFIG;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::time::Duration;
+
+/// 单次请求超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// 最大尝试次数 (含首次请求)
+const WEBHOOK_MAX_ATTEMPTS: u32 = 3;
+/// 重试间隔基数，第 n 次重试等待 n 倍
+const WEBHOOK_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+static WEBHOOK_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+});
+
+/// RKS 变化通知的请求体
+#[derive(Debug, Clone, Serialize)]
+pub struct RksChangePayload {
+    pub player_id: String,
+    pub player_name: String,
+    pub old_rks: f64,
+    pub new_rks: f64,
+}
+
+/// 若配置了 RKS_WEBHOOK_URL 且变化量超过 RKS_WEBHOOK_DELTA，则在后台推送通知。
+/// 推送失败只记录日志，不影响调用方。
+pub fn notify_rks_change(payload: RksChangePayload) {
+    let Some(url) = CONFIG.rks_webhook_url.clone() else {
+        return;
+    };
+    if (payload.new_rks - payload.old_rks).abs() <= CONFIG.rks_webhook_delta {
+        return;
+    }
+
+    tokio::spawn(async move {
+        for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+            match WEBHOOK_CLIENT.post(&url).json(&payload).send().await {
+                Ok(resp) if resp.status().is_success() => {
+                    log::debug!(
+                        "玩家[{}] RKS 变化通知已推送: {:.4} -> {:.4}",
+                        payload.player_id,
+                        payload.old_rks,
+                        payload.new_rks
+                    );
+                    return;
+                }
+                Ok(resp) => log::warn!(
+                    "玩家[{}] RKS 变化通知推送失败 (第 {attempt} 次): HTTP {}",
+                    payload.player_id,
+                    resp.status()
+                ),
+                Err(e) => log::warn!(
+                    "玩家[{}] RKS 变化通知推送失败 (第 {attempt} 次): {e}",
+                    payload.player_id
+                ),
+            }
+            if attempt < WEBHOOK_MAX_ATTEMPTS {
+                tokio::time::sleep(WEBHOOK_RETRY_BACKOFF * attempt).await;
+            }
+        }
+        log::error!(
+            "玩家[{}] RKS 变化通知在 {WEBHOOK_MAX_ATTEMPTS} 次尝试后仍未送达，已放弃",
+            payload.player_id
+        );
+    });
+}
@@ -8,9 +8,14 @@ use zip::ZipArchive;
 use crate::models::b30::{B30Record, B30Result};
 use crate::models::rks::{RksRecord, RksResult};
 use crate::models::save::{GameSave, SaveSummary, SongRecord};
+use crate::models::stats::DataAmount;
 use crate::utils::crypto::{decrypt, validate_session_token};
-use crate::utils::data_loader::{get_difficulty_by_id, get_song_name_by_id};
+use crate::utils::data_loader::{get_difficulty_by_id, get_song_id_by_name, get_song_name_by_id};
 use crate::utils::error::{AppError, AppResult};
+use crate::utils::rks_utils::{self, is_rks_eligible_acc};
+
+/// 存档中的难度名称，顺序与存档中难度的位序一致
+const DIFFICULTY_NAMES: [&str; 5] = ["EZ", "HD", "IN", "AT", "Legacy"];
 
 // BinaryReader and other functions remain the same...
 // (The rest of the file content is omitted for brevity as it doesn't need changes)
@@ -173,13 +178,26 @@ impl<'a> BinaryReader<'a> {
         Ok(all_keys)
     }
 
+    /// 解析 gameRecord 二进制数据
+    ///
+    /// 数据布局（整数均为小端序）:
+    /// - `varint` 歌曲数量
+    /// - 每首歌曲:
+    ///   - `string` 歌曲ID（带 `.0` 后缀或 `Lv` 等级后缀）
+    ///   - `varint` 该歌曲记录的字节长度（用于校验解析位置）
+    ///   - `u8` unlock: 第 i 位表示难度 i 存在记录，i 依次为 EZ/HD/IN/AT/Legacy (0..=4)
+    ///   - `u8` fc_flags: 第 i 位表示难度 i 已 Full Combo（AP 同样会置位）
+    ///   - 对 unlock 中置位的每个难度按顺序: `u32` 分数, `f32` ACC
+    ///
+    /// 例如 `unlock = 0b0001_0100`, `fc_flags = 0b0000_0100` 表示 IN 与 Legacy 有记录，
+    /// 其中 IN 已 FC，随后依次为 IN 的 (分数, ACC) 与 Legacy 的 (分数, ACC)。
     fn read_game_record_aligned(
         &mut self,
     ) -> AppResult<HashMap<String, HashMap<String, SongRecord>>> {
         log::debug!("进入 read_game_record_aligned");
         self.reset_bit_reading();
 
-        let diff_list = ["EZ", "HD", "IN", "AT", "Legacy"];
+        let diff_list = DIFFICULTY_NAMES;
         let mut all_records = HashMap::new();
 
         let song_count = self.read_var_int_aligned()?;
@@ -216,8 +234,11 @@ impl<'a> BinaryReader<'a> {
                     log::trace!("GameRecord: 歌曲 '{song_id}', 难度 '{diff_name}', 读取到 score = {score}, acc = {acc}");
 
                     let is_fc_or_ap = ((fc_flags >> level_index) & 1) != 0;
-                    let is_ap = score == 1000000;
-                    let is_fc = is_fc_or_ap && !is_ap;
+                    let (is_fc, is_ap) = classify_fc_ap(is_fc_or_ap, score, acc);
+                    if is_ap && !is_fc_or_ap {
+                        // 旧版本（含 Legacy 难度）的记录可能未置 FC 位，以分数/ACC 为准
+                        log::debug!("GameRecord: 歌曲 '{song_id}', 难度 '{diff_name}' 为 AP 但未设置 FC 位，已按 AP 处理");
+                    }
                     log::trace!("GameRecord: 歌曲 '{song_id}', 难度 '{diff_name}', is_fc_or_ap = {is_fc_or_ap}, is_ap = {is_ap}, is_fc = {is_fc}");
 
                     let record = SongRecord {
@@ -226,6 +247,8 @@ impl<'a> BinaryReader<'a> {
                         fc: Some(is_fc),
                         difficulty: None,
                         rks: None,
+                        song_name: None,
+                        predicted_constant: None,
                     };
 
                     difficulties.insert(diff_name.to_string(), record);
@@ -265,6 +288,93 @@ impl<'a> BinaryReader<'a> {
     }
 }
 
+/// 根据 FC 位、分数与 ACC 判定 (is_fc, is_ap)
+/// - AP: 分数为 1000000，或 ACC 达到 100%（部分历史记录的分数格式不同，仅靠分数会误判）
+/// - FC: 设置了 FC 位且不是 AP（AP 单独由 ACC 体现，与原有语义保持一致）
+fn classify_fc_ap(fc_bit: bool, score: u32, acc: f32) -> (bool, bool) {
+    let is_ap = is_ap(score as f64, acc as f64);
+    (fc_bit && !is_ap, is_ap)
+}
+
+/// 按分数与 ACC 判定是否为 AP，与存档解析时的判定规则一致
+pub fn is_ap(score: f64, acc: f64) -> bool {
+    score >= 1_000_000.0 || acc >= 100.0
+}
+
+/// 读取玩家在游戏中选择的背景 (user 中的 background)，返回对应的歌曲ID
+///
+/// 存档中记录的是曲目名称，也兼容直接记录歌曲ID的情况；未设置或无法对应到已收录的曲目时返回 `None`。
+pub fn player_background_song_id(user: &HashMap<String, Value>) -> Option<String> {
+    let background = user.get("background")?.as_str()?.trim();
+    if background.is_empty() {
+        return None;
+    }
+    if get_song_name_by_id(background).is_some() {
+        return Some(background.to_string());
+    }
+    get_song_id_by_name(background)
+}
+
+/// 解析 gameProgress 中的课题模式等级 (challengeModeRank)
+///
+/// 数值的首位表示颜色 (1 绿、2 蓝、3 红、4 金、5 彩)，其余位为等级，例如 542 为 Rainbow 42。
+/// 未获得课题等级或数值无法识别时返回 `None`。
+pub fn decode_challenge_rank(
+    game_progress: &HashMap<String, Value>,
+) -> Option<(&'static str, u32)> {
+    let rank_num = game_progress.get("challengeModeRank")?.as_i64()?;
+    if rank_num <= 0 {
+        return None;
+    }
+    let rank_str = rank_num.to_string();
+    let (color_char, level_str) = rank_str.split_at(1);
+    let color = match color_char {
+        "1" => "Green",
+        "2" => "Blue",
+        "3" => "Red",
+        "4" => "Gold",
+        "5" => "Rainbow",
+        _ => return None,
+    };
+    Some((color, level_str.parse().ok()?))
+}
+
+/// 解析 gameProgress 中的 Data 数量 (money)
+///
+/// money 为 [KB, MB, GB, TB, PB] 五个计数，缺少的单位按 0 处理。存档中没有 money 字段时返回 `None`。
+pub fn decode_data_amount(game_progress: &HashMap<String, Value>) -> Option<DataAmount> {
+    let money = game_progress.get("money")?.as_array()?;
+    let unit = |index: usize| money.get(index).and_then(Value::as_u64).unwrap_or(0);
+    let (kb, mb, gb, tb, pb) = (unit(0), unit(1), unit(2), unit(3), unit(4));
+
+    let parts: Vec<String> = [(pb, "PB"), (tb, "TB"), (gb, "GB"), (mb, "MB"), (kb, "KB")]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{count} {unit}"))
+        .collect();
+    let display = (!parts.is_empty()).then(|| parts.join(", "));
+
+    Some(DataAmount {
+        kb,
+        mb,
+        gb,
+        tb,
+        pb,
+        display,
+    })
+}
+
+/// 将外部传入的难度名称规范化为存档中的写法 (EZ, HD, IN, AT, Legacy)
+///
+/// 忽略大小写与首尾空白；无法识别的名称去除空白后原样返回，由调用方校验。
+pub fn normalize_difficulty(difficulty: &str) -> String {
+    let trimmed = difficulty.trim();
+    DIFFICULTY_NAMES
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(trimmed))
+        .map_or_else(|| trimmed.to_string(), |name| name.to_string())
+}
+
 pub fn check_session_token(token: &str) -> AppResult<()> {
     if !validate_session_token(token) {
         return Err(AppError::InvalidSessionToken);
@@ -478,31 +588,38 @@ fn parse_settings01(reader: &mut BinaryReader) -> AppResult<HashMap<String, Valu
     );
     map.insert(
         "bright".to_string(),
-        Value::Number(serde_json::Number::from_f64(reader.read_float_aligned()?.into()).unwrap()),
+        float_value(reader.read_float_aligned()?),
     );
     map.insert(
         "musicVolume".to_string(),
-        Value::Number(serde_json::Number::from_f64(reader.read_float_aligned()?.into()).unwrap()),
+        float_value(reader.read_float_aligned()?),
     );
     map.insert(
         "effectVolume".to_string(),
-        Value::Number(serde_json::Number::from_f64(reader.read_float_aligned()?.into()).unwrap()),
+        float_value(reader.read_float_aligned()?),
     );
     map.insert(
         "hitSoundVolume".to_string(),
-        Value::Number(serde_json::Number::from_f64(reader.read_float_aligned()?.into()).unwrap()),
+        float_value(reader.read_float_aligned()?),
     );
     map.insert(
         "soundOffset".to_string(),
-        Value::Number(serde_json::Number::from_f64(reader.read_float_aligned()?.into()).unwrap()),
+        float_value(reader.read_float_aligned()?),
     );
     map.insert(
         "noteScale".to_string(),
-        Value::Number(serde_json::Number::from_f64(reader.read_float_aligned()?.into()).unwrap()),
+        float_value(reader.read_float_aligned()?),
     );
     Ok(map)
 }
 
+// NaN 等无法用 JSON 表示的浮点数记为 null，避免异常存档导致解析 panic
+fn float_value(value: f32) -> Value {
+    serde_json::Number::from_f64(value.into())
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
 fn parse_game_key02(reader: &mut BinaryReader) -> AppResult<HashMap<String, Value>> {
     let mut map = HashMap::new();
     map.insert(
@@ -651,7 +768,7 @@ pub fn parse_save_with_difficulty(save_data: &[u8]) -> AppResult<GameSave> {
                     if difficulty > 0.0 {
                         if let Some(acc) = record.acc {
                             log::trace!("    有 ACC: {acc}, 定数: {difficulty}, 准备计算 RKS");
-                            if acc >= 70.0 {
+                            if is_rks_eligible_acc(acc) {
                                 let rks =
                                     crate::utils::rks_utils::calculate_chart_rks(acc, difficulty);
                                 log::trace!("    计算得到 RKS: {rks}");
@@ -674,6 +791,7 @@ pub fn parse_save_with_difficulty(save_data: &[u8]) -> AppResult<GameSave> {
                 }
             }
         }
+        crate::utils::data_loader::record_unknown_save_songs(game_record.keys());
         log::debug!("难度和 RKS 信息添加完成");
     } else {
         log::debug!("存档中没有 GameRecord 数据");
@@ -684,10 +802,7 @@ pub fn parse_save_with_difficulty(save_data: &[u8]) -> AppResult<GameSave> {
 
 #[allow(dead_code)]
 pub fn calculate_rks(save: &GameSave) -> AppResult<RksResult> {
-    let game_record = save
-        .game_record
-        .as_ref()
-        .ok_or_else(|| AppError::Other("没有游戏记录数据".to_string()))?;
+    let game_record = save.played_records()?;
 
     let mut rks_records = Vec::new();
 
@@ -696,7 +811,7 @@ pub fn calculate_rks(save: &GameSave) -> AppResult<RksResult> {
 
         for (diff_name, record) in difficulties {
             if let Some(difficulty) = get_difficulty_by_id(song_id, diff_name) {
-                if record.acc.unwrap_or(0.0) > 70.0 {
+                if is_rks_eligible_acc(record.acc.unwrap_or(0.0)) {
                     let rks_record = RksRecord::new(
                         song_id.clone(),
                         song_name.clone(),
@@ -714,19 +829,58 @@ pub fn calculate_rks(save: &GameSave) -> AppResult<RksResult> {
     Ok(RksResult::new(rks_records))
 }
 
-#[allow(dead_code)]
-pub fn get_summary_from_base64(summary_base64: &str) -> AppResult<SaveSummary> {
-    let _summary_data = general_purpose::STANDARD.decode(summary_base64)?;
-
-    Err(AppError::Other("摘要解析功能尚未完全实现".to_string()))
+/// 解析云端存档元数据 (saveInfo 中的 `results[0]`)，无需下载存档文件
+///
+/// 校验和、文件大小、下载地址与更新时间直接取自元数据；存档版本、课题等级、RKS、
+/// 游戏版本、头像与各难度统计解码自 base64 编码的 `summary` 字段。
+pub fn parse_save_summary(save_info: &Value) -> AppResult<SaveSummary> {
+    let field = |value: &Value, name: &str| {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AppError::Other(format!("存档元数据中缺少 {name}")))
+    };
+    let checksum = field(&save_info["gameFile"]["metaData"]["_checksum"], "校验和")?;
+    let update_at = field(&save_info["updatedAt"], "更新时间")?;
+    let url = field(&save_info["gameFile"]["url"], "存档URL")?;
+    let file_size = save_info["gameFile"]["metaData"]["size"].as_u64();
+
+    let summary_data =
+        general_purpose::STANDARD.decode(field(&save_info["summary"], "存档摘要")?)?;
+    let mut reader = BinaryReader::new(&summary_data);
+    let save_version = reader.read_byte_aligned()?;
+    let challenge = reader.read_short_int_aligned()?;
+    let rks = reader.read_float_aligned()?;
+    let game_version = reader.read_byte_aligned()?;
+    let avatar = reader.read_string_aligned()?;
+    let mut level_stats = [[0u16; 3]; 4];
+    for stats in &mut level_stats {
+        for count in stats.iter_mut() {
+            *count = reader.read_short_int_aligned()?;
+        }
+    }
+    let [ez, hd, inl, at] = level_stats;
+
+    Ok(SaveSummary {
+        checksum,
+        update_at,
+        url,
+        file_size,
+        save_version,
+        challenge,
+        rks,
+        game_version,
+        avatar,
+        ez,
+        hd,
+        inl,
+        at,
+    })
 }
 
 pub fn calculate_b30(save: &GameSave) -> AppResult<B30Result> {
     log::debug!("进入 calculate_b30 函数");
-    let game_record = save
-        .game_record
-        .as_ref()
-        .ok_or_else(|| AppError::Other("没有游戏记录数据".to_string()))?;
+    let game_record = save.played_records()?;
     log::debug!("B30: 获取到 GameRecord，包含 {} 首歌曲", game_record.len());
 
     log::debug!("B30: 开始收集有效成绩记录...");
@@ -737,7 +891,7 @@ pub fn calculate_b30(save: &GameSave) -> AppResult<B30Result> {
                 .iter() // 改为串行迭代
                 .filter_map(|(diff_name, record)| {
                     if let (Some(acc), Some(difficulty)) = (record.acc, record.difficulty) {
-                        if acc >= 70.0 && difficulty > 0.0 {
+                        if is_rks_eligible_acc(acc) && difficulty > 0.0 {
                             let rks = crate::utils::rks_utils::calculate_chart_rks(acc, difficulty);
                             let is_ap = record.score == Some(1_000_000.0);
                             Some(B30Record {
@@ -772,9 +926,20 @@ pub fn calculate_b30(save: &GameSave) -> AppResult<B30Result> {
 
     log::debug!("B30: 开始计算 Top 27...");
     all_played_records.sort_by(|a, b| {
-        b.rks
-            .partial_cmp(&a.rks)
-            .unwrap_or(std::cmp::Ordering::Equal)
+        rks_utils::compare_rank(
+            (
+                a.rks.unwrap_or(f64::NEG_INFINITY),
+                a.acc.unwrap_or(0.0),
+                &a.song_id,
+                &a.difficulty_str,
+            ),
+            (
+                b.rks.unwrap_or(f64::NEG_INFINITY),
+                b.acc.unwrap_or(0.0),
+                &b.song_id,
+                &b.difficulty_str,
+            ),
+        )
     });
     let top_27: Vec<B30Record> = all_played_records.iter().take(27).cloned().collect();
     log::debug!("B30: Top 27 实际数量: {}", top_27.len());
@@ -784,9 +949,20 @@ pub fn calculate_b30(save: &GameSave) -> AppResult<B30Result> {
         all_played_records.into_iter().filter(|r| r.is_ap).collect();
     log::debug!("B30: 找到 {} 条 AP 记录", ap_records.len());
     ap_records.sort_by(|a, b| {
-        b.rks
-            .partial_cmp(&a.rks)
-            .unwrap_or(std::cmp::Ordering::Equal)
+        rks_utils::compare_rank(
+            (
+                a.rks.unwrap_or(f64::NEG_INFINITY),
+                a.acc.unwrap_or(0.0),
+                &a.song_id,
+                &a.difficulty_str,
+            ),
+            (
+                b.rks.unwrap_or(f64::NEG_INFINITY),
+                b.acc.unwrap_or(0.0),
+                &b.song_id,
+                &b.difficulty_str,
+            ),
+        )
     });
     let top_3_ap: Vec<B30Record> = ap_records.into_iter().take(3).collect();
     log::debug!("B30: Top 3 AP 实际数量: {}", top_3_ap.len());
@@ -814,3 +990,132 @@ pub fn calculate_b30(save: &GameSave) -> AppResult<B30Result> {
         top_3_ap,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 gameRecord 的布局编码一首歌曲，`charts` 为按难度顺序排列的 (难度序号, 分数, ACC)
+    fn encode_song(buf: &mut Vec<u8>, song_id: &str, fc_flags: u8, charts: &[(u8, u32, f32)]) {
+        buf.push(song_id.len() as u8);
+        buf.extend_from_slice(song_id.as_bytes());
+        buf.push((2 + charts.len() * 8) as u8);
+        buf.push(
+            charts
+                .iter()
+                .fold(0, |unlock, (index, _, _)| unlock | 1 << index),
+        );
+        buf.push(fc_flags);
+        for (_, score, acc) in charts {
+            buf.extend_from_slice(&score.to_le_bytes());
+            buf.extend_from_slice(&acc.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn game_record_reads_fc_and_ap_from_bit_layout() {
+        let mut data = vec![2];
+        // IN 已 FC；Legacy 为 AP 但未置 FC 位
+        encode_song(
+            &mut data,
+            "Glaciaxion.SunsetRay.0",
+            0b0000_0100,
+            &[(2, 990_000, 99.5), (4, 1_000_000, 100.0)],
+        );
+        // EZ 为 AP 且置了 FC 位；HD 有记录但未 FC
+        encode_song(
+            &mut data,
+            "Credits.FrozenMoss.0",
+            0b0000_0001,
+            &[(0, 1_000_000, 100.0), (1, 950_000, 98.0)],
+        );
+
+        let records = BinaryReader::new(&data).read_game_record_aligned().unwrap();
+
+        let first = &records["Glaciaxion.SunsetRay"];
+        assert_eq!(first.len(), 2);
+        assert_eq!(first["IN"].score, Some(990_000.0));
+        assert_eq!(first["IN"].fc, Some(true));
+        assert_eq!(first["Legacy"].acc, Some(100.0));
+        assert_eq!(first["Legacy"].fc, Some(false));
+
+        let second = &records["Credits.FrozenMoss"];
+        assert_eq!(second["EZ"].fc, Some(false));
+        assert!(is_ap(
+            second["EZ"].score.unwrap(),
+            second["EZ"].acc.unwrap()
+        ));
+        assert_eq!(second["HD"].fc, Some(false));
+        assert!(!second.contains_key("IN"));
+    }
+
+    fn game_progress(money: Value) -> HashMap<String, Value> {
+        HashMap::from([("money".to_string(), money)])
+    }
+
+    #[test]
+    fn money_is_decoded_per_unit() {
+        let amount =
+            decode_data_amount(&game_progress(serde_json::json!([512, 0, 2, 0, 0]))).unwrap();
+        assert_eq!(
+            amount,
+            DataAmount {
+                kb: 512,
+                mb: 0,
+                gb: 2,
+                tb: 0,
+                pb: 0,
+                display: Some("2 GB, 512 KB".to_string()),
+            }
+        );
+
+        // 旧存档只有四个单位，缺少的按 0 处理
+        let amount = decode_data_amount(&game_progress(serde_json::json!([1, 2, 3, 4]))).unwrap();
+        assert_eq!((amount.tb, amount.pb), (4, 0));
+        assert_eq!(amount.display.as_deref(), Some("4 TB, 3 GB, 2 MB, 1 KB"));
+    }
+
+    #[test]
+    fn empty_money_has_no_display_text() {
+        let amount =
+            decode_data_amount(&game_progress(serde_json::json!([0, 0, 0, 0, 0]))).unwrap();
+        assert_eq!(amount, DataAmount::default());
+        assert_eq!(decode_data_amount(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn mixed_case_difficulties_are_normalized() {
+        for (input, expected) in [
+            ("in", "IN"),
+            ("In", "IN"),
+            ("IN", "IN"),
+            (" at ", "AT"),
+            ("eZ", "EZ"),
+            ("LEGACY", "Legacy"),
+        ] {
+            assert_eq!(normalize_difficulty(input), expected);
+        }
+        // 无法识别的名称只去除空白，由调用方校验
+        assert_eq!(normalize_difficulty(" SP "), "SP");
+    }
+
+    #[test]
+    fn ap_is_not_reported_as_fc() {
+        assert_eq!(classify_fc_ap(true, 1_000_000, 100.0), (false, true));
+        assert_eq!(classify_fc_ap(false, 999_999, 100.0), (false, true));
+        assert_eq!(classify_fc_ap(true, 995_000, 99.8), (true, false));
+        assert_eq!(classify_fc_ap(false, 995_000, 99.8), (false, false));
+    }
+
+    #[test]
+    fn b30_of_empty_save_is_empty_save() {
+        let save = GameSave {
+            game_key: None,
+            game_progress: None,
+            game_record: Some(HashMap::new()),
+            settings: None,
+            user: None,
+        };
+        assert!(matches!(calculate_b30(&save), Err(AppError::EmptySave)));
+    }
+}
@@ -0,0 +1,60 @@
+use sqlx::migrate::Migrator;
+use sqlx::SqlitePool;
+
+/// 编译进程序的数据库迁移
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// 程序期望的 schema 版本，即最新一个迁移的版本号
+pub fn expected_version() -> i64 {
+    MIGRATOR
+        .migrations
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+/// 数据库中已成功应用的最新迁移版本，从未运行过迁移时为 None
+pub async fn applied_version(pool: &SqlitePool) -> Result<Option<i64>, sqlx::Error> {
+    let has_table: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')",
+    )
+    .fetch_one(pool)
+    .await?;
+    if !has_table {
+        return Ok(None);
+    }
+    sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1")
+        .fetch_one(pool)
+        .await
+}
+
+/// 运行数据库迁移，并校验数据库与程序的 schema 版本一致，返回当前 schema 版本
+///
+/// 数据库已被更新版本的程序迁移过 (如回滚部署) 时不运行迁移，直接返回错误；
+/// 已应用的迁移会被跳过，重复启动不会重复执行。
+pub async fn migrate(pool: &SqlitePool) -> Result<i64, String> {
+    let expected = expected_version();
+    let read_version_error = |e: sqlx::Error| format!("无法读取数据库 schema 版本: {e}");
+
+    if let Some(applied) = applied_version(pool).await.map_err(read_version_error)? {
+        if applied > expected {
+            return Err(format!(
+                "数据库 schema 版本 {applied} 高于程序期望的 {expected}，数据库可能已被更新版本的程序迁移过，请部署匹配的程序版本"
+            ));
+        }
+    }
+
+    MIGRATOR.run(pool).await.map_err(|e| e.to_string())?;
+
+    let applied = applied_version(pool)
+        .await
+        .map_err(read_version_error)?
+        .unwrap_or(0);
+    if applied != expected {
+        return Err(format!(
+            "迁移后数据库 schema 版本为 {applied}，与程序期望的 {expected} 不一致"
+        ));
+    }
+    Ok(applied)
+}
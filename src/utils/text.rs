@@ -0,0 +1,51 @@
+use std::borrow::Cow;
+
+/// 截取字符串的前 `max_chars` 个字符，按字符边界截断，不足时原样返回
+///
+/// 用于日志中缩短校验和、歌曲ID等字段；直接按字节切片在字符串过短或含多字节字符时会 panic。
+pub fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// 按字符数截断用于图片展示的文本，超出 `max_chars` 时保留前 `max_chars - 1` 个字符并以省略号结尾
+pub fn ellipsize(s: &str, max_chars: usize) -> Cow<'_, str> {
+    if s.chars().nth(max_chars).is_none() {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!(
+            "{}…",
+            truncate_chars(s, max_chars.saturating_sub(1))
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_checksum_is_returned_unchanged() {
+        assert_eq!(truncate_chars("ab12f", 8), "ab12f");
+        assert_eq!(truncate_chars("unknown", 8), "unknown");
+        assert_eq!(truncate_chars("0123456789abcdef", 8), "01234567");
+    }
+
+    #[test]
+    fn multibyte_song_id_is_cut_on_char_boundary() {
+        let song_id = "光.姜米條";
+        assert_eq!(truncate_chars(song_id, 3), "光.姜");
+        assert_eq!(truncate_chars(song_id, 20), song_id);
+        assert_eq!(truncate_chars("", 8), "");
+    }
+
+    #[test]
+    fn ellipsize_keeps_the_limit_including_the_ellipsis() {
+        assert_eq!(ellipsize("Rrhar'il", 8), "Rrhar'il");
+        assert_eq!(ellipsize("Rrhar'il", 5), "Rrha…");
+        assert_eq!(ellipsize("光.姜米條", 4), "光.姜…");
+        assert_eq!(ellipsize("光.姜米條", 4).chars().count(), 4);
+    }
+}
@@ -1,8 +1,123 @@
+use crate::config::CONFIG;
 use crate::models::user::IdentifierRequest;
 use crate::services::user::UserService;
 use crate::utils::error::{AppError, AppResult};
+use crate::utils::save_parser::check_session_token;
+use actix_web::dev::ServiceRequest;
 use actix_web::web;
 
+/// 响应头：本次请求实际使用的数据源 (`internal` 或 `external`)
+///
+/// 内部 Token 失效并回退到外部数据源时为 `external`，客户端可据此提示用户重新绑定。
+pub const DATA_SOURCE_HEADER: &str = "X-Data-Source";
+
+/// Token 失效时给出的重新登录指引
+///
+/// 服务端只保存 LeanCloud 会话令牌，扫码登录时取得的 TapTap 凭证在换取会话令牌后即被丢弃，
+/// 且 LeanCloud 没有续期接口，因此会话失效后只能重新扫码登录。
+pub const REAUTH_HINT: &str = "请通过 /auth/qrcode 重新扫码登录获取新的 Token";
+
+/// 访问日志中的请求行 (替代 Logger 的 `%r`)
+///
+/// 查询参数中的 `token` / `api_token` 只保留前 4 个字符，
+/// 避免 `GET /token/list?token=` 之类的请求把完整 Token 写入访问日志。
+pub fn redacted_request_line(req: &ServiceRequest) -> String {
+    let query = req.query_string();
+    let target = if query.is_empty() {
+        req.path().to_string()
+    } else {
+        let redacted = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, value)) if key == "token" || key == "api_token" => {
+                    format!("{key}={}***", value.chars().take(4).collect::<String>())
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        format!("{}?{redacted}", req.path())
+    };
+    format!("{} {target} {:?}", req.method(), req.version())
+}
+
+/// 请求所使用的数据源，写入 [`DATA_SOURCE_HEADER`]
+pub fn data_source_of(req: &IdentifierRequest) -> &'static str {
+    if req.data_source.as_deref() == Some("external") {
+        "external"
+    } else {
+        "internal"
+    }
+}
+
+/// 内部 Token 被拒绝时，返回改用外部数据源的身份信息
+///
+/// 仅在配置了 `EXTERNAL_FALLBACK_ENABLED=true`、请求使用内部数据源、错误为 Token 失效，
+/// 且请求带有外部数据源可识别的身份 (`platform`+`platform_id` 或 `api_user_id`) 时返回。
+/// 回退请求不携带原 Token，外部数据源改用平台或 API 身份认证。
+pub fn external_fallback(req: &IdentifierRequest, err: &AppError) -> Option<IdentifierRequest> {
+    if !CONFIG.external_fallback_enabled || req.data_source.as_deref() == Some("external") {
+        return None;
+    }
+    if !matches!(err, AppError::AuthError(_) | AppError::InvalidSessionToken) {
+        return None;
+    }
+    let non_empty = |value: &Option<String>| value.as_deref().is_some_and(|v| !v.trim().is_empty());
+    let has_platform_identity = non_empty(&req.platform) && non_empty(&req.platform_id);
+    if !has_platform_identity && !non_empty(&req.api_user_id) {
+        return None;
+    }
+
+    log::warn!(
+        "内部 Token 被拒绝，回退到外部数据源 (platform={:?}, platform_id={:?}, api_user_id={:?})",
+        req.platform,
+        req.platform_id,
+        req.api_user_id
+    );
+    Some(IdentifierRequest {
+        token: None,
+        data_source: Some("external".to_string()),
+        ..req.clone()
+    })
+}
+
+/// Token 所属的平台绑定，Token 被服务器拒绝时用于提示用户需要重新绑定的账号
+#[derive(Debug, Clone)]
+pub struct BindingContext {
+    pub platform: String,
+    pub platform_id: String,
+}
+
+/// 解析得到的 Token 及其来源绑定，直接提供 Token 时没有绑定信息
+#[derive(Debug, Clone)]
+pub struct ResolvedToken {
+    pub token: String,
+    pub binding: Option<BindingContext>,
+}
+
+impl ResolvedToken {
+    /// Token 被拒绝时，在错误信息中注明失效的绑定；其他错误原样返回
+    pub fn annotate_error(&self, err: AppError) -> AppError {
+        let Some(binding) = &self.binding else {
+            return err;
+        };
+        match err {
+            AppError::AuthError(_) | AppError::InvalidSessionToken => {
+                log::warn!(
+                    "平台 '{}' 的 ID '{}' 绑定的 Token 已被拒绝",
+                    binding.platform,
+                    binding.platform_id
+                );
+                AppError::AuthError(format!(
+                    "平台 {} 的 ID {} 绑定的 Token 无效或已过期，{REAUTH_HINT}，并通过 POST /bind 更新该绑定",
+                    binding.platform, binding.platform_id
+                ))
+            }
+            other => other,
+        }
+    }
+}
+
 /// 从请求中解析出SessionToken
 /// 优先使用请求体中的token字段
 /// 如果token字段不存在，尝试使用platform和platform_id字段查询数据库获取绑定的token
@@ -11,17 +126,34 @@ pub async fn resolve_token(
     req: &web::Json<IdentifierRequest>,
     user_service: &web::Data<UserService>,
 ) -> AppResult<String> {
+    resolve_token_with_binding(req, user_service)
+        .await
+        .map(|resolved| resolved.token)
+}
+
+/// 与 [`resolve_token`] 相同，但同时返回 Token 来源的绑定，
+/// 便于在 Token 被拒绝时告知用户需要重新绑定哪个账号
+pub async fn resolve_token_with_binding(
+    req: &web::Json<IdentifierRequest>,
+    user_service: &web::Data<UserService>,
+) -> AppResult<ResolvedToken> {
     // 检查是否为外部数据源
     if req.data_source.as_deref() == Some("external") {
         log::debug!("检测到外部数据源请求，返回占位符token");
         // 外部数据源不需要真实的token，返回占位符
-        return Ok("external_placeholder_token".to_string());
+        return Ok(ResolvedToken {
+            token: "external_placeholder_token".to_string(),
+            binding: None,
+        });
     }
 
     if let Some(token) = &req.token {
         if !token.trim().is_empty() {
             log::debug!("从请求体 token 字段解析到 Token");
-            return Ok(token.clone());
+            return Ok(ResolvedToken {
+                token: token.clone(),
+                binding: None,
+            });
         }
     }
 
@@ -37,7 +169,13 @@ pub async fn resolve_token(
                         "通过平台ID查询到绑定，获取 Token: {}",
                         binding.session_token
                     );
-                    return Ok(binding.session_token);
+                    return Ok(ResolvedToken {
+                        token: binding.session_token,
+                        binding: Some(BindingContext {
+                            platform: platform.clone(),
+                            platform_id: platform_id.clone(),
+                        }),
+                    });
                 }
                 Err(AppError::UserBindingNotFound(_)) => {
                     log::warn!("平台 '{platform}' 的 ID '{platform_id}' 未找到或未绑定 Token");
@@ -59,6 +197,27 @@ pub async fn resolve_token(
     ))
 }
 
+/// 解析 Token 并校验格式，返回 `token` 字段已填入解析结果的请求
+///
+/// `get_rks_with_source` 等服务函数从 `request.token` 读取内部数据源的 Token，
+/// 通过平台绑定解析到的 Token 不在原请求中，因此调用这些函数时需传入此处返回的请求。
+/// 外部数据源的请求原样返回。
+pub async fn resolve_request(
+    req: &web::Json<IdentifierRequest>,
+    user_service: &web::Data<UserService>,
+) -> AppResult<(IdentifierRequest, ResolvedToken)> {
+    let resolved = resolve_token_with_binding(req, user_service).await?;
+    if req.data_source.as_deref() == Some("external") {
+        return Ok((req.0.clone(), resolved));
+    }
+    check_session_token(&resolved.token).map_err(|e| resolved.annotate_error(e))?;
+    let request = IdentifierRequest {
+        token: Some(resolved.token.clone()),
+        ..req.0.clone()
+    };
+    Ok((request, resolved))
+}
+
 /// 从请求中获取内部用户ID
 /// 首先尝试解析token获取平台绑定，然后返回关联的内部ID
 #[allow(dead_code)]